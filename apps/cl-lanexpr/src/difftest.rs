@@ -0,0 +1,211 @@
+// Pipelines to run a lanexpr file, for the differential testing (--diff-test)
+// - ref / ref-O2: C or python reference (see obtests::difftest::RefPipeline)
+// - irint3a / irintsm: translation to the IR, run with the interpreter
+// - irintsm-3a: translation to irintsm, lowered to irint3a (see irconv::smto3a), run with the irint3a interpreter
+// - irint3a-sm: translation to irint3a, lowered to irintsm (see irconv::smfrom3a), run with the irintsm interpreter
+// - <pipeline>-opt: same as the 4 pipelines above, with the passes of the IR run by the interpreter:
+//   switch lowering for irint3a, peephole optimizer for irintsm
+// - llvm-O0 / llvm-O2: binary compiled with LLVM
+//
+// Interpreters panics are reported as exit code -1, same as crashes of the binaries
+
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+use lanexpr::ast;
+use lanexpr::bindapp::BindApp;
+use lanexpr::parser;
+use lanexpr::translater;
+use lanexpr::typecheck;
+use obtests::difftest::{DiffReport, DiffTest, ExecResult, Pipeline, RefPipeline};
+use obtests::utils;
+
+fn parse_and_check(src_path: &str) -> (ast::ASTExprPtr, BindApp) {
+    let mut ps = parser::Parser::new_from_file(src_path);
+    let ast = ps.parse();
+    let mut tc = typecheck::TypeCheck::new();
+    tc.check(&ast);
+    let ba = tc.get_bindings();
+    (ast, ba)
+}
+
+// Run an interpreter, the module is translated again for every run
+fn run_interpreter<F: FnOnce() -> (Vec<u8>, i32)>(f: F) -> ExecResult {
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok((stdout, exit_code)) => ExecResult { stdout, exit_code },
+        Err(_) => ExecResult {
+            stdout: vec![],
+            exit_code: -1,
+        },
+    }
+}
+
+// Run irint3a code with the interpreter, after the IR passes if `opt` (switch lowering)
+fn run_irint3a(mut code: irint3a::ir::Module, opt: bool, stdin: &[u8]) -> (Vec<u8>, i32) {
+    if opt {
+        irint3a::switchlowering::lower_switches_module(&mut code);
+        irint3a::irvalidation::validate_module(&code, None).unwrap();
+    }
+    let mut rt = interp_irint3a::runtime::Runtime::new(code);
+    rt.reset_stdin_raw(stdin);
+    let ret = rt.run();
+    (rt.stdout().to_vec(), ret.get_val())
+}
+
+// Run irintsm code with the interpreter, after the IR passes if `opt` (peephole optimizer)
+fn run_irintsm(code: irintsm::ir::Module, opt: bool, stdin: &[u8]) -> (Vec<u8>, i32) {
+    let code = match opt {
+        true => irintsm::peephole::optimize_module(&code).0,
+        false => code,
+    };
+    let mut rt = interp_irintsm::runtime::Runtime::new(code);
+    rt.reset_stdin_raw(stdin);
+    let ret = rt.run();
+    (rt.stdout().to_vec(), ret.get_val())
+}
+
+// Name of an interpreter pipeline: <ir>(-<lowered ir>)?(-opt)?
+fn interpreter_name(ir: &str, lowered: Option<&str>, opt: bool) -> String {
+    let mut res = ir.to_string();
+    if let Some(lowered) = lowered {
+        res.push('-');
+        res.push_str(lowered);
+    }
+    if opt {
+        res.push_str("-opt");
+    }
+    res
+}
+
+struct Irint3aPipeline {
+    src: Option<(ast::ASTExprPtr, BindApp)>,
+    to_sm: bool,
+    opt: bool,
+}
+
+impl Pipeline for Irint3aPipeline {
+    fn name(&self) -> String {
+        let lowered = if self.to_sm { Some("sm") } else { None };
+        interpreter_name("irint3a", lowered, self.opt)
+    }
+
+    fn prepare(&mut self, src_path: &str) -> bool {
+        self.src = Some(parse_and_check(src_path));
+        true
+    }
+
+    fn run(&mut self, stdin: &[u8]) -> ExecResult {
+        let (ast, ba) = self.src.as_ref().unwrap();
+        let (to_sm, opt) = (self.to_sm, self.opt);
+        run_interpreter(|| {
+            let (code, _names) = translater::irint3a::Translater::new(ast, ba).translate();
+            if to_sm {
+                run_irintsm(irconv::smfrom3a::translate(&code).unwrap(), opt, stdin)
+            } else {
+                run_irint3a(code, opt, stdin)
+            }
+        })
+    }
+}
+
+struct IrintsmPipeline {
+    src: Option<(ast::ASTExprPtr, BindApp)>,
+    to_3a: bool,
+    opt: bool,
+}
+
+impl Pipeline for IrintsmPipeline {
+    fn name(&self) -> String {
+        let lowered = if self.to_3a { Some("3a") } else { None };
+        interpreter_name("irintsm", lowered, self.opt)
+    }
+
+    fn prepare(&mut self, src_path: &str) -> bool {
+        self.src = Some(parse_and_check(src_path));
+        true
+    }
+
+    fn run(&mut self, stdin: &[u8]) -> ExecResult {
+        let (ast, ba) = self.src.as_ref().unwrap();
+        let (to_3a, opt) = (self.to_3a, self.opt);
+        run_interpreter(|| {
+            let code = translater::irintsmtl::Translater::new(ast, ba).translate();
+            if to_3a {
+                run_irint3a(irconv::smto3a::translate(&code), opt, stdin)
+            } else {
+                run_irintsm(code, opt, stdin)
+            }
+        })
+    }
+}
+
+struct LlvmPipeline {
+    opt_level: u8,
+    bin_path: Option<String>,
+}
+
+impl Pipeline for LlvmPipeline {
+    fn name(&self) -> String {
+        format!("llvm-O{}", self.opt_level)
+    }
+
+    // Not available if clang fails to build the binary
+    fn prepare(&mut self, src_path: &str) -> bool {
+        let (ast, ba) = parse_and_check(src_path);
+        let bin_path = format!(
+            "/tmp/lanexpr_difftest_bin_{}_O{}",
+            utils::calculate_hash(src_path),
+            self.opt_level
+        );
+        let _ = std::fs::remove_file(&bin_path);
+        let built = catch_unwind(AssertUnwindSafe(|| {
+            translater::llvmbin::compile_to_binary_opt(&ast, &ba, &bin_path, self.opt_level)
+        }));
+
+        let available = built.is_ok() && std::path::Path::new(&bin_path).is_file();
+        self.bin_path = if available { Some(bin_path) } else { None };
+        available
+    }
+
+    fn run(&mut self, stdin: &[u8]) -> ExecResult {
+        let bin_path = self.bin_path.as_ref().unwrap();
+        let (stdout, exit_code) = utils::run_cmd_status(bin_path, &[] as &[&str], stdin);
+        ExecResult { stdout, exit_code }
+    }
+}
+
+/// Run the lanexpr file with all pipelines, and compare the results
+/// Uses the stdin files if not empty, otherwise the inputs of the test (see obtests::difftest::list_inputs)
+pub fn run_diff_test(src_path: &str, stdin_paths: &[&str]) -> DiffReport {
+    let mut dt = DiffTest::new();
+    dt.add_pipeline(Box::new(RefPipeline::new(false)));
+    dt.add_pipeline(Box::new(RefPipeline::new(true)));
+    for opt in &[false, true] {
+        for lowered in &[false, true] {
+            dt.add_pipeline(Box::new(Irint3aPipeline {
+                src: None,
+                to_sm: *lowered,
+                opt: *opt,
+            }));
+            dt.add_pipeline(Box::new(IrintsmPipeline {
+                src: None,
+                to_3a: *lowered,
+                opt: *opt,
+            }));
+        }
+    }
+    for opt_level in &[0, 2] {
+        dt.add_pipeline(Box::new(LlvmPipeline {
+            opt_level: *opt_level,
+            bin_path: None,
+        }));
+    }
+
+    let inputs = match stdin_paths.len() {
+        0 => obtests::difftest::list_inputs(src_path),
+        _ => stdin_paths
+            .iter()
+            .map(|path| std::fs::read(path).expect("Failed to read stdin file"))
+            .collect(),
+    };
+    dt.run(src_path, &inputs)
+}
@@ -1,5 +1,7 @@
 extern crate clap;
 
+mod difftest;
+
 use clap::{App, Arg};
 use lanexpr::ast;
 use lanexpr::bindapp::BindApp;
@@ -18,10 +20,10 @@ fn do_typecheck(ast: &ast::ASTExprPtr) -> BindApp {
     tc.get_bindings()
 }
 
-fn gen_irint3a(root: &ast::ASTExprPtr, ba: &BindApp) {
+fn gen_irint3a(root: &ast::ASTExprPtr, ba: &BindApp, locs: &ast::ASTLocs) {
     use irint3a::irprinter::CodePrintable;
 
-    let tr = translater::irint3a::Translater::new(root, ba);
+    let tr = translater::irint3a::Translater::new_with_locations(root, ba, locs);
     let (code, mut names) = tr.translate();
     names.complete_undefined(&code);
     code.print_code(&mut std::io::stdout(), Some(&names));
@@ -31,8 +33,8 @@ fn gen_irintsm(root: &ast::ASTExprPtr, ba: &BindApp) {
     use irintsm::irprinter::CodePrintable;
 
     let tr = translater::irintsmtl::Translater::new(root, ba);
-    let code = tr.translate();
-    code.print_code(&mut std::io::stdout());
+    let (code, names) = tr.translate_with_names();
+    code.print_code_with_names(&mut std::io::stdout(), &names);
 }
 
 fn gen_llvm_ir(root: &ast::ASTExprPtr, ba: &BindApp, out_path: Option<&str>) {
@@ -96,6 +98,20 @@ fn main() {
                 .long("bin-llvm")
                 .help("Create an executable by generating LLVM IR"),
         )
+        .arg(
+            Arg::with_name("diff-test")
+                .long("diff-test")
+                .help("Run the program with all backends (interpreters, LLVM, C / python reference) and compare the results"),
+        )
+        .arg(
+            Arg::with_name("stdin")
+                .long("stdin")
+                .value_name("FILE")
+                .help("Set a stdin file for --diff-test (can be used multiple times, default: input_<name> directory next to the input)")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
         .get_matches();
 
     let input_path = matches.value_of("INPUT").unwrap();
@@ -111,9 +127,10 @@ fn main() {
         let ati = do_typecheck(&ast);
         ati.dump_bindings();
     } else if matches.occurrences_of("gen-irint3a") > 0 {
-        let ast = do_parse(input_path);
+        let mut ps = parser::Parser::new_from_file(input_path);
+        let ast = ps.parse();
         let ati = do_typecheck(&ast);
-        gen_irint3a(&ast, &ati);
+        gen_irint3a(&ast, &ati, ps.locations());
     } else if matches.occurrences_of("gen-irintsm") > 0 {
         let ast = do_parse(input_path);
         let ati = do_typecheck(&ast);
@@ -122,6 +139,13 @@ fn main() {
         let ast = do_parse(input_path);
         let ati = do_typecheck(&ast);
         gen_llvm_ir(&ast, &ati, output_path);
+    } else if matches.occurrences_of("diff-test") > 0 {
+        let stdin_paths: Vec<_> = matches.values_of("stdin").into_iter().flatten().collect();
+        let report = difftest::run_diff_test(input_path, &stdin_paths);
+        report.print(&mut std::io::stdout());
+        if !report.is_ok() {
+            std::process::exit(1);
+        }
     } else if matches.occurrences_of("bin-llvm") > 0 {
         let output_path = output_path.unwrap_or("./a.out");
         let ast = do_parse(input_path);
@@ -0,0 +1,44 @@
+use std::process::Command;
+
+// Run cl-lanexpr --diff-test, fails if any pipeline gives a different result,
+// or if the LLVM pipelines are unavailable (nothing would be compared with the binaries)
+fn diff_test(dir: &str, test_name: &str) {
+    let path = format!("{}/{}.le", dir, test_name);
+    let out = Command::new(env!("CARGO_BIN_EXE_cl-lanexpr"))
+        .args([path.as_str(), "--diff-test"])
+        .output()
+        .expect("Failed to run cl-lanexpr");
+
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    println!("{}", stdout);
+    assert!(out.status.success(), "Diff test {} failed", test_name);
+    let skipped = stdout
+        .lines()
+        .find(|line| line.starts_with("Skipped (unavailable):"))
+        .unwrap_or("");
+    assert!(
+        !skipped.contains("llvm"),
+        "Diff test {}: LLVM pipelines unavailable",
+        test_name
+    );
+}
+
+#[test]
+fn diff_test_basics_fact() {
+    diff_test("../../libs/lanexpr/tests/basics", "fact");
+}
+
+#[test]
+fn diff_test_basics_calc() {
+    diff_test("../../libs/lanexpr/tests/basics", "calc");
+}
+
+#[test]
+fn diff_test_basics_cat() {
+    diff_test("../../libs/lanexpr/tests/basics", "cat");
+}
+
+#[test]
+fn diff_test_algos1_binsearch() {
+    diff_test("../../libs/lanexpr/tests/algos1", "binsearch");
+}
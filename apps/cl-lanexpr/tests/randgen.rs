@@ -0,0 +1,103 @@
+use lanexpr::parser;
+use lanexpr::randgen;
+use lanexpr::translater;
+use lanexpr::typecheck;
+
+const NB_SEEDS: u64 = 300;
+
+type ExecResult = (Vec<u8>, i32);
+
+// Run a random program with both interpreters, and the code lowered to the other IR
+// Returns the outputs and exit codes: irint3a, irintsm, irintsm lowered to irint3a, irint3a lowered to irintsm
+fn run_interpreters(code: &str) -> (ExecResult, ExecResult, ExecResult, ExecResult) {
+    let mut ps = parser::Parser::new_from_str(code);
+    let ast = ps.parse();
+    let mut tc = typecheck::TypeCheck::new();
+    tc.check(&ast);
+    let ba = tc.get_bindings();
+
+    let (code_3a, _names) = translater::irint3a::Translater::new(&ast, &ba).translate();
+    let code_3a_sm = irconv::smfrom3a::translate(&code_3a).unwrap();
+    let mut rt = interp_irint3a::runtime::Runtime::new(code_3a);
+    let ret = rt.run();
+    let res_3a = (rt.stdout().to_vec(), ret.get_val());
+
+    let code_sm = translater::irintsmtl::Translater::new(&ast, &ba).translate();
+    let code_sm_3a = irconv::smto3a::translate(&code_sm);
+    let mut rt = interp_irintsm::runtime::Runtime::new(code_sm);
+    let ret = rt.run();
+    let res_sm = (rt.stdout().to_vec(), ret.get_val());
+
+    let mut rt = interp_irint3a::runtime::Runtime::new(code_sm_3a);
+    let ret = rt.run();
+    let res_sm_3a = (rt.stdout().to_vec(), ret.get_val());
+
+    let mut rt = interp_irintsm::runtime::Runtime::new(code_3a_sm);
+    let ret = rt.run();
+    let res_3a_sm = (rt.stdout().to_vec(), ret.get_val());
+
+    (res_3a, res_sm, res_sm_3a, res_3a_sm)
+}
+
+#[test]
+fn randgen_interpreters() {
+    for seed in 0..NB_SEEDS {
+        let code = randgen::gen_source(seed);
+        let (res_3a, res_sm, res_sm_3a, res_3a_sm) = run_interpreters(&code);
+        assert!(
+            res_3a == res_sm,
+            "Seed {}: irint3a and irintsm give different results\n{}",
+            seed,
+            code
+        );
+        assert!(
+            res_sm == res_sm_3a,
+            "Seed {}: irintsm lowered to irint3a gives a different result\n{}",
+            seed,
+            code
+        );
+        assert!(
+            res_3a == res_3a_sm,
+            "Seed {}: irint3a lowered to irintsm gives a different result\n{}",
+            seed,
+            code
+        );
+        assert!(!res_3a.0.is_empty(), "Seed {}: empty output", seed);
+    }
+}
+
+// irintsm code of random programs must give the same text after a round-trip through the binary format
+#[test]
+fn randgen_irintsm_binary() {
+    use irintsm::irprinter::CodePrintable;
+
+    let print = |code: &irintsm::ir::Module| {
+        let mut out: Vec<u8> = vec![];
+        code.print_code(&mut out);
+        String::from_utf8(out).unwrap()
+    };
+
+    for seed in 0..NB_SEEDS {
+        let code = randgen::gen_source(seed);
+        let mut ps = parser::Parser::new_from_str(&code);
+        let ast = ps.parse();
+        let mut tc = typecheck::TypeCheck::new();
+        tc.check(&ast);
+        let ba = tc.get_bindings();
+
+        let code_sm = translater::irintsmtl::Translater::new(&ast, &ba).translate();
+        let (code_3a, _names) = translater::irint3a::Translater::new(&ast, &ba).translate();
+        let code_3a_sm = irconv::smfrom3a::translate(&code_3a).unwrap();
+
+        for module in &[code_sm, code_3a_sm] {
+            let bin = irintsm::irbinary::write_module(module);
+            let module2 = irintsm::irbinary::read_module(&bin).unwrap();
+            assert!(
+                print(module) == print(&module2),
+                "Seed {}: binary round-trip changed the code\n{}",
+                seed,
+                code
+            );
+        }
+    }
+}
@@ -0,0 +1,242 @@
+// Interactive debugger (--debug option)
+//
+// Commands are read from stdin, one per line (the program stdin is given by --stdin).
+// After each command, the new output of the program is printed, then the next instruction to run.
+
+use std::io::{BufRead, Write};
+
+use interp_irint3a::debugger::{Debugger, InsAddress, StopReason};
+use interp_irint3a::runtime::Runtime;
+use interp_irint3a::snapshot::Snapshot;
+use irint3a::irnames::ModuleNames;
+use irint3a::irprinter;
+
+const HELP: &str = "Commands:
+  s, step [N]                   run N instructions (default: 1)
+  c, continue                   run until the next breakpoint
+  rs, reverse-step [N]          go back N instructions (default: 1)
+  rc, reverse-continue          go back to the previous breakpoint hit
+  goto STEP                     go to the state after STEP executed instructions
+  b, break FUN [BB [POS]]       add a breakpoint (default: first instruction of the function)
+  d, delete FUN [BB [POS]]      remove a breakpoint
+  bl, breakpoints               list all breakpoints
+  bt, backtrace                 print the call stack
+  regs                          print the registers of the current function
+  save FILE                     write the runtime state to FILE
+  load FILE                     restore the runtime state from FILE (can't go back before this state)
+  h, help                       print this help
+  q, quit                       quit the debugger";
+
+struct DebugSession<'a> {
+    dbg: Debugger,
+    names: &'a ModuleNames,
+    out_len: usize, // size of the program output already printed
+}
+
+pub fn run_debugger(rt: Runtime, names: &ModuleNames) {
+    let mut session = DebugSession {
+        dbg: Debugger::new(rt),
+        names,
+        out_len: 0,
+    };
+    session.print_position();
+
+    let stdin = std::io::stdin();
+    loop {
+        print!("(dbg) ");
+        std::io::stdout().flush().unwrap();
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap() == 0 {
+            break;
+        }
+
+        let args: Vec<_> = line.split_whitespace().collect();
+        if args.is_empty() {
+            continue;
+        }
+        if args[0] == "q" || args[0] == "quit" {
+            break;
+        }
+        if let Err(msg) = session.exec(&args) {
+            println!("error: {}", msg);
+        }
+    }
+}
+
+impl<'a> DebugSession<'a> {
+    fn exec(&mut self, args: &[&str]) -> Result<(), String> {
+        match args[0] {
+            "s" | "step" => {
+                let count = parse_count(args.get(1))?;
+                let mut reason = StopReason::Step;
+                for _ in 0..count {
+                    reason = self.dbg.step();
+                    if reason != StopReason::Step {
+                        break;
+                    }
+                }
+                self.print_stop(reason);
+            }
+            "c" | "continue" => {
+                let reason = self.dbg.cont();
+                self.print_stop(reason);
+            }
+            "rs" | "reverse-step" => {
+                let count = parse_count(args.get(1))?;
+                let target = self.dbg.steps().saturating_sub(count);
+                self.dbg.goto_step(target);
+                let reason = match self.dbg.steps() {
+                    x if x == self.dbg.first_step() => StopReason::Begin,
+                    _ => StopReason::Step,
+                };
+                self.print_stop(reason);
+            }
+            "rc" | "reverse-continue" => {
+                let reason = self.dbg.reverse_continue();
+                self.print_stop(reason);
+            }
+            "goto" => {
+                let steps = args
+                    .get(1)
+                    .and_then(|x| x.parse().ok())
+                    .ok_or("goto: expected a number of steps")?;
+                self.dbg.goto_step(steps);
+                self.print_stop(StopReason::Step);
+            }
+            "b" | "break" => {
+                let addr = self.parse_address(&args[1..])?;
+                self.dbg.add_breakpoint(addr);
+                println!("Breakpoint at {}", self.address_name(addr));
+            }
+            "d" | "delete" => {
+                let addr = self.parse_address(&args[1..])?;
+                if !self.dbg.remove_breakpoint(addr) {
+                    return Err(format!("no breakpoint at {}", self.address_name(addr)));
+                }
+            }
+            "bl" | "breakpoints" => {
+                for addr in self.dbg.breakpoints() {
+                    println!("  {}", self.address_name(*addr));
+                }
+            }
+            "bt" | "backtrace" => {
+                for (idx, addr) in self.dbg.runtime().call_stack().iter().rev().enumerate() {
+                    println!("  #{} {}", idx, self.address_name(*addr));
+                }
+            }
+            "regs" => {
+                let (fun, _, _) = self.dbg.position();
+                let fun_names = self.names.get_function(fun).unwrap();
+                for (reg, val) in self.dbg.runtime().registers() {
+                    let name = fun_names.get_register_name(reg).unwrap_or("?");
+                    println!("  %{} = {}", name, val);
+                }
+            }
+            "save" => {
+                let path = args.get(1).ok_or("save: expected a file path")?;
+                self.dbg.runtime().snapshot().save(path);
+            }
+            "load" => {
+                let path = args.get(1).ok_or("load: expected a file path")?;
+                self.dbg.load_snapshot(&Snapshot::load(path));
+                self.out_len = self.dbg.runtime().stdout().len();
+                self.print_position();
+            }
+            "h" | "help" => println!("{}", HELP),
+            cmd => return Err(format!("unknown command {} (see help)", cmd)),
+        }
+        Ok(())
+    }
+
+    fn print_stop(&mut self, reason: StopReason) {
+        // the output may be shorter after going back
+        let out = self.dbg.runtime().stdout();
+        if out.len() > self.out_len {
+            std::io::stdout().write_all(&out[self.out_len..]).unwrap();
+            if !out.ends_with(b"\n") {
+                println!();
+            }
+        }
+        self.out_len = out.len();
+
+        match reason {
+            StopReason::Step => {}
+            StopReason::Breakpoint => println!("Breakpoint hit"),
+            StopReason::Exit(code) => println!("Program exited with code {}", code),
+            StopReason::Begin => println!("Beginning of the program"),
+        }
+        if self.dbg.exit_code().is_none() {
+            self.print_position();
+        }
+    }
+
+    fn print_position(&self) {
+        let addr = self.dbg.position();
+        let (fun, bb, pos) = addr;
+        let code = self.dbg.runtime().code();
+        let ins = code.get_fun(fun).unwrap().get_basic_block(bb).get_ins(pos);
+
+        let mut ins_str = vec![];
+        irprinter::print_ins(code, &mut ins_str, self.names, fun, ins);
+        println!(
+            "[{}] {}: {}",
+            self.dbg.steps(),
+            self.address_name(addr),
+            String::from_utf8(ins_str).unwrap()
+        );
+    }
+
+    fn address_name(&self, addr: InsAddress) -> String {
+        let (fun, bb, pos) = addr;
+        let fun_names = self.names.get_function(fun).unwrap();
+        let res = format!(
+            "{}:{}:{}",
+            self.names.get_function_name(fun).unwrap(),
+            fun_names.get_basic_block_name(bb).unwrap(),
+            pos
+        );
+        match self.dbg.runtime().ins_loc(fun, bb, pos) {
+            Some(loc) => format!("{} ({})", res, loc),
+            None => res,
+        }
+    }
+
+    fn parse_address(&self, args: &[&str]) -> Result<InsAddress, String> {
+        let fun_name = args.get(0).ok_or("expected a function name")?;
+        let fun_id = self
+            .names
+            .get_function_id(fun_name)
+            .ok_or(format!("unknown function {}", fun_name))?;
+        let fun = self.dbg.runtime().code().get_fun(fun_id).unwrap();
+        if fun.is_extern() {
+            return Err(format!("{} is an extern function", fun_name));
+        }
+
+        let bb_id = match args.get(1) {
+            Some(bb_name) => self
+                .names
+                .get_function(fun_id)
+                .unwrap()
+                .get_basic_block_id(bb_name)
+                .ok_or(format!("unknown basic block {}", bb_name))?,
+            None => fun.basic_blocks_list()[0],
+        };
+        let pos = match args.get(2) {
+            Some(pos) => pos
+                .parse()
+                .map_err(|_| format!("invalid instruction index {}", pos))?,
+            None => 0,
+        };
+        if pos >= fun.get_basic_block(bb_id).size() {
+            return Err(format!("invalid instruction index {}", pos));
+        }
+        Ok((fun_id, bb_id, pos))
+    }
+}
+
+fn parse_count(arg: Option<&&str>) -> Result<u64, String> {
+    match arg {
+        Some(x) => x.parse().map_err(|_| format!("invalid count {}", x)),
+        None => Ok(1),
+    }
+}
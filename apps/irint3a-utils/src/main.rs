@@ -1,21 +1,87 @@
 extern crate clap;
 
+mod debug;
+
 use clap::{App, Arg};
 use std::collections::HashMap;
-use std::io::Read;
-use std::io::Write;
+use std::fs::File;
+use std::io::BufReader;
 
+use interp_irint3a::natives;
 use interp_irint3a::runtime;
+use interp_irint3a::tracer;
+use irint3a::irdiagnostics::write_diagnostics;
+use irint3a::irlinker::Linker;
 use irint3a::irparser::Parser;
 use irint3a::irprinter::CodePrintable;
 
 fn set_stdin(rt: &mut interp_irint3a::runtime::Runtime, path: &str) {
     if path == "-" {
-        let mut data = vec![];
-        std::io::stdin().read_to_end(&mut data).unwrap();
-        rt.reset_stdin_raw(&data);
+        rt.set_stdin(Box::new(std::io::stdin()));
     } else {
-        rt.reset_stdin_path(path);
+        let f = File::open(path).expect("Failed to open stdin file");
+        rt.set_stdin(Box::new(BufReader::new(f)));
+    }
+}
+
+fn create_natives(files_root: Option<&str>) -> natives::NativeRegistry {
+    let mut natives = natives::NativeRegistry::default();
+    if let Some(root) = files_root {
+        natives.register_host_files(root);
+    }
+    natives
+}
+
+// Parse an input file, print the warnings and errors, and exit if it's invalid
+fn parse_file(path: &str) -> (irint3a::ir::Module, irint3a::irnames::ModuleNames) {
+    let source = match std::fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(err) => {
+            eprintln!("error: cannot read {}: {}", path, err);
+            std::process::exit(1);
+        }
+    };
+    let res = Parser::from_str(&source).build_with_warnings();
+    let mut stderr = std::io::stderr();
+    match res {
+        Ok((code, names, warnings)) => {
+            write_diagnostics(&mut stderr, &warnings, path, &source);
+            (code, names)
+        }
+        Err(diags) => {
+            write_diagnostics(&mut stderr, &diags, path, &source);
+            let nb_errors = diags.iter().filter(|diag| diag.is_error()).count();
+            eprintln!("{}: {} error(s)", path, nb_errors);
+            std::process::exit(1);
+        }
+    }
+}
+
+// Parse all the input files, and link them if there are several
+fn load_inputs(
+    in_paths: &[&str],
+    natives: &natives::NativeRegistry,
+) -> (irint3a::ir::Module, irint3a::irnames::ModuleNames) {
+    if in_paths.len() == 1 {
+        return parse_file(in_paths[0]);
+    }
+
+    let mut linker = Linker::new();
+    for path in in_paths {
+        let (code, names) = parse_file(path);
+        linker.add_module(code, names);
+    }
+    linker.set_natives(&natives.ids());
+    match linker.link() {
+        Ok(res) => res,
+        Err(diags) => {
+            // the linked module has no source file
+            let path = in_paths.join(" + ");
+            write_diagnostics(&mut std::io::stderr(), &diags, &path, "");
+            let nb_errors = diags.iter().filter(|diag| diag.is_error()).count();
+            eprintln!("link: {} error(s)", nb_errors);
+            std::process::exit(1);
+        }
     }
 }
 
@@ -26,8 +92,9 @@ fn main() {
         .about("Utils to manipulate irint3a IR files")
         .arg(
             Arg::with_name("INPUT")
-                .help("Set the input file")
-                .required(true),
+                .help("Set the input files (several files are linked together)")
+                .required(true)
+                .multiple(true),
         )
         .arg(
             Arg::with_name("OUTPUT")
@@ -47,9 +114,33 @@ fn main() {
             Arg::with_name("stdin")
                 .long("stdin")
                 .value_name("FILE")
-                .help("Set the stdin file for the interpreter environment")
+                .help("Set the stdin file for the interpreter environment (- to read the process stdin while running, default: empty)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("files-root")
+                .long("files-root")
+                .value_name("DIR")
+                .help("Enable the natives to access host files (fopen, fclose, fgetc, fputc) inside DIR")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("trace")
+                .long("trace")
+                .value_name("FILE")
+                .help("Write an execution trace of the interpreter to FILE (use trace-diff to compare traces)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("debug")
+                .long("debug")
+                .help("Run the IR program with an interactive debugger (commands are read from stdin, the program stdin must be set with --stdin)"),
+        )
+        .arg(
+            Arg::with_name("memcheck")
+                .long("memcheck")
+                .help("Run the IR program with the memory-safety checker: stops at the first invalid access"),
+        )
         .arg(
             Arg::with_name("dump-cfg")
                 .long("dump-cfg")
@@ -57,26 +148,105 @@ fn main() {
                 .help("Create a dot output file for the CFG of the corresponding function")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("profile")
+                .long("profile")
+                .value_name("FILE")
+                .help("Run the IR program and write a profiling report to FILE")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("coverage")
+                .long("coverage")
+                .value_name("FILE")
+                .help("Run the IR program and write the code annotated with execution counts to FILE")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("profile-cfg")
+                .long("profile-cfg")
+                .value_name("FUNCTION")
+                .help("Run the IR program and create a dot output file for the CFG of the corresponding function, with execution counts")
+                .takes_value(true),
+        )
         .get_matches();
 
-    let in_path = matches.value_of("INPUT").unwrap();
+    let in_paths: Vec<_> = matches.values_of("INPUT").unwrap().collect();
     let out_path = matches.value_of("OUTPUT");
-    let ps = Parser::from_file(&in_path);
-    let (code, names) = ps.build();
+    let files_root = matches.value_of("files-root");
+    let (code, names) = load_inputs(&in_paths, &create_natives(files_root));
 
     if matches.occurrences_of("dump") > 0 {
         code.print_code(&mut std::io::stdout(), Some(&names));
     }
 
-    if matches.occurrences_of("run") > 0 {
-        let mut rt = runtime::Runtime::new(code);
+    let profile_path = matches.value_of("profile");
+    let coverage_path = matches.value_of("coverage");
+    let profile_cfg = matches.value_of("profile-cfg");
+    let use_profiler = profile_path.is_some() || coverage_path.is_some() || profile_cfg.is_some();
+
+    let use_memcheck = matches.occurrences_of("memcheck") > 0;
+
+    if matches.occurrences_of("debug") > 0 {
+        let mut rt = runtime::Runtime::new_with_natives(code, create_natives(files_root));
+        if let Some(path) = matches.value_of("stdin") {
+            rt.reset_stdin_path(path);
+        }
+        debug::run_debugger(rt, &names);
+        return;
+    }
+
+    if matches.occurrences_of("run") > 0 || use_profiler || use_memcheck {
+        let mut rt = runtime::Runtime::new_with_natives(code, create_natives(files_root));
+
+        if let Some(path) = matches.value_of("stdin") {
+            set_stdin(&mut rt, path);
+        }
+        rt.set_stdout(Box::new(std::io::stdout()));
+        if let Some(trace_path) = matches.value_of("trace") {
+            rt.set_tracer(Box::new(tracer::TraceWriter::create(trace_path)));
+        }
+        if use_profiler {
+            rt.enable_profiler();
+        }
+        if use_memcheck {
+            rt.enable_memcheck();
+        }
+
+        // the runtime panics on execution errors: report the source location of the faulting instruction
+        let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| rt.try_run()));
+        let ret_code = match res {
+            Ok(Ok(ret_code)) => ret_code,
+            Ok(Err(err)) => {
+                eprintln!("{}", err.to_string_with_names(&names));
+                std::process::exit(1);
+            }
+            Err(_) => {
+                if let Some(loc) = rt.current_loc() {
+                    eprintln!("Runtime error at {}", loc);
+                }
+                std::process::exit(101);
+            }
+        };
 
-        if let Some(stdin_path) = matches.value_of("stdin") {
-            set_stdin(&mut rt, stdin_path);
+        if let Some(profiler) = rt.profiler() {
+            if let Some(path) = profile_path {
+                let mut os = File::create(path).expect("Failed to create profile output file");
+                profiler.write_report(&mut os, rt.code(), Some(&names));
+            }
+            if let Some(path) = coverage_path {
+                let mut os = File::create(path).expect("Failed to create coverage output file");
+                profiler.write_coverage(&mut os, rt.code(), Some(&names));
+            }
+            if let Some(fun_name) = profile_cfg {
+                let fun_id = names
+                    .get_function_id(fun_name)
+                    .expect("profile-cfg: function not found");
+                let out_path = out_path.unwrap_or("cfg.dot");
+                profiler.write_cfg_dot(out_path, rt.code(), Some(&names), fun_id);
+            }
         }
 
-        let ret_code = rt.run();
-        std::io::stdout().write_all(rt.stdout()).unwrap();
         std::process::exit(ret_code.get_val());
     }
 
@@ -18,7 +18,11 @@ impl UserRunner for IRRunner {
         let ba = tc.get_bindings();
 
         // translation
-        let tr = lanexpr::translater::irint3a::Translater::new(&root, &ba);
+        let tr = lanexpr::translater::irint3a::Translater::new_with_locations(
+            &root,
+            &ba,
+            ps.locations(),
+        );
 
         // execution
         let code = tr.translate().0;
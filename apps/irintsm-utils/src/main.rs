@@ -1,24 +1,101 @@
 extern crate clap;
 
 use clap::{App, Arg};
-use std::io::Read;
-use std::io::Write;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
 
+use interp_irintsm::natives;
 use interp_irintsm::runtime;
+use interp_irintsm::tracer;
 
+use irintsm::ir;
+use irintsm::irbinary;
+use irintsm::irnames::ModuleNames;
 use irintsm::irparser::Parser;
 use irintsm::irprinter::CodePrintable;
+use irintsm::irvalidation;
+use irintsm::liveness;
+use irintsm::peephole;
 
 fn set_stdin(rt: &mut interp_irintsm::runtime::Runtime, path: &str) {
     if path == "-" {
-        let mut data = vec![];
-        std::io::stdin().read_to_end(&mut data).unwrap();
-        rt.reset_stdin_raw(&data);
+        rt.set_stdin(Box::new(std::io::stdin()));
     } else {
-        rt.reset_stdin_path(path);
+        let f = File::open(path).expect("Failed to open stdin file");
+        rt.set_stdin(Box::new(BufReader::new(f)));
     }
 }
 
+// Find a function by name, or by id
+fn find_function(code: &ir::Module, names: &ModuleNames, fun: &str) -> Option<ir::FunctionRef> {
+    if let Some(fun_id) = names.get_function_id(fun) {
+        return Some(fun_id);
+    }
+    let fun_id = ir::FunctionRef::new(fun.parse::<usize>().ok()?);
+    code.fun_list()
+        .iter()
+        .find(|f| f.id() == fun_id)
+        .map(|f| f.id())
+}
+
+// Name of an instruction in the messages: function, basic block and instruction index
+// Functions and basic blocks without names are identified by their id and their index
+fn ins_location(
+    code: &ir::Module,
+    names: &ModuleNames,
+    fun_id: ir::FunctionRef,
+    bb_id: ir::BasicBlockRef,
+    ins_id: usize,
+) -> String {
+    let fun_names = names.get_function(fun_id);
+    let bb_idx = code
+        .get_fun(fun_id)
+        .bb_list()
+        .iter()
+        .position(|bb| bb.id() == bb_id)
+        .unwrap();
+    format!(
+        "Function {}, basic block {}, instruction {}",
+        names
+            .get_function_name(fun_id)
+            .unwrap_or(&fun_id.to_string()),
+        fun_names
+            .and_then(|n| n.get_basic_block_name(bb_id))
+            .unwrap_or(&bb_idx.to_string()),
+        ins_id
+    )
+}
+
+// Write the CFG of a function to a dot file, with the operands stack height at the entry of every basic block
+fn dump_cfg(fun: &ir::Function, names: &ModuleNames, out_path: &str) {
+    if fun.is_extern() {
+        panic!("dump-cfg: function {} is not defined", fun.id());
+    }
+    let cfg = irintsm::controlflow::build_cfg(fun);
+    let (stack_infos, _) = irvalidation::compute_stack_infos(fun);
+    let fun_names = names.get_function(fun.id());
+
+    let vnames: HashMap<usize, String> = fun
+        .bb_list()
+        .iter()
+        .enumerate()
+        .map(|(bb_idx, bb)| {
+            let label = match fun_names.and_then(|n| n.get_basic_block_name(bb.id())) {
+                Some(name) => name.to_string(),
+                None => bb_idx.to_string(),
+            };
+            let height = match stack_infos.entry_heights.get(&bb.id()) {
+                Some(height) => format!("stack: {}", height),
+                None => "unreachable".to_string(),
+            };
+            (bb_idx, format!("\"{}\\n{}\"", label, height))
+        })
+        .collect();
+
+    cfg.write_dot(out_path, Some("cfg"), Some(&vnames));
+}
+
 fn main() {
     let matches = App::new("irintsm-utils")
         .version("0.1.0")
@@ -29,7 +106,49 @@ fn main() {
                 .help("Set the input file")
                 .required(true),
         )
+        .arg(
+            Arg::with_name("OUTPUT")
+                .short("o")
+                .long("output")
+                .value_name("FILE")
+                .help("Set the program output file")
+                .takes_value(true),
+        )
         .arg(Arg::with_name("dump").long("dump").help("Dump the IR"))
+        .arg(
+            Arg::with_name("load-bin")
+                .long("load-bin")
+                .help("The input file is a binary module (see --emit-bin) instead of IR text"),
+        )
+        .arg(
+            Arg::with_name("emit-bin")
+                .long("emit-bin")
+                .value_name("FILE")
+                .help("Write the module to FILE in the binary format (names are not kept)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("peephole")
+                .long("peephole")
+                .help("Run the peephole optimizer before the other options, and print how many times each rule was applied (on stderr)"),
+        )
+        .arg(
+            Arg::with_name("check-stack")
+                .long("check-stack")
+                .help("Check the operands stack height of all functions, and print their maximum stack depth"),
+        )
+        .arg(
+            Arg::with_name("dump-cfg")
+                .long("dump-cfg")
+                .value_name("FUNCTION")
+                .help("Create a dot output file for the CFG of the corresponding function (name or id), with the operands stack height at the entry of every basic block")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("dead-stores")
+                .long("dead-stores")
+                .help("Find the store instructions whose value is never loaded"),
+        )
         .arg(
             Arg::with_name("run")
                 .long("run")
@@ -39,29 +158,117 @@ fn main() {
             Arg::with_name("stdin")
                 .long("stdin")
                 .value_name("FILE")
-                .help("Set the stdin file for the interpreter environment")
+                .help("Set the stdin file for the interpreter environment (- to read the process stdin while running, default: empty)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("files-root")
+                .long("files-root")
+                .value_name("DIR")
+                .help("Enable the natives to access host files (fopen, fclose, fgetc, fputc) inside DIR")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("trace")
+                .long("trace")
+                .value_name("FILE")
+                .help("Write an execution trace of the interpreter to FILE (use trace-diff to compare traces)")
                 .takes_value(true),
         )
         .get_matches();
 
     let in_path = matches.value_of("INPUT").unwrap();
-    let ps = Parser::from_file(&in_path);
-    let code = ps.build();
+    let out_path = matches.value_of("OUTPUT");
+    let (mut code, names) = if matches.occurrences_of("load-bin") > 0 {
+        let bytes = std::fs::read(in_path).expect("Failed to read input file");
+        match irbinary::read_module(&bytes) {
+            Ok(code) => (code, ModuleNames::new()),
+            Err(err) => panic!("Invalid binary module: {:?}", err),
+        }
+    } else {
+        let ps = Parser::from_file(&in_path);
+        ps.build_with_names()
+    };
+
+    if matches.occurrences_of("peephole") > 0 {
+        let (new_code, stats) = peephole::optimize_module(&code);
+        code = new_code;
+        for (rule, count) in stats.counts() {
+            eprintln!("Peephole {}: {}", rule, count);
+        }
+    }
+
+    if let Some(bin_path) = matches.value_of("emit-bin") {
+        std::fs::write(bin_path, irbinary::write_module(&code))
+            .expect("Failed to write binary file");
+    }
 
     if matches.occurrences_of("dump") > 0 {
-        code.print_code(&mut std::io::stdout());
+        code.print_code_with_names(&mut std::io::stdout(), &names);
         println!("\n");
     }
 
+    if matches.occurrences_of("check-stack") > 0 {
+        let (infos, errs) = irvalidation::validate_module_stack(&code);
+        for fun in code.fun_list() {
+            if let Some(fun_infos) = infos.get(&fun.id()) {
+                println!(
+                    "Function {}: max stack depth {}",
+                    fun.id(),
+                    fun_infos.max_depth
+                );
+            }
+        }
+        for err in &errs {
+            match err {
+                irvalidation::ValidationError::Stack(err) => eprintln!(
+                    "error: {}: {}",
+                    ins_location(&code, &names, err.fun_id, err.bb_id, err.ins_id),
+                    err.kind
+                ),
+                err => eprintln!("error: {}", err),
+            }
+        }
+        if !errs.is_empty() {
+            std::process::exit(1);
+        }
+    }
+
+    if let Some(cfg_fname) = matches.value_of("dump-cfg") {
+        let out_path = out_path.unwrap_or("cfg.dot");
+        let fun_id = find_function(&code, &names, cfg_fname).expect("dump-cfg: function not found");
+        dump_cfg(code.get_fun(fun_id), &names, out_path);
+    }
+
+    if matches.occurrences_of("dead-stores") > 0 {
+        for st in liveness::find_module_dead_stores(&code) {
+            let fun_names = names.get_function(st.fun_id);
+            println!(
+                "{}: dead store to local {}",
+                ins_location(&code, &names, st.fun_id, st.bb_id, st.ins_id),
+                fun_names
+                    .and_then(|n| n.get_local_name(st.local))
+                    .unwrap_or(&st.local.to_string()),
+            );
+        }
+    }
+
     if matches.occurrences_of("run") > 0 {
-        let mut rt = runtime::Runtime::new(code);
+        let mut natives = natives::NativeRegistry::default();
+        if let Some(root) = matches.value_of("files-root") {
+            natives.register_host_files(root);
+        }
+        let mut rt = runtime::Runtime::new_with_natives(code, natives);
 
-        if let Some(stdin_path) = matches.value_of("stdin") {
-            set_stdin(&mut rt, stdin_path);
+        if let Some(path) = matches.value_of("stdin") {
+            set_stdin(&mut rt, path);
+        }
+        rt.set_stdout(Box::new(std::io::stdout()));
+        if let Some(trace_path) = matches.value_of("trace") {
+            rt.set_tracer(Box::new(tracer::TraceWriter::create(trace_path)));
         }
 
         let ret_code = rt.run();
-        std::io::stdout().write_all(rt.stdout()).unwrap();
         std::process::exit(ret_code.get_val());
     }
 }
@@ -0,0 +1,347 @@
+extern crate clap;
+
+use clap::{App, Arg};
+
+// Trace files are made of one event per line, the first character is the kind of event
+// (see interp_irint3a::tracer and interp_irintsm::tracer)
+// Both traces are filtered to only keep the events of the requested level, and are aligned:
+// after a difference, the comparison resumes at the next events that are the same in both traces,
+// so an extra or missing event is reported alone, not with all the events that follow it
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Level {
+    All,
+    Mem,
+    Call,
+}
+
+impl Level {
+    fn from_str(s: &str) -> Self {
+        match s {
+            "all" => Level::All,
+            "mem" => Level::Mem,
+            "call" => Level::Call,
+            _ => panic!("Invalid trace level {}: expected all, mem or call", s),
+        }
+    }
+
+    fn keep_event(&self, kind: char) -> bool {
+        match self {
+            Level::All => true,
+            Level::Mem => "MCNTX".contains(kind),
+            Level::Call => "CNTX".contains(kind),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Event {
+    line: usize, // line number in the trace file (starting at 1)
+    text: String,
+}
+
+impl Event {
+    fn kind(&self) -> char {
+        self.text.chars().next().unwrap()
+    }
+}
+
+fn parse_trace(data: &str, level: Level) -> Vec<Event> {
+    data.lines()
+        .enumerate()
+        .map(|(idx, text)| Event {
+            line: idx + 1,
+            text: text.trim().to_string(),
+        })
+        .filter(|ev| !ev.text.is_empty() && !ev.text.starts_with('#'))
+        .filter(|ev| level.keep_event(ev.kind()))
+        .collect()
+}
+
+fn read_trace(path: &str, level: Level) -> Vec<Event> {
+    let data = std::fs::read_to_string(path).expect("Failed to read trace file");
+    parse_trace(&data, level)
+}
+
+// Number of equal events needed after a difference to consider the traces synchronized again
+const SYNC_EVENTS: usize = 3;
+
+// Maximum number of skipped events (in both traces) searched to synchronize the traces again
+const MAX_RESYNC: usize = 2000;
+
+/// Part of the traces that differ: events t1[pos1..pos1+len1] are replaced by t2[pos2..pos2+len2]
+/// (len1 = 0 for missing events in trace 1, len2 = 0 for extra events in trace 1)
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Hunk {
+    pos1: usize,
+    len1: usize,
+    pos2: usize,
+    len2: usize,
+}
+
+// Returns true if t1[i1..] and t2[i2..] start with SYNC_EVENTS equal events (or end together)
+fn is_sync(t1: &[Event], i1: usize, t2: &[Event], i2: usize) -> bool {
+    for k in 0..SYNC_EVENTS {
+        match (t1.get(i1 + k), t2.get(i2 + k)) {
+            (None, None) => return true,
+            (Some(ev1), Some(ev2)) if ev1.text == ev2.text => {}
+            _ => return false,
+        }
+    }
+    true
+}
+
+// Find the smallest number of events to skip in both traces to synchronize them again
+fn resync(t1: &[Event], i1: usize, t2: &[Event], i2: usize) -> (usize, usize) {
+    let rem1 = t1.len() - i1;
+    let rem2 = t2.len() - i2;
+    for dist in 1..=std::cmp::min(MAX_RESYNC, rem1 + rem2) {
+        for skip1 in 0..=dist {
+            let skip2 = dist - skip1;
+            if skip1 <= rem1 && skip2 <= rem2 && is_sync(t1, i1 + skip1, t2, i2 + skip2) {
+                return (skip1, skip2);
+            }
+        }
+    }
+    // the traces don't synchronize again, all the remaining events are different
+    (rem1, rem2)
+}
+
+/// Align both traces, and returns all the parts that are different (empty if the traces are the same)
+fn align(t1: &[Event], t2: &[Event]) -> Vec<Hunk> {
+    let mut res = vec![];
+    let (mut i1, mut i2) = (0, 0);
+    loop {
+        while i1 < t1.len() && i2 < t2.len() && t1[i1].text == t2[i2].text {
+            i1 += 1;
+            i2 += 1;
+        }
+        if i1 == t1.len() && i2 == t2.len() {
+            return res;
+        }
+
+        let (len1, len2) = resync(t1, i1, t2, i2);
+        res.push(Hunk {
+            pos1: i1,
+            len1,
+            pos2: i2,
+            len2,
+        });
+        i1 += len1;
+        i2 += len2;
+    }
+}
+
+// Rebuild the call stack (function ids) from the calls / returns events
+// The program always starts in function 0
+fn call_stack(events: &[Event]) -> Vec<String> {
+    let mut res = vec!["0".to_string()];
+    for ev in events {
+        match ev.kind() {
+            'C' => res.push(ev.text.split_whitespace().nth(1).unwrap().to_string()),
+            'T' => {
+                res.pop();
+            }
+            _ => {}
+        }
+    }
+    res
+}
+
+fn describe_event(path: &str, ev: Option<&Event>) -> String {
+    match ev {
+        Some(ev) => format!("{}:{}: {}", path, ev.line, ev.text),
+        None => format!("{}: <end of trace>", path),
+    }
+}
+
+fn main() {
+    let matches = App::new("trace-diff")
+        .version("0.1.0")
+        .author("Steven Lariau <obs145628@gmail.com>")
+        .about("Compare two execution traces of the interpreters")
+        .arg(
+            Arg::with_name("TRACE1")
+                .help("Set the first trace file")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("TRACE2")
+                .help("Set the second trace file")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("level")
+                .long("level")
+                .value_name("LEVEL")
+                .help("Set the compared events: all, mem or call (default: all)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("max-diffs")
+                .long("max-diffs")
+                .value_name("N")
+                .help("Set the maximum number of differences displayed (default: 10)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("context")
+                .long("context")
+                .value_name("N")
+                .help("Set the number of common events displayed before the divergence (default: 5)")
+                .takes_value(true),
+        )
+        .get_matches();
+
+    let path1 = matches.value_of("TRACE1").unwrap();
+    let path2 = matches.value_of("TRACE2").unwrap();
+    let level = Level::from_str(matches.value_of("level").unwrap_or("all"));
+    let context: usize = matches
+        .value_of("context")
+        .unwrap_or("5")
+        .parse()
+        .expect("Invalid context value");
+    let max_diffs: usize = matches
+        .value_of("max-diffs")
+        .unwrap_or("10")
+        .parse()
+        .expect("Invalid max-diffs value");
+
+    let t1 = read_trace(path1, level);
+    let t2 = read_trace(path2, level);
+
+    let hunks = align(&t1, &t2);
+    let first = match hunks.first() {
+        Some(hunk) => *hunk,
+        None => {
+            println!("Traces are the same ({} events)", t1.len());
+            return;
+        }
+    };
+
+    let idx = first.pos1;
+    println!("Traces diverge at event #{}", idx);
+    println!("  {}", describe_event(path1, t1.get(idx)));
+    println!("  {}", describe_event(path2, t2.get(first.pos2)));
+    println!("Call stack: {}", call_stack(&t1[..idx]).join(" -> "));
+
+    let ctx_begin = idx.saturating_sub(context);
+    if ctx_begin < idx {
+        println!("Last common events:");
+        for ev in &t1[ctx_begin..idx] {
+            println!("  {}", ev.text);
+        }
+    }
+
+    println!("{} difference(s):", hunks.len());
+    for hunk in hunks.iter().take(max_diffs) {
+        println!("@@ #{} / #{}", hunk.pos1, hunk.pos2);
+        for ev in &t1[hunk.pos1..hunk.pos1 + hunk.len1] {
+            println!("- {}", describe_event(path1, Some(ev)));
+        }
+        for ev in &t2[hunk.pos2..hunk.pos2 + hunk.len2] {
+            println!("+ {}", describe_event(path2, Some(ev)));
+        }
+    }
+    if hunks.len() > max_diffs {
+        println!("... {} more difference(s)", hunks.len() - max_diffs);
+    }
+
+    std::process::exit(1);
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    const TRACE_3A: &str = "# trace irint3a
+I 0:0:0 movi 2
+R 1 2
+I 0:0:1 call 2
+C 1 2
+I 1:0:0 ret 2
+T 2
+R 0 2
+I 0:0:2 call 2
+N 261 2 7 -> 0
+M 2 7
+X 0
+";
+
+    const TRACE_SM: &str = "# trace irintsm
+I 0:0:0 const 2
+P 2
+I 0:0:1 call 2
+C 1 2
+I 1:1:0 ret 2
+T 2
+P 2
+I 0:0:2 call 2
+N 261 2 8 -> 0
+M 2 8
+X 0
+";
+
+    #[test]
+    fn diverge_native_call() {
+        let t1 = parse_trace(TRACE_3A, Level::Call);
+        let t2 = parse_trace(TRACE_SM, Level::Call);
+        assert_eq!(t1.len(), 4);
+        let hunk = Hunk {
+            pos1: 2,
+            len1: 1,
+            pos2: 2,
+            len2: 1,
+        };
+        assert_eq!(align(&t1, &t2), vec![hunk]);
+        assert_eq!(t1[2].line, 10);
+        assert_eq!(call_stack(&t1[..2]), vec!["0".to_string()]);
+    }
+
+    #[test]
+    fn diverge_at_end() {
+        let t1 = parse_trace(TRACE_3A, Level::Mem);
+        let t2 = parse_trace(&TRACE_3A.replace("X 0\n", ""), Level::Mem);
+        assert_eq!(align(&t1, &t1), vec![]);
+        let hunk = Hunk {
+            pos1: 4,
+            len1: 1,
+            pos2: 4,
+            len2: 0,
+        };
+        assert_eq!(align(&t1, &t2), vec![hunk]);
+    }
+
+    #[test]
+    fn align_extra_events() {
+        // an extra memory write in trace 2, and a different return value later
+        let calls: String = (1..=6)
+            .map(|i| format!("C {} {}\nT {}\n", i, i, i))
+            .collect();
+        let t1 = parse_trace(&format!("{}X 0\n", calls), Level::All);
+        let calls2 = calls
+            .replace("T 2\n", "T 2\nM 4 4\n")
+            .replace("T 5", "T 50");
+        let t2 = parse_trace(&format!("{}X 0\n", calls2), Level::All);
+        let hunks = align(&t1, &t2);
+        assert_eq!(
+            hunks,
+            vec![
+                Hunk {
+                    pos1: 4,
+                    len1: 0,
+                    pos2: 4,
+                    len2: 1,
+                },
+                Hunk {
+                    pos1: 9,
+                    len1: 1,
+                    pos2: 10,
+                    len2: 1,
+                },
+            ]
+        );
+        assert_eq!(call_stack(&t1[..hunks[1].pos1]), vec!["0", "5"]);
+    }
+}
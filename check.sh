@@ -24,8 +24,10 @@ check_proj() {
 
 check_proj libs/asmparser
 check_proj libs/clangutils
+check_proj libs/interp_common
 check_proj libs/interp_irint3a
 check_proj libs/interp_irintsm
+check_proj libs/irconv
 check_proj libs/irint3a
 check_proj libs/irintsm
 check_proj libs/lanexpr
@@ -39,3 +41,4 @@ check_proj apps/irint3a-utils/
 check_proj apps/irintsm-utils/
 check_proj apps/lexer-list/
 check_proj apps/mini-calc-eval/
+check_proj apps/trace-diff/
@@ -1,4 +1,4 @@
-use oblexer::stream::Stream;
+use oblexer::stream::{Position, Stream};
 
 // Internal Lexer, start splitting input to make parser implem easier
 // Detect:
@@ -19,20 +19,24 @@ pub enum Token {
 pub struct Lexer {
     is: Stream,
     next: Option<Token>,
+    next_pos: Position,
 }
 
 impl Lexer {
     pub fn from_file(path: &str) -> Self {
-        Lexer {
-            is: Stream::from_file(path),
-            next: None,
-        }
+        Lexer::new(Stream::from_file(path))
     }
 
     pub fn from_str(path: &str) -> Self {
+        Lexer::new(Stream::from_str(path))
+    }
+
+    fn new(is: Stream) -> Self {
+        let next_pos = is.position();
         Lexer {
-            is: Stream::from_str(path),
+            is,
             next: None,
+            next_pos,
         }
     }
 
@@ -41,6 +45,12 @@ impl Lexer {
         self.next.as_ref().unwrap()
     }
 
+    /// Position of the first character of the next token
+    pub fn peek_pos(&mut self) -> Position {
+        self.load_next();
+        self.next_pos
+    }
+
     pub fn next(&mut self) -> Token {
         self.load_next();
         let mut res = None;
@@ -68,6 +78,7 @@ impl Lexer {
             }
             self.is.next_char();
         }
+        self.next_pos = self.is.position();
 
         // 2) Read comment
         if self.is.get_char().unwrap() == ';' {
@@ -1,5 +1,7 @@
 use crate::lexer::{Lexer, Token};
 
+pub use oblexer::stream::Position;
+
 /// Parsed argument of an instruction
 #[derive(Debug)]
 pub enum InsArg {
@@ -23,6 +25,8 @@ pub struct Ins {
     pub name: String,
     pub args: Vec<InsArg>,
     pub comments: Vec<String>,
+    pub pos: Position, // position of the instruction name
+    pub label_pos: Option<Position>,
 }
 
 /// Parsed definition
@@ -30,6 +34,14 @@ pub struct Ins {
 pub struct Def {
     pub line: String,
     pub comments: Vec<String>,
+    pub pos: Position, // position of the '.'
+}
+
+/// Syntax error, the parser continues with the next token
+#[derive(Debug)]
+pub struct Error {
+    pub mess: &'static str,
+    pub pos: Position,
 }
 
 /// The object produced by the paser
@@ -37,6 +49,7 @@ pub struct Def {
 pub enum Item {
     Ins(Ins),
     Def(Def),
+    Error(Error),
 }
 
 /// Parser for IR/ASM code
@@ -77,11 +90,12 @@ impl Parser {
 
     fn read_next(&mut self) -> Option<Item> {
         // 1) skip first comments and stop at eof
-        let main = loop {
+        let (main, pos) = loop {
+            let pos = self.lex.peek_pos();
             let tok = self.lex.next();
             match tok {
                 Token::Comment(_) => {}
-                _ => break tok,
+                _ => break (tok, pos),
             }
         };
 
@@ -90,57 +104,78 @@ impl Parser {
             Token::EOF => None,
 
             // 2) parse def
-            Token::Def(def) => self.next_def(def),
+            Token::Def(def) => self.next_def(def, pos),
 
             // 3) parse instruction
-            Token::Ins(ins) => self.next_ins(ins, None, vec![]),
+            Token::Ins(ins) => self.next_ins(ins, None, vec![], pos),
 
             // 4) parse label
-            Token::Label(label) => self.next_label(label),
+            Token::Label(label) => self.next_label(label, pos),
         }
     }
 
-    fn next_label(&mut self, label: String) -> Option<Item> {
+    fn next_label(&mut self, label: String, label_pos: Position) -> Option<Item> {
         let mut comments = vec![];
-        let main = loop {
+        let (main, pos) = loop {
+            let pos = self.lex.peek_pos();
             let tok = self.lex.next();
-            match tok {
-                Token::Ins(ins) => break ins,
-                Token::Comment(com) => comments.push(com),
-                Token::Label(_) => panic!("An instruction can only have one label"),
-                Token::Def(_) => panic!("A definition cannot have a label"),
-                Token::EOF => panic!("End Of File after label"),
-            }
+            let mess = match tok {
+                Token::Ins(ins) => break (ins, pos),
+                Token::Comment(com) => {
+                    comments.push(com);
+                    continue;
+                }
+                Token::Label(_) => "An instruction can only have one label",
+                Token::Def(_) => "A definition cannot have a label",
+                Token::EOF => "End Of File after label",
+            };
+            return Some(Item::Error(Error {
+                mess,
+                pos: label_pos,
+            }));
         };
-        self.next_ins(main, Some(label), comments)
+        self.next_ins(main, Some((label, label_pos)), comments, pos)
     }
 
-    fn next_def(&mut self, def: String) -> Option<Item> {
+    fn next_def(&mut self, def: String, pos: Position) -> Option<Item> {
         let comments = self.parse_comments();
 
         Some(Item::Def(Def {
             line: def,
             comments,
+            pos,
         }))
     }
 
     fn next_ins(
         &mut self,
         ins: String,
-        label: Option<String>,
+        label: Option<(String, Position)>,
         comments: Vec<String>,
+        pos: Position,
     ) -> Option<Item> {
         let mut comments = comments;
         comments.append(&mut self.parse_comments());
 
-        let label = self.parse_label(label);
-        let (name, args) = self.parse_ins(ins);
+        let label_pos = label.as_ref().map(|(_, pos)| *pos);
+        let label = self.parse_label(label.map(|(name, _)| name));
+        let (name, args) = match self.parse_ins(ins) {
+            Some(res) => res,
+            None => {
+                return Some(Item::Error(Error {
+                    mess: "Empty instruction argument",
+                    pos,
+                }))
+            }
+        };
 
         Some(Item::Ins(Ins {
             label,
             name,
             args,
             comments,
+            pos,
+            label_pos,
         }))
     }
 
@@ -152,37 +187,39 @@ impl Parser {
         })
     }
 
-    fn parse_ins_arg(&self, arg: &str) -> InsArg {
-        let firstc = arg.chars().next().expect("Empty instruction argument");
+    // Returns None if the argument is empty
+    fn parse_ins_arg(&self, arg: &str) -> Option<InsArg> {
+        let firstc = arg.chars().next()?;
         let is_id = firstc == '%';
 
         if !is_id {
-            return match arg.parse::<u64>() {
+            return Some(match arg.parse::<u64>() {
                 Ok(val) => InsArg::Const(val),
                 Err(_) => InsArg::Name(arg.to_string()),
-            };
+            });
         }
 
         let id_val = &arg[1..];
-        match id_val.parse::<u64>() {
+        Some(match id_val.parse::<u64>() {
             Ok(val) => InsArg::IdInt(val),
             Err(_) => InsArg::IdName(id_val.to_string()),
-        }
+        })
     }
 
-    fn parse_ins(&self, line: String) -> (String, Vec<InsArg>) {
+    // Returns None if one of the arguments is empty
+    fn parse_ins(&self, line: String) -> Option<(String, Vec<InsArg>)> {
         let sline = line.as_str();
         let name_end = line.find(' ');
         if name_end.is_none() {
-            return (line, vec![]);
+            return Some((line, vec![]));
         }
         let name = &sline[0..name_end.unwrap()];
-        let args: Vec<_> = sline[name_end.unwrap()..]
+        let args = sline[name_end.unwrap()..]
             .split(',')
             .map(|x| self.parse_ins_arg(x.trim()))
-            .collect();
+            .collect::<Option<Vec<_>>>()?;
 
-        (name.to_string(), args)
+        Some((name.to_string(), args))
     }
 
     fn parse_comments(&mut self) -> Vec<String> {
@@ -232,4 +269,38 @@ mod tests {
     fn hello_42() {
         test_file("../irintsm/tests/hello_42.ir");
     }
+
+    #[test]
+    fn positions_and_errors() {
+        let mut ps =
+            Parser::from_str(".define 0 main\nL0:\n  add %r0, , %r1\n  L1: ret %r0 ; end\nL2:");
+        let pos = |line, col| Position { line, col };
+        match ps.next() {
+            Some(Item::Def(def)) => assert_eq!(def.pos, pos(1, 1)),
+            _ => unreachable!(),
+        }
+        match ps.next() {
+            Some(Item::Error(err)) => {
+                assert_eq!(err.mess, "Empty instruction argument");
+                assert_eq!(err.pos, pos(3, 3));
+            }
+            _ => unreachable!(),
+        }
+        match ps.next() {
+            Some(Item::Ins(ins)) => {
+                assert_eq!(ins.name, "ret");
+                assert_eq!(ins.pos, pos(4, 7));
+                assert_eq!(ins.label_pos, Some(pos(4, 3)));
+            }
+            _ => unreachable!(),
+        }
+        match ps.next() {
+            Some(Item::Error(err)) => {
+                assert_eq!(err.mess, "End Of File after label");
+                assert_eq!(err.pos, pos(5, 1));
+            }
+            _ => unreachable!(),
+        }
+        assert!(ps.next().is_none());
+    }
 }
@@ -9,6 +9,7 @@ pub struct ClangCommandBuilder {
     in_files: Option<Vec<String>>,
     out_file: Option<String>,
     out_type: Option<ClangOutputType>,
+    opt_level: Option<u8>,
     clang_bin: &'static str,
 }
 
@@ -18,6 +19,7 @@ impl ClangCommandBuilder {
             in_files: None,
             out_file: None,
             out_type: None,
+            opt_level: None,
             clang_bin: "clang",
         }
     }
@@ -49,6 +51,12 @@ impl ClangCommandBuilder {
         self
     }
 
+    /// Optimization level (-O<level>), no option by default
+    pub fn set_opt_level(mut self, level: u8) -> Self {
+        self.opt_level = Some(level);
+        self
+    }
+
     pub fn run(self) {
         let in_files = self.in_files.expect("Missing input file");
         let out_file = self.out_file.expect("Imissing output file");
@@ -67,6 +75,11 @@ impl ClangCommandBuilder {
             ClangOutputType::BINARY => {}
         }
 
+        let opt_arg = self.opt_level.map(|level| format!("-O{}", level));
+        if let Some(opt_arg) = &opt_arg {
+            args.push(opt_arg);
+        }
+
         Command::new(self.clang_bin)
             .args(&args)
             .output()
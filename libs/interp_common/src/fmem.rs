@@ -0,0 +1,30 @@
+// Utils for the flat memory (fmem) of the interpreters
+// fmem is an array of FLAT_MEMORY_SIZE words, accessed by the natives fmemget / fmemset / fmemcpy
+
+/// Number of words of the flat memory
+pub const FLAT_MEMORY_SIZE: i32 = 16 * 1024 * 1024;
+
+/// Maximum length of the strings read in fmem by the natives (file paths)
+pub const MAX_STR_LEN: i32 = 4096;
+
+/// Read a string stored in fmem at `addr`, one char per word, ending with 0
+/// `load` returns the word at an address of fmem
+/// Fails if the string is outside of fmem, or longer than MAX_STR_LEN
+pub fn load_str<F>(load: F, addr: i32) -> Result<String, &'static str>
+where
+    F: Fn(i32) -> i32,
+{
+    if !(0..FLAT_MEMORY_SIZE).contains(&addr) {
+        return Err("string address outside of flat memory");
+    }
+    let end = std::cmp::min(FLAT_MEMORY_SIZE, addr.saturating_add(MAX_STR_LEN));
+    let mut res = String::new();
+    for addr in addr..end {
+        let c = load(addr);
+        if c == 0 {
+            return Ok(res);
+        }
+        res.push(c as u8 as char);
+    }
+    Err("string without 0 at the end")
+}
@@ -0,0 +1,121 @@
+// Host files opened by the natives fopen / fclose / fgetc / fputc
+//
+// All paths are relative to a root directory, the program can't access any file outside of it:
+// the path must not be empty, absolute, or contain .., and it must stay inside the root directory
+// once the symbolic links are resolved (a dangling link is rejected, it would be followed when creating the file)
+// File descriptors are indices in a table, closed files are set to None
+
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Component, Path, PathBuf};
+
+/// Table of the host files opened by the program
+pub struct HostFiles {
+    root: PathBuf,
+    files: Vec<Option<File>>,
+}
+
+impl HostFiles {
+    /// Create an empty table, all paths are relative to `root`
+    pub fn new(root: &str) -> Self {
+        HostFiles {
+            root: PathBuf::from(root),
+            files: vec![],
+        }
+    }
+
+    /// Path in the root directory, or None if it's trying to go outside
+    /// Symbolic links are resolved, so a link inside the root can't be used to go outside
+    pub fn sandbox_path(&self, path: &str) -> Option<PathBuf> {
+        let path = Path::new(path);
+        if path.as_os_str().is_empty() {
+            return None;
+        }
+        if !path
+            .components()
+            .all(|x| matches!(x, Component::Normal(_) | Component::CurDir))
+        {
+            return None;
+        }
+
+        let root = self.root.canonicalize().ok()?;
+        let path = root.join(path);
+        let real_path = match path.canonicalize() {
+            Ok(real_path) => real_path,
+            // a dangling link would be followed when the file is created
+            Err(_) if path.symlink_metadata().is_ok() => return None,
+            // new file: only its parent directory exists
+            Err(_) => path.parent()?.canonicalize().ok()?.join(path.file_name()?),
+        };
+        if real_path.starts_with(&root) {
+            Some(real_path)
+        } else {
+            None
+        }
+    }
+
+    /// Open a file, `mode` is 0 for read, 1 for write (truncate), 2 for append
+    /// Returns the file descriptor, or -1 if it fails
+    pub fn open(&mut self, path: &str, mode: i32) -> i32 {
+        let path = match self.sandbox_path(path) {
+            Some(path) => path,
+            None => return -1,
+        };
+
+        let file = match mode {
+            0 => File::open(path),
+            1 => File::create(path),
+            2 => OpenOptions::new().append(true).create(true).open(path),
+            _ => return -1,
+        };
+
+        match file {
+            Ok(file) => {
+                self.files.push(Some(file));
+                self.files.len() as i32 - 1
+            }
+            Err(_) => -1,
+        }
+    }
+
+    fn get_file(&mut self, fd: i32) -> Option<&mut File> {
+        if fd < 0 {
+            return None;
+        }
+        self.files.get_mut(fd as usize)?.as_mut()
+    }
+
+    /// Close a file, returns 0, or -1 if fd is invalid
+    pub fn close(&mut self, fd: i32) -> i32 {
+        if self.get_file(fd).is_none() {
+            return -1;
+        }
+        self.files[fd as usize] = None;
+        0
+    }
+
+    /// Returns the next byte of the file, or -1 if eof or fd is invalid
+    pub fn getc(&mut self, fd: i32) -> i32 {
+        let file = match self.get_file(fd) {
+            Some(file) => file,
+            None => return -1,
+        };
+        let mut buf = [0u8; 1];
+        match file.read(&mut buf) {
+            Ok(1) => buf[0] as i32,
+            _ => -1,
+        }
+    }
+
+    /// Write a byte to the file, returns 0, or -1 if it fails
+    pub fn putc(&mut self, fd: i32, c: u8) -> i32 {
+        let file = match self.get_file(fd) {
+            Some(file) => file,
+            None => return -1,
+        };
+        match file.write_all(&[c]) {
+            Ok(()) => 0,
+            Err(_) => -1,
+        }
+    }
+}
@@ -0,0 +1,69 @@
+pub mod fmem;
+pub mod hostfiles;
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn sandbox_path() {
+        let root = std::env::temp_dir().join("interp_common_sandbox");
+        std::fs::create_dir_all(root.join("d")).unwrap();
+        let files = hostfiles::HostFiles::new(root.to_str().unwrap());
+        let root = root.canonicalize().unwrap();
+
+        assert_eq!(files.sandbox_path("d/f.txt"), Some(root.join("d/f.txt")));
+        assert_eq!(files.sandbox_path("./f.txt"), Some(root.join("f.txt")));
+        assert_eq!(files.sandbox_path(""), None);
+        assert_eq!(files.sandbox_path("../f.txt"), None);
+        assert_eq!(files.sandbox_path("d/../f.txt"), None);
+        assert_eq!(files.sandbox_path("/etc/passwd"), None);
+        // the parent directory must exist
+        assert_eq!(files.sandbox_path("e/f.txt"), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn sandbox_path_symlink() {
+        let root = std::env::temp_dir().join("interp_common_sandbox_symlink");
+        let outside = std::env::temp_dir().join("interp_common_sandbox_outside");
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::create_dir_all(&outside).unwrap();
+        std::fs::write(outside.join("f.txt"), b"x").unwrap();
+        let links = [
+            ("file", outside.join("f.txt")),
+            ("dir", outside.clone()),
+            ("dangling", outside.join("none.txt")),
+            ("inside", root.join("f.txt")),
+        ];
+        std::fs::write(root.join("f.txt"), b"y").unwrap();
+        for (name, target) in &links {
+            let _ = std::fs::remove_file(root.join(name));
+            std::os::unix::fs::symlink(target, root.join(name)).unwrap();
+        }
+
+        let files = hostfiles::HostFiles::new(root.to_str().unwrap());
+        assert_eq!(files.sandbox_path("file"), None);
+        assert_eq!(files.sandbox_path("dir/f.txt"), None);
+        assert_eq!(files.sandbox_path("dir/new.txt"), None);
+        assert_eq!(files.sandbox_path("dangling"), None);
+        let root = root.canonicalize().unwrap();
+        assert_eq!(files.sandbox_path("inside"), Some(root.join("f.txt")));
+    }
+
+    #[test]
+    fn load_str() {
+        let mem = |addr: i32| match addr {
+            10 => 'a' as i32,
+            11 => 'b' as i32,
+            12 => 0,
+            _ => 'z' as i32,
+        };
+        assert_eq!(fmem::load_str(mem, 10), Ok("ab".to_string()));
+        assert_eq!(fmem::load_str(mem, 12), Ok("".to_string()));
+        assert!(fmem::load_str(mem, 13).is_err());
+        assert!(fmem::load_str(mem, -1).is_err());
+        assert!(fmem::load_str(mem, fmem::FLAT_MEMORY_SIZE).is_err());
+    }
+}
@@ -0,0 +1,212 @@
+// Debugger with breakpoints and reverse execution
+//
+// The debugger takes a snapshot of the runtime every `interval` executed instructions (checkpoints).
+// The execution is deterministic, so going back to any previous step is done by
+// restoring the last checkpoint before this step, and running the program again until this step.
+//
+// A breakpoint is an instruction address (function, basic block, instruction index).
+// A breakpoint is hit when the next instruction to run is at this address.
+//
+// reverse_continue goes back to the last breakpoint hit before the current step:
+// the segments between checkpoints are re-executed from the most recent to the oldest one,
+// until a segment with a breakpoint hit is found.
+//
+// The runtime must use buffered stdin / stdout (see Runtime::snapshot).
+
+use std::collections::BTreeSet;
+
+use irint3a::ir;
+
+use crate::runtime::{ExitCode, Runtime};
+use crate::snapshot::Snapshot;
+
+/// Default number of instructions between 2 checkpoints
+pub const DEFAULT_CHECKPOINT_INTERVAL: u64 = 100_000;
+
+/// Address of an instruction: function, basic block, instruction index
+pub type InsAddress = (ir::FunctionId, ir::BasicBlockId, usize);
+
+/// Why the execution stopped
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StopReason {
+    /// One instruction was executed (or reversed)
+    Step,
+    /// The next instruction is a breakpoint
+    Breakpoint,
+    /// The program called exit, it can't continue
+    Exit(i32),
+    /// Back to the beginning of the program (or of the debugging session), it can't go back further
+    Begin,
+}
+
+pub struct Debugger {
+    rt: Runtime,
+    interval: u64,
+    checkpoints: Vec<Snapshot>, // sorted by steps, the first one is the beginning of the program
+    breakpoints: BTreeSet<InsAddress>,
+    exit_code: Option<ExitCode>,
+}
+
+impl Debugger {
+    /// Start debugging the runtime from its current state
+    pub fn new(rt: Runtime) -> Self {
+        Self::new_with_interval(rt, DEFAULT_CHECKPOINT_INTERVAL)
+    }
+
+    /// Same as new, with a checkpoint every `interval` instructions
+    pub fn new_with_interval(rt: Runtime, interval: u64) -> Self {
+        assert!(interval > 0, "Invalid checkpoint interval 0");
+        let checkpoints = vec![rt.snapshot()];
+        Debugger {
+            rt,
+            interval,
+            checkpoints,
+            breakpoints: BTreeSet::new(),
+            exit_code: None,
+        }
+    }
+
+    pub fn runtime(&self) -> &Runtime {
+        &self.rt
+    }
+
+    /// Number of instructions executed since the beginning of the program
+    pub fn steps(&self) -> u64 {
+        self.rt.steps()
+    }
+
+    /// First step of the debugging session, it's not possible to go back before
+    pub fn first_step(&self) -> u64 {
+        self.checkpoints[0].steps()
+    }
+
+    /// Restore a saved state, and start a new debugging session from it
+    /// The breakpoints are kept
+    pub fn load_snapshot(&mut self, snap: &Snapshot) {
+        self.rt.restore(snap);
+        self.checkpoints = vec![snap.clone()];
+        self.exit_code = None;
+    }
+
+    /// Address of the next instruction to run
+    pub fn position(&self) -> InsAddress {
+        *self.rt.call_stack().last().unwrap()
+    }
+
+    /// Returns the exit code if the program exited
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit_code.map(|x| x.get_val())
+    }
+
+    pub fn add_breakpoint(&mut self, addr: InsAddress) {
+        self.breakpoints.insert(addr);
+    }
+
+    /// Returns false if there was no breakpoint at this address
+    pub fn remove_breakpoint(&mut self, addr: InsAddress) -> bool {
+        self.breakpoints.remove(&addr)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = &InsAddress> {
+        self.breakpoints.iter()
+    }
+
+    /// Run one instruction
+    pub fn step(&mut self) -> StopReason {
+        if let Some(code) = self.exit_code {
+            return StopReason::Exit(code.get_val());
+        }
+
+        self.exit_code = self.rt.step();
+        let steps = self.rt.steps();
+        if self.exit_code.is_none()
+            && self.checkpoints.last().unwrap().steps() + self.interval <= steps
+        {
+            self.checkpoints.push(self.rt.snapshot());
+        }
+
+        match self.exit_code {
+            Some(code) => StopReason::Exit(code.get_val()),
+            None => StopReason::Step,
+        }
+    }
+
+    /// Run until the next breakpoint, or the end of the program
+    pub fn cont(&mut self) -> StopReason {
+        loop {
+            match self.step() {
+                StopReason::Step if self.at_breakpoint() => return StopReason::Breakpoint,
+                StopReason::Step => {}
+                reason => return reason,
+            }
+        }
+    }
+
+    /// Go back one instruction
+    pub fn reverse_step(&mut self) -> StopReason {
+        match self.steps() {
+            steps if steps == self.first_step() => StopReason::Begin,
+            steps => {
+                self.goto_step(steps - 1);
+                StopReason::Step
+            }
+        }
+    }
+
+    /// Go back to the last breakpoint hit, or the beginning of the program
+    pub fn reverse_continue(&mut self) -> StopReason {
+        let mut limit = self.steps();
+        for cp_idx in (0..self.checkpoints.len()).rev() {
+            let cp_steps = self.checkpoints[cp_idx].steps();
+            if cp_steps >= limit {
+                continue;
+            }
+
+            // run the segment [cp_steps, limit[ and find the last breakpoint hit
+            self.restore(cp_idx);
+            let mut last_hit = None;
+            while self.steps() < limit {
+                if self.at_breakpoint() {
+                    last_hit = Some(self.steps());
+                }
+                self.step();
+            }
+
+            if let Some(hit) = last_hit {
+                self.goto_step(hit);
+                return StopReason::Breakpoint;
+            }
+            limit = cp_steps;
+        }
+
+        self.restore(0);
+        StopReason::Begin
+    }
+
+    /// Go to the state after `steps` executed instructions (forward or backward)
+    /// Stops earlier if the program exits, or at the first step if `steps` is before
+    pub fn goto_step(&mut self, steps: u64) {
+        if steps < self.steps() || self.exit_code.is_some() {
+            let cp_idx = self
+                .checkpoints
+                .iter()
+                .rposition(|cp| cp.steps() <= steps)
+                .unwrap_or(0);
+            self.restore(cp_idx);
+        }
+        while self.steps() < steps {
+            if let StopReason::Exit(_) = self.step() {
+                return;
+            }
+        }
+    }
+
+    fn at_breakpoint(&self) -> bool {
+        !self.breakpoints.is_empty() && self.breakpoints.contains(&self.position())
+    }
+
+    fn restore(&mut self, cp_idx: usize) {
+        self.rt.restore(&self.checkpoints[cp_idx]);
+        self.exit_code = None;
+    }
+}
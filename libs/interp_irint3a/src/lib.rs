@@ -1,4 +1,10 @@
+pub mod debugger;
+pub mod memcheck;
+pub mod natives;
+pub mod profiler;
 pub mod runtime;
+pub mod snapshot;
+pub mod tracer;
 
 #[cfg(test)]
 mod tests {
@@ -7,7 +13,7 @@ mod tests {
 
     pub fn run_file(path: &str, expected: &str) {
         let ps = irint3a::irparser::Parser::from_file(path);
-        let (module, _name) = ps.build();
+        let (module, _name) = ps.build().unwrap();
 
         let mut rt = runtime::Runtime::new(module);
         rt.run();
@@ -19,4 +25,868 @@ mod tests {
     fn run_hello_42() {
         run_file("../irint3a/tests/hello_42.ir", "42\n");
     }
+
+    #[test]
+    fn run_globals() {
+        run_file("../irint3a/tests/globals.ir", "Hi!2\n");
+    }
+
+    #[test]
+    fn run_stack_array() {
+        run_file("../irint3a/tests/stack_array.ir", "285\n");
+    }
+
+    #[test]
+    fn run_bitops() {
+        run_file("../irint3a/tests/bitops.ir", "MIOT?A\n1100111\n");
+    }
+
+    #[test]
+    fn run_widths() {
+        run_file("../irint3a/tests/widths.ir", "JA@1M01OK!F\n");
+    }
+
+    #[test]
+    fn run_funptr() {
+        run_file("../irint3a/tests/funptr.ir", "A7\n");
+    }
+
+    #[test]
+    fn run_linked() {
+        let mut linker = irint3a::irlinker::Linker::new();
+        for path in &["../irint3a/tests/link_main.ir", "../irint3a/runtime/runtime.ir"] {
+            let (module, names) = irint3a::irparser::Parser::from_file(path).build().unwrap();
+            linker.add_module(module, names);
+        }
+        let natives = natives::NativeRegistry::default();
+        linker.set_natives(&natives.ids());
+        let (module, _names) = linker.link().ok().unwrap();
+
+        let mut rt = runtime::Runtime::new_with_natives(module, natives);
+        assert_eq!(rt.run().get_val(), 0);
+        assert_eq!(rt.stdout(), b"-42\n42\n");
+    }
+
+    #[test]
+    fn run_switch() {
+        let expected = "...ABCC.EFG....\nabzcedz\nwz?\n";
+        run_file("../irint3a/tests/switch.ir", expected);
+
+        // same result once lowered to jumpt, and then to br
+        let ps = irint3a::irparser::Parser::from_file("../irint3a/tests/switch.ir");
+        let (mut module, _names) = ps.build().unwrap();
+        irint3a::switchlowering::lower_switches_module(&mut module);
+        let mut rt = runtime::Runtime::new(module.clone());
+        assert_eq!(rt.run().get_val(), 0);
+        assert_eq!(rt.stdout(), expected.as_bytes());
+        irint3a::switchlowering::lower_jump_tables_module(&mut module);
+        let mut rt = runtime::Runtime::new(module);
+        assert_eq!(rt.run().get_val(), 0);
+        assert_eq!(rt.stdout(), expected.as_bytes());
+    }
+
+    #[test]
+    fn profile_switch() {
+        let ps = irint3a::irparser::Parser::from_file("../irint3a/tests/switch.ir");
+        let (module, names) = ps.build().unwrap();
+        let mut rt = runtime::Runtime::new(module);
+        rt.enable_profiler();
+        rt.run();
+
+        // the report lists all the edges of the switch, with the number of times they are taken
+        let mut report = vec![];
+        rt.profiler()
+            .unwrap()
+            .write_report(&mut report, rt.code(), Some(&names));
+        let report = String::from_utf8(report).unwrap();
+        let dense = report.split("Function _dense:").nth(1).unwrap();
+        let edges: Vec<_> = dense
+            .lines()
+            .take_while(|line| !line.starts_with("Function"))
+            .filter(|line| line.trim_start().starts_with("->"))
+            .take(7)
+            .map(|line| line.split_whitespace().collect::<Vec<_>>())
+            .collect();
+        let edges: Vec<_> = edges.iter().map(|x| (x[1], x[2])).collect();
+        assert_eq!(
+            edges,
+            vec![
+                ("La", "1"),
+                ("Lb", "1"),
+                ("Lc", "2"),
+                ("Le", "1"),
+                ("Lf", "1"),
+                ("Lg", "1"),
+                ("Ldefault", "8")
+            ]
+        );
+    }
+
+    #[test]
+    fn profile_hello_42() {
+        let ps = irint3a::irparser::Parser::from_file("../irint3a/tests/hello_42.ir");
+        let (module, names) = ps.build().unwrap();
+        let fun_id = |name| names.get_function_id(name).unwrap();
+
+        let mut rt = runtime::Runtime::new(module);
+        rt.enable_profiler();
+        rt.run();
+        let prof = rt.profiler().unwrap();
+
+        assert_eq!(prof.function_profile(fun_id("_iprint_rec")).calls, 3);
+        assert_eq!(prof.function_profile(fun_id("_iprint")).calls, 1);
+        assert_eq!(prof.function_profile(fun_id("_putchar")).calls, 3);
+        assert_eq!(prof.function_profile(fun_id("_main")).inclusive, prof.total());
+
+        let rec = prof.function_profile(fun_id("_iprint_rec"));
+        let iprint = prof.function_profile(fun_id("_iprint"));
+        assert_eq!(iprint.inclusive, iprint.exclusive + rec.exclusive);
+    }
+
+    #[test]
+    fn trace_hello_42() {
+        let ps = irint3a::irparser::Parser::from_file("../irint3a/tests/hello_42.ir");
+        let (module, _names) = ps.build().unwrap();
+        let path = std::env::temp_dir().join("interp_irint3a_trace_hello_42.trace");
+        let path = path.to_str().unwrap();
+
+        let mut rt = runtime::Runtime::new(module);
+        rt.set_tracer(Box::new(tracer::TraceWriter::create(path)));
+        rt.run();
+
+        let trace = std::fs::read_to_string(path).unwrap();
+        let lines: Vec<_> = trace.lines().collect();
+        assert_eq!(lines[0], "# trace irint3a");
+        assert_eq!(lines[1], "I 0:0:0 movi 42");
+        assert_eq!(lines[2], "R 1 42");
+        assert_eq!(*lines.last().unwrap(), "X 0");
+        let natives: Vec<_> = lines
+            .iter()
+            .filter(|l| l.starts_with("N 257 "))
+            .cloned()
+            .collect();
+        assert_eq!(natives, vec!["N 257 52 -> 0", "N 257 50 -> 0", "N 257 10 -> 0"]);
+        assert_eq!(lines.iter().filter(|l| l.starts_with("C 1 ")).count(), 3);
+    }
+
+    #[test]
+    fn custom_natives() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        let ps = irint3a::irparser::Parser::from_str(
+            "
+.declare 300 print_int
+.declare 258 exit
+
+.define 0 main
+L0:
+  movi %r1, 42
+  call %r0, print_int, %r1
+  movi %r1, 3
+  call %r0, exit, %r1
+  ret %r0
+",
+        );
+        let (module, names) = ps.build().unwrap();
+
+        let printed = Rc::new(RefCell::new(vec![]));
+        let printed_ref = printed.clone();
+        let mut natives = natives::NativeRegistry::default();
+        natives.register_named("print_int", 1, move |_ctx, args| {
+            printed_ref.borrow_mut().push(args[0]);
+            0
+        });
+        natives.bind_module_names(&module, &names);
+        assert_eq!(natives.get_name(irint3a::ir::FunctionId(300)), Some("print_int"));
+
+        let mut rt = runtime::Runtime::new_with_natives(module, natives);
+        assert_eq!(rt.run().get_val(), 3);
+        assert_eq!(*printed.borrow(), vec![42]);
+    }
+
+    #[test]
+    fn host_files() {
+        let root = std::env::temp_dir().join("interp_irint3a_host_files");
+        std::fs::create_dir_all(&root).unwrap();
+
+        // write 'x' to f.txt, read it back and print it
+        // then try to open ../f.txt, exit code is the returned fd + 2
+        let ps = irint3a::irparser::Parser::from_str(
+            "
+.declare 257 putc
+.declare 258 exit
+.declare 261 fmemset
+.declare 263 fopen
+.declare 264 fclose
+.declare 265 fgetc
+.declare 266 fputc
+
+.define 0 main
+L0:
+  movi %r0, 10
+  movi %r1, 102
+  call %r9, fmemset, %r0, %r1
+  movi %r0, 11
+  movi %r1, 46
+  call %r9, fmemset, %r0, %r1
+  movi %r0, 12
+  movi %r1, 116
+  call %r9, fmemset, %r0, %r1
+  movi %r0, 13
+  movi %r1, 120
+  call %r9, fmemset, %r0, %r1
+  movi %r0, 14
+  movi %r1, 116
+  call %r9, fmemset, %r0, %r1
+  movi %r0, 10
+  movi %r1, 1
+  call %r2, fopen, %r0, %r1
+  movi %r3, 120
+  call %r9, fputc, %r2, %r3
+  call %r9, fclose, %r2
+  movi %r1, 0
+  call %r2, fopen, %r0, %r1
+  call %r3, fgetc, %r2
+  call %r9, putc, %r3
+  movi %r0, 20
+  movi %r1, 46
+  call %r9, fmemset, %r0, %r1
+  movi %r0, 21
+  call %r9, fmemset, %r0, %r1
+  movi %r0, 22
+  movi %r1, 47
+  call %r9, fmemset, %r0, %r1
+  movi %r0, 23
+  movi %r1, 102
+  call %r9, fmemset, %r0, %r1
+  movi %r0, 20
+  movi %r1, 0
+  call %r2, fopen, %r0, %r1
+  movi %r1, 2
+  add %r3, %r2, %r1
+  call %r9, exit, %r3
+  ret %r0
+",
+        );
+        let (module, _names) = ps.build().unwrap();
+
+        let mut natives = natives::NativeRegistry::default();
+        natives.register_host_files(root.to_str().unwrap());
+        let mut rt = runtime::Runtime::new_with_natives(module, natives);
+        assert_eq!(rt.run().get_val(), 1);
+        assert_eq!(rt.stdout(), b"x");
+        assert_eq!(std::fs::read(root.join("f.txt")).unwrap(), b"x");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn host_files_symlink() {
+        let root = std::env::temp_dir().join("interp_irint3a_host_files_symlink");
+        let outside = std::env::temp_dir().join("interp_irint3a_host_files_outside");
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::create_dir_all(&outside).unwrap();
+        std::fs::write(outside.join("f.txt"), b"x").unwrap();
+        let link = root.join("l");
+        let _ = std::fs::remove_file(&link);
+        std::os::unix::fs::symlink(outside.join("f.txt"), &link).unwrap();
+
+        // open l for reading, and then for writing, exit code is the sum of the fds + 2
+        let ps = irint3a::irparser::Parser::from_str(
+            "
+.declare 258 exit
+.declare 261 fmemset
+.declare 263 fopen
+
+.define 0 main
+L0:
+  movi %r0, 10
+  movi %r1, 108
+  call %r9, fmemset, %r0, %r1
+  movi %r1, 0
+  call %r2, fopen, %r0, %r1
+  movi %r1, 1
+  call %r3, fopen, %r0, %r1
+  add %r4, %r2, %r3
+  movi %r1, 2
+  add %r4, %r4, %r1
+  call %r9, exit, %r4
+  ret %r0
+",
+        );
+        let (module, _names) = ps.build().unwrap();
+
+        let mut natives = natives::NativeRegistry::default();
+        natives.register_host_files(root.to_str().unwrap());
+        let mut rt = runtime::Runtime::new_with_natives(module, natives);
+        assert_eq!(rt.run().get_val(), 0);
+        assert_eq!(std::fs::read(outside.join("f.txt")).unwrap(), b"x");
+    }
+
+    fn run_memcheck(code: &str) -> Result<i32, memcheck::MemCheckError> {
+        let (module, _names) = irint3a::irparser::Parser::from_str(code).build().unwrap();
+        let mut rt = runtime::Runtime::new(module);
+        rt.enable_memcheck();
+        rt.try_run().map(|code| code.get_val())
+    }
+
+    #[test]
+    fn memcheck_locals() {
+        let res = run_memcheck(
+            "
+.declare 258 exit
+
+.define 0 main
+L0:
+  movi %r0, 5
+  call %r1, fact, %r0
+  call %r9, exit, %r1
+  ret %r9
+
+.define 1 fact
+L0:
+  alloca %r1
+  store %r1, %r0
+  load %r2, %r1
+  movi %r3, 2
+  cmplt %r4, %r2, %r3
+  br %r4, Lres1, Lrec
+Lres1:
+  movi %r3, 1
+  jump Lend
+Lrec:
+  movi %r3, 1
+  sub %r4, %r2, %r3
+  call %r5, fact, %r4
+  mul %r3, %r2, %r5
+  jump Lend
+Lend:
+  ret %r3
+",
+        );
+        assert_eq!(res, Ok(120));
+
+        let err = run_memcheck(
+            "
+.declare 258 exit
+
+.define 0 main
+L0:
+  call %r0, get_addr
+  movi %r1, 0
+  call %r2, get_addr
+  load %r3, %r0
+  ret %r3
+
+.define 1 get_addr
+L0:
+  alloca %r0
+  movi %r1, 3
+  store %r0, %r1
+  ret %r0
+",
+        )
+        .unwrap_err();
+        assert_eq!(err.kind, memcheck::MemCheckErrorKind::UseAfterReturn);
+        assert_eq!((err.fun.0, err.bb.0, err.ins_idx), (0, 0, 3));
+
+        let err = run_memcheck(
+            "
+.declare 258 exit
+
+.define 0 main
+L0:
+  alloca %r0
+  load %r1, %r0
+  call %r9, exit, %r1
+  ret %r9
+",
+        )
+        .unwrap_err();
+        assert_eq!(err.kind, memcheck::MemCheckErrorKind::UninitLocal);
+
+        let err = run_memcheck(
+            "
+.declare 258 exit
+
+.define 0 main
+L0:
+  movi %r0, 0
+  store %r0, %r0
+  call %r9, exit, %r0
+  ret %r9
+",
+        )
+        .unwrap_err();
+        assert_eq!(err.kind, memcheck::MemCheckErrorKind::InvalidAddress);
+
+        let err = run_memcheck(
+            "
+.declare 258 exit
+
+.define 0 main
+L0:
+  movi %r0, 2
+  add %r1, %r0, %r2
+  call %r9, exit, %r1
+  ret %r9
+",
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.kind,
+            memcheck::MemCheckErrorKind::UninitRegister(irint3a::ir::RegId(2))
+        );
+
+        // returning an uninitialized register is allowed, but not reading the result
+        let err = run_memcheck(
+            "
+.declare 258 exit
+
+.define 0 main
+L0:
+  call %r0, nothing
+  call %r1, nothing
+  call %r9, exit, %r1
+  ret %r9
+
+.define 1 nothing
+L0:
+  ret %r0
+",
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.kind,
+            memcheck::MemCheckErrorKind::UninitRegister(irint3a::ir::RegId(1))
+        );
+        assert_eq!((err.fun.0, err.ins_idx), (0, 2));
+    }
+
+    #[test]
+    fn memcheck_globals() {
+        let ps = irint3a::irparser::Parser::from_file("../irint3a/tests/globals.ir");
+        let (module, _names) = ps.build().unwrap();
+        let mut rt = runtime::Runtime::new(module);
+        rt.enable_memcheck();
+        assert_eq!(rt.try_run().map(|code| code.get_val()), Ok(0));
+        assert_eq!(rt.stdout(), b"Hi!2\n");
+
+        // globals can't be reached from an address built without gaddr
+        let err = run_memcheck(
+            "
+.global 0 tab 2 5 6
+
+.define 0 main
+L0:
+  gaddr %r0, tab
+  movi %r1, 4
+  add %r2, %r0, %r1
+  load %r3, %r2
+  add %r4, %r1, %r0
+  sub %r5, %r4, %r1
+  load %r6, %r5
+  sub %r7, %r0, %r0
+  load %r8, %r7
+  ret %r8
+",
+        )
+        .unwrap_err();
+        assert_eq!(err.kind, memcheck::MemCheckErrorKind::InvalidAddress);
+        assert_eq!(err.ins_idx, 8);
+    }
+
+    #[test]
+    #[should_panic(expected = "declared with 2 arguments, but the native putc expects 1")]
+    fn natives_signatures() {
+        let (module, _names) = irint3a::irparser::Parser::from_str(
+            "
+.declare 257 putc 2
+
+.define 0 main
+L0:
+  movi %r0, 65
+  call %r1, putc, %r0, %r0
+  ret %r1
+",
+        )
+        .build()
+        .unwrap();
+        runtime::Runtime::new(module);
+    }
+
+    #[test]
+    fn memcheck_funptr() {
+        let ps = irint3a::irparser::Parser::from_file("../irint3a/tests/funptr.ir");
+        let (module, _names) = ps.build().unwrap();
+        let mut rt = runtime::Runtime::new(module);
+        rt.enable_memcheck();
+        assert_eq!(rt.try_run().map(|code| code.get_val()), Ok(0));
+        assert_eq!(rt.stdout(), b"A7\n");
+
+        // a function address isn't a memory address
+        let err = run_memcheck(
+            "
+.define 0 main
+L0:
+  funaddr %r0, main
+  load %r1, %r0
+  ret %r1
+",
+        )
+        .unwrap_err();
+        assert_eq!(err.kind, memcheck::MemCheckErrorKind::InvalidAddress);
+        assert_eq!(err.ins_idx, 1);
+
+        // and an address of a local isn't a function address
+        let err = run_memcheck(
+            "
+.define 0 main
+L0:
+  alloca %r0
+  callr %r1, %r0
+  ret %r1
+",
+        )
+        .unwrap_err();
+        assert_eq!(err.kind, memcheck::MemCheckErrorKind::InvalidFunctionAddress);
+        assert_eq!(err.ins_idx, 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid function address")]
+    fn callr_invalid() {
+        let (module, _names) = irint3a::irparser::Parser::from_str(
+            "
+.define 0 main
+L0:
+  movi %r0, 0
+  callr %r1, %r0
+  ret %r1
+",
+        )
+        .build()
+        .unwrap();
+        // the error is returned without the memory checker
+        let mut rt = runtime::Runtime::new(module.clone());
+        let err = rt.try_run().unwrap_err();
+        assert_eq!(err.kind, memcheck::MemCheckErrorKind::InvalidFunctionAddress);
+        assert_eq!(err.ins_idx, 1);
+        runtime::Runtime::new(module).run();
+    }
+
+    #[test]
+    fn debug_locations() {
+        let code = "
+.declare 258 exit
+
+.define 0 main
+L0:
+  movi %r0, 3 ; !loc t.le:1:9
+  movi %r1, 0 ; !loc t.le:2:9
+  div %r2, %r0, %r1 ; !loc t.le:2:7
+  div %r2, %r0, %r3 ; !loc t.le:3:7
+  call %r2, exit, %r2
+  ret %r2
+";
+        // the memory checker gives the location of the faulting instruction
+        let err = run_memcheck(&code.replace("movi %r1, 0", "movi %r1, 1")).unwrap_err();
+        assert_eq!(err.ins_idx, 3);
+        assert_eq!(err.loc, Some(irint3a::ir::DebugLoc::new("t.le", 3, 7)));
+        assert!(err.to_string().ends_with("instruction 3) at t.le:3:7"));
+
+        // the runtime stops at the faulting instruction when it panics
+        let (module, _names) = irint3a::irparser::Parser::from_str(code).build().unwrap();
+        let mut rt = runtime::Runtime::new(module);
+        let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| rt.run()));
+        assert!(res.is_err());
+        assert_eq!(
+            rt.current_loc().map(|loc| loc.to_string()),
+            Some("t.le:2:7".to_string())
+        );
+
+        // the profiler counts the instructions per source line
+        let code = code
+            .replace("movi %r1, 0", "movi %r1, 1")
+            .replace("%r3", "%r1");
+        let (module, _names) = irint3a::irparser::Parser::from_str(&code).build().unwrap();
+        let mut rt = runtime::Runtime::new(module);
+        rt.enable_profiler();
+        rt.run();
+        let lines = rt.profiler().unwrap().source_lines_counts(rt.code());
+        let lines: Vec<_> = lines
+            .iter()
+            .map(|((file, line), count)| (&file[..], *line, *count))
+            .collect();
+        assert_eq!(lines, vec![("t.le", 1, 1), ("t.le", 2, 2), ("t.le", 3, 1)]);
+    }
+
+    #[test]
+    fn memcheck_stack_array() {
+        let ps = irint3a::irparser::Parser::from_file("../irint3a/tests/stack_array.ir");
+        let (module, _names) = ps.build().unwrap();
+        let mut rt = runtime::Runtime::new(module);
+        rt.enable_memcheck();
+        assert_eq!(rt.try_run().map(|code| code.get_val()), Ok(0));
+
+        // the array is in the middle of the frame: only going outside of the frame is an error
+        let code = "
+.declare 258 exit
+
+.define 0 main
+L0:
+  alloca %r0
+  alloca %r1, 4
+  movi %r2, 5
+  movi %r4, 4
+  mul %r5, %r2, %r4
+  add %r3, %r1, %r5
+  store %r3, %r2
+  call %r9, exit, %r2
+  ret %r9
+";
+        let err = run_memcheck(code).unwrap_err();
+        assert_eq!(err.kind, memcheck::MemCheckErrorKind::AddressOutOfBounds);
+        assert_eq!(err.ins_idx, 6);
+        assert_eq!(
+            run_memcheck(&code.replace("movi %r2, 5", "movi %r2, 3")),
+            Ok(3)
+        );
+        let code = code.replace("add %r3, %r1, %r5", "sub %r3, %r1, %r5");
+        let err = run_memcheck(&code).unwrap_err();
+        assert_eq!(err.kind, memcheck::MemCheckErrorKind::AddressOutOfBounds);
+        assert_eq!(
+            run_memcheck(&code.replace("movi %r2, 5", "movi %r2, 1")),
+            Ok(1)
+        );
+    }
+
+    #[test]
+    fn stack_array_bounds() {
+        // load / store are checked against the locals of the frame even without the memory checker
+        let run = |code: &str| {
+            let (module, _names) = irint3a::irparser::Parser::from_str(code).build().unwrap();
+            runtime::Runtime::new(module)
+                .try_run()
+                .map(|code| code.get_val())
+        };
+        let code = "
+.declare 258 exit
+
+.define 0 main
+L0:
+  alloca %r0
+  alloca %r1, 4
+  movi %r2, 4
+  movi %r4, 4
+  mul %r5, %r2, %r4
+  add %r3, %r1, %r5
+  load %r6, %r3
+  call %r9, exit, %r2
+  ret %r9
+";
+        let err = run(code).unwrap_err();
+        assert_eq!(err.kind, memcheck::MemCheckErrorKind::AddressOutOfBounds);
+        assert_eq!(err.ins_idx, 6);
+        assert_eq!(run(&code.replace("movi %r2, 4", "movi %r2, 3")), Ok(3));
+
+        // an address moved past the 16 bits local index doesn't reach the next frame
+        let code = "
+.declare 258 exit
+
+.define 0 main
+L0:
+  alloca %r0
+  call %r1, f, %r0
+  call %r9, exit, %r1
+  ret %r9
+
+.define 1 f
+L0:
+  alloca %r1
+  movi %r2, 65536
+  add %r3, %r0, %r2
+  movi %r4, 7
+  store %r3, %r4
+  ret %r4
+";
+        let err = run(code).unwrap_err();
+        assert_eq!(err.kind, memcheck::MemCheckErrorKind::AddressOutOfBounds);
+        assert_eq!(err.ins_idx, 4);
+
+        // or the previous frame
+        let code = code
+            .replace("alloca %r1\n", "alloca %r1\n  movr %r0, %r1\n")
+            .replace("movi %r2, 65536", "movi %r2, -1");
+        let err = run(&code).unwrap_err();
+        assert_eq!(err.kind, memcheck::MemCheckErrorKind::AddressOutOfBounds);
+        assert_eq!(err.ins_idx, 5);
+    }
+
+    #[test]
+    fn memcheck_fmem() {
+        let code = "
+.declare 260 fmemget
+.declare 261 fmemset
+.declare 267 fmemalloc
+.declare 268 fmemfree
+.declare 258 exit
+
+.define 0 main
+L0:
+  movi %r0, 4
+  call %r1, fmemalloc, %r0
+  movi %r2, 3
+  add %r3, %r1, %r2
+  movi %r4, 7
+  call %r5, fmemset, %r3, %r4
+  call %r6, fmemget, %r3
+  call %r5, fmemfree, %r1
+  call %r9, exit, %r6
+  ret %r9
+";
+        assert_eq!(run_memcheck(code), Ok(7));
+
+        let (module, _names) = irint3a::irparser::Parser::from_str(code).build().unwrap();
+        assert_eq!(runtime::Runtime::new(module).run().get_val(), 7);
+
+        let err = run_memcheck(&code.replace("movi %r2, 3", "movi %r2, 4")).unwrap_err();
+        assert!(matches!(
+            err.kind,
+            memcheck::MemCheckErrorKind::FmemOutOfBounds(_)
+        ));
+        assert_eq!(err.ins_idx, 5);
+
+        let err = run_memcheck(&code.replace(
+            "  call %r6, fmemget, %r3\n  call %r5, fmemfree, %r1\n",
+            "  call %r5, fmemfree, %r1\n  call %r6, fmemget, %r3\n",
+        ))
+        .unwrap_err();
+        assert!(matches!(
+            err.kind,
+            memcheck::MemCheckErrorKind::FmemUseAfterFree(_)
+        ));
+
+        let err = run_memcheck(&code.replace("fmemfree, %r1", "fmemfree, %r3")).unwrap_err();
+        assert!(matches!(
+            err.kind,
+            memcheck::MemCheckErrorKind::FmemInvalidFree(_)
+        ));
+
+        // the end of the copy overflows i32
+        let code = "
+.declare 262 fmemcpy
+.declare 267 fmemalloc
+
+.define 0 main
+L0:
+  movi %r0, 4
+  call %r1, fmemalloc, %r0
+  movi %r2, 2147483647
+  call %r3, fmemcpy, %r1, %r1, %r2
+  ret %r3
+";
+        let err = run_memcheck(code).unwrap_err();
+        assert!(matches!(
+            err.kind,
+            memcheck::MemCheckErrorKind::FmemOutOfBounds(_)
+        ));
+        assert_eq!(err.ins_idx, 3);
+    }
+
+    #[test]
+    fn snapshot_fmem() {
+        let code = "
+.declare 258 exit
+.declare 261 fmemset
+
+.define 0 main
+L0:
+  movi %r0, 5
+  movi %r1, 7
+  call %r9, fmemset, %r0, %r1
+  movi %r0, 6
+  call %r9, fmemset, %r0, %r1
+  movi %r0, 100
+  call %r9, fmemset, %r0, %r1
+  call %r9, exit, %r9
+  ret %r9
+";
+        let (module, _names) = irint3a::irparser::Parser::from_str(code).build().unwrap();
+        let mut rt = runtime::Runtime::new(module);
+        let snap = rt.snapshot();
+        assert!(snap.fmem.is_empty());
+        rt.run();
+        // only the written words are saved, the rest of fmem is zero
+        let snap = rt.snapshot();
+        assert_eq!(snap.fmem, vec![(5, vec![7, 7]), (100, vec![7])]);
+    }
+
+    #[test]
+    fn snapshot_restore() {
+        let ps = irint3a::irparser::Parser::from_file("../irint3a/tests/hello_42.ir");
+        let (module, _names) = ps.build().unwrap();
+        let mut rt = runtime::Runtime::new(module);
+        while rt.stdout().is_empty() {
+            rt.step();
+        }
+        let steps = rt.steps();
+        assert_eq!(rt.stdout(), b"4");
+
+        let snap = rt.snapshot();
+        let mut data = vec![];
+        snap.write(&mut data);
+        let snap2 = snapshot::Snapshot::read(&mut &data[..]);
+        assert_eq!(snap, snap2);
+
+        assert_eq!(rt.run().get_val(), 0);
+        assert_eq!(rt.stdout(), b"42\n");
+        let total = rt.steps();
+
+        rt.restore(&snap2);
+        assert_eq!(rt.steps(), steps);
+        assert_eq!(rt.stdout(), b"4");
+        assert_eq!(rt.run().get_val(), 0);
+        assert_eq!(rt.stdout(), b"42\n");
+        assert_eq!(rt.steps(), total);
+    }
+
+    #[test]
+    fn debugger_reverse() {
+        use irint3a::ir::{BasicBlockId, FunctionId};
+
+        let ps = irint3a::irparser::Parser::from_file("../irint3a/tests/hello_42.ir");
+        let (module, names) = ps.build().unwrap();
+        let fun_id = names.get_function_id("_iprint_rec").unwrap();
+        let rt = runtime::Runtime::new(module);
+
+        // break on every call to putchar in _iprint_rec
+        let mut dbg = debugger::Debugger::new_with_interval(rt, 7);
+        dbg.add_breakpoint((fun_id, BasicBlockId(1), 7));
+        assert_eq!(dbg.cont(), debugger::StopReason::Breakpoint);
+        let hit1 = dbg.steps();
+        assert_eq!(dbg.runtime().stdout(), b"");
+        assert_eq!(dbg.cont(), debugger::StopReason::Breakpoint);
+        let hit2 = dbg.steps();
+        assert_eq!(dbg.runtime().stdout(), b"4");
+        assert_eq!(dbg.cont(), debugger::StopReason::Exit(0));
+        assert_eq!(dbg.runtime().stdout(), b"42\n");
+
+        assert_eq!(dbg.reverse_continue(), debugger::StopReason::Breakpoint);
+        assert_eq!(dbg.steps(), hit2);
+        assert_eq!(dbg.runtime().stdout(), b"4");
+        assert_eq!(dbg.reverse_step(), debugger::StopReason::Step);
+        assert_eq!(dbg.steps(), hit2 - 1);
+        assert_eq!(dbg.reverse_continue(), debugger::StopReason::Breakpoint);
+        assert_eq!(dbg.steps(), hit1);
+        assert_eq!(dbg.position(), (fun_id, BasicBlockId(1), 7));
+        assert_eq!(dbg.reverse_continue(), debugger::StopReason::Begin);
+        assert_eq!(dbg.steps(), 0);
+        assert_eq!(dbg.position(), (FunctionId(0), BasicBlockId(0), 0));
+        assert_eq!(dbg.reverse_step(), debugger::StopReason::Begin);
+
+        dbg.goto_step(hit2);
+        assert_eq!(dbg.runtime().stdout(), b"4");
+        assert_eq!(dbg.cont(), debugger::StopReason::Exit(0));
+    }
 }
@@ -0,0 +1,157 @@
+// Memory-safety checker mode of the runtime (see Runtime::enable_memcheck)
+//
+// Every register and local variable has a shadow state:
+// - registers are initialized when written, locals when stored
+// - values that are addresses (created by alloca or gaddr) are tagged with the frame index and the frame generation
+//   The tag follows the value when copied: movr, load, store, call arguments and return value,
+//   and with address arithmetic: add of an address and a non-address, sub of a non-address from an address.
+//   Any other instruction creates a value without tag.
+// - globals are stored like the locals of an extra frame, always alive and initialized
+//   (an address can go from one global to another one with arithmetic, this isn't detected)
+//
+// Every frame has an unique generation number, so an address of a frame that returned
+// is detected even if a newer frame is now at the same index.
+//
+// Before each instruction, the runtime checks that:
+// - all registers read by the instruction are initialized
+//   (except for ret: the returned value can be uninitialized, eg for void functions,
+//    the destination register of the caller stays uninitialized)
+// - load / store addresses have a tag, for a frame still alive,
+//   and are inside the locals of this frame (or the globals), even after address arithmetic
+// - the local read by load is initialized
+// - callr is called with a function address (created by funaddr). Function addresses have no tag,
+//   so a load / store with them is an invalid address
+//
+// The flat memory is only checked in the heap area (the regions given by fmemalloc):
+// all accesses must be inside a region not yet freed, and fmemfree must be called with the beginning of a region.
+// The rest of the flat memory is not checked.
+
+use std::fmt;
+
+use irint3a::ir;
+use irint3a::irnames;
+
+/// Address tag: frame index and generation of the frame that allocated the local
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct PtrTag {
+    pub frame_idx: usize,
+    pub gen: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemCheckErrorKind {
+    /// Read of a register never written in this frame
+    UninitRegister(ir::RegId),
+    /// Load of a local variable never stored
+    UninitLocal,
+    /// Load / Store with an address of a frame that already returned
+    UseAfterReturn,
+    /// Load / Store with a value not created by alloca / gaddr
+    InvalidAddress,
+    /// Load / Store with an address moved by arithmetic outside of the locals of its frame (or the globals)
+    AddressOutOfBounds,
+    /// Indirect call with a value not created by funaddr
+    InvalidFunctionAddress,
+    /// Access to the fmem heap outside of all allocated regions
+    FmemOutOfBounds(i32),
+    /// Access to a fmem region already freed
+    FmemUseAfterFree(i32),
+    /// fmemfree with an address that isn't the beginning of an allocated region
+    FmemInvalidFree(i32),
+}
+
+impl fmt::Display for MemCheckErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MemCheckErrorKind::UninitRegister(reg) => {
+                write!(f, "read of uninitialized register %r{}", reg.0)
+            }
+            MemCheckErrorKind::UninitLocal => write!(f, "load of uninitialized local variable"),
+            MemCheckErrorKind::UseAfterReturn => {
+                write!(f, "access to a local variable of a function that returned")
+            }
+            MemCheckErrorKind::InvalidAddress => write!(f, "access to an invalid address"),
+            MemCheckErrorKind::AddressOutOfBounds => {
+                write!(f, "access to an address outside of its memory area")
+            }
+            MemCheckErrorKind::InvalidFunctionAddress => {
+                write!(f, "indirect call to an invalid function address")
+            }
+            MemCheckErrorKind::FmemOutOfBounds(addr) => {
+                write!(f, "fmem access out of allocated regions at @{}", addr)
+            }
+            MemCheckErrorKind::FmemUseAfterFree(addr) => {
+                write!(f, "fmem access to a freed region at @{}", addr)
+            }
+            MemCheckErrorKind::FmemInvalidFree(addr) => {
+                write!(f, "fmemfree of an invalid address @{}", addr)
+            }
+        }
+    }
+}
+
+/// Error detected by the checker, with the position of the faulting instruction
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MemCheckError {
+    pub kind: MemCheckErrorKind,
+    pub fun: ir::FunctionId,
+    pub bb: ir::BasicBlockId,
+    pub ins_idx: usize,
+    pub loc: Option<ir::DebugLoc>,
+}
+
+impl MemCheckError {
+    /// Describe the error, with the names of the function, basic block and register
+    pub fn to_string_with_names(&self, names: &irnames::ModuleNames) -> String {
+        let fun_names = names.get_function(self.fun);
+        let fun_name = names
+            .get_function_name(self.fun)
+            .map(|x| x.to_string())
+            .unwrap_or_else(|| format!("{}", self.fun.0));
+        let bb_name = fun_names
+            .and_then(|x| x.get_basic_block_name(self.bb))
+            .map(|x| x.to_string())
+            .unwrap_or_else(|| format!("{}", self.bb.0));
+
+        let kind = match self.kind {
+            MemCheckErrorKind::UninitRegister(reg) => {
+                match fun_names.and_then(|x| x.get_register_name(reg)) {
+                    Some(name) => format!("read of uninitialized register %{}", name),
+                    None => format!("{}", self.kind),
+                }
+            }
+            _ => format!("{}", self.kind),
+        };
+
+        format!(
+            "memcheck: {} (function {}, block {}, instruction {}){}",
+            kind,
+            fun_name,
+            bb_name,
+            self.ins_idx,
+            self.loc_suffix()
+        )
+    }
+
+    // Source location of the instruction, if it has one
+    fn loc_suffix(&self) -> String {
+        match &self.loc {
+            Some(loc) => format!(" at {}", loc),
+            None => String::new(),
+        }
+    }
+}
+
+impl fmt::Display for MemCheckError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "memcheck: {} (function {}, block {}, instruction {}){}",
+            self.kind,
+            self.fun.0,
+            self.bb.0,
+            self.ins_idx,
+            self.loc_suffix()
+        )
+    }
+}
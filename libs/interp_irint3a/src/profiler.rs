@@ -0,0 +1,326 @@
+// Optional instrumentation of the runtime, to know where a program spends its time
+//
+// The profiler is notified by the runtime for every executed instruction,
+// every branch between basic blocks, and every function call / return.
+// It counts:
+// - executed instructions, per instruction / basic block / function
+// - calls per function
+// - exclusive (only the function code) and inclusive (with all callees) instructions counts
+// - CFG edges frequencies
+// - executed instructions per source line, for code with debug locations
+//
+// The basic block count is the count of its first instruction
+
+use std::collections::HashMap;
+use std::io::Write;
+
+use irint3a::controlflow;
+use irint3a::ir;
+use irint3a::irnames;
+use irint3a::irprinter;
+
+/// Execution counts of one function
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FunctionProfile {
+    pub calls: u64,
+    pub exclusive: u64,
+    pub inclusive: u64,
+}
+
+pub struct Profiler {
+    total: u64,
+    ins_counts: HashMap<(ir::FunctionId, ir::BasicBlockId, usize), u64>,
+    edge_counts: HashMap<(ir::FunctionId, ir::BasicBlockId, ir::BasicBlockId), u64>,
+    funs: HashMap<ir::FunctionId, FunctionProfile>,
+
+    // stack of active calls, with the total count when the call started
+    active: Vec<(ir::FunctionId, u64)>,
+    // number of times every function is in the active stack, to handle recursion
+    active_count: HashMap<ir::FunctionId, usize>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Profiler {
+            total: 0,
+            ins_counts: HashMap::new(),
+            edge_counts: HashMap::new(),
+            funs: HashMap::new(),
+
+            active: vec![],
+            active_count: HashMap::new(),
+        }
+    }
+
+    /// Total number of executed instructions
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// Number of times one instruction was executed
+    pub fn ins_count(&self, fun: ir::FunctionId, bb: ir::BasicBlockId, ins_idx: usize) -> u64 {
+        self.ins_counts
+            .get(&(fun, bb, ins_idx))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Number of times the execution entered one basic block
+    pub fn bb_count(&self, fun: ir::FunctionId, bb: ir::BasicBlockId) -> u64 {
+        self.ins_count(fun, bb, 0)
+    }
+
+    /// Number of times the execution went from basic block `src` to `dst`
+    pub fn edge_count(&self, fun: ir::FunctionId, src: ir::BasicBlockId, dst: ir::BasicBlockId) -> u64 {
+        self.edge_counts.get(&(fun, src, dst)).copied().unwrap_or(0)
+    }
+
+    /// Returns the counts of one function
+    /// Inclusive counts are up to date even for functions still running
+    pub fn function_profile(&self, fun: ir::FunctionId) -> FunctionProfile {
+        let mut res = self.funs.get(&fun).copied().unwrap_or_default();
+        // add the running part of the outermost active call
+        if let Some((_, start)) = self.active.iter().find(|(id, _)| *id == fun) {
+            res.inclusive += self.total - start;
+        }
+        res
+    }
+
+    /// Number of executed instructions per source line (file, line)
+    /// Only the instructions with a debug location are counted, sorted by file and line
+    pub fn source_lines_counts(&self, module: &ir::Module) -> Vec<((String, usize), u64)> {
+        let mut counts = HashMap::new();
+        for ((fun, bb, ins_idx), count) in &self.ins_counts {
+            let bb = module.get_fun(*fun).unwrap().get_basic_block(*bb);
+            if let Some(loc) = bb.get_ins_loc(*ins_idx) {
+                *counts
+                    .entry((loc.file().to_string(), loc.line()))
+                    .or_insert(0) += count;
+            }
+        }
+
+        let mut res: Vec<_> = counts.into_iter().collect();
+        res.sort();
+        res
+    }
+
+    pub(crate) fn on_ins(&mut self, fun: ir::FunctionId, bb: ir::BasicBlockId, ins_idx: usize) {
+        self.total += 1;
+        *self.ins_counts.entry((fun, bb, ins_idx)).or_insert(0) += 1;
+        self.funs.entry(fun).or_default().exclusive += 1;
+    }
+
+    pub(crate) fn on_edge(&mut self, fun: ir::FunctionId, src: ir::BasicBlockId, dst: ir::BasicBlockId) {
+        *self.edge_counts.entry((fun, src, dst)).or_insert(0) += 1;
+    }
+
+    pub(crate) fn on_enter(&mut self, fun: ir::FunctionId) {
+        self.funs.entry(fun).or_default().calls += 1;
+        self.active.push((fun, self.total));
+        *self.active_count.entry(fun).or_insert(0) += 1;
+    }
+
+    pub(crate) fn on_native_call(&mut self, fun: ir::FunctionId) {
+        self.funs.entry(fun).or_default().calls += 1;
+    }
+
+    pub(crate) fn on_leave(&mut self) {
+        let (fun, start) = self.active.pop().unwrap();
+        let count = self.active_count.get_mut(&fun).unwrap();
+        *count -= 1;
+        if *count == 0 {
+            self.funs.get_mut(&fun).unwrap().inclusive += self.total - start;
+        }
+    }
+
+    /// Write a text report with counts for all functions, basic blocks and edges
+    /// Names are generated if `names` is none
+    pub fn write_report(
+        &self,
+        writer: &mut dyn Write,
+        module: &ir::Module,
+        names: Option<&irnames::ModuleNames>,
+    ) {
+        let mut gen_names = irnames::ModuleNames::new();
+        let names = match names {
+            Some(names) => names,
+            None => {
+                gen_names.complete_undefined(module);
+                &gen_names
+            }
+        };
+        let percent = |count: u64| {
+            if self.total == 0 {
+                0.0
+            } else {
+                100.0 * count as f64 / self.total as f64
+            }
+        };
+
+        writeln!(writer, "Total instructions: {}", self.total).unwrap();
+        writeln!(writer).unwrap();
+
+        // 1) Functions, sorted by exclusive count
+        let mut funs: Vec<_> = module
+            .funs()
+            .iter()
+            .map(|fun| (fun.id(), self.function_profile(fun.id())))
+            .filter(|(_, prof)| prof.calls > 0)
+            .collect();
+        funs.sort_by(|a, b| b.1.exclusive.cmp(&a.1.exclusive).then(a.0.cmp(&b.0)));
+
+        writeln!(
+            writer,
+            "{:<24} {:>10} {:>12} {:>7} {:>12} {:>7}",
+            "Function", "Calls", "Exclusive", "%", "Inclusive", "%"
+        )
+        .unwrap();
+        for (fun_id, prof) in &funs {
+            writeln!(
+                writer,
+                "{:<24} {:>10} {:>12} {:>6.2}% {:>12} {:>6.2}%",
+                names.get_function_name(*fun_id).unwrap(),
+                prof.calls,
+                prof.exclusive,
+                percent(prof.exclusive),
+                prof.inclusive,
+                percent(prof.inclusive)
+            )
+            .unwrap();
+        }
+
+        // 2) Basic blocks and edges, for every executed function
+        for (fun_id, _) in &funs {
+            let fun = module.get_fun(*fun_id).unwrap();
+            if fun.is_extern() {
+                continue;
+            }
+            let fun_names = names.get_function(*fun_id).unwrap();
+
+            writeln!(writer).unwrap();
+            writeln!(writer, "Function {}:", names.get_function_name(*fun_id).unwrap()).unwrap();
+            let cfg = controlflow::build_cfg(fun);
+            for bb_id in fun.basic_blocks_list() {
+                let bb = fun.get_basic_block(*bb_id);
+                let bb_count = self.bb_count(*fun_id, *bb_id);
+                let bb_ins: u64 = (0..bb.size())
+                    .map(|idx| self.ins_count(*fun_id, *bb_id, idx))
+                    .sum();
+                writeln!(
+                    writer,
+                    "  {:<20} entries: {:>10}  instructions: {:>12}",
+                    fun_names.get_basic_block_name(*bb_id).unwrap(),
+                    bb_count,
+                    bb_ins
+                )
+                .unwrap();
+
+                for succ in cfg.adj(bb_id.0) {
+                    let succ = ir::BasicBlockId(*succ);
+                    writeln!(
+                        writer,
+                        "    -> {:<17} {:>10}",
+                        fun_names.get_basic_block_name(succ).unwrap(),
+                        self.edge_count(*fun_id, *bb_id, succ)
+                    )
+                    .unwrap();
+                }
+            }
+        }
+
+        // 3) Source lines, if the code has debug locations
+        let lines = self.source_lines_counts(module);
+        if !lines.is_empty() {
+            writeln!(writer).unwrap();
+            writeln!(
+                writer,
+                "{:<32} {:>12} {:>7}",
+                "Source line", "Instructions", "%"
+            )
+            .unwrap();
+            for ((file, line), count) in &lines {
+                writeln!(
+                    writer,
+                    "{:<32} {:>12} {:>6.2}%",
+                    format!("{}:{}", file, line),
+                    count,
+                    percent(*count)
+                )
+                .unwrap();
+            }
+        }
+    }
+
+    /// Write the CFG of function `fun_id` to a dot file, with frequencies on the edges
+    /// Names are generated if `names` is none
+    pub fn write_cfg_dot(
+        &self,
+        path: &str,
+        module: &ir::Module,
+        names: Option<&irnames::ModuleNames>,
+        fun_id: ir::FunctionId,
+    ) {
+        let mut gen_names = irnames::ModuleNames::new();
+        let names = match names {
+            Some(names) => names,
+            None => {
+                gen_names.complete_undefined(module);
+                &gen_names
+            }
+        };
+        let fun = module.get_fun(fun_id).unwrap();
+        let fun_names = names.get_function(fun_id).unwrap();
+        let cfg = controlflow::build_cfg(fun);
+
+        let vnames: HashMap<usize, String> = fun
+            .basic_blocks_list()
+            .iter()
+            .map(|bb_id| {
+                let bb_name = fun_names.get_basic_block_name(*bb_id).unwrap();
+                let count = self.bb_count(fun_id, *bb_id);
+                (bb_id.0, format!("\"{} ({})\"", bb_name, count))
+            })
+            .collect();
+
+        let mut elabels = HashMap::new();
+        for v in 0..cfg.vcount() {
+            for w in cfg.adj(v) {
+                let count = self.edge_count(fun_id, ir::BasicBlockId(v), ir::BasicBlockId(*w));
+                elabels.insert((v, *w), format!("{}", count));
+            }
+        }
+
+        cfg.write_dot_labels(path, Some("cfg"), Some(&vnames), Some(&elabels));
+    }
+
+    /// Write the code of the module, annotated with the execution count of every instruction
+    /// Instructions never executed are marked with #####
+    pub fn write_coverage(
+        &self,
+        writer: &mut dyn Write,
+        module: &ir::Module,
+        names: Option<&irnames::ModuleNames>,
+    ) {
+        irprinter::print_code_annotated(module, writer, names, self);
+    }
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl irprinter::InsAnnotator for Profiler {
+    fn annotate_ins(
+        &self,
+        fun: ir::FunctionId,
+        bb: ir::BasicBlockId,
+        ins_idx: usize,
+    ) -> Option<String> {
+        Some(match self.ins_count(fun, bb, ins_idx) {
+            0 => "#####".to_string(),
+            count => format!("{}", count),
+        })
+    }
+}
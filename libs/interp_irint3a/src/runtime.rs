@@ -1,13 +1,21 @@
 use std::collections::HashMap;
 use std::fs::File;
-use std::io::Read;
-use std::num::Wrapping;
+use std::io::{Read, Write};
 
+use interp_common::fmem::FLAT_MEMORY_SIZE;
 use irint3a::ir;
+use irint3a::registers::GetRegistersDefUse;
 
-/// Represent a word value in the Runtime, it's usually a signed integer or an address
+use crate::memcheck::{MemCheckError, MemCheckErrorKind, PtrTag};
+use crate::natives::{NativeContext, NativeRegistry};
+use crate::profiler::Profiler;
+use crate::snapshot::{FrameState, Snapshot};
+use crate::tracer::Tracer;
+
+/// Represent a register value in the Runtime, it's usually a signed integer or an address
+/// Integers are stored sign-extended from their width (eg: an i8 is always between -128 and 127)
 #[derive(Clone, Copy, Debug)]
-struct RTVal(i32);
+struct RTVal(i64);
 
 /// The exit code at the end of the execution
 #[derive(Clone, Copy, Debug)]
@@ -22,8 +30,14 @@ impl ExitCode {
 // Contains all the registers and local variables for each function frame
 struct Frame {
     regs: HashMap<ir::RegId, RTVal>,
-    locals: Vec<RTVal>,
+    locals: Vec<u8>,
     ret_reg: ir::RegId, //where the caller wants the return value to be saved
+
+    // shadow state for memcheck mode
+    gen: u64, //unique for each frame
+    regs_tag: HashMap<ir::RegId, PtrTag>,
+    locals_init: Vec<bool>,
+    locals_tag: Vec<Option<PtrTag>>,
 }
 
 impl Frame {
@@ -32,6 +46,11 @@ impl Frame {
             regs: HashMap::new(),
             locals: vec![],
             ret_reg: ir::RegId(0),
+
+            gen: 0,
+            regs_tag: HashMap::new(),
+            locals_init: vec![],
+            locals_tag: vec![],
         }
     }
 
@@ -59,12 +78,34 @@ impl Frame {
         self.regs.insert(reg, val);
     }
 
-    // Allocate a new local variable, and returns its index
-    fn alloca(&mut self) -> usize {
-        let res = self.locals.len();
-        self.locals.push(RTVal(0));
+    // Allocate a new local variable of `size` bytes, aligned on `align` bytes,
+    // and returns the index of its first byte
+    fn alloca(&mut self, size: usize, align: usize) -> usize {
+        let len = self.locals.len();
+        let res = len + (align - len % align) % align;
+        if res + size > MAX_FRAME_BYTES {
+            panic!(
+                "Failed to allocate memory: more than {} bytes in the frame",
+                MAX_FRAME_BYTES
+            );
+        }
+        self.locals.resize(res + size, 0);
+        self.locals_init.resize(res + size, false);
+        self.locals_tag.resize(res + size, None);
         res
     }
+
+    // Address tag of the value at `local_idx` (memcheck mode)
+    // Only a full i32 value stored with a tag is an address
+    fn local_tag(&self, local_idx: usize, width: ir::IntWidth) -> Option<PtrTag> {
+        let tags = &self.locals_tag[local_idx..local_idx + width.bytes()];
+        match tags[0] {
+            Some(tag) if width == ir::IntWidth::I32 && tags.iter().all(|x| *x == Some(tag)) => {
+                Some(tag)
+            }
+            _ => None,
+        }
+    }
 }
 
 // Needed to know the next instruction to run
@@ -84,8 +125,19 @@ impl CodeAddress {
     }
 }
 
-// Only local variables on the stack are addressable
+// Only local variables on the stack and globals are addressable
 // As such, an adress has 2 parts: the frame index, and the local index in the frame
+// Globals are stored like the locals of an extra frame, with the index GLOBALS_FRAME
+//
+// The locals of a frame are a contiguous area of bytes: alloca gives the index of the first byte of the variable,
+// and address arithmetic moves inside this area (address + 1 is the next byte).
+// Values are stored in little-endian, and alloca aligns the variable on the size of its values.
+// An address that goes outside the area is invalid: load / store always check that the bytes are inside the area
+// of the frame (see Runtime::get_mem), and return an error.
+// The local index has 16 bits, and is followed by a guard bit always 0 in a valid address:
+// an address moved up to 64 KiB past the end (or before the beginning) of the local index sets it,
+// so it's an out of bounds local index instead of an address of the next (or previous) frame.
+// The frame index is in the 15 remaining bits.
 #[derive(Clone, Copy, Debug)]
 struct MemAddress(RTVal);
 
@@ -93,22 +145,52 @@ impl MemAddress {
     fn new(frame_idx: usize, local_idx: usize) -> Self {
         let frame_idx = frame_idx as u32;
         let local_idx = local_idx as u32;
-        let addr = (frame_idx << 16) | local_idx;
-        MemAddress(RTVal(addr as i32))
+        let addr = (frame_idx << 17) | local_idx;
+        MemAddress(RTVal(addr as i32 as i64))
     }
 
     fn frame_idx(&self) -> usize {
         let addr = (self.0).0 as u32;
-        (addr >> 16) as usize
+        (addr >> 17) as usize
     }
 
+    // The local index with the guard bit: always out of bounds if the guard bit is set
     fn local_idx(&self) -> usize {
         let addr = (self.0).0 as u32;
-        (addr & 0xFFFF) as usize
+        (addr & 0x1FFFF) as usize
+    }
+}
+
+const GLOBALS_FRAME: usize = 0x7FFF;
+
+// Function addresses (created by funaddr) use the reserved frame index FUNCTIONS_FRAME,
+// and the function id as local index: they are never confused with an address of a local or a global,
+// and a load / store with a function address fails (there is no frame at this index).
+const FUNCTIONS_FRAME: usize = 0x7FFE;
+
+impl MemAddress {
+    fn from_fun(fun: ir::FunctionId) -> Self {
+        if fun.0 >= MAX_FRAME_BYTES {
+            panic!(
+                "Failed to take function address: function id {} too big",
+                fun.0
+            );
+        }
+        MemAddress::new(FUNCTIONS_FRAME, fun.0)
+    }
+
+    // The function of a function address, None for any other value
+    fn fun(&self) -> Option<ir::FunctionId> {
+        let val = (self.0).0;
+        if val != val as i32 as i64 || self.frame_idx() != FUNCTIONS_FRAME {
+            return None;
+        }
+        Some(ir::FunctionId(self.local_idx()))
     }
 }
 
-const FLAT_MEMORY_SIZE: i32 = 16 * 1024 * 1024;
+// The local index has 16 bits
+const MAX_FRAME_BYTES: usize = 0x10000;
 
 pub struct FlatMemory {
     data: Vec<i32>,
@@ -151,6 +233,40 @@ impl FlatMemory {
         }
     }
 
+    // Encode the memory as runs of non-zero words: (address of the first word, words)
+    pub(crate) fn to_sparse(&self) -> Vec<(i32, Vec<i32>)> {
+        let mut res = vec![];
+        let mut run: Option<(i32, Vec<i32>)> = None;
+        for (addr, val) in self.data.iter().enumerate() {
+            if *val == 0 {
+                res.extend(run.take());
+                continue;
+            }
+            match &mut run {
+                Some((_, words)) => words.push(*val),
+                None => run = Some((addr as i32, vec![*val])),
+            }
+        }
+        res.extend(run);
+        res
+    }
+
+    pub(crate) fn from_sparse(runs: &[(i32, Vec<i32>)]) -> Self {
+        let mut res = FlatMemory::new();
+        if runs.is_empty() {
+            return res;
+        }
+
+        res.data = vec![0; FLAT_MEMORY_SIZE as usize];
+        for (addr, words) in runs {
+            res.check_idx(*addr);
+            res.check_idx(*addr + words.len() as i32 - 1);
+            let beg = *addr as usize;
+            res.data[beg..beg + words.len()].copy_from_slice(words);
+        }
+        res
+    }
+
     fn check_idx(&self, idx: i32) {
         if idx < 0 {
             panic!("flat memory: trying to access negative index");
@@ -160,12 +276,10 @@ impl FlatMemory {
         }
     }
 
+    // The memory is allocated zeroed on the first store (the pages are only committed when they are written)
     fn lazy_init(&mut self) {
         if self.data.len() == 0 {
-            self.data = Vec::with_capacity(FLAT_MEMORY_SIZE as usize);
-            unsafe {
-                self.data.set_len(FLAT_MEMORY_SIZE as usize);
-            }
+            self.data = vec![0; FLAT_MEMORY_SIZE as usize];
         }
     }
 }
@@ -173,53 +287,273 @@ impl FlatMemory {
 pub struct Runtime {
     code: ir::Module,
     frames: Vec<Frame>,
+    globals: Frame,
+    globals_offsets: HashMap<ir::GlobalId, usize>, //index of the first byte of every global
     call_stack: Vec<CodeAddress>,
     ins_status: Option<ExitCode>, //status of last executed instruction
 
-    stdin: Vec<u8>,
-    stdin_pos: usize,
-    stdout: Vec<u8>,
-    fmem: FlatMemory,
+    ctx: NativeContext,
+    natives: NativeRegistry,
+
+    profiler: Option<Profiler>,
+    tracer: Option<Box<dyn Tracer>>,
+
+    memcheck: bool,
+    next_frame_gen: u64,
+
+    steps: u64, //number of executed instructions
 }
 
 impl Runtime {
-    /// Create a new initialized runtime
+    /// Create a new initialized runtime, with the standard native functions
     pub fn new(code: ir::Module) -> Self {
+        Self::new_with_natives(code, NativeRegistry::default())
+    }
+
+    /// Create a new initialized runtime, with a custom registry of native functions
+    pub fn new_with_natives(code: ir::Module, natives: NativeRegistry) -> Self {
         let mut res = Runtime {
             code,
             frames: vec![],
+            globals: Frame::new(),
+            globals_offsets: HashMap::new(),
             call_stack: vec![],
             ins_status: None,
 
-            stdin: vec![],
-            stdin_pos: 0,
-            stdout: vec![],
-            fmem: FlatMemory::new(),
+            ctx: NativeContext::new(),
+            natives,
+
+            profiler: None,
+            tracer: None,
+
+            memcheck: false,
+            next_frame_gen: 0,
+
+            steps: 0,
         };
+        res.check_natives_signatures();
         res.reset();
         res
     }
 
+    // The extern functions declared with a signature must have the arity of their native
+    // Natives are otherwise only checked when called
+    fn check_natives_signatures(&self) {
+        for fun in self.code.funs().iter().filter(|fun| fun.is_extern()) {
+            let (sig, arity) = match (fun.signature(), self.natives.get_arity(fun.id())) {
+                (Some(sig), Some(arity)) => (sig, arity),
+                _ => continue,
+            };
+            if sig.nb_args() != arity {
+                panic!(
+                    "Extern function #{} declared with {} arguments, but the native {} expects {}",
+                    fun.id().0,
+                    sig.nb_args(),
+                    self.natives.get_name(fun.id()).unwrap(),
+                    arity
+                );
+            }
+        }
+    }
+
     /// Reset the Runtime to the starting point of the program
     pub fn reset(&mut self) {
         self.frames.clear();
         self.call_stack.clear();
-        self.stdout.clear();
+        self.ctx.reset_stdout();
         self.ins_status = None;
+        self.steps = 0;
+        self.reset_globals();
 
         self.call_stack.push(self.begin_of_fun(ir::FunctionId(0)));
-        self.frames.push(Frame::new());
+        self.push_frame(Frame::new());
+
+        if self.profiler.is_some() {
+            self.enable_profiler();
+        }
+    }
+
+    // Allocate all globals, with their init data
+    fn reset_globals(&mut self) {
+        self.globals = Frame::new();
+        self.globals.gen = u64::MAX;
+        self.globals_offsets.clear();
+
+        for global in self.code.globals() {
+            let width = global.width();
+            let offset = self.globals.alloca(global.bytes(), width.bytes());
+            self.globals_offsets.insert(global.id(), offset);
+            for (idx, val) in global.init().iter().enumerate() {
+                let pos = offset + idx * width.bytes();
+                write_val(&mut self.globals.locals[pos..], width, RTVal(*val));
+            }
+        }
+        for init in &mut self.globals.locals_init {
+            *init = true;
+        }
+    }
+
+    /// Start counting executed instructions, calls and branches
+    /// Resets the counts if the profiler was already enabled
+    pub fn enable_profiler(&mut self) {
+        let mut profiler = Profiler::new();
+        for addr in &self.call_stack {
+            profiler.on_enter(addr.fun);
+        }
+        self.profiler = Some(profiler);
+    }
+
+    /// Returns the profiler, if enabled
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+
+    /// Set a tracer notified of all the events of the execution
+    pub fn set_tracer(&mut self, tracer: Box<dyn Tracer>) {
+        self.ctx.enable_fmem_log();
+        self.tracer = Some(tracer);
+    }
+
+    /// Remove the tracer, and returns it
+    pub fn take_tracer(&mut self) -> Option<Box<dyn Tracer>> {
+        self.ctx.disable_fmem_log();
+        self.tracer.take()
+    }
+
+    /// Enable the memory-safety checker (see memcheck.rs)
+    /// Must be called before running the program
+    pub fn enable_memcheck(&mut self) {
+        self.memcheck = true;
+        self.ctx.enable_fmem_check();
+    }
+
+    /// Save the complete state of the execution (see snapshot.rs)
+    /// stdin and stdout must be buffered, and the memory checker disabled
+    pub fn snapshot(&self) -> Snapshot {
+        if self.memcheck {
+            panic!("Failed to save runtime state: not supported in memcheck mode");
+        }
+
+        let (stdin_pos, stdout) = self.ctx.save_io();
+        let (fmem, heap) = self.ctx.save_fmem();
+        let frames = self
+            .frames
+            .iter()
+            .map(|frame| {
+                let mut regs: Vec<_> = frame.regs.iter().map(|(r, v)| (r.0, v.0)).collect();
+                regs.sort_unstable();
+                FrameState {
+                    ret_reg: frame.ret_reg.0,
+                    gen: frame.gen,
+                    regs,
+                    locals: frame.locals.clone(),
+                }
+            })
+            .collect();
+        let call_stack = self
+            .call_stack
+            .iter()
+            .map(|addr| (addr.fun.0, addr.bb.0, addr.pos))
+            .collect();
+
+        Snapshot {
+            steps: self.steps,
+            status: self.ins_status.map(|x| x.0),
+            next_frame_gen: self.next_frame_gen,
+            frames,
+            globals: self.globals.locals.clone(),
+            call_stack,
+            stdin_pos,
+            stdout,
+            fmem,
+            heap,
+        }
+    }
+
+    /// Go back to the state saved in the snapshot
+    /// The runtime must have the same code and the same stdin data than when the snapshot was taken
+    pub fn restore(&mut self, snap: &Snapshot) {
+        if self.memcheck {
+            panic!("Failed to restore runtime state: not supported in memcheck mode");
+        }
+
+        self.ctx.restore_io(snap.stdin_pos, &snap.stdout);
+        self.ctx.restore_fmem(&snap.fmem, &snap.heap);
+        self.frames = snap
+            .frames
+            .iter()
+            .map(|state| {
+                let mut frame = Frame::new();
+                for (reg, val) in &state.regs {
+                    frame.set_reg(ir::RegId(*reg), RTVal(*val));
+                }
+                frame.alloca(state.locals.len(), 1);
+                frame.locals.copy_from_slice(&state.locals);
+                frame.ret_reg = ir::RegId(state.ret_reg);
+                frame.gen = state.gen;
+                frame
+            })
+            .collect();
+        self.globals.locals.copy_from_slice(&snap.globals);
+        self.call_stack = snap
+            .call_stack
+            .iter()
+            .map(|(fun, bb, pos)| CodeAddress {
+                fun: ir::FunctionId(*fun),
+                bb: ir::BasicBlockId(*bb),
+                pos: *pos,
+            })
+            .collect();
+        self.ins_status = snap.status.map(ExitCode);
+        self.next_frame_gen = snap.next_frame_gen;
+        self.steps = snap.steps;
+    }
+
+    /// Number of instructions executed since the beginning of the program
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Returns the call stack (function, basic block, instruction index) of the next instructions to run
+    /// The last one is the current instruction
+    pub fn call_stack(&self) -> Vec<(ir::FunctionId, ir::BasicBlockId, usize)> {
+        self.call_stack
+            .iter()
+            .map(|addr| (addr.fun, addr.bb, addr.pos))
+            .collect()
+    }
+
+    /// Returns the values of all registers of the current frame, sorted by register
+    pub fn registers(&self) -> Vec<(ir::RegId, i64)> {
+        let mut res: Vec<_> = self
+            .frames
+            .last()
+            .unwrap()
+            .regs
+            .iter()
+            .map(|(reg, val)| (*reg, val.0))
+            .collect();
+        res.sort_unstable();
+        res
+    }
+
+    /// Returns the code being executed
+    pub fn code(&self) -> &ir::Module {
+        &self.code
     }
 
     /// Run only one instruction
     /// Returns an exitcode if the instruction calls exit
+    /// Panics if the memory checker finds an error, or on a runtime error (see try_step)
     pub fn step(&mut self) -> Option<ExitCode> {
-        let ins = self.fetch_ins().clone();
-        self.exec_ins(ins);
-        self.ins_status
+        match self.try_step() {
+            Ok(res) => res,
+            Err(err) => panic!("{}", err),
+        }
     }
 
     /// Run the program until the end
+    /// Panics if the memory checker finds an error, or on a runtime error (see try_step)
     pub fn run(&mut self) -> ExitCode {
         loop {
             if let Some(ret) = self.step() {
@@ -228,24 +562,108 @@ impl Runtime {
         }
     }
 
+    /// Same as step, but returns the errors found by the memory checker
+    /// Some errors are always returned, even without the memory checker:
+    /// load / store outside of the locals of a frame, and callr with an invalid function address
+    /// The faulting instruction is not executed, except for the heap errors of the natives
+    /// (fmemget, fmemset, fmemcpy, fmemfree): they are found during the call, the invalid access is skipped,
+    /// but the native returns and its result is written to the destination register
+    pub fn try_step(&mut self) -> Result<Option<ExitCode>, MemCheckError> {
+        let ins = self.fetch_ins().clone();
+        let ins_addr = self.call_stack.last().unwrap().clone();
+        if self.memcheck {
+            if let Err(kind) = self.memcheck_ins(&ins) {
+                return Err(memcheck_error(kind, &ins_addr, self.current_loc().cloned()));
+            }
+        }
+
+        if let Some(profiler) = &mut self.profiler {
+            profiler.on_ins(ins_addr.fun, ins_addr.bb, ins_addr.pos);
+        }
+        let operands = match self.tracer {
+            Some(_) => self.ins_operands(&ins),
+            None => vec![],
+        };
+        if let Some(tracer) = &mut self.tracer {
+            tracer.trace_ins(ins_addr.fun, ins_addr.bb, ins_addr.pos, &ins, &operands);
+        }
+
+        if let Err(kind) = self.exec_ins(ins) {
+            return Err(memcheck_error(kind, &ins_addr, self.current_loc().cloned()));
+        }
+        self.steps += 1;
+        if let Some(kind) = self.ctx.take_memcheck_error() {
+            let loc = self
+                .ins_loc(ins_addr.fun, ins_addr.bb, ins_addr.pos)
+                .cloned();
+            return Err(memcheck_error(kind, &ins_addr, loc));
+        }
+
+        if let (Some(tracer), Some(status)) = (&mut self.tracer, self.ins_status) {
+            tracer.trace_exit(status.get_val());
+        }
+        Ok(self.ins_status)
+    }
+
+    /// Same as run, but returns the errors found by the memory checker (and the runtime errors, see try_step)
+    pub fn try_run(&mut self) -> Result<ExitCode, MemCheckError> {
+        loop {
+            if let Some(ret) = self.try_step()? {
+                return Ok(ret);
+            }
+        }
+    }
+
     /// Set stdin stream from raw bytes data
     pub fn reset_stdin_raw(&mut self, data: &[u8]) {
-        self.stdin = Vec::from(data);
-        self.stdin_pos = 0;
+        self.ctx.reset_stdin(Vec::from(data));
     }
 
     /// Set stdin stream from binary file
     pub fn reset_stdin_path(&mut self, path: &str) {
         let mut f = File::open(path).expect("Failed to open stdin file");
-        self.stdin.clear();
-        f.read_to_end(&mut self.stdin)
-            .expect("Failed to read stdin file");
-        self.stdin_pos = 0;
+        let mut data = vec![];
+        f.read_to_end(&mut data).expect("Failed to read stdin file");
+        self.ctx.reset_stdin(data);
+    }
+
+    /// Read stdin from a stream, byte per byte while the program runs
+    pub fn set_stdin(&mut self, is: Box<dyn Read>) {
+        self.ctx.set_stdin(is);
+    }
+
+    /// Write stdout to a stream, flushed after every byte
+    /// stdout() is then always empty
+    pub fn set_stdout(&mut self, os: Box<dyn Write>) {
+        self.ctx.set_stdout(os);
     }
 
     /// Returns the output of the program
+    /// Empty if stdout is a stream (see set_stdout)
     pub fn stdout(&self) -> &[u8] {
-        &self.stdout
+        self.ctx.stdout()
+    }
+
+    /// Returns the debug location of an instruction, if it has one
+    pub fn ins_loc(
+        &self,
+        fun: ir::FunctionId,
+        bb: ir::BasicBlockId,
+        ins_idx: usize,
+    ) -> Option<&ir::DebugLoc> {
+        let bb = self.code.get_fun(fun)?.get_basic_block(bb);
+        if ins_idx < bb.size() {
+            bb.get_ins_loc(ins_idx)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the debug location of the next instruction to be executed, if it has one
+    /// After a panic of the runtime, it's the location of the faulting instruction
+    pub fn current_loc(&self) -> Option<&ir::DebugLoc> {
+        let addr = self.call_stack.last()?;
+        self.ins_loc(addr.fun, addr.bb, addr.pos)
     }
 
     fn get_ins(&self, addr: &CodeAddress) -> &ir::Ins {
@@ -267,32 +685,62 @@ impl Runtime {
         addr.pos += 1;
     }
 
-    fn get_mem(&self, addr: &MemAddress) -> &RTVal {
-        self.frames
-            .get(addr.frame_idx())
-            .expect("Failed to access memory: invalid frame index")
+    // Returns the frame of the memory address (or the globals)
+    fn mem_frame(&self, frame_idx: usize) -> Option<&Frame> {
+        if frame_idx == GLOBALS_FRAME {
+            Some(&self.globals)
+        } else {
+            self.frames.get(frame_idx)
+        }
+    }
+
+    fn mem_frame_mut(&mut self, frame_idx: usize) -> Option<&mut Frame> {
+        if frame_idx == GLOBALS_FRAME {
+            Some(&mut self.globals)
+        } else {
+            self.frames.get_mut(frame_idx)
+        }
+    }
+
+    // Returns the bytes of a value of `width` in memory
+    // Fails if the address isn't an i32, if there is no frame at its index,
+    // or if the bytes aren't all inside the locals of the frame (checked in all modes)
+    fn get_mem(&self, addr: &MemAddress, width: ir::IntWidth) -> Result<&[u8], MemCheckErrorKind> {
+        let range = mem_range(addr, width)?;
+        self.mem_frame(addr.frame_idx())
+            .ok_or(MemCheckErrorKind::InvalidAddress)?
             .locals
-            .get(addr.local_idx())
-            .expect("Failed to access memory: invalid local index")
+            .get(range)
+            .ok_or(MemCheckErrorKind::AddressOutOfBounds)
     }
 
-    fn get_mem_mut(&mut self, addr: &MemAddress) -> &mut RTVal {
-        self.frames
-            .get_mut(addr.frame_idx())
-            .expect("Failed to access memory: invalid frame index")
+    fn get_mem_mut(
+        &mut self,
+        addr: &MemAddress,
+        width: ir::IntWidth,
+    ) -> Result<&mut [u8], MemCheckErrorKind> {
+        let range = mem_range(addr, width)?;
+        self.mem_frame_mut(addr.frame_idx())
+            .ok_or(MemCheckErrorKind::InvalidAddress)?
             .locals
-            .get_mut(addr.local_idx())
-            .expect("Failed to access memory: invalid local index")
+            .get_mut(range)
+            .ok_or(MemCheckErrorKind::AddressOutOfBounds)
     }
 
-    // Load 32b data from memory
-    fn load(&self, addr: &MemAddress) -> RTVal {
-        *self.get_mem(addr)
+    // Load data of `width` from memory
+    fn load(&self, addr: &MemAddress, width: ir::IntWidth) -> Result<RTVal, MemCheckErrorKind> {
+        Ok(read_val(self.get_mem(addr, width)?, width))
     }
 
-    // Store 32n data to memory
-    fn store(&mut self, addr: &MemAddress, val: RTVal) {
-        *self.get_mem_mut(addr) = val;
+    // Store data of `width` to memory
+    fn store(
+        &mut self,
+        addr: &MemAddress,
+        width: ir::IntWidth,
+        val: RTVal,
+    ) -> Result<(), MemCheckErrorKind> {
+        write_val(self.get_mem_mut(addr, width)?, width, val);
+        Ok(())
     }
 
     // Get register value on the current frame
@@ -302,7 +750,109 @@ impl Runtime {
 
     // Get register value on the current frame
     fn set_reg(&mut self, reg: ir::RegId, val: RTVal) {
-        self.frames.last_mut().unwrap().set_reg(reg, val);
+        if let Some(tracer) = &mut self.tracer {
+            tracer.trace_reg_write(reg, val.0);
+        }
+        let frame = self.frames.last_mut().unwrap();
+        frame.set_reg(reg, val);
+        if self.memcheck {
+            frame.regs_tag.remove(&reg);
+        }
+    }
+
+    // Get the address tag of a register on the current frame (memcheck mode)
+    fn get_reg_tag(&self, reg: ir::RegId) -> Option<PtrTag> {
+        self.frames.last().unwrap().regs_tag.get(&reg).copied()
+    }
+
+    // Set the address tag of a register on the current frame (memcheck mode)
+    fn set_reg_tag(&mut self, reg: ir::RegId, tag: Option<PtrTag>) {
+        if !self.memcheck {
+            return;
+        }
+        let frame = self.frames.last_mut().unwrap();
+        match tag {
+            Some(tag) => frame.regs_tag.insert(reg, tag),
+            None => frame.regs_tag.remove(&reg),
+        };
+    }
+
+    fn push_frame(&mut self, mut frame: Frame) {
+        if self.frames.len() == FUNCTIONS_FRAME {
+            panic!("Failed to call function: stack overflow");
+        }
+        frame.gen = self.next_frame_gen;
+        self.next_frame_gen += 1;
+        self.frames.push(frame);
+    }
+
+    // Values of all register operands read by an instruction (or the constant for movi)
+    fn ins_operands(&self, ins: &ir::Ins) -> Vec<i64> {
+        if let ir::Ins::Movi(ins) = ins {
+            return vec![ins.const_val()];
+        }
+        let mut regs = vec![];
+        ins.get_registers_read(&mut regs);
+        regs.iter().map(|reg| self.get_reg(*reg).0).collect()
+    }
+
+    // Check an instruction before running it (memcheck mode)
+    fn memcheck_ins(&self, ins: &ir::Ins) -> Result<(), MemCheckErrorKind> {
+        let frame = self.frames.last().unwrap();
+        if let ir::Ins::Ret(_) = ins {
+            return Ok(());
+        }
+        let mut regs = vec![];
+        ins.get_registers_read(&mut regs);
+        if let Some(reg) = regs.into_iter().find(|reg| !frame.regs.contains_key(reg)) {
+            return Err(MemCheckErrorKind::UninitRegister(reg));
+        }
+
+        match ins {
+            ir::Ins::Load(ins) => {
+                let addr = self.memcheck_address(ins.src(), ins.width())?;
+                let idx = addr.local_idx();
+                let init = &self.mem_frame(addr.frame_idx()).unwrap().locals_init;
+                if !init[idx..idx + ins.width().bytes()].iter().all(|x| *x) {
+                    return Err(MemCheckErrorKind::UninitLocal);
+                }
+            }
+            ir::Ins::Store(ins) => {
+                self.memcheck_address(ins.dst(), ins.width())?;
+            }
+            ir::Ins::Callr(ins) => {
+                let addr = MemAddress(self.get_reg(ins.fptr()));
+                match addr.fun() {
+                    Some(fun) if self.code.get_fun(fun).is_some() => {}
+                    _ => return Err(MemCheckErrorKind::InvalidFunctionAddress),
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    // Check that a register contains a valid address for an access of `width` (memcheck mode)
+    fn memcheck_address(
+        &self,
+        reg: ir::RegId,
+        width: ir::IntWidth,
+    ) -> Result<MemAddress, MemCheckErrorKind> {
+        let tag = self
+            .get_reg_tag(reg)
+            .ok_or(MemCheckErrorKind::InvalidAddress)?;
+        let addr = MemAddress(self.get_reg(reg));
+
+        let frame = match self.mem_frame(tag.frame_idx) {
+            Some(frame) if frame.gen == tag.gen => frame,
+            _ => return Err(MemCheckErrorKind::UseAfterReturn),
+        };
+        if addr.frame_idx() != tag.frame_idx
+            || addr.local_idx() + width.bytes() > frame.locals.len()
+        {
+            return Err(MemCheckErrorKind::AddressOutOfBounds);
+        }
+        Ok(addr)
     }
 
     fn begin_of_fun(&self, fun_id: ir::FunctionId) -> CodeAddress {
@@ -315,29 +865,29 @@ impl Runtime {
         }
     }
 
-    fn exec_ins(&mut self, ins: ir::Ins) {
-        /*
-        println!(
-                "exec ins {:?} at {:?}",
-                ins,
-                self.call_stack.last().unwrap()
-            );
-        */
-
+    // Returns an error if the instruction can't be executed (it's not executed)
+    fn exec_ins(&mut self, ins: ir::Ins) -> Result<(), MemCheckErrorKind> {
         self.ins_status = None;
         match ins {
             ir::Ins::Movi(ins) => self.exec_ins_movi(ins),
             ir::Ins::Movr(ins) => self.exec_ins_movr(ins),
-            ir::Ins::Load(ins) => self.exec_ins_load(ins),
-            ir::Ins::Store(ins) => self.exec_ins_store(ins),
+            ir::Ins::Load(ins) => self.exec_ins_load(ins)?,
+            ir::Ins::Store(ins) => self.exec_ins_store(ins)?,
             ir::Ins::Alloca(ins) => self.exec_ins_alloca(ins),
+            ir::Ins::Gaddr(ins) => self.exec_ins_gaddr(ins),
             ir::Ins::Opbin(ins) => self.exec_ins_opbin(ins),
             ir::Ins::Cmpbin(ins) => self.exec_ins_cmpbin(ins),
+            ir::Ins::Conv(ins) => self.exec_ins_conv(ins),
             ir::Ins::Jump(ins) => self.exec_ins_jump(ins),
             ir::Ins::Br(ins) => self.exec_ins_br(ins),
+            ir::Ins::Switch(ins) => self.exec_ins_switch(ins),
+            ir::Ins::Jumpt(ins) => self.exec_ins_jumpt(ins),
             ir::Ins::Call(ins) => self.exec_ins_call(ins),
+            ir::Ins::Funaddr(ins) => self.exec_ins_funaddr(ins),
+            ir::Ins::Callr(ins) => self.exec_ins_callr(ins)?,
             ir::Ins::Ret(ins) => self.exec_ins_ret(ins),
         }
+        Ok(())
     }
 
     fn exec_ins_movi(&mut self, ins: ir::InsMovi) {
@@ -346,79 +896,163 @@ impl Runtime {
     }
 
     fn exec_ins_movr(&mut self, ins: ir::InsMovr) {
+        let tag = self.get_reg_tag(ins.src());
         self.set_reg(ins.dst(), self.get_reg(ins.src()));
+        self.set_reg_tag(ins.dst(), tag);
         self.next_ins();
     }
 
-    fn exec_ins_load(&mut self, ins: ir::InsLoad) {
+    fn exec_ins_load(&mut self, ins: ir::InsLoad) -> Result<(), MemCheckErrorKind> {
+        let width = ins.width();
         let src_addr = MemAddress(self.get_reg(ins.src()));
-        self.set_reg(ins.dst(), self.load(&src_addr));
+        let val = self.load(&src_addr, width)?;
+        self.set_reg(ins.dst(), val);
+        if self.memcheck {
+            let frame = self.mem_frame(src_addr.frame_idx()).unwrap();
+            let tag = frame.local_tag(src_addr.local_idx(), width);
+            self.set_reg_tag(ins.dst(), tag);
+        }
         self.next_ins();
-        /*
-        println!(
-                "load r{}, r{} ({})",
-                ins.dst().0,
-                ins.src().0,
-                self.load(&src_addr).0,
-            );
-        */
+        Ok(())
     }
 
-    fn exec_ins_store(&mut self, ins: ir::InsStore) {
+    fn exec_ins_store(&mut self, ins: ir::InsStore) -> Result<(), MemCheckErrorKind> {
+        let width = ins.width();
         let dst_addr = MemAddress(self.get_reg(ins.dst()));
-        self.store(&dst_addr, self.get_reg(ins.src()));
+        let val = self.get_reg(ins.src());
+        self.store(&dst_addr, width, val)?;
+        if let Some(tracer) = &mut self.tracer {
+            tracer.trace_local_write((dst_addr.0).0 as i32, width.sign_extend(val.0));
+        }
+        if self.memcheck {
+            let tag = match width {
+                ir::IntWidth::I32 => self.get_reg_tag(ins.src()),
+                _ => None,
+            };
+            let frame = self.mem_frame_mut(dst_addr.frame_idx()).unwrap();
+            let idx = dst_addr.local_idx();
+            for pos in idx..idx + width.bytes() {
+                frame.locals_init[pos] = true;
+                frame.locals_tag[pos] = tag;
+            }
+        }
         self.next_ins();
-        /*
-        println!(
-                "store r{}, r{} ({})",
-                ins.dst().0,
-                ins.src().0,
-                self.get_reg(ins.src()).0,
-            );
-        */
+        Ok(())
     }
 
     fn exec_ins_alloca(&mut self, ins: ir::InsAlloca) {
         let frame_idx = self.frames.len() - 1;
-        let local_idx = self.frames.last_mut().unwrap().alloca();
+        let local_idx = self
+            .frames
+            .last_mut()
+            .unwrap()
+            .alloca(ins.bytes(), ins.width().bytes());
         let addr = MemAddress::new(frame_idx, local_idx);
         self.set_reg(ins.dst(), addr.0);
+        let gen = self.frames[frame_idx].gen;
+        self.set_reg_tag(ins.dst(), Some(PtrTag { frame_idx, gen }));
+        self.next_ins();
+    }
+
+    fn exec_ins_gaddr(&mut self, ins: ir::InsGaddr) {
+        let offset = *self
+            .globals_offsets
+            .get(&ins.global())
+            .expect("Failed to take global address: unknown global");
+        let addr = MemAddress::new(GLOBALS_FRAME, offset);
+        self.set_reg(ins.dst(), addr.0);
+        let tag = PtrTag {
+            frame_idx: GLOBALS_FRAME,
+            gen: self.globals.gen,
+        };
+        self.set_reg_tag(ins.dst(), Some(tag));
         self.next_ins();
     }
 
     fn exec_ins_opbin(&mut self, ins: ir::InsOpbin) {
-        let src1 = Wrapping(self.get_reg(ins.src1()).0);
-        let src2 = Wrapping(self.get_reg(ins.src2()).0);
+        let width = ins.width();
+        let src1 = width.sign_extend(self.get_reg(ins.src1()).0);
+        let src2 = width.sign_extend(self.get_reg(ins.src2()).0);
+        // address arithmetic keeps the tag (address + offset, offset + address, address - offset)
+        let tag = match (
+            ins.kind(),
+            self.get_reg_tag(ins.src1()),
+            self.get_reg_tag(ins.src2()),
+        ) {
+            (ir::InsOpbinKind::Add, Some(tag), None)
+            | (ir::InsOpbinKind::Add, None, Some(tag))
+            | (ir::InsOpbinKind::Sub, Some(tag), None) => Some(tag),
+            _ => None,
+        };
+
+        // shifts only use the lowest bits of src2 (5 bits for i32)
+        let shift = src2 as u32 & (width.bits() - 1);
 
+        // computed on 64 bits, and truncated to the width
         let res = match ins.kind() {
-            ir::InsOpbinKind::Add => src1 + src2,
-            ir::InsOpbinKind::Sub => src1 - src2,
-            ir::InsOpbinKind::Mul => src1 * src2,
-            ir::InsOpbinKind::Div => src1 / src2,
-            ir::InsOpbinKind::Mod => src1 % src2,
+            ir::InsOpbinKind::Add => src1.wrapping_add(src2),
+            ir::InsOpbinKind::Sub => src1.wrapping_sub(src2),
+            ir::InsOpbinKind::Mul => src1.wrapping_mul(src2),
+            ir::InsOpbinKind::Div => src1.wrapping_div(src2),
+            ir::InsOpbinKind::Mod => src1.wrapping_rem(src2),
+            ir::InsOpbinKind::And => src1 & src2,
+            ir::InsOpbinKind::Or => src1 | src2,
+            ir::InsOpbinKind::Xor => src1 ^ src2,
+            ir::InsOpbinKind::Shl => src1 << shift,
+            ir::InsOpbinKind::Shr => (width.zero_extend(src1) >> shift) as i64,
+            ir::InsOpbinKind::Sar => src1 >> shift,
         };
 
-        self.set_reg(ins.dst(), RTVal(res.0));
+        self.set_reg(ins.dst(), RTVal(width.sign_extend(res)));
+        self.set_reg_tag(ins.dst(), tag);
         self.next_ins();
     }
 
     fn exec_ins_cmpbin(&mut self, ins: ir::InsCmpbin) {
-        let src1 = self.get_reg(ins.src1()).0;
-        let src2 = self.get_reg(ins.src2()).0;
+        let width = ins.width();
+        let src1 = width.sign_extend(self.get_reg(ins.src1()).0);
+        let src2 = width.sign_extend(self.get_reg(ins.src2()).0);
 
         let res = match ins.kind() {
             ir::InsCmpbinKind::Eq => src1 == src2,
+            ir::InsCmpbinKind::Ne => src1 != src2,
             ir::InsCmpbinKind::Lt => src1 < src2,
+            ir::InsCmpbinKind::Le => src1 <= src2,
             ir::InsCmpbinKind::Gt => src1 > src2,
-        } as i32;
+            ir::InsCmpbinKind::Ge => src1 >= src2,
+            ir::InsCmpbinKind::Ult => width.zero_extend(src1) < width.zero_extend(src2),
+            ir::InsCmpbinKind::Ule => width.zero_extend(src1) <= width.zero_extend(src2),
+            ir::InsCmpbinKind::Ugt => width.zero_extend(src1) > width.zero_extend(src2),
+            ir::InsCmpbinKind::Uge => width.zero_extend(src1) >= width.zero_extend(src2),
+        } as i64;
 
         self.set_reg(ins.dst(), RTVal(res));
         self.next_ins();
     }
 
+    fn exec_ins_conv(&mut self, ins: ir::InsConv) {
+        let src = self.get_reg(ins.src()).0;
+        let res = match ins.kind() {
+            ir::InsConvKind::Sext => ins.from().sign_extend(src),
+            ir::InsConvKind::Zext => ins.from().zero_extend(src) as i64,
+            ir::InsConvKind::Trunc => src,
+        };
+
+        self.set_reg(ins.dst(), RTVal(ins.to().sign_extend(res)));
+        self.next_ins();
+    }
+
+    // Notify the profiler of a branch from the current basic block to `dst`
+    fn profile_edge(&mut self, dst: ir::BasicBlockId) {
+        if let Some(profiler) = &mut self.profiler {
+            let addr = self.call_stack.last().unwrap();
+            profiler.on_edge(addr.fun, addr.bb, dst);
+        }
+    }
+
     fn exec_ins_jump(&mut self, ins: ir::InsJump) {
+        self.profile_edge(ins.dst());
         self.call_stack.last_mut().unwrap().jump_to_bb(ins.dst());
-        //println!("jump L{}", ins.dst().0);
     }
 
     fn exec_ins_br(&mut self, ins: ir::InsBr) {
@@ -428,34 +1062,82 @@ impl Runtime {
         } else {
             ins.dst_false()
         };
+        self.profile_edge(next_bb);
+        self.call_stack.last_mut().unwrap().jump_to_bb(next_bb);
+    }
+
+    fn exec_ins_switch(&mut self, ins: ir::InsSwitch) {
+        let next_bb = ins.target(self.get_reg(ins.src()).0);
+        self.profile_edge(next_bb);
+        self.call_stack.last_mut().unwrap().jump_to_bb(next_bb);
+    }
+
+    fn exec_ins_jumpt(&mut self, ins: ir::InsJumpt) {
+        let idx = self.get_reg(ins.src()).0;
+        let next_bb = ins.target(idx).unwrap_or_else(|| {
+            panic!(
+                "Failed to exec jumpt instruction: index {} outside of the table",
+                ins.width().zero_extend(idx)
+            )
+        });
+        self.profile_edge(next_bb);
         self.call_stack.last_mut().unwrap().jump_to_bb(next_bb);
-        /*
-        println!(
-                "br r{}, L{}, L{}, (L{})",
-                ins.src().0,
-                ins.dst_true().0,
-                ins.dst_false().0,
-                next_bb.0
-            );
-        */
     }
 
     fn exec_ins_call(&mut self, ins: ir::InsCall) {
-        let args = ins.args().iter().map(|x| self.get_reg(*x)).collect();
-        let fun = self.code.get_fun(ins.fun()).expect(&format!(
-            "Failed to call function: unkown function address {}",
-            ins.fun().0
-        ));
+        self.call_fun(ins.fun(), ins.args(), ins.dst());
+    }
+
+    fn exec_ins_funaddr(&mut self, ins: ir::InsFunaddr) {
+        let addr = MemAddress::from_fun(ins.fun());
+        self.set_reg(ins.dst(), addr.0);
+        self.next_ins();
+    }
+
+    fn exec_ins_callr(&mut self, ins: ir::InsCallr) -> Result<(), MemCheckErrorKind> {
+        let addr = MemAddress(self.get_reg(ins.fptr()));
+        match addr.fun() {
+            Some(fun) if self.code.get_fun(fun).is_some() => {
+                self.call_fun(fun, ins.args(), ins.dst());
+                Ok(())
+            }
+            _ => Err(MemCheckErrorKind::InvalidFunctionAddress),
+        }
+    }
+
+    // Call a function (or a native), the result will be stored in dst
+    fn call_fun(&mut self, fun_id: ir::FunctionId, args_regs: &[ir::RegId], dst: ir::RegId) {
+        let args: Vec<_> = args_regs.iter().map(|x| self.get_reg(*x)).collect();
+        let fun = self.code.get_fun(fun_id).unwrap_or_else(|| {
+            panic!(
+                "Failed to call function: unknown function address {}",
+                fun_id.0
+            )
+        });
+        if let Some(profiler) = &mut self.profiler {
+            if fun.is_extern() {
+                profiler.on_native_call(fun_id);
+            } else {
+                profiler.on_enter(fun_id);
+            }
+        }
         if fun.is_extern() {
-            let ret = self.call_native(ins.fun(), args);
-            self.set_reg(ins.dst(), ret);
+            let ret = self.call_native(fun_id, args);
+            self.set_reg(dst, ret);
             self.next_ins();
             return;
         }
 
-        self.frames.push(Frame::new_from_call(&args, ins.dst()));
-        self.call_stack.push(self.begin_of_fun(ins.fun()));
-        //println!("call F{}", ins.fun().0);
+        if let Some(tracer) = &mut self.tracer {
+            let args_vals: Vec<_> = args.iter().map(|x| x.0).collect();
+            tracer.trace_call(fun_id, &args_vals);
+        }
+        let args_tags: Vec<_> = args_regs.iter().map(|x| self.get_reg_tag(*x)).collect();
+        self.push_frame(Frame::new_from_call(&args, dst));
+        for (idx, tag) in args_tags.into_iter().enumerate() {
+            self.set_reg_tag(ir::RegId(idx), tag);
+        }
+        self.call_stack.push(self.begin_of_fun(fun_id));
     }
 
     fn exec_ins_ret(&mut self, ins: ir::InsRet) {
@@ -464,109 +1146,80 @@ impl Runtime {
         }
 
         let ret_val = self.get_reg(ins.src());
+        let ret_tag = self.get_reg_tag(ins.src());
+        let ret_init = self.frames.last().unwrap().regs.contains_key(&ins.src());
+        if let Some(tracer) = &mut self.tracer {
+            tracer.trace_ret(ret_val.0);
+        }
         let ret_reg = self.frames.last().unwrap().get_ret_reg();
         self.frames.pop();
         self.call_stack.pop();
+        if let Some(profiler) = &mut self.profiler {
+            profiler.on_leave();
+        }
         self.next_ins();
         self.set_reg(ret_reg, ret_val);
-    }
-
-    fn call_native(&mut self, fun: ir::FunctionId, args: Vec<RTVal>) -> RTVal {
-        match fun.0 {
-            257 => self.call_native_putc(args),
-            258 => self.call_native_exit(args),
-            259 => self.call_native_getc(args),
-            260 => self.call_native_fmemget(args),
-            261 => self.call_native_fmemset(args),
-            262 => self.call_native_fmemcpy(args),
-            _ => panic!("Failed to called extern function: unknown id {}", fun.0),
+        self.set_reg_tag(ret_reg, ret_tag);
+        if self.memcheck && !ret_init {
+            // returning an uninitialized value is allowed (eg: void functions), it stays uninitialized
+            self.frames.last_mut().unwrap().regs.remove(&ret_reg);
         }
     }
 
-    fn call_native_putc(&mut self, args: Vec<RTVal>) -> RTVal {
-        if args.len() != 1 {
-            panic!(
-                "Failed to call putc: expected 1 argument, got {}",
-                args.len()
-            )
-        }
-
-        let bval = args[0].0 as u8;
-        self.stdout.push(bval);
-        RTVal(0)
-    }
-
-    fn call_native_exit(&mut self, args: Vec<RTVal>) -> RTVal {
-        if args.len() != 1 {
-            panic!(
-                "Failed to call exit: expected 1 argument, got {}",
-                args.len()
-            )
-        }
-
-        let exit_val = args[0].0 as u8;
-        self.ins_status = Some(ExitCode(exit_val));
-        RTVal(0)
-    }
-
-    fn call_native_getc(&mut self, args: Vec<RTVal>) -> RTVal {
-        if args.len() != 0 {
-            panic!(
-                "Failed to call getc: expected 0 argument, got {}",
-                args.len()
-            )
-        }
+    // Native functions only work with i32 values
+    fn call_native(&mut self, fun: ir::FunctionId, args: Vec<RTVal>) -> RTVal {
+        let args: Vec<_> = args.iter().map(|x| x.0 as i32).collect();
+        let ret = self.natives.call(fun, &mut self.ctx, &args);
 
-        match self.stdin.get(self.stdin_pos) {
-            Some(bval) => {
-                self.stdin_pos += 1;
-                RTVal(*bval as i32)
+        if let Some(tracer) = &mut self.tracer {
+            tracer.trace_native(fun, &args, ret);
+            for (addr, val) in self.ctx.take_fmem_log() {
+                tracer.trace_fmem_write(addr, val);
             }
-            None => RTVal(-1), //eof
         }
-    }
-
-    fn call_native_fmemget(&mut self, args: Vec<RTVal>) -> RTVal {
-        if args.len() != 1 {
-            panic!(
-                "Failed to call fmemget: expected 1 argument, got {}",
-                args.len()
-            )
+        if let Some(code) = self.ctx.take_exit_code() {
+            self.ins_status = Some(ExitCode(code));
         }
-
-        let pos = args[0].0;
-        let val = self.fmem.load(pos);
-        //println!("get @{} => {}", pos, val);
-        RTVal(val)
+        RTVal(ret as i64)
     }
+}
 
-    fn call_native_fmemset(&mut self, args: Vec<RTVal>) -> RTVal {
-        if args.len() != 2 {
-            panic!(
-                "Failed to call fmemdet: expected 2 arguments, got {}",
-                args.len()
-            )
-        }
+// Read a value of `width` from little-endian bytes
+fn read_val(data: &[u8], width: ir::IntWidth) -> RTVal {
+    let mut buf = [0; 8];
+    buf[..width.bytes()].copy_from_slice(&data[..width.bytes()]);
+    RTVal(width.sign_extend(i64::from_le_bytes(buf)))
+}
 
-        let pos = args[0].0;
-        let val = args[1].0;
-        self.fmem.store(pos, val);
-        //println!("set @{}, {}", pos, val);
-        RTVal(0)
-    }
+// Write a value of `width` to little-endian bytes
+fn write_val(data: &mut [u8], width: ir::IntWidth, val: RTVal) {
+    data[..width.bytes()].copy_from_slice(&val.0.to_le_bytes()[..width.bytes()]);
+}
 
-    fn call_native_fmemcpy(&mut self, args: Vec<RTVal>) -> RTVal {
-        if args.len() != 3 {
-            panic!(
-                "Failed to call fmemdet: expected 3 arguments, got {}",
-                args.len()
-            )
-        }
+// Range of the bytes of a value of `width` in the locals of the address frame
+// Fails if the address isn't an i32 (the frame index would be truncated)
+fn mem_range(
+    addr: &MemAddress,
+    width: ir::IntWidth,
+) -> Result<std::ops::Range<usize>, MemCheckErrorKind> {
+    let val = (addr.0).0;
+    if val != val as i32 as i64 {
+        return Err(MemCheckErrorKind::InvalidAddress);
+    }
+    let idx = addr.local_idx();
+    Ok(idx..idx + width.bytes())
+}
 
-        let dst = args[0].0;
-        let src = args[1].0;
-        let len = args[2].0;
-        self.fmem.copy(dst, src, len);
-        RTVal(0)
+fn memcheck_error(
+    kind: MemCheckErrorKind,
+    addr: &CodeAddress,
+    loc: Option<ir::DebugLoc>,
+) -> MemCheckError {
+    MemCheckError {
+        kind,
+        fun: addr.fun,
+        bb: addr.bb,
+        ins_idx: addr.pos,
+        loc,
     }
 }
@@ -0,0 +1,271 @@
+// Saved state of the runtime (see Runtime::snapshot and Runtime::restore)
+//
+// A snapshot contains everything needed to continue the execution from the same point:
+// - the frames (registers and local variables), the globals and the call stack
+// - the number of executed instructions
+// - the position in stdin (stdin data isn't saved, the runtime must be restored with the same input)
+// - the program output
+// - the flat memory, and the state of the fmem heap allocator
+//
+// The flat memory is encoded as a list of runs of non-zero words:
+// most programs only use a few words of the 16M words flat memory.
+//
+// Snapshots can be written to / read from a binary file.
+// All numbers are little-endian, lists are prefixed by their size:
+//   magic "IR3ASNAP", version (u32)
+//   steps (u64), status (i32, -1 if none), next frame generation (u64)
+//   frames: [ret_reg (u32), generation (u64), registers: [(reg u32, val i64)], locals: [u8]]
+//   globals: [u8]
+//   call stack: [(fun u32, bb u32, pos u32)]
+//   stdin position (u64), stdout: [u8]
+//   fmem: [(addr i32, words: [i32])]
+//   heap end (i32), heap regions: [(addr i32, size i32, live u8)]
+//
+// The profiler, tracer, memcheck shadow state and opened host files aren't saved.
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+
+const MAGIC: &[u8; 8] = b"IR3ASNAP";
+const VERSION: u32 = 3;
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct FrameState {
+    pub ret_reg: usize,
+    pub gen: u64,
+    pub regs: Vec<(usize, i64)>,
+    pub locals: Vec<u8>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct HeapState {
+    pub end: i32,
+    pub regions: Vec<(i32, i32, bool)>,
+}
+
+/// Complete state of a Runtime at some point of the execution
+#[derive(Clone, Debug, PartialEq)]
+pub struct Snapshot {
+    pub(crate) steps: u64,
+    pub(crate) status: Option<u8>,
+    pub(crate) next_frame_gen: u64,
+    pub(crate) frames: Vec<FrameState>,
+    pub(crate) globals: Vec<u8>,
+    pub(crate) call_stack: Vec<(usize, usize, usize)>,
+    pub(crate) stdin_pos: usize,
+    pub(crate) stdout: Vec<u8>,
+    pub(crate) fmem: Vec<(i32, Vec<i32>)>,
+    pub(crate) heap: HeapState,
+}
+
+impl Snapshot {
+    /// Number of instructions executed when the snapshot was taken
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Write the snapshot to a binary file
+    pub fn save(&self, path: &str) {
+        let f = File::create(path).expect("Failed to create snapshot file");
+        let mut os = BufWriter::new(f);
+        self.write(&mut os);
+        os.flush().expect("Failed to write snapshot file");
+    }
+
+    /// Read a snapshot from a binary file
+    pub fn load(path: &str) -> Self {
+        let f = File::open(path).expect("Failed to open snapshot file");
+        Self::read(&mut BufReader::new(f))
+    }
+
+    pub fn write(&self, os: &mut dyn Write) {
+        let mut os = Encoder { os };
+        os.bytes(MAGIC);
+        os.u32(VERSION);
+        os.u64(self.steps);
+        os.i32(self.status.map(|x| x as i32).unwrap_or(-1));
+        os.u64(self.next_frame_gen);
+
+        os.len(self.frames.len());
+        for frame in &self.frames {
+            os.u32(frame.ret_reg as u32);
+            os.u64(frame.gen);
+            os.len(frame.regs.len());
+            for (reg, val) in &frame.regs {
+                os.u32(*reg as u32);
+                os.u64(*val as u64);
+            }
+            os.len(frame.locals.len());
+            os.bytes(&frame.locals);
+        }
+        os.len(self.globals.len());
+        os.bytes(&self.globals);
+
+        os.len(self.call_stack.len());
+        for (fun, bb, pos) in &self.call_stack {
+            os.u32(*fun as u32);
+            os.u32(*bb as u32);
+            os.u32(*pos as u32);
+        }
+
+        os.u64(self.stdin_pos as u64);
+        os.len(self.stdout.len());
+        os.bytes(&self.stdout);
+
+        os.len(self.fmem.len());
+        for (addr, words) in &self.fmem {
+            os.i32(*addr);
+            os.i32_list(words);
+        }
+
+        os.i32(self.heap.end);
+        os.len(self.heap.regions.len());
+        for (addr, size, live) in &self.heap.regions {
+            os.i32(*addr);
+            os.i32(*size);
+            os.bytes(&[*live as u8]);
+        }
+    }
+
+    pub fn read(is: &mut dyn Read) -> Self {
+        let mut is = Decoder { is };
+        if &is.bytes(8)[..] != MAGIC {
+            panic!("Failed to read snapshot: invalid file format");
+        }
+        let version = is.u32();
+        if version != VERSION {
+            panic!("Failed to read snapshot: unsupported version {}", version);
+        }
+
+        let steps = is.u64();
+        let status = match is.i32() {
+            -1 => None,
+            x => Some(x as u8),
+        };
+        let next_frame_gen = is.u64();
+
+        let frames = (0..is.len())
+            .map(|_| {
+                let ret_reg = is.u32() as usize;
+                let gen = is.u64();
+                let regs = (0..is.len())
+                    .map(|_| (is.u32() as usize, is.u64() as i64))
+                    .collect();
+                let locals_len = is.len();
+                let locals = is.bytes(locals_len);
+                FrameState {
+                    ret_reg,
+                    gen,
+                    regs,
+                    locals,
+                }
+            })
+            .collect();
+        let globals_len = is.len();
+        let globals = is.bytes(globals_len);
+
+        let call_stack = (0..is.len())
+            .map(|_| (is.u32() as usize, is.u32() as usize, is.u32() as usize))
+            .collect();
+
+        let stdin_pos = is.u64() as usize;
+        let stdout_len = is.len();
+        let stdout = is.bytes(stdout_len);
+
+        let fmem = (0..is.len()).map(|_| (is.i32(), is.i32_list())).collect();
+
+        let heap_end = is.i32();
+        let regions = (0..is.len())
+            .map(|_| (is.i32(), is.i32(), is.bytes(1)[0] != 0))
+            .collect();
+
+        Snapshot {
+            steps,
+            status,
+            next_frame_gen,
+            frames,
+            globals,
+            call_stack,
+            stdin_pos,
+            stdout,
+            fmem,
+            heap: HeapState {
+                end: heap_end,
+                regions,
+            },
+        }
+    }
+}
+
+struct Encoder<'a> {
+    os: &'a mut dyn Write,
+}
+
+impl<'a> Encoder<'a> {
+    fn bytes(&mut self, data: &[u8]) {
+        self.os
+            .write_all(data)
+            .expect("Failed to write snapshot");
+    }
+
+    fn u32(&mut self, x: u32) {
+        self.bytes(&x.to_le_bytes());
+    }
+
+    fn u64(&mut self, x: u64) {
+        self.bytes(&x.to_le_bytes());
+    }
+
+    fn i32(&mut self, x: i32) {
+        self.bytes(&x.to_le_bytes());
+    }
+
+    fn len(&mut self, x: usize) {
+        self.u32(x as u32);
+    }
+
+    fn i32_list(&mut self, data: &[i32]) {
+        self.len(data.len());
+        for x in data {
+            self.i32(*x);
+        }
+    }
+}
+
+struct Decoder<'a> {
+    is: &'a mut dyn Read,
+}
+
+impl<'a> Decoder<'a> {
+    fn bytes(&mut self, len: usize) -> Vec<u8> {
+        let mut res = vec![0; len];
+        self.is
+            .read_exact(&mut res)
+            .expect("Failed to read snapshot: unexpected end of file");
+        res
+    }
+
+    fn u32(&mut self) -> u32 {
+        let mut buf = [0; 4];
+        buf.copy_from_slice(&self.bytes(4));
+        u32::from_le_bytes(buf)
+    }
+
+    fn u64(&mut self) -> u64 {
+        let mut buf = [0; 8];
+        buf.copy_from_slice(&self.bytes(8));
+        u64::from_le_bytes(buf)
+    }
+
+    fn i32(&mut self) -> i32 {
+        self.u32() as i32
+    }
+
+    fn len(&mut self) -> usize {
+        self.u32() as usize
+    }
+
+    fn i32_list(&mut self) -> Vec<i32> {
+        (0..self.len()).map(|_| self.i32()).collect()
+    }
+}
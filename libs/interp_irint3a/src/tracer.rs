@@ -0,0 +1,174 @@
+// Hooks to follow the execution of the runtime step by step
+//
+// A tracer is notified by the runtime for every executed instruction (with its operands values),
+// every register / memory write, every call / return, and at the exit of the program.
+//
+// TraceWriter writes all these events to a compact text trace file, one event per line:
+// - I <fun>:<bb>:<pos> <opname> <operands values...>   instruction executed
+// - R <reg> <val>                                      register write
+// - L <addr> <val>                                     local variable write (stack memory)
+// - M <addr> <val>                                     flat memory write
+// - C <fun> <args...>                                  call to a function defined in the module
+// - N <fun> <args...> -> <ret>                         call to a native function
+// - T <val>                                            return from a function
+// - X <code>                                           exit of the program
+// Lines starting with # are comments
+//
+// The format is the same than the interp_irintsm traces (except for the R lines, irintsm has P lines),
+// so traces of both interpreters can be compared at the calls / memory level
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use irint3a::ir;
+
+pub trait Tracer {
+    /// Called before executing an instruction, with the values of its register operands
+    fn trace_ins(
+        &mut self,
+        _fun: ir::FunctionId,
+        _bb: ir::BasicBlockId,
+        _pos: usize,
+        _ins: &ir::Ins,
+        _operands: &[i64],
+    ) {
+    }
+
+    fn trace_reg_write(&mut self, _reg: ir::RegId, _val: i64) {}
+
+    /// Write to stack memory (or globals), `val` is truncated to the width of the store
+    fn trace_local_write(&mut self, _addr: i32, _val: i64) {}
+
+    fn trace_fmem_write(&mut self, _addr: i32, _val: i32) {}
+
+    fn trace_call(&mut self, _fun: ir::FunctionId, _args: &[i64]) {}
+
+    fn trace_native(&mut self, _fun: ir::FunctionId, _args: &[i32], _ret: i32) {}
+
+    fn trace_ret(&mut self, _val: i64) {}
+
+    /// Last event of the program, the tracer must be flushed
+    fn trace_exit(&mut self, _code: i32) {}
+}
+
+/// Tracer that writes all events to a trace file
+pub struct TraceWriter {
+    os: Box<dyn Write>,
+}
+
+impl TraceWriter {
+    pub fn new(os: Box<dyn Write>) -> Self {
+        let mut res = TraceWriter { os };
+        writeln!(res.os, "# trace irint3a").unwrap();
+        res
+    }
+
+    /// Create a new trace file
+    pub fn create(path: &str) -> Self {
+        let os = File::create(path).expect("Failed to create trace file");
+        Self::new(Box::new(BufWriter::new(os)))
+    }
+
+    fn write_vals<T: std::fmt::Display>(&mut self, vals: &[T]) {
+        for val in vals {
+            write!(self.os, " {}", val).unwrap();
+        }
+    }
+}
+
+impl Tracer for TraceWriter {
+    fn trace_ins(
+        &mut self,
+        fun: ir::FunctionId,
+        bb: ir::BasicBlockId,
+        pos: usize,
+        ins: &ir::Ins,
+        operands: &[i64],
+    ) {
+        write!(self.os, "I {}:{}:{} {}", fun.0, bb.0, pos, ins_opname(ins)).unwrap();
+        self.write_vals(operands);
+        writeln!(self.os).unwrap();
+    }
+
+    fn trace_reg_write(&mut self, reg: ir::RegId, val: i64) {
+        writeln!(self.os, "R {} {}", reg.0, val).unwrap();
+    }
+
+    fn trace_local_write(&mut self, addr: i32, val: i64) {
+        writeln!(self.os, "L {} {}", addr, val).unwrap();
+    }
+
+    fn trace_fmem_write(&mut self, addr: i32, val: i32) {
+        writeln!(self.os, "M {} {}", addr, val).unwrap();
+    }
+
+    fn trace_call(&mut self, fun: ir::FunctionId, args: &[i64]) {
+        write!(self.os, "C {}", fun.0).unwrap();
+        self.write_vals(args);
+        writeln!(self.os).unwrap();
+    }
+
+    fn trace_native(&mut self, fun: ir::FunctionId, args: &[i32], ret: i32) {
+        write!(self.os, "N {}", fun.0).unwrap();
+        self.write_vals(args);
+        writeln!(self.os, " -> {}", ret).unwrap();
+    }
+
+    fn trace_ret(&mut self, val: i64) {
+        writeln!(self.os, "T {}", val).unwrap();
+    }
+
+    fn trace_exit(&mut self, code: i32) {
+        writeln!(self.os, "X {}", code).unwrap();
+        self.os.flush().expect("Failed to write trace file");
+    }
+}
+
+fn ins_opname(ins: &ir::Ins) -> &'static str {
+    match ins {
+        ir::Ins::Movi(_) => "movi",
+        ir::Ins::Movr(_) => "movr",
+        ir::Ins::Load(_) => "load",
+        ir::Ins::Store(_) => "store",
+        ir::Ins::Alloca(_) => "alloca",
+        ir::Ins::Gaddr(_) => "gaddr",
+        ir::Ins::Opbin(ins) => match ins.kind() {
+            ir::InsOpbinKind::Add => "add",
+            ir::InsOpbinKind::Sub => "sub",
+            ir::InsOpbinKind::Mul => "mul",
+            ir::InsOpbinKind::Div => "div",
+            ir::InsOpbinKind::Mod => "mod",
+            ir::InsOpbinKind::And => "and",
+            ir::InsOpbinKind::Or => "or",
+            ir::InsOpbinKind::Xor => "xor",
+            ir::InsOpbinKind::Shl => "shl",
+            ir::InsOpbinKind::Shr => "shr",
+            ir::InsOpbinKind::Sar => "sar",
+        },
+        ir::Ins::Cmpbin(ins) => match ins.kind() {
+            ir::InsCmpbinKind::Eq => "cmpeq",
+            ir::InsCmpbinKind::Lt => "cmplt",
+            ir::InsCmpbinKind::Gt => "cmpgt",
+            ir::InsCmpbinKind::Ne => "cmpne",
+            ir::InsCmpbinKind::Le => "cmple",
+            ir::InsCmpbinKind::Ge => "cmpge",
+            ir::InsCmpbinKind::Ult => "cmpult",
+            ir::InsCmpbinKind::Ule => "cmpule",
+            ir::InsCmpbinKind::Ugt => "cmpugt",
+            ir::InsCmpbinKind::Uge => "cmpuge",
+        },
+        ir::Ins::Conv(ins) => match ins.kind() {
+            ir::InsConvKind::Sext => "sext",
+            ir::InsConvKind::Zext => "zext",
+            ir::InsConvKind::Trunc => "trunc",
+        },
+        ir::Ins::Jump(_) => "jump",
+        ir::Ins::Br(_) => "br",
+        ir::Ins::Switch(_) => "switch",
+        ir::Ins::Jumpt(_) => "jumpt",
+        ir::Ins::Call(_) => "call",
+        ir::Ins::Funaddr(_) => "funaddr",
+        ir::Ins::Callr(_) => "callr",
+        ir::Ins::Ret(_) => "ret",
+    }
+}
@@ -1,9 +1,145 @@
+pub mod natives;
 pub mod runtime;
+pub mod tracer;
 
 #[cfg(test)]
 mod tests {
+
+    use super::*;
+
     #[test]
     fn it_works() {
         assert_eq!(2 + 2, 4);
     }
+
+    #[test]
+    fn run_bitops() {
+        let module = irintsm::irparser::Parser::from_file("../irintsm/tests/bitops.ir").build();
+        let (optimized, stats) = irintsm::peephole::optimize_module(&module);
+        assert!(stats.get("const_fold") > 0);
+
+        // the peephole optimizer must fold the constants like the interpreter computes them
+        for module in [module, optimized] {
+            let mut rt = runtime::Runtime::new(module);
+            assert_eq!(rt.run().get_val(), 0);
+            assert_eq!(rt.stdout(), b"MIOT?A\n1100111\n");
+        }
+    }
+
+    #[test]
+    fn trace_hello_42() {
+        let ps = irintsm::irparser::Parser::from_file("../irintsm/tests/hello_42.ir");
+        let module = ps.build();
+        let path = std::env::temp_dir().join("interp_irintsm_trace_hello_42.trace");
+        let path = path.to_str().unwrap();
+
+        let mut rt = runtime::Runtime::new(module);
+        rt.set_tracer(Box::new(tracer::TraceWriter::create(path)));
+        rt.run();
+        assert_eq!(rt.stdout(), b"42\n");
+
+        let trace = std::fs::read_to_string(path).unwrap();
+        let lines: Vec<_> = trace.lines().collect();
+        assert_eq!(lines[0], "# trace irintsm");
+        assert_eq!(lines[1], "I 0:0:0 const 42");
+        assert_eq!(lines[2], "P 42");
+        assert_eq!(*lines.last().unwrap(), "X 0");
+        let natives: Vec<_> = lines
+            .iter()
+            .filter(|l| l.starts_with("N 257 "))
+            .cloned()
+            .collect();
+        assert_eq!(natives, vec!["N 257 52 -> 0", "N 257 50 -> 0", "N 257 10 -> 0"]);
+        assert_eq!(lines.iter().filter(|l| l.starts_with("C 1 ")).count(), 3);
+    }
+
+    #[test]
+    fn custom_natives() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        let ps = irintsm::irparser::Parser::from_str(
+            "
+.declare 257
+.declare 258
+.declare 300
+
+.define 0
+0:
+  const 42
+  call %300, 1
+  call %257, 1
+  const 3
+  call %258, 1
+  ret
+",
+        );
+        let module = ps.build();
+
+        let printed = Rc::new(RefCell::new(vec![]));
+        let printed_ref = printed.clone();
+        let mut natives = natives::NativeRegistry::default();
+        natives.register(irintsm::ir::FunctionRef::new(300), "print_int", 1, move |_ctx, args| {
+            printed_ref.borrow_mut().push(args[0]);
+            65
+        });
+        // mock putc to ignore the output
+        natives.register(irintsm::ir::FunctionRef::new(257), "putc", 1, |_ctx, _args| 0);
+
+        let mut rt = runtime::Runtime::new_with_natives(module, natives);
+        assert_eq!(rt.run().get_val(), 3);
+        assert_eq!(*printed.borrow(), vec![42]);
+        assert_eq!(rt.stdout(), b"");
+    }
+
+    // Writer to a shared buffer, to check the output while the program runs
+    struct SharedWriter(std::rc::Rc<std::cell::RefCell<Vec<u8>>>);
+
+    impl std::io::Write for SharedWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn stream_io() {
+        // echo 2 chars, then exit
+        let ps = irintsm::irparser::Parser::from_str(
+            "
+.declare 257
+.declare 258
+.declare 259
+
+.define 0
+0:
+  call %259, 0
+  call %257, 1
+  pop
+  call %259, 0
+  call %257, 1
+  pop
+  const 0
+  call %258, 1
+  ret
+",
+        );
+        let module = ps.build();
+        let out = std::rc::Rc::new(std::cell::RefCell::new(vec![]));
+
+        let mut rt = runtime::Runtime::new(module);
+        rt.set_stdin(Box::new(std::io::Cursor::new(b"ab\n".to_vec())));
+        rt.set_stdout(Box::new(SharedWriter(out.clone())));
+
+        rt.step();
+        rt.step();
+        assert_eq!(*out.borrow(), b"a");
+        rt.run();
+        assert_eq!(*out.borrow(), b"ab");
+        assert_eq!(rt.stdout(), b"");
+    }
 }
@@ -0,0 +1,328 @@
+// Native functions are the extern functions of the module (declared but not defined)
+// They are implemented by Rust closures, stored in a NativeRegistry
+//
+// Each native has a name, a number of arguments (checked at every call), and is bound to a function id.
+// A native can be registered without id, and bound later to an id by its name
+//
+// Natives only have access to the NativeContext: stdin, stdout, flat memory, and exit of the program
+// The default registry implements the standard functions of the LExpr reference:
+// - 257 putc(c)
+// - 258 exit(code)
+// - 259 getc() (-1 if eof)
+// - 260 fmemget(addr)
+// - 261 fmemset(addr, val)
+// - 262 fmemcpy(dst, src, len)
+//
+// Optional natives to access host files, sandboxed to a root directory (see register_host_files):
+// - 263 fopen(path, mode) returns a file descriptor, or -1 if it fails
+//   path is the address in fmem of a string (one char per word, ending with 0, at most 4096 chars)
+//   mode is 0 for read, 1 for write (truncate), 2 for append
+//   the path must be relative to the root directory, without any ..,
+//   and must stay inside the root directory once the symbolic links are resolved (see interp_common::hostfiles)
+// - 264 fclose(fd) returns 0, or -1 if fd is invalid
+// - 265 fgetc(fd) returns the next byte, or -1 if eof or fd is invalid
+// - 266 fputc(fd, c) returns 0, or -1 if it fails
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::rc::Rc;
+
+use interp_common::fmem;
+use interp_common::hostfiles::HostFiles;
+use irintsm::ir;
+
+use crate::runtime::FlatMemory;
+
+// stdin is either preloaded in memory, or read byte per byte from a stream
+enum Input {
+    Buffer(Vec<u8>, usize),
+    Stream(Box<dyn Read>),
+}
+
+// stdout is either saved in memory, or written and flushed to a stream on every byte
+enum Output {
+    Buffer(Vec<u8>),
+    Stream(Box<dyn Write>),
+}
+
+/// State of the runtime accessible by the native functions
+pub struct NativeContext {
+    stdin: Input,
+    stdout: Output,
+    fmem: FlatMemory,
+    exit_code: Option<u8>,
+
+    // all fmem writes are saved when the runtime is traced
+    fmem_log: Option<Vec<(i32, i32)>>,
+}
+
+impl NativeContext {
+    pub(crate) fn new() -> Self {
+        NativeContext {
+            stdin: Input::Buffer(vec![], 0),
+            stdout: Output::Buffer(vec![]),
+            fmem: FlatMemory::new(),
+            exit_code: None,
+
+            fmem_log: None,
+        }
+    }
+
+    /// Read one byte from stdin, returns None if eof
+    pub fn getc(&mut self) -> Option<u8> {
+        match &mut self.stdin {
+            Input::Buffer(data, pos) => {
+                let res = data.get(*pos).copied();
+                if res.is_some() {
+                    *pos += 1;
+                }
+                res
+            }
+            Input::Stream(is) => read_byte(is.as_mut()),
+        }
+    }
+
+    /// Write one byte to stdout
+    pub fn putc(&mut self, c: u8) {
+        match &mut self.stdout {
+            Output::Buffer(data) => data.push(c),
+            Output::Stream(os) => {
+                os.write_all(&[c]).expect("Failed to write to stdout");
+                os.flush().expect("Failed to write to stdout");
+            }
+        }
+    }
+
+    /// Stop the program after the native call returns
+    pub fn exit(&mut self, code: u8) {
+        self.exit_code = Some(code);
+    }
+
+    pub fn fmem_load(&self, addr: i32) -> i32 {
+        self.fmem.load(addr)
+    }
+
+    /// Read a string stored in fmem, one char per word, ending with 0
+    /// Fails if the string is outside of fmem, or longer than fmem::MAX_STR_LEN
+    pub fn fmem_load_str(&self, addr: i32) -> Result<String, &'static str> {
+        fmem::load_str(|addr| self.fmem.load(addr), addr)
+    }
+
+    pub fn fmem_store(&mut self, addr: i32, val: i32) {
+        self.fmem.store(addr, val);
+        if let Some(log) = &mut self.fmem_log {
+            log.push((addr, val));
+        }
+    }
+
+    pub fn fmem_copy(&mut self, dst: i32, src: i32, len: i32) {
+        self.fmem.copy(dst, src, len);
+        if let Some(log) = &mut self.fmem_log {
+            for addr in dst..dst + len {
+                log.push((addr, self.fmem.load(addr)));
+            }
+        }
+    }
+
+    pub(crate) fn reset_stdin(&mut self, data: Vec<u8>) {
+        self.stdin = Input::Buffer(data, 0);
+    }
+
+    pub(crate) fn set_stdin(&mut self, is: Box<dyn Read>) {
+        self.stdin = Input::Stream(is);
+    }
+
+    pub(crate) fn set_stdout(&mut self, os: Box<dyn Write>) {
+        self.stdout = Output::Stream(os);
+    }
+
+    pub(crate) fn stdout(&self) -> &[u8] {
+        match &self.stdout {
+            Output::Buffer(data) => data,
+            Output::Stream(_) => &[],
+        }
+    }
+
+    pub(crate) fn reset_stdout(&mut self) {
+        if let Output::Buffer(data) = &mut self.stdout {
+            data.clear();
+        }
+    }
+
+    pub(crate) fn take_exit_code(&mut self) -> Option<u8> {
+        self.exit_code.take()
+    }
+
+    pub(crate) fn enable_fmem_log(&mut self) {
+        self.fmem_log = Some(vec![]);
+    }
+
+    pub(crate) fn take_fmem_log(&mut self) -> Vec<(i32, i32)> {
+        match &mut self.fmem_log {
+            Some(log) => std::mem::take(log),
+            None => vec![],
+        }
+    }
+}
+
+/// Implementation of a native function: called with the arguments values, returns the result
+pub type NativeFn = Box<dyn FnMut(&mut NativeContext, &[i32]) -> i32>;
+
+struct Native {
+    name: String,
+    arity: usize,
+    fun: NativeFn,
+}
+
+pub struct NativeRegistry {
+    natives: Vec<Native>,
+    ids: HashMap<ir::FunctionRef, usize>,
+}
+
+impl NativeRegistry {
+    /// Create a registry without any native function
+    pub fn new() -> Self {
+        NativeRegistry {
+            natives: vec![],
+            ids: HashMap::new(),
+        }
+    }
+
+    /// Register a native function bound to an id
+    /// Replaces the previous native with the same id or name
+    pub fn register<F>(&mut self, id: ir::FunctionRef, name: &str, arity: usize, fun: F)
+    where
+        F: FnMut(&mut NativeContext, &[i32]) -> i32 + 'static,
+    {
+        self.register_named(name, arity, fun);
+        self.bind(name, id);
+    }
+
+    /// Register a native function without id, it must be bound later to be called
+    /// Replaces the previous native with the same name
+    pub fn register_named<F>(&mut self, name: &str, arity: usize, fun: F)
+    where
+        F: FnMut(&mut NativeContext, &[i32]) -> i32 + 'static,
+    {
+        let native = Native {
+            name: name.to_string(),
+            arity,
+            fun: Box::new(fun),
+        };
+
+        match self.natives.iter().position(|x| x.name == name) {
+            Some(idx) => self.natives[idx] = native,
+            None => self.natives.push(native),
+        }
+    }
+
+    /// Bind the native function `name` to an id
+    pub fn bind(&mut self, name: &str, id: ir::FunctionRef) {
+        let idx = self
+            .natives
+            .iter()
+            .position(|x| x.name == name)
+            .unwrap_or_else(|| panic!("Failed to bind native: unknown function {}", name));
+        self.ids.insert(id, idx);
+    }
+
+    /// Register the natives to open, read and write host files (ids 263 to 266)
+    /// All paths are relative to the directory `root`
+    pub fn register_host_files(&mut self, root: &str) {
+        let files = Rc::new(RefCell::new(HostFiles::new(root)));
+
+        let files_ref = files.clone();
+        self.register(ir::FunctionRef::new(263), "fopen", 2, move |ctx, args| {
+            match ctx.fmem_load_str(args[0]) {
+                Ok(path) => files_ref.borrow_mut().open(&path, args[1]),
+                Err(_) => -1,
+            }
+        });
+        let files_ref = files.clone();
+        self.register(ir::FunctionRef::new(264), "fclose", 1, move |_ctx, args| {
+            files_ref.borrow_mut().close(args[0])
+        });
+        let files_ref = files.clone();
+        self.register(ir::FunctionRef::new(265), "fgetc", 1, move |_ctx, args| {
+            files_ref.borrow_mut().getc(args[0])
+        });
+        self.register(ir::FunctionRef::new(266), "fputc", 2, move |_ctx, args| {
+            files.borrow_mut().putc(args[0], args[1] as u8)
+        });
+    }
+
+    /// Returns the name of the native bound to `id`
+    pub fn get_name(&self, id: ir::FunctionRef) -> Option<&str> {
+        let idx = *self.ids.get(&id)?;
+        Some(&self.natives[idx].name)
+    }
+
+    /// Call the native bound to `id`
+    /// Panics if there is no native, or if the number of arguments is invalid
+    pub fn call(&mut self, id: ir::FunctionRef, ctx: &mut NativeContext, args: &[i32]) -> i32 {
+        let idx = *self
+            .ids
+            .get(&id)
+            .unwrap_or_else(|| panic!("Failed to called extern function: unknown id {}", id));
+        let native = &mut self.natives[idx];
+
+        if args.len() != native.arity {
+            panic!(
+                "Failed to call {}: expected {} argument{}, got {}",
+                native.name,
+                native.arity,
+                if native.arity == 1 { "" } else { "s" },
+                args.len()
+            );
+        }
+
+        (native.fun)(ctx, args)
+    }
+}
+
+impl Default for NativeRegistry {
+    /// Registry with all the standard functions
+    fn default() -> Self {
+        let mut res = NativeRegistry::new();
+
+        res.register(ir::FunctionRef::new(257), "putc", 1, |ctx, args| {
+            ctx.putc(args[0] as u8);
+            0
+        });
+        res.register(ir::FunctionRef::new(258), "exit", 1, |ctx, args| {
+            ctx.exit(args[0] as u8);
+            0
+        });
+        res.register(ir::FunctionRef::new(259), "getc", 0, |ctx, _args| match ctx.getc() {
+            Some(c) => c as i32,
+            None => -1, //eof
+        });
+        res.register(ir::FunctionRef::new(260), "fmemget", 1, |ctx, args| {
+            ctx.fmem_load(args[0])
+        });
+        res.register(ir::FunctionRef::new(261), "fmemset", 2, |ctx, args| {
+            ctx.fmem_store(args[0], args[1]);
+            0
+        });
+        res.register(ir::FunctionRef::new(262), "fmemcpy", 3, |ctx, args| {
+            ctx.fmem_copy(args[0], args[1], args[2]);
+            0
+        });
+
+        res
+    }
+}
+
+// Read one byte from a stream, None if eof
+fn read_byte(is: &mut dyn Read) -> Option<u8> {
+    let mut buf = [0u8; 1];
+    loop {
+        match is.read(&mut buf) {
+            Ok(0) => return None,
+            Ok(_) => return Some(buf[0]),
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => panic!("Failed to read stdin: {}", e),
+        }
+    }
+}
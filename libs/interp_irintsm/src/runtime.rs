@@ -1,9 +1,14 @@
 use std::collections::HashMap;
 use std::fs::File;
-use std::io::Read;
+use std::io::{Read, Write};
 use std::num::Wrapping;
 
+use interp_common::fmem::FLAT_MEMORY_SIZE;
 use irintsm::ir;
+use irintsm::ir::OperandsSizeEffect;
+
+use crate::natives::{NativeContext, NativeRegistry};
+use crate::tracer::Tracer;
 
 /// Represent a word value in the Runtime, it's always signed 32 bits integer
 #[derive(Clone, Copy, Debug)]
@@ -91,8 +96,6 @@ impl CodeAddress {
     }
 }
 
-const FLAT_MEMORY_SIZE: i32 = 16 * 1024 * 1024;
-
 pub struct FlatMemory {
     data: Vec<i32>,
 }
@@ -143,12 +146,10 @@ impl FlatMemory {
         }
     }
 
+    // The memory is allocated zeroed on the first store (the pages are only committed when they are written)
     fn lazy_init(&mut self) {
         if self.data.len() == 0 {
-            self.data = Vec::with_capacity(FLAT_MEMORY_SIZE as usize);
-            unsafe {
-                self.data.set_len(FLAT_MEMORY_SIZE as usize);
-            }
+            self.data = vec![0; FLAT_MEMORY_SIZE as usize];
         }
     }
 }
@@ -159,25 +160,30 @@ pub struct Runtime {
     call_stack: Vec<CodeAddress>,
     ins_status: Option<ExitCode>, //status of last executed instruction
 
-    stdin: Vec<u8>,
-    stdin_pos: usize,
-    stdout: Vec<u8>,
-    fmem: FlatMemory,
+    ctx: NativeContext,
+    natives: NativeRegistry,
+
+    tracer: Option<Box<dyn Tracer>>,
 }
 
 impl Runtime {
-    /// Create a new initialized runtime
+    /// Create a new initialized runtime, with the standard native functions
     pub fn new(code: ir::Module) -> Self {
+        Self::new_with_natives(code, NativeRegistry::default())
+    }
+
+    /// Create a new initialized runtime, with a custom registry of native functions
+    pub fn new_with_natives(code: ir::Module, natives: NativeRegistry) -> Self {
         let mut res = Runtime {
             code,
             frames: vec![],
             call_stack: vec![],
             ins_status: None,
 
-            stdin: vec![],
-            stdin_pos: 0,
-            stdout: vec![],
-            fmem: FlatMemory::new(),
+            ctx: NativeContext::new(),
+            natives,
+
+            tracer: None,
         };
         res.reset();
         res
@@ -187,7 +193,7 @@ impl Runtime {
     pub fn reset(&mut self) {
         self.frames.clear();
         self.call_stack.clear();
-        self.stdout.clear();
+        self.ctx.reset_stdout();
         self.ins_status = None;
 
         self.call_stack
@@ -199,7 +205,17 @@ impl Runtime {
     /// Returns an exitcode if the instruction calls exit
     pub fn step(&mut self) -> Option<ExitCode> {
         let ins = self.fetch_ins().clone();
+        let operands = self.tracer.as_ref().map(|_| self.ins_operands(&ins));
+        if let (Some(tracer), Some(operands)) = (&mut self.tracer, operands) {
+            let addr = self.call_stack.last().unwrap();
+            tracer.trace_ins(addr.fun, addr.bb, addr.ins_pos, &ins, &operands);
+        }
+
         self.exec_ins(ins);
+
+        if let (Some(tracer), Some(status)) = (&mut self.tracer, self.ins_status) {
+            tracer.trace_exit(status.get_val());
+        }
         self.ins_status
     }
 
@@ -214,22 +230,43 @@ impl Runtime {
 
     /// Set stdin stream from raw bytes data
     pub fn reset_stdin_raw(&mut self, data: &[u8]) {
-        self.stdin = Vec::from(data);
-        self.stdin_pos = 0;
+        self.ctx.reset_stdin(Vec::from(data));
     }
 
     /// Set stdin stream from binary file
     pub fn reset_stdin_path(&mut self, path: &str) {
         let mut f = File::open(path).expect("Failed to open stdin file");
-        self.stdin.clear();
-        f.read_to_end(&mut self.stdin)
-            .expect("Failed to read stdin file");
-        self.stdin_pos = 0;
+        let mut data = vec![];
+        f.read_to_end(&mut data).expect("Failed to read stdin file");
+        self.ctx.reset_stdin(data);
+    }
+
+    /// Read stdin from a stream, byte per byte while the program runs
+    pub fn set_stdin(&mut self, is: Box<dyn Read>) {
+        self.ctx.set_stdin(is);
+    }
+
+    /// Write stdout to a stream, flushed after every byte
+    /// stdout() is then always empty
+    pub fn set_stdout(&mut self, os: Box<dyn Write>) {
+        self.ctx.set_stdout(os);
     }
 
     /// Returns the output of the program
+    /// Empty if stdout is a stream (see set_stdout)
     pub fn stdout(&self) -> &[u8] {
-        &self.stdout
+        self.ctx.stdout()
+    }
+
+    /// Set a tracer notified of all the events of the execution
+    pub fn set_tracer(&mut self, tracer: Box<dyn Tracer>) {
+        self.ctx.enable_fmem_log();
+        self.tracer = Some(tracer);
+    }
+
+    /// Remove the tracer, and returns it
+    pub fn take_tracer(&mut self) -> Option<Box<dyn Tracer>> {
+        self.tracer.take()
     }
 
     fn get_ins(&self, addr: &CodeAddress) -> &ir::Ins {
@@ -260,6 +297,9 @@ impl Runtime {
 
     // Get register value on the current frame
     fn set_local(&mut self, id: ir::LocalsIndex, val: RTVal) {
+        if let Some(tracer) = &mut self.tracer {
+            tracer.trace_local_write(id, val.0);
+        }
         self.frames.last_mut().unwrap().set_local(id, val);
     }
 
@@ -280,9 +320,25 @@ impl Runtime {
 
     // Push 1 value to the operands stack on the current frame
     fn push_op(&mut self, val: RTVal) {
+        if let Some(tracer) = &mut self.tracer {
+            tracer.trace_push(val.0);
+        }
         self.frames.last_mut().unwrap().push_op(val);
     }
 
+    // Values of all operands read by an instruction:
+    // values popped from the stack, the constant for const, the local value for load
+    fn ins_operands(&self, ins: &ir::Ins) -> Vec<i32> {
+        match ins {
+            ir::Ins::Const(ins) => return vec![ins.val()],
+            ir::Ins::Load(ins) => return vec![self.get_local(ins.src()).0],
+            _ => {}
+        }
+        let operands = &self.frames.last().unwrap().operands;
+        let start = operands.len().saturating_sub(ins.operands_pops());
+        operands[start..].iter().map(|val| val.0).collect()
+    }
+
     fn addr_of_function_begin(&self, fun_id: ir::FunctionRef) -> CodeAddress {
         let fun = self.code.get_fun(fun_id);
         let bb = &fun.bb_list()[0];
@@ -294,14 +350,6 @@ impl Runtime {
     }
 
     fn exec_ins(&mut self, ins: ir::Ins) {
-        /*
-        println!(
-                "exec ins {:?} at {:?}",
-                ins,
-                self.call_stack.last().unwrap()
-            );
-        */
-
         self.ins_status = None;
 
         match ins {
@@ -350,10 +398,14 @@ impl Runtime {
             ir::InsOpbin::Mul => src1 * src2,
             ir::InsOpbin::Div => src1 / src2,
             ir::InsOpbin::Rem => src1 % src2,
+            ir::InsOpbin::And => src1 & src2,
+            ir::InsOpbin::Or => src1 | src2,
+            ir::InsOpbin::Xor => src1 ^ src2,
+            ir::InsOpbin::Shl => src1 << (src2.0 as usize & 31),
+            ir::InsOpbin::Shr => Wrapping(((src1.0 as u32) >> (src2.0 & 31)) as i32),
+            ir::InsOpbin::Sar => src1 >> (src2.0 as usize & 31),
         };
 
-        //println!("{:?}: {} . {} => {}", ins, src1.0, src2.0, res.0);
-
         self.push_op(RTVal(res.0));
         self.next_ins();
     }
@@ -363,8 +415,15 @@ impl Runtime {
 
         let res = match ins {
             ir::InsCmpbin::Eq => src1.0 == src2.0,
+            ir::InsCmpbin::Ne => src1.0 != src2.0,
             ir::InsCmpbin::Lt => src1.0 < src2.0,
+            ir::InsCmpbin::Le => src1.0 <= src2.0,
             ir::InsCmpbin::Gt => src1.0 > src2.0,
+            ir::InsCmpbin::Ge => src1.0 >= src2.0,
+            ir::InsCmpbin::Ult => (src1.0 as u32) < (src2.0 as u32),
+            ir::InsCmpbin::Ule => (src1.0 as u32) <= (src2.0 as u32),
+            ir::InsCmpbin::Ugt => (src1.0 as u32) > (src2.0 as u32),
+            ir::InsCmpbin::Uge => (src1.0 as u32) >= (src2.0 as u32),
         } as i32;
 
         self.push_op(RTVal(res));
@@ -388,7 +447,6 @@ impl Runtime {
     fn exec_ins_call(&mut self, ins: ir::InsCall) {
         let args = self.pop_n_ops(ins.nb_args());
         let fun = self.code.get_fun(ins.fun());
-        //println!("call {}: {:?}", fun.id(), args);
         if fun.is_extern() {
             let ret = self.call_native(ins.fun(), args);
             self.push_op(ret);
@@ -396,6 +454,10 @@ impl Runtime {
             return;
         }
 
+        if let Some(tracer) = &mut self.tracer {
+            let args_vals: Vec<_> = args.iter().map(|x| x.0).collect();
+            tracer.trace_call(ins.fun(), &args_vals);
+        }
         self.frames.push(Frame::new_from_call(&args));
         self.call_stack.push(self.addr_of_function_begin(ins.fun()));
     }
@@ -406,6 +468,9 @@ impl Runtime {
         }
 
         let ret_val = self.pop_op();
+        if let Some(tracer) = &mut self.tracer {
+            tracer.trace_ret(ret_val.0);
+        }
         self.frames.pop();
         self.call_stack.pop();
         self.push_op(ret_val);
@@ -413,114 +478,18 @@ impl Runtime {
     }
 
     fn call_native(&mut self, fun: ir::FunctionRef, args: Vec<RTVal>) -> RTVal {
-        let fun_putc = ir::FunctionRef::new(257);
-        let fun_exit = ir::FunctionRef::new(258);
-        let fun_getc = ir::FunctionRef::new(259);
-        let fun_fmemget = ir::FunctionRef::new(260);
-        let fun_fmemset = ir::FunctionRef::new(261);
-        let fun_fmemcpy = ir::FunctionRef::new(262);
-
-        if fun == fun_putc {
-            self.call_native_putc(args)
-        } else if fun == fun_exit {
-            self.call_native_exit(args)
-        } else if fun == fun_getc {
-            self.call_native_getc(args)
-        } else if fun == fun_fmemget {
-            self.call_native_fmemget(args)
-        } else if fun == fun_fmemset {
-            self.call_native_fmemset(args)
-        } else if fun == fun_fmemcpy {
-            self.call_native_fmemcpy(args)
-        } else {
-            panic!("Failed to called extern function: unknown id {}", fun)
-        }
-    }
-
-    fn call_native_putc(&mut self, args: Vec<RTVal>) -> RTVal {
-        if args.len() != 1 {
-            panic!(
-                "Failed to call putc: expected 1 argument, got {}",
-                args.len()
-            )
-        }
-
-        let bval = args[0].0 as u8;
-        self.stdout.push(bval);
-        RTVal(0)
-    }
-
-    fn call_native_exit(&mut self, args: Vec<RTVal>) -> RTVal {
-        if args.len() != 1 {
-            panic!(
-                "Failed to call exit: expected 1 argument, got {}",
-                args.len()
-            )
-        }
-
-        let exit_val = args[0].0 as u8;
-        self.ins_status = Some(ExitCode(exit_val));
-        RTVal(0)
-    }
+        let args: Vec<_> = args.iter().map(|x| x.0).collect();
+        let ret = self.natives.call(fun, &mut self.ctx, &args);
 
-    fn call_native_getc(&mut self, args: Vec<RTVal>) -> RTVal {
-        if args.len() != 0 {
-            panic!(
-                "Failed to call getc: expected 0 argument, got {}",
-                args.len()
-            )
-        }
-
-        match self.stdin.get(self.stdin_pos) {
-            Some(bval) => {
-                self.stdin_pos += 1;
-                RTVal(*bval as i32)
+        if let Some(tracer) = &mut self.tracer {
+            tracer.trace_native(fun, &args, ret);
+            for (addr, val) in self.ctx.take_fmem_log() {
+                tracer.trace_fmem_write(addr, val);
             }
-            None => RTVal(-1), //eof
         }
-    }
-
-    fn call_native_fmemget(&mut self, args: Vec<RTVal>) -> RTVal {
-        if args.len() != 1 {
-            panic!(
-                "Failed to call fmemget: expected 1 argument, got {}",
-                args.len()
-            )
+        if let Some(code) = self.ctx.take_exit_code() {
+            self.ins_status = Some(ExitCode(code));
         }
-
-        let pos = args[0].0;
-        let val = self.fmem.load(pos);
-        //println!("get @{} => {}", pos, val);
-        RTVal(val)
-    }
-
-    fn call_native_fmemset(&mut self, args: Vec<RTVal>) -> RTVal {
-        if args.len() != 2 {
-            panic!(
-                "Failed to call fmemdet: expected 2 arguments, got {}",
-                args.len()
-            )
-        }
-
-        let pos = args[0].0;
-        let val = args[1].0;
-        self.fmem.store(pos, val);
-        //println!("set @{}, {}", pos, val);
-        RTVal(0)
-    }
-
-    fn call_native_fmemcpy(&mut self, args: Vec<RTVal>) -> RTVal {
-        if args.len() != 3 {
-            panic!(
-                "Failed to call fmemdet: expected 3 arguments, got {}",
-                args.len()
-            )
-        }
-
-        let dst = args[0].0;
-        let src = args[1].0;
-        let len = args[2].0;
-        self.fmem.copy(dst, src, len);
-        RTVal(0)
+        RTVal(ret)
     }
 }
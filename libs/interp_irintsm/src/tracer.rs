@@ -0,0 +1,164 @@
+// Hooks to follow the execution of the runtime step by step
+//
+// A tracer is notified by the runtime for every executed instruction (with its operands values),
+// every operands stack push, every local / memory write, every call / return,
+// and at the exit of the program.
+//
+// TraceWriter writes all these events to a compact text trace file, one event per line:
+// - I <fun>:<bb>:<pos> <opname> <operands values...>   instruction executed
+// - P <val>                                            push on the operands stack
+// - L <local> <val>                                    local variable write
+// - M <addr> <val>                                     flat memory write
+// - C <fun> <args...>                                  call to a function defined in the module
+// - N <fun> <args...> -> <ret>                         call to a native function
+// - T <val>                                            return from a function
+// - X <code>                                           exit of the program
+// Lines starting with # are comments
+//
+// The format is the same than the interp_irint3a traces (except for the P lines, irint3a has R lines),
+// so traces of both interpreters can be compared at the calls / memory level
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use irintsm::ir;
+
+pub trait Tracer {
+    /// Called before executing an instruction, with the values of its operands
+    /// (values popped from the stack, the constant for const, the local value for load)
+    fn trace_ins(
+        &mut self,
+        _fun: ir::FunctionRef,
+        _bb: ir::BasicBlockRef,
+        _pos: usize,
+        _ins: &ir::Ins,
+        _operands: &[i32],
+    ) {
+    }
+
+    fn trace_push(&mut self, _val: i32) {}
+
+    fn trace_local_write(&mut self, _local: ir::LocalsIndex, _val: i32) {}
+
+    fn trace_fmem_write(&mut self, _addr: i32, _val: i32) {}
+
+    fn trace_call(&mut self, _fun: ir::FunctionRef, _args: &[i32]) {}
+
+    fn trace_native(&mut self, _fun: ir::FunctionRef, _args: &[i32], _ret: i32) {}
+
+    fn trace_ret(&mut self, _val: i32) {}
+
+    /// Last event of the program, the tracer must be flushed
+    fn trace_exit(&mut self, _code: i32) {}
+}
+
+/// Tracer that writes all events to a trace file
+pub struct TraceWriter {
+    os: Box<dyn Write>,
+}
+
+impl TraceWriter {
+    pub fn new(os: Box<dyn Write>) -> Self {
+        let mut res = TraceWriter { os };
+        writeln!(res.os, "# trace irintsm").unwrap();
+        res
+    }
+
+    /// Create a new trace file
+    pub fn create(path: &str) -> Self {
+        let os = File::create(path).expect("Failed to create trace file");
+        Self::new(Box::new(BufWriter::new(os)))
+    }
+
+    fn write_vals(&mut self, vals: &[i32]) {
+        for val in vals {
+            write!(self.os, " {}", val).unwrap();
+        }
+    }
+}
+
+impl Tracer for TraceWriter {
+    fn trace_ins(
+        &mut self,
+        fun: ir::FunctionRef,
+        bb: ir::BasicBlockRef,
+        pos: usize,
+        ins: &ir::Ins,
+        operands: &[i32],
+    ) {
+        write!(self.os, "I {}:{}:{} {}", fun, bb, pos, ins_opname(ins)).unwrap();
+        self.write_vals(operands);
+        writeln!(self.os).unwrap();
+    }
+
+    fn trace_push(&mut self, val: i32) {
+        writeln!(self.os, "P {}", val).unwrap();
+    }
+
+    fn trace_local_write(&mut self, local: ir::LocalsIndex, val: i32) {
+        writeln!(self.os, "L {} {}", local, val).unwrap();
+    }
+
+    fn trace_fmem_write(&mut self, addr: i32, val: i32) {
+        writeln!(self.os, "M {} {}", addr, val).unwrap();
+    }
+
+    fn trace_call(&mut self, fun: ir::FunctionRef, args: &[i32]) {
+        write!(self.os, "C {}", fun).unwrap();
+        self.write_vals(args);
+        writeln!(self.os).unwrap();
+    }
+
+    fn trace_native(&mut self, fun: ir::FunctionRef, args: &[i32], ret: i32) {
+        write!(self.os, "N {}", fun).unwrap();
+        self.write_vals(args);
+        writeln!(self.os, " -> {}", ret).unwrap();
+    }
+
+    fn trace_ret(&mut self, val: i32) {
+        writeln!(self.os, "T {}", val).unwrap();
+    }
+
+    fn trace_exit(&mut self, code: i32) {
+        writeln!(self.os, "X {}", code).unwrap();
+        self.os.flush().expect("Failed to write trace file");
+    }
+}
+
+fn ins_opname(ins: &ir::Ins) -> &'static str {
+    match ins {
+        ir::Ins::Pop(_) => "pop",
+        ir::Ins::Const(_) => "const",
+        ir::Ins::Load(_) => "load",
+        ir::Ins::Store(_) => "store",
+        ir::Ins::Opbin(ins) => match ins {
+            ir::InsOpbin::Add => "add",
+            ir::InsOpbin::Sub => "sub",
+            ir::InsOpbin::Mul => "mul",
+            ir::InsOpbin::Div => "div",
+            ir::InsOpbin::Rem => "rem",
+            ir::InsOpbin::And => "and",
+            ir::InsOpbin::Or => "or",
+            ir::InsOpbin::Xor => "xor",
+            ir::InsOpbin::Shl => "shl",
+            ir::InsOpbin::Shr => "shr",
+            ir::InsOpbin::Sar => "sar",
+        },
+        ir::Ins::Cmpbin(ins) => match ins {
+            ir::InsCmpbin::Eq => "cmpeq",
+            ir::InsCmpbin::Lt => "cmplt",
+            ir::InsCmpbin::Gt => "cmpgt",
+            ir::InsCmpbin::Ne => "cmpne",
+            ir::InsCmpbin::Le => "cmple",
+            ir::InsCmpbin::Ge => "cmpge",
+            ir::InsCmpbin::Ult => "cmpult",
+            ir::InsCmpbin::Ule => "cmpule",
+            ir::InsCmpbin::Ugt => "cmpugt",
+            ir::InsCmpbin::Uge => "cmpuge",
+        },
+        ir::Ins::Jump(_) => "jump",
+        ir::Ins::Br(_) => "br",
+        ir::Ins::Call(_) => "call",
+        ir::Ins::Ret(_) => "ret",
+    }
+}
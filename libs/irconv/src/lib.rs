@@ -0,0 +1,434 @@
+pub mod smfrom3a;
+pub mod smto3a;
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    // Run the irintsm code, and the lowered irint3a code, returns both outputs and exit codes
+    fn run_sm_3a(module: irintsm::ir::Module) -> ((Vec<u8>, i32), (Vec<u8>, i32)) {
+        let code_3a = smto3a::translate(&module);
+
+        let mut rt = interp_irintsm::runtime::Runtime::new(module);
+        let ret = rt.run();
+        let res_sm = (rt.stdout().to_vec(), ret.get_val());
+
+        let mut rt = interp_irint3a::runtime::Runtime::new(code_3a);
+        let ret = rt.run();
+        let res_3a = (rt.stdout().to_vec(), ret.get_val());
+
+        (res_sm, res_3a)
+    }
+
+    // Run the irint3a code, and the lowered irintsm code, returns both outputs and exit codes
+    fn run_3a_sm(module: irint3a::ir::Module) -> ((Vec<u8>, i32), (Vec<u8>, i32)) {
+        let code_sm = smfrom3a::translate(&module).unwrap();
+
+        let mut rt = interp_irint3a::runtime::Runtime::new(module);
+        let ret = rt.run();
+        let res_3a = (rt.stdout().to_vec(), ret.get_val());
+
+        let mut rt = interp_irintsm::runtime::Runtime::new(code_sm);
+        let ret = rt.run();
+        let res_sm = (rt.stdout().to_vec(), ret.get_val());
+
+        (res_3a, res_sm)
+    }
+
+    #[test]
+    fn smto3a_hello_42() {
+        let ps = irintsm::irparser::Parser::from_file("../irintsm/tests/hello_42.ir");
+        let (res_sm, res_3a) = run_sm_3a(ps.build());
+        assert_eq!(res_sm, (b"42\n".to_vec(), 0));
+        assert_eq!(res_3a, res_sm);
+    }
+
+    #[test]
+    fn smto3a_bitops() {
+        let ps = irintsm::irparser::Parser::from_file("../irintsm/tests/bitops.ir");
+        let (res_sm, res_3a) = run_sm_3a(ps.build());
+        assert_eq!(res_sm, (b"MIOT?A\n1100111\n".to_vec(), 0));
+        assert_eq!(res_3a, res_sm);
+    }
+
+    #[test]
+    fn smto3a_fixtures() {
+        // the main function of the fixtures is replaced by a call to function 1, the exit code is its result
+        let fixtures: &[(&str, &[i32], i32)] = &[
+            ("../irintsm/tests/fn_add.ir", &[3, 4], 7),
+            ("../irintsm/tests/fn_fact.ir", &[5], 120),
+        ];
+        for (path, args, exp_code) in fixtures {
+            let code = std::fs::read_to_string(path).unwrap();
+            let args: String = args.iter().map(|x| format!("  const {}\n", x)).collect();
+            let main = format!(
+                ".declare 258\n.define 0\n0:\n{}  call %1, {}\n  call %258, 1\n  ret\n",
+                args,
+                args.lines().count()
+            );
+            let code = code.replace(".define 0 ;function _main\n0:\n  const 0\n  ret", &main);
+            let module = irintsm::irparser::Parser::from_str(&code).build();
+            assert_eq!(
+                smto3a::translate(&module).funs().len(),
+                module.fun_list().len()
+            );
+
+            let (res_sm, res_3a) = run_sm_3a(module);
+            assert_eq!(res_sm, (vec![], *exp_code));
+            assert_eq!(res_3a, res_sm);
+        }
+    }
+
+    #[test]
+    fn smto3a_calls_joins() {
+        // fact(5) + add(3, 4), with unused values left on the stack before the joins
+        let ps = irintsm::irparser::Parser::from_str(
+            "
+.declare 257
+.declare 258
+
+.define 0
+0:
+  const 5
+  call %1, 1
+  const 3
+  const 4
+  call %2, 2
+  add
+  store 0
+  load 0
+  const 100
+  cmpgt
+  br %1, %2
+
+1:
+  const 7
+  const 33
+  jump %3
+
+2:
+  const 9
+  const 32
+  const 1
+  br %3, %3
+
+3:
+  call %257, 1
+  const 10
+  call %257, 1
+  load 0
+  call %258, 1
+  ret
+
+.define 1
+0:
+  load 0
+  const 2
+  cmplt
+  br %1, %2
+
+1:
+  const 1
+  jump %3
+
+2:
+  load 0
+  load 0
+  const 1
+  sub
+  call %1, 1
+  mul
+  jump %3
+
+3:
+  ret
+
+.define 2
+0:
+  load 0
+  load 1
+  add
+  ret
+",
+        );
+        let (res_sm, res_3a) = run_sm_3a(ps.build());
+        assert_eq!(res_sm, (b"!\n".to_vec(), 127));
+        assert_eq!(res_3a, res_sm);
+    }
+
+    #[test]
+    fn smfrom3a_hello_42() {
+        let (module, _names) = irint3a::irparser::Parser::from_file("../irint3a/tests/hello_42.ir")
+            .build()
+            .unwrap();
+        let (res_3a, res_sm) = run_3a_sm(module);
+        assert_eq!(res_3a, (b"42\n".to_vec(), 0));
+        assert_eq!(res_sm, res_3a);
+    }
+
+    #[test]
+    fn smfrom3a_alloca() {
+        // sum of the squares 1..=10, with an alloca in the loop, written by a callee
+        let (module, _names) = irint3a::irparser::Parser::from_str(
+            "
+.declare 257 putc
+.declare 258 exit
+
+.define 0 main
+L0:
+  alloca %r0
+  movi %r1, 1
+  movi %r2, 1
+  movi %r3, 11
+  jump Lcond
+Lcond:
+  cmplt %r4, %r1, %r3
+  br %r4, Lbody, Lexit
+Lbody:
+  alloca %r5
+  call %r6, square, %r5, %r1
+  load %r6, %r5
+  load %r7, %r0
+  add %r7, %r7, %r6
+  store %r0, %r7
+  add %r1, %r1, %r2
+  jump Lcond
+Lexit:
+  movi %r4, 10
+  load %r7, %r0
+  mod %r4, %r7, %r4
+  movi %r6, 48
+  add %r4, %r4, %r6
+  call %r6, putc, %r4
+  load %r7, %r0
+  call %r6, exit, %r7
+  ret %r6
+
+.define 1 square
+L0:
+  alloca %r2
+  mul %r3, %r1, %r1
+  store %r2, %r3
+  load %r3, %r2
+  store %r0, %r3
+  ret %r3
+",
+        )
+        .build()
+        .unwrap();
+        let (res_3a, res_sm) = run_3a_sm(module);
+        assert_eq!(res_3a, (b"5".to_vec(), 385 % 256));
+        assert_eq!(res_sm, res_3a);
+    }
+
+    #[test]
+    fn smfrom3a_stack_array() {
+        let (module, _names) =
+            irint3a::irparser::Parser::from_file("../irint3a/tests/stack_array.ir")
+                .build()
+                .unwrap();
+        let (res_3a, res_sm) = run_3a_sm(module);
+        assert_eq!(res_3a, (b"285\n".to_vec(), 0));
+        assert_eq!(res_sm, res_3a);
+    }
+
+    #[test]
+    fn smfrom3a_bitops() {
+        let (module, _names) = irint3a::irparser::Parser::from_file("../irint3a/tests/bitops.ir")
+            .build()
+            .unwrap();
+        let (res_3a, res_sm) = run_3a_sm(module);
+        assert_eq!(res_3a, (b"MIOT?A\n1100111\n".to_vec(), 0));
+        assert_eq!(res_sm, res_3a);
+    }
+
+    #[test]
+    fn smfrom3a_globals() {
+        let (module, _names) = irint3a::irparser::Parser::from_file("../irint3a/tests/globals.ir")
+            .build()
+            .unwrap();
+        let (res_3a, res_sm) = run_3a_sm(module);
+        assert_eq!(res_3a, (b"Hi!2\n".to_vec(), 0));
+        assert_eq!(res_sm, res_3a);
+
+        // the allocas must not overwrite the globals
+        let (module, _names) = irint3a::irparser::Parser::from_str(
+            "
+.global 0 tab 3 7 8
+.global 1 total 1 -4
+
+.declare 258 exit
+
+.define 0 main
+L0:
+  alloca %r0
+  movi %r1, 0
+  store %r0, %r1
+  gaddr %r2, tab
+  call %r3, sum, %r2, %r0
+  load %r4, %r0
+  gaddr %r5, total
+  load %r6, %r5
+  add %r4, %r4, %r6
+  call %r3, exit, %r4
+  ret %r3
+
+.define 1 sum
+L0:
+  alloca %r2
+  alloca %r3
+  movi %r4, 1
+  movi %r8, 4
+  store %r2, %r4
+  store %r3, %r4
+  load %r5, %r0
+  add %r6, %r0, %r8
+  load %r7, %r6
+  add %r5, %r5, %r7
+  add %r6, %r6, %r8
+  load %r7, %r6
+  add %r5, %r5, %r7
+  store %r1, %r5
+  ret %r5
+",
+        )
+        .build()
+        .unwrap();
+        let (res_3a, res_sm) = run_3a_sm(module);
+        assert_eq!(res_3a, (vec![], 11));
+        assert_eq!(res_sm, res_3a);
+    }
+
+    #[test]
+    fn smfrom3a_widths() {
+        // i8 / i16 arithmetic, conversions, and packed bytes in memory
+        let (module, _names) = irint3a::irparser::Parser::from_str(
+            "
+.global.i8 0 msg 6 72 105 -1 33 10 0
+.global.i16 1 tab 3 -2 300 7
+
+.declare 257 putc
+.declare 258 exit
+
+.define 0 main
+L0:
+  gaddr %r0, msg
+  movi %r1, 1
+  movi.i8 %r2, 0
+  jump Lloop
+Lloop:
+  load.i8 %r3, %r0
+  cmpeq.i8 %r4, %r3, %r2
+  br %r4, Lend, Lbody
+Lbody:
+  cmplt.i8 %r4, %r3, %r2
+  add %r0, %r0, %r1
+  br %r4, Lloop, Lput
+Lput:
+  call %r5, putc, %r3
+  jump Lloop
+Lend:
+  alloca.i8 %r6, 4
+  movi.i8 %r7, 120
+  add %r8, %r6, %r1
+  store.i8 %r8, %r7
+  load.i8 %r9, %r8
+  add.i8 %r9, %r9, %r7
+  zext.i8.i32 %r10, %r9
+  movi.i8 %r11, 3
+  shr.i8 %r12, %r9, %r11
+  sext.i8.i32 %r13, %r12
+  add %r10, %r10, %r13
+  load %r14, %r6
+  add %r10, %r10, %r14
+  gaddr %r15, tab
+  movi %r16, 2
+  add %r15, %r15, %r16
+  load.i16 %r17, %r15
+  trunc.i16.i8 %r18, %r17
+  movi.i8 %r19, 250
+  cmpult.i8 %r20, %r18, %r19
+  add %r10, %r10, %r20
+  call %r5, exit, %r10
+  ret %r5
+",
+        )
+        .build()
+        .unwrap();
+        let (res_3a, res_sm) = run_3a_sm(module);
+        // 240 as i8 is -16: 240 + (-16 >>> 3 = 30) + (120 << 8) + (44 <u 250 = 1)
+        assert_eq!(res_3a, (b"Hi!\n".to_vec(), (240 + 30 + (120 << 8) + 1) % 256));
+        assert_eq!(res_sm, res_3a);
+    }
+
+    #[test]
+    fn smfrom3a_switch() {
+        let (module, _names) = irint3a::irparser::Parser::from_file("../irint3a/tests/switch.ir")
+            .build()
+            .unwrap();
+        let (res_3a, res_sm) = run_3a_sm(module);
+        assert_eq!(res_3a, (b"...ABCC.EFG....\nabzcedz\nwz?\n".to_vec(), 0));
+        assert_eq!(res_sm, res_3a);
+    }
+
+    #[test]
+    fn smfrom3a_i64() {
+        let (module, _names) = irint3a::irparser::Parser::from_file("../irint3a/tests/widths.ir")
+            .build()
+            .unwrap();
+        let err = smfrom3a::translate(&module).err().unwrap();
+        assert!(err.contains("i64 values are not supported"));
+    }
+
+    #[test]
+    fn smfrom3a_funptr() {
+        let (module, _names) = irint3a::irparser::Parser::from_file("../irint3a/tests/funptr.ir")
+            .build()
+            .unwrap();
+        let err = smfrom3a::translate(&module).err().unwrap();
+        assert!(err.contains("function addresses are not supported"));
+    }
+
+    #[test]
+    fn smfrom3a_fmem_heap() {
+        let (module, _names) = irint3a::irparser::Parser::from_str(
+            "
+.declare 267 fmemalloc
+.define 0 main
+L0:
+  movi %r0, 4
+  call %r1, fmemalloc, %r0
+  ret %r1
+",
+        )
+        .build()
+        .unwrap();
+        let err = smfrom3a::translate(&module).err().unwrap();
+        assert!(err.contains("fmemalloc and fmemfree are not supported"));
+    }
+
+    #[test]
+    fn smfrom3a_schedule() {
+        // (r0 + r1) * r2: the sum and the product stay on the operands stack
+        let (module, _names) = irint3a::irparser::Parser::from_str(
+            "
+.define 0 main
+L0:
+  add %r3, %r0, %r1
+  mul %r4, %r3, %r2
+  ret %r4
+",
+        )
+        .build()
+        .unwrap();
+        let code = smfrom3a::translate(&module).unwrap();
+        let fun = code.get_fun(irintsm::ir::FunctionRef::new(0));
+        let ins = fun.bb_list()[0].ins_list();
+        assert_eq!(ins.len(), 6);
+        assert!(matches!(ins[2], irintsm::ir::Ins::Opbin(irintsm::ir::InsOpbin::Add)));
+        assert!(matches!(ins[3], irintsm::ir::Ins::Load(_)));
+        assert!(matches!(ins[4], irintsm::ir::Ins::Opbin(irintsm::ir::InsOpbin::Mul)));
+        assert!(matches!(ins[5], irintsm::ir::Ins::Ret(_)));
+    }
+}
@@ -0,0 +1,545 @@
+// Lowering of irint3a (registers) to irintsm (stack machine)
+//
+// Functions keep the same id, extern functions stay extern.
+// The natives fmemget (260) and fmemset (261) are declared if missing, they are needed for alloca.
+//
+// Registers:
+// Register i is the local i (arguments are in registers 0, 1, 2, etc, like the locals)
+// Every instruction loads its operands on the operands stack, computes, and stores the result:
+// add %2, %0, %1 => load 0; load 1; add; store 2
+//
+// Stack scheduling:
+// A value used once, by the instruction right after its definition, stays on the operands stack.
+// This is done by removing every `store x; load x` where it's the only load of x in the function.
+// No other path can read x, so the store is useless.
+//
+// Integer widths:
+// irintsm values are 32 bits, so i64 isn't supported (translate returns an error)
+// i8 and i16 values are stored sign-extended in 32 bits, like in the irint3a interpreter:
+// the result of every operation is sign-extended again (shl then sar),
+// the operands of unsigned comparisons and shr are zero-extended, and shift amounts are masked.
+// sext is a simple move, zext a mask, and trunc a sign-extension from the smaller width.
+//
+// Memory:
+// irintsm has no addressable memory, so alloca / load / store use the flat memory (fmem natives).
+// fmem is addressed by words, and irint3a by bytes: the byte address b is the byte b % 4 of the fmem word b / 4
+// (little-endian). i8 / i16 loads extract the bytes of the word, stores read and rewrite the whole word.
+// An i16 / i32 access must stay inside one word (aligned addresses).
+// The alloca stack is at the end of fmem, and grows down:
+// - fmem[FMEM_STACK_TOP] is the number of words used by the stack (0 at the beginning of the program)
+// - alloca of n bytes increases it by the number of words needed, and returns the address of the first word
+//   (FMEM_STACK_TOP - size) * 4, the words are set to 0
+// - a function with alloca saves the size at the entry, and restores it before every ret
+// The addresses are different than the ones of the irint3a interpreter,
+// but programs only use them with load / store (and address arithmetic inside a global).
+// They must not use the end of the fmem for something else.
+// The fmem heap of the interpreters (fmemalloc / fmemfree) is the second half of the fmem, so it overlaps
+// the alloca stack and the globals: calls to fmemalloc (267) and fmemfree (268) aren't supported
+// (translate returns an error).
+//
+// Globals:
+// All globals are just before FMEM_STACK_TOP, in the module order, and the alloca stack is below them.
+// Every global starts on a new word, its values are packed in the words (4 i8 values per word).
+// The function 0 starts by setting the stack size to the size of all globals, and writing the init data.
+// The other words are 0, like all the fmem at the beginning of the program.
+// gaddr is a constant address.
+//
+// Switches:
+// irintsm has no switch and no indirect jump, they are lowered to comparisons and br before the translation
+// (see irint3a switchlowering: the switches are lowered, and then the jump tables of the dense switches).
+//
+// Function addresses:
+// irintsm calls can only reference a function statically, so funaddr and callr aren't supported (translate returns an error).
+
+use std::collections::{HashMap, HashSet};
+
+use irint3a::ir as ir3a;
+use irint3a::registers::GetRegistersUse;
+use irint3a::switchlowering;
+use irintsm::ir as irsm;
+use irintsm::irbuilder::IRBuilder;
+
+const NATIVE_FMEMGET: usize = 260;
+const NATIVE_FMEMSET: usize = 261;
+const NATIVE_FMEMALLOC: usize = 267;
+const NATIVE_FMEMFREE: usize = 268;
+
+/// Address in fmem of the alloca stack size, the stack is just before
+/// Last word of the fmem of the interpreters
+pub const FMEM_STACK_TOP: i32 = 16 * 1024 * 1024 - 1;
+
+/// Translate a whole irint3a module to irintsm
+/// Returns an error if the module uses a construct that irintsm can't represent
+/// Panics if the irintsm code is invalid
+pub fn translate(module: &ir3a::Module) -> Result<irsm::Module, String> {
+    let globals = GlobalsLayout::new(module)?;
+    let mut builder = IRBuilder::new();
+    for fun in module.funs() {
+        let id = irsm::FunctionRef::new(fun.id().0);
+        builder.create_function(Some(id));
+    }
+    for native in &[NATIVE_FMEMGET, NATIVE_FMEMSET] {
+        if module.get_fun(ir3a::FunctionId(*native)).is_none() {
+            builder.create_function(Some(irsm::FunctionRef::new(*native)));
+        }
+    }
+
+    for fun in module.funs() {
+        if !fun.is_extern() {
+            let mut fun = fun.clone();
+            switchlowering::lower_switches(&mut fun);
+            switchlowering::lower_jump_tables(&mut fun);
+            FunctionLowering::new(&fun, &globals).translate(&mut builder)?;
+        }
+    }
+
+    Ok(builder.finish())
+}
+
+// Address of all globals in fmem
+struct GlobalsLayout {
+    size: i32,                           // number of words of all globals
+    addrs: HashMap<ir3a::GlobalId, i32>, // fmem word address
+    init: Vec<(i32, i32)>,               // (address, value) of all init data words
+}
+
+impl GlobalsLayout {
+    fn new(module: &ir3a::Module) -> Result<Self, String> {
+        let size: usize = module.globals().iter().map(|g| words(g.bytes())).sum();
+        let size = size as i32;
+        let mut addr = FMEM_STACK_TOP - size;
+        let mut addrs = HashMap::new();
+        let mut init = vec![];
+        for global in module.globals() {
+            let width = check_width(global.width())?;
+            addrs.insert(global.id(), addr);
+            let mut data = vec![0u32; words(global.init().len() * width.bytes())];
+            for (idx, val) in global.init().iter().enumerate() {
+                let pos = idx * width.bytes();
+                data[pos / 4] |= (width.zero_extend(*val) as u32) << (8 * (pos % 4));
+            }
+            for (idx, val) in data.iter().enumerate() {
+                init.push((addr + idx as i32, *val as i32));
+            }
+            addr += words(global.bytes()) as i32;
+        }
+
+        Ok(GlobalsLayout { size, addrs, init })
+    }
+}
+
+struct FunctionLowering<'a> {
+    fun: &'a ir3a::Function,
+    globals: &'a GlobalsLayout,
+    init_globals: bool, // the function 0 must init all globals
+    has_alloca: bool,
+    stack_save: irsm::LocalsIndex, // stack size at the entry of the function
+    bbs: Vec<Vec<irsm::Ins>>,
+}
+
+impl<'a> FunctionLowering<'a> {
+    fn new(fun: &'a ir3a::Function, globals: &'a GlobalsLayout) -> Self {
+        let mut regs = HashSet::new();
+        let mut has_alloca = false;
+        for bb_id in fun.basic_blocks_list() {
+            for ins in fun.get_basic_block(*bb_id).iter() {
+                has_alloca |= matches!(ins, ir3a::Ins::Alloca(_));
+                ins.get_register_use(&mut regs);
+            }
+        }
+        let nb_regs = regs.iter().map(|reg| reg.0 + 1).max().unwrap_or(0);
+
+        FunctionLowering {
+            fun,
+            globals,
+            init_globals: fun.id() == ir3a::FunctionId(0) && globals.size > 0,
+            has_alloca,
+            stack_save: irsm::LocalsIndex::new(nb_regs),
+            bbs: vec![],
+        }
+    }
+
+    fn translate(mut self, builder: &mut IRBuilder) -> Result<(), String> {
+        for bb_id in self.fun.basic_blocks_list() {
+            let mut code = vec![];
+            for ins in self.fun.get_basic_block(*bb_id).iter() {
+                self.translate_ins(&mut code, ins)?;
+            }
+            self.bbs.push(code);
+        }
+        self.schedule();
+
+        let fun_id = irsm::FunctionRef::new(self.fun.id().0);
+        let mut prologue = None;
+        if self.has_alloca || self.init_globals {
+            // in a new block, the entry point may be the target of a jump
+            prologue = Some(builder.create_basic_block(fun_id));
+        }
+        let bbs_map: HashMap<_, _> = self
+            .fun
+            .basic_blocks_list()
+            .iter()
+            .map(|bb_id| (*bb_id, builder.create_basic_block(fun_id)))
+            .collect();
+
+        if let Some(prologue) = prologue {
+            builder.set_insert_point(prologue);
+            if self.init_globals {
+                let fmemset = irsm::FunctionRef::new(NATIVE_FMEMSET);
+                builder.ins_const(FMEM_STACK_TOP);
+                builder.ins_const(self.globals.size);
+                builder.ins_call(fmemset, 2);
+                builder.ins_pop();
+                for (addr, val) in &self.globals.init {
+                    builder.ins_const(*addr);
+                    builder.ins_const(*val);
+                    builder.ins_call(fmemset, 2);
+                    builder.ins_pop();
+                }
+            }
+            if self.has_alloca {
+                builder.ins_const(FMEM_STACK_TOP);
+                builder.ins_call(irsm::FunctionRef::new(NATIVE_FMEMGET), 1);
+                builder.ins_store(self.stack_save);
+            }
+            builder.ins_jump(bbs_map[&self.fun.basic_blocks_list()[0]]);
+        }
+
+        for (bb_id, code) in self.fun.basic_blocks_list().iter().zip(self.bbs) {
+            builder.set_insert_point(bbs_map[bb_id]);
+            for ins in code {
+                let ins = match ins {
+                    irsm::Ins::Jump(ins) => irsm::Ins::Jump(irsm::InsJump::new(
+                        bbs_map[&ir3a::BasicBlockId(ins.dst().id())],
+                    )),
+                    irsm::Ins::Br(ins) => irsm::Ins::Br(irsm::InsBr::new(
+                        bbs_map[&ir3a::BasicBlockId(ins.dst_true().id())],
+                        bbs_map[&ir3a::BasicBlockId(ins.dst_false().id())],
+                    )),
+                    ins => ins,
+                };
+                builder.add_instruction(ins);
+            }
+        }
+        Ok(())
+    }
+
+    // Remove all `store x; load x` when it's the only load of x
+    fn schedule(&mut self) {
+        let mut loads = HashMap::new();
+        for ins in self.bbs.iter().flatten() {
+            if let irsm::Ins::Load(ins) = ins {
+                *loads.entry(ins.src()).or_insert(0) += 1;
+            }
+        }
+
+        for code in &mut self.bbs {
+            let mut i = 0;
+            while i + 1 < code.len() {
+                match (code[i], code[i + 1]) {
+                    (irsm::Ins::Store(st), irsm::Ins::Load(ld))
+                        if st.dst() == ld.src() && loads[&ld.src()] == 1 =>
+                    {
+                        code.drain(i..i + 2);
+                    }
+                    _ => i += 1,
+                }
+            }
+        }
+    }
+
+    // Generate the code for one instruction
+    // Jumps use the irint3a basic block ids, they are changed later
+    fn translate_ins(&self, code: &mut Vec<irsm::Ins>, ins: &ir3a::Ins) -> Result<(), String> {
+        match ins {
+            ir3a::Ins::Movi(ins) => {
+                check_width(ins.width())?;
+                code.push(constant(ins.const_val() as i32));
+                code.push(store(ins.dst()));
+            }
+            ir3a::Ins::Movr(ins) => {
+                code.push(load(ins.src()));
+                code.push(store(ins.dst()));
+            }
+            ir3a::Ins::Load(ins) => {
+                let width = check_width(ins.width())?;
+                push_word_addr(code, ins.src());
+                code.push(call(NATIVE_FMEMGET, 1));
+                if width != ir3a::IntWidth::I32 {
+                    // move the value to the highest bits of the word, and sign-extend it
+                    let bits = 32 - width.bits() as i32;
+                    code.push(constant(bits));
+                    push_byte_shift(code, ins.src());
+                    code.push(opbin(irsm::InsOpbin::Sub));
+                    code.push(opbin(irsm::InsOpbin::Shl));
+                    code.push(constant(bits));
+                    code.push(opbin(irsm::InsOpbin::Sar));
+                }
+                code.push(store(ins.dst()));
+            }
+            ir3a::Ins::Store(ins) => {
+                let width = check_width(ins.width())?;
+                push_word_addr(code, ins.dst());
+                if width == ir3a::IntWidth::I32 {
+                    code.push(load(ins.src()));
+                } else {
+                    // (word & !(mask << shift)) | ((src & mask) << shift)
+                    let mask = width.zero_extend(-1) as i32;
+                    push_word_addr(code, ins.dst());
+                    code.push(call(NATIVE_FMEMGET, 1));
+                    code.push(constant(mask));
+                    push_byte_shift(code, ins.dst());
+                    code.push(opbin(irsm::InsOpbin::Shl));
+                    code.push(constant(-1));
+                    code.push(opbin(irsm::InsOpbin::Xor));
+                    code.push(opbin(irsm::InsOpbin::And));
+                    code.push(load(ins.src()));
+                    code.push(constant(mask));
+                    code.push(opbin(irsm::InsOpbin::And));
+                    push_byte_shift(code, ins.dst());
+                    code.push(opbin(irsm::InsOpbin::Shl));
+                    code.push(opbin(irsm::InsOpbin::Or));
+                }
+                code.push(call(NATIVE_FMEMSET, 2));
+                code.push(irsm::Ins::Pop(irsm::InsPop::new()));
+            }
+            ir3a::Ins::Alloca(ins) => {
+                check_width(ins.width())?;
+                self.translate_alloca(code, ins.dst(), words(ins.bytes()))
+            }
+            ir3a::Ins::Gaddr(ins) => {
+                let addr = self.globals.addrs[&ins.global()];
+                code.push(constant(addr * 4));
+                code.push(store(ins.dst()));
+            }
+            ir3a::Ins::Opbin(ins) => {
+                let width = check_width(ins.width())?;
+                let is_shift = matches!(
+                    ins.kind(),
+                    ir3a::InsOpbinKind::Shl | ir3a::InsOpbinKind::Shr | ir3a::InsOpbinKind::Sar
+                );
+                code.push(load(ins.src1()));
+                if matches!(ins.kind(), ir3a::InsOpbinKind::Shr) {
+                    push_zero_extend(code, width);
+                }
+                code.push(load(ins.src2()));
+                if is_shift && width != ir3a::IntWidth::I32 {
+                    code.push(constant(width.bits() as i32 - 1));
+                    code.push(opbin(irsm::InsOpbin::And));
+                }
+                code.push(irsm::Ins::Opbin(match ins.kind() {
+                    ir3a::InsOpbinKind::Add => irsm::InsOpbin::Add,
+                    ir3a::InsOpbinKind::Sub => irsm::InsOpbin::Sub,
+                    ir3a::InsOpbinKind::Mul => irsm::InsOpbin::Mul,
+                    ir3a::InsOpbinKind::Div => irsm::InsOpbin::Div,
+                    ir3a::InsOpbinKind::Mod => irsm::InsOpbin::Rem,
+                    ir3a::InsOpbinKind::And => irsm::InsOpbin::And,
+                    ir3a::InsOpbinKind::Or => irsm::InsOpbin::Or,
+                    ir3a::InsOpbinKind::Xor => irsm::InsOpbin::Xor,
+                    ir3a::InsOpbinKind::Shl => irsm::InsOpbin::Shl,
+                    ir3a::InsOpbinKind::Shr => irsm::InsOpbin::Shr,
+                    ir3a::InsOpbinKind::Sar => irsm::InsOpbin::Sar,
+                }));
+                push_sign_extend(code, width);
+                code.push(store(ins.dst()));
+            }
+            ir3a::Ins::Cmpbin(ins) => {
+                let width = check_width(ins.width())?;
+                let is_unsigned = matches!(
+                    ins.kind(),
+                    ir3a::InsCmpbinKind::Ult
+                        | ir3a::InsCmpbinKind::Ule
+                        | ir3a::InsCmpbinKind::Ugt
+                        | ir3a::InsCmpbinKind::Uge
+                );
+                code.push(load(ins.src1()));
+                if is_unsigned {
+                    push_zero_extend(code, width);
+                }
+                code.push(load(ins.src2()));
+                if is_unsigned {
+                    push_zero_extend(code, width);
+                }
+                code.push(irsm::Ins::Cmpbin(match ins.kind() {
+                    ir3a::InsCmpbinKind::Eq => irsm::InsCmpbin::Eq,
+                    ir3a::InsCmpbinKind::Lt => irsm::InsCmpbin::Lt,
+                    ir3a::InsCmpbinKind::Gt => irsm::InsCmpbin::Gt,
+                    ir3a::InsCmpbinKind::Ne => irsm::InsCmpbin::Ne,
+                    ir3a::InsCmpbinKind::Le => irsm::InsCmpbin::Le,
+                    ir3a::InsCmpbinKind::Ge => irsm::InsCmpbin::Ge,
+                    ir3a::InsCmpbinKind::Ult => irsm::InsCmpbin::Ult,
+                    ir3a::InsCmpbinKind::Ule => irsm::InsCmpbin::Ule,
+                    ir3a::InsCmpbinKind::Ugt => irsm::InsCmpbin::Ugt,
+                    ir3a::InsCmpbinKind::Uge => irsm::InsCmpbin::Uge,
+                }));
+                code.push(store(ins.dst()));
+            }
+            ir3a::Ins::Conv(ins) => {
+                check_width(ins.from())?;
+                check_width(ins.to())?;
+                code.push(load(ins.src()));
+                match ins.kind() {
+                    ir3a::InsConvKind::Sext => {}
+                    ir3a::InsConvKind::Zext => push_zero_extend(code, ins.from()),
+                    ir3a::InsConvKind::Trunc => push_sign_extend(code, ins.to()),
+                }
+                code.push(store(ins.dst()));
+            }
+            ir3a::Ins::Jump(ins) => {
+                code.push(irsm::Ins::Jump(irsm::InsJump::new(bb_ref(ins.dst()))));
+            }
+            ir3a::Ins::Br(ins) => {
+                code.push(load(ins.src()));
+                code.push(irsm::Ins::Br(irsm::InsBr::new(
+                    bb_ref(ins.dst_true()),
+                    bb_ref(ins.dst_false()),
+                )));
+            }
+            ir3a::Ins::Call(ins) => {
+                if ins.fun().0 == NATIVE_FMEMALLOC || ins.fun().0 == NATIVE_FMEMFREE {
+                    return Err(
+                        "Failed to translate to irintsm: fmemalloc and fmemfree are not supported"
+                            .to_string(),
+                    );
+                }
+                for arg in ins.args() {
+                    code.push(load(*arg));
+                }
+                code.push(call(ins.fun().0, ins.args().len()));
+                code.push(store(ins.dst()));
+            }
+            ir3a::Ins::Switch(_) | ir3a::Ins::Jumpt(_) => {
+                unreachable!("switch and jumpt instructions are lowered before the translation")
+            }
+            ir3a::Ins::Funaddr(_) | ir3a::Ins::Callr(_) => {
+                return Err(
+                    "Failed to translate to irintsm: function addresses are not supported"
+                        .to_string(),
+                );
+            }
+            ir3a::Ins::Ret(ins) => {
+                if self.has_alloca {
+                    // free all alloca of the function
+                    code.push(irsm::Ins::Const(irsm::InsConst::new(FMEM_STACK_TOP)));
+                    code.push(irsm::Ins::Load(irsm::InsLoad::new(self.stack_save)));
+                    code.push(call(NATIVE_FMEMSET, 2));
+                    code.push(irsm::Ins::Pop(irsm::InsPop::new()));
+                }
+                code.push(load(ins.src()));
+                code.push(irsm::Ins::Ret(irsm::InsRet::new()));
+            }
+        }
+        Ok(())
+    }
+
+    // Allocate `size` words
+    fn translate_alloca(&self, code: &mut Vec<irsm::Ins>, dst: ir3a::RegId, size: usize) {
+        let top = irsm::Ins::Const(irsm::InsConst::new(FMEM_STACK_TOP));
+
+        // fmem[top] = fmem[top] + size
+        code.push(top);
+        code.push(top);
+        code.push(call(NATIVE_FMEMGET, 1));
+        code.push(irsm::Ins::Const(irsm::InsConst::new(size as i32)));
+        code.push(irsm::Ins::Opbin(irsm::InsOpbin::Add));
+        code.push(call(NATIVE_FMEMSET, 2));
+        code.push(irsm::Ins::Pop(irsm::InsPop::new()));
+
+        // dst = (top - fmem[top]) * 4
+        code.push(top);
+        code.push(top);
+        code.push(call(NATIVE_FMEMGET, 1));
+        code.push(irsm::Ins::Opbin(irsm::InsOpbin::Sub));
+        code.push(constant(2));
+        code.push(opbin(irsm::InsOpbin::Shl));
+        code.push(store(dst));
+
+        // fmem[dst / 4 + i] = 0
+        for i in 0..size {
+            push_word_addr(code, dst);
+            if i > 0 {
+                code.push(irsm::Ins::Const(irsm::InsConst::new(i as i32)));
+                code.push(irsm::Ins::Opbin(irsm::InsOpbin::Add));
+            }
+            code.push(irsm::Ins::Const(irsm::InsConst::new(0)));
+            code.push(call(NATIVE_FMEMSET, 2));
+            code.push(irsm::Ins::Pop(irsm::InsPop::new()));
+        }
+    }
+}
+
+// irintsm values are 32 bits, i64 can't be lowered
+fn check_width(width: ir3a::IntWidth) -> Result<ir3a::IntWidth, String> {
+    if width == ir3a::IntWidth::I64 {
+        return Err("Failed to translate to irintsm: i64 values are not supported".to_string());
+    }
+    Ok(width)
+}
+
+// number of fmem words needed for `bytes`
+fn words(bytes: usize) -> usize {
+    match bytes % 4 {
+        0 => bytes / 4,
+        _ => bytes / 4 + 1,
+    }
+}
+
+// Push the fmem word address of the byte address in `reg`: reg >> 2
+fn push_word_addr(code: &mut Vec<irsm::Ins>, reg: ir3a::RegId) {
+    code.push(load(reg));
+    code.push(constant(2));
+    code.push(opbin(irsm::InsOpbin::Shr));
+}
+
+// Push the position in bits of the byte address in `reg` inside its word: (reg & 3) * 8
+fn push_byte_shift(code: &mut Vec<irsm::Ins>, reg: ir3a::RegId) {
+    code.push(load(reg));
+    code.push(constant(3));
+    code.push(opbin(irsm::InsOpbin::And));
+    code.push(constant(3));
+    code.push(opbin(irsm::InsOpbin::Shl));
+}
+
+// Sign-extend the value on top of the stack from `width`
+fn push_sign_extend(code: &mut Vec<irsm::Ins>, width: ir3a::IntWidth) {
+    if width != ir3a::IntWidth::I32 {
+        let bits = 32 - width.bits() as i32;
+        code.push(constant(bits));
+        code.push(opbin(irsm::InsOpbin::Shl));
+        code.push(constant(bits));
+        code.push(opbin(irsm::InsOpbin::Sar));
+    }
+}
+
+// Zero-extend the value on top of the stack from `width`
+fn push_zero_extend(code: &mut Vec<irsm::Ins>, width: ir3a::IntWidth) {
+    if width != ir3a::IntWidth::I32 {
+        code.push(constant(width.zero_extend(-1) as i32));
+        code.push(opbin(irsm::InsOpbin::And));
+    }
+}
+
+fn constant(val: i32) -> irsm::Ins {
+    irsm::Ins::Const(irsm::InsConst::new(val))
+}
+
+fn opbin(kind: irsm::InsOpbin) -> irsm::Ins {
+    irsm::Ins::Opbin(kind)
+}
+
+fn load(reg: ir3a::RegId) -> irsm::Ins {
+    irsm::Ins::Load(irsm::InsLoad::new(irsm::LocalsIndex::new(reg.0)))
+}
+
+fn store(reg: ir3a::RegId) -> irsm::Ins {
+    irsm::Ins::Store(irsm::InsStore::new(irsm::LocalsIndex::new(reg.0)))
+}
+
+fn call(fun: usize, nb_args: usize) -> irsm::Ins {
+    irsm::Ins::Call(irsm::InsCall::new(irsm::FunctionRef::new(fun), nb_args))
+}
+
+// temporary reference, with the id of the irint3a basic block
+fn bb_ref(bb: ir3a::BasicBlockId) -> irsm::BasicBlockRef {
+    irsm::BasicBlockRef::new(bb.0)
+}
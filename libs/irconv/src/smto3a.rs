@@ -0,0 +1,258 @@
+// Lowering of irintsm (stack machine) to irint3a (registers)
+//
+// Functions keep the same id, extern functions stay extern.
+//
+// Registers:
+// The height of the operands stack is known statically at every instruction,
+// so every slot of the operands stack is mapped to a register.
+// With L the number of locals of the function:
+// - local i is the register i (arguments are in locals 0, 1, 2, etc, like the registers)
+// - slot h of the operands stack (0 is the bottom) is the register L + h
+//
+// Instructions:
+// - const / load push a value: movi / movr to the slot register
+// - store pops a value: movr to the local register
+// - pop does nothing, the slot will be overwritten
+// - opbin / cmpbin: the 2 top slots are the operands, the result is written in the lowest one
+// - call %f, n: the n top slots are the arguments, the result is written in the lowest one
+// - br / ret use the top slot
+//
+// Joins:
+// The stack height at the beginning of a basic block may depend on the predecessor,
+// because some code leave unused values on the operands stack (eg: result of a call).
+// These values can never be read, so the entry height of a basic block is the minimum
+// over all its predecessors, and only the top values are kept.
+// When the height of the predecessor is greater, the top values are moved to the bottom slots
+// before the jump. For br, this is done in a new basic block for this edge.
+//
+// Unreachable basic blocks are not translated.
+
+use std::collections::HashMap;
+
+use irint3a::ir as ir3a;
+use irint3a::irbuilder::IRBuilder;
+use irintsm::ir as irsm;
+use irintsm::ir::OperandsSizeEffect;
+
+/// Translate a whole irintsm module to irint3a
+/// The irintsm code must be valid, and the operands stack must never underflow
+/// Panics if the code can't be translated
+pub fn translate(module: &irsm::Module) -> ir3a::Module {
+    let mut res = ir3a::Module::new();
+    for fun in module.fun_list() {
+        let id = ir3a::FunctionId(fun.id().id());
+        if fun.is_extern() {
+            res.create_extern_function(id);
+        } else {
+            res.create_function(Some(id));
+            let mut builder = IRBuilder::new(res.get_fun_mut(id).unwrap());
+            FunctionLowering::new(fun).translate(&mut builder);
+        }
+    }
+
+    irint3a::irvalidation::validate_module(&res, None).unwrap();
+    res
+}
+
+fn successors(bb: &irsm::BasicBlock) -> Vec<irsm::BasicBlockRef> {
+    match bb.ins_list().last() {
+        Some(irsm::Ins::Jump(ins)) => vec![ins.dst()],
+        Some(irsm::Ins::Br(ins)) => vec![ins.dst_true(), ins.dst_false()],
+        _ => vec![],
+    }
+}
+
+struct FunctionLowering<'a> {
+    fun: &'a irsm::Function,
+    nb_locals: usize,
+    entry_heights: HashMap<irsm::BasicBlockRef, usize>,
+    bbs: HashMap<irsm::BasicBlockRef, ir3a::BasicBlockId>,
+}
+
+impl<'a> FunctionLowering<'a> {
+    fn new(fun: &'a irsm::Function) -> Self {
+        let nb_locals = fun
+            .bb_list()
+            .iter()
+            .flat_map(|bb| bb.ins_list().iter())
+            .filter_map(|ins| match ins {
+                irsm::Ins::Load(ins) => Some(ins.src().id() + 1),
+                irsm::Ins::Store(ins) => Some(ins.dst().id() + 1),
+                _ => None,
+            })
+            .max()
+            .unwrap_or(0);
+
+        FunctionLowering {
+            fun,
+            nb_locals,
+            entry_heights: HashMap::new(),
+            bbs: HashMap::new(),
+        }
+    }
+
+    fn translate(mut self, builder: &mut IRBuilder) {
+        self.compute_heights();
+
+        // create all basic blocks first, in the same order (entry point stays the first one)
+        for bb in self.fun.bb_list() {
+            if self.entry_heights.contains_key(&bb.id()) {
+                let bb_id = builder.create_basic_block();
+                self.bbs.insert(bb.id(), bb_id);
+            }
+        }
+
+        for bb in self.fun.bb_list() {
+            if let Some(&height) = self.entry_heights.get(&bb.id()) {
+                builder.set_insert_point(self.bbs[&bb.id()]);
+                self.translate_bb(builder, bb, height);
+            }
+        }
+    }
+
+    // compute the height of the operands stack at the beginning of every reachable basic block
+    // the height is the minimum over all the predecessors
+    fn compute_heights(&mut self) {
+        let entry = self.fun.bb_list()[0].id();
+        self.entry_heights.insert(entry, 0);
+        let mut worklist = vec![entry];
+
+        while let Some(bb_id) = worklist.pop() {
+            let bb = self.fun.get_bb(bb_id);
+            let height = self.exit_height(bb, self.entry_heights[&bb_id]);
+            for succ in successors(bb) {
+                let update = match self.entry_heights.get(&succ) {
+                    Some(succ_height) => height < *succ_height,
+                    None => true,
+                };
+                if update {
+                    self.entry_heights.insert(succ, height);
+                    worklist.push(succ);
+                }
+            }
+        }
+    }
+
+    // returns the height of the operands stack before the branch instruction (after popping the br value)
+    fn exit_height(&self, bb: &irsm::BasicBlock, entry_height: usize) -> usize {
+        let mut height = entry_height;
+        for ins in bb.ins_list() {
+            if height < ins.operands_pops() {
+                panic!(
+                    "Cannot lower function #{}: operands stack underflow in basic block {}",
+                    self.fun.id(),
+                    bb.id()
+                );
+            }
+            height = (height as i32 + ins.operands_size_change()) as usize;
+        }
+        height
+    }
+
+    fn local(&self, id: irsm::LocalsIndex) -> ir3a::RegId {
+        ir3a::RegId(id.id())
+    }
+
+    fn slot(&self, height: usize) -> ir3a::RegId {
+        ir3a::RegId(self.nb_locals + height)
+    }
+
+    fn translate_bb(&self, builder: &mut IRBuilder, bb: &irsm::BasicBlock, entry_height: usize) {
+        let mut h = entry_height;
+        for ins in bb.ins_list() {
+            match ins {
+                irsm::Ins::Pop(_) => {}
+                irsm::Ins::Const(ins) => builder.ins_movi(self.slot(h), ins.val()),
+                irsm::Ins::Load(ins) => builder.ins_movr(self.slot(h), self.local(ins.src())),
+                irsm::Ins::Store(ins) => builder.ins_movr(self.local(ins.dst()), self.slot(h - 1)),
+                irsm::Ins::Opbin(ins) => {
+                    let kind = match ins {
+                        irsm::InsOpbin::Add => ir3a::InsOpbinKind::Add,
+                        irsm::InsOpbin::Sub => ir3a::InsOpbinKind::Sub,
+                        irsm::InsOpbin::Mul => ir3a::InsOpbinKind::Mul,
+                        irsm::InsOpbin::Div => ir3a::InsOpbinKind::Div,
+                        irsm::InsOpbin::Rem => ir3a::InsOpbinKind::Mod,
+                        irsm::InsOpbin::And => ir3a::InsOpbinKind::And,
+                        irsm::InsOpbin::Or => ir3a::InsOpbinKind::Or,
+                        irsm::InsOpbin::Xor => ir3a::InsOpbinKind::Xor,
+                        irsm::InsOpbin::Shl => ir3a::InsOpbinKind::Shl,
+                        irsm::InsOpbin::Shr => ir3a::InsOpbinKind::Shr,
+                        irsm::InsOpbin::Sar => ir3a::InsOpbinKind::Sar,
+                    };
+                    let (dst, src) = (self.slot(h - 2), self.slot(h - 1));
+                    builder.append_ins(ir3a::Ins::Opbin(ir3a::InsOpbin::new(kind, dst, dst, src)));
+                }
+                irsm::Ins::Cmpbin(ins) => {
+                    let kind = match ins {
+                        irsm::InsCmpbin::Eq => ir3a::InsCmpbinKind::Eq,
+                        irsm::InsCmpbin::Lt => ir3a::InsCmpbinKind::Lt,
+                        irsm::InsCmpbin::Gt => ir3a::InsCmpbinKind::Gt,
+                        irsm::InsCmpbin::Ne => ir3a::InsCmpbinKind::Ne,
+                        irsm::InsCmpbin::Le => ir3a::InsCmpbinKind::Le,
+                        irsm::InsCmpbin::Ge => ir3a::InsCmpbinKind::Ge,
+                        irsm::InsCmpbin::Ult => ir3a::InsCmpbinKind::Ult,
+                        irsm::InsCmpbin::Ule => ir3a::InsCmpbinKind::Ule,
+                        irsm::InsCmpbin::Ugt => ir3a::InsCmpbinKind::Ugt,
+                        irsm::InsCmpbin::Uge => ir3a::InsCmpbinKind::Uge,
+                    };
+                    let (dst, src) = (self.slot(h - 2), self.slot(h - 1));
+                    builder
+                        .append_ins(ir3a::Ins::Cmpbin(ir3a::InsCmpbin::new(kind, dst, dst, src)));
+                }
+                irsm::Ins::Jump(ins) => {
+                    self.edge_moves(builder, ins.dst(), h);
+                    builder.ins_jump(self.bbs[&ins.dst()]);
+                }
+                irsm::Ins::Br(ins) => {
+                    let dst_true = self.edge_block(builder, ins.dst_true(), h - 1);
+                    let dst_false = self.edge_block(builder, ins.dst_false(), h - 1);
+                    builder.ins_br(self.slot(h - 1), dst_true, dst_false);
+                }
+                irsm::Ins::Call(ins) => {
+                    let base = h - ins.nb_args();
+                    let args = (base..h).map(|i| self.slot(i)).collect();
+                    builder.ins_call(self.slot(base), ir3a::FunctionId(ins.fun().id()), args);
+                }
+                irsm::Ins::Ret(_) => builder.ins_ret(self.slot(h - 1)),
+            }
+            h = (h as i32 + ins.operands_size_change()) as usize;
+        }
+    }
+
+    // move the top values of the operands stack to the bottom slots, when leaving with `height` values
+    fn edge_moves(&self, builder: &mut IRBuilder, dst: irsm::BasicBlockRef, height: usize) {
+        if self.needs_moves(dst, height) {
+            let dst_height = self.entry_heights[&dst];
+            let shift = height - dst_height;
+            for i in 0..dst_height {
+                builder.ins_movr(self.slot(i), self.slot(i + shift));
+            }
+        }
+    }
+
+    fn needs_moves(&self, dst: irsm::BasicBlockRef, height: usize) -> bool {
+        let dst_height = self.entry_heights[&dst];
+        dst_height > 0 && dst_height < height
+    }
+
+    // returns the basic block to branch to for an edge of a br
+    // a new basic block is created if values need to be moved
+    fn edge_block(
+        &self,
+        builder: &mut IRBuilder,
+        dst: irsm::BasicBlockRef,
+        height: usize,
+    ) -> ir3a::BasicBlockId {
+        if !self.needs_moves(dst, height) {
+            return self.bbs[&dst];
+        }
+
+        let act_bb = builder.get_insert_point().unwrap();
+        let edge_bb = builder.create_basic_block();
+        builder.set_insert_point(edge_bb);
+        self.edge_moves(builder, dst, height);
+        builder.ins_jump(self.bbs[&dst]);
+        builder.set_insert_point(act_bb);
+        edge_bb
+    }
+}
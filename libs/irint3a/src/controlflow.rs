@@ -26,6 +26,17 @@ pub fn build_cfg(fun: &ir::Function) -> Digraph {
                     add_edge_once(&mut g, bb_v, ins.dst_true().0);
                     add_edge_once(&mut g, bb_v, ins.dst_false().0);
                 }
+                ir::Ins::Switch(ins) => {
+                    for (_, dst) in ins.cases() {
+                        add_edge_once(&mut g, bb_v, dst.0);
+                    }
+                    add_edge_once(&mut g, bb_v, ins.dst_default().0);
+                }
+                ir::Ins::Jumpt(ins) => {
+                    for dst in ins.table() {
+                        add_edge_once(&mut g, bb_v, dst.0);
+                    }
+                }
                 _ => {}
             }
         }
@@ -55,6 +55,19 @@ impl Digraph {
         path: &str,
         gname: Option<&str>,
         vnames: Option<&HashMap<usize, String>>,
+    ) {
+        self.write_dot_labels(path, gname, vnames, None);
+    }
+
+    // Same as write_dot, with extra labels on edges
+    // `elabels` optional map of labels for edges (v, w)
+    // If none, or not defined for one edge, the edge has no label
+    pub fn write_dot_labels(
+        &self,
+        path: &str,
+        gname: Option<&str>,
+        vnames: Option<&HashMap<usize, String>>,
+        elabels: Option<&HashMap<(usize, usize), String>>,
     ) {
         let gname = gname.unwrap_or("g");
         let base_names = HashMap::new();
@@ -65,6 +78,8 @@ impl Digraph {
                 None => format!("{}", x),
             })
             .collect();
+        let base_labels = HashMap::new();
+        let elabels = elabels.unwrap_or(&base_labels);
 
         let mut os = File::create(path).expect("Failed to create output dot file");
 
@@ -72,7 +87,15 @@ impl Digraph {
 
         for v in 0..self.vcount() {
             for w in self.adj(v) {
-                write!(os, "  {} -> {};\n", vnames[v], vnames[*w]).unwrap();
+                match elabels.get(&(v, *w)) {
+                    Some(label) => write!(
+                        os,
+                        "  {} -> {} [label=\"{}\"];\n",
+                        vnames[v], vnames[*w], label
+                    )
+                    .unwrap(),
+                    None => write!(os, "  {} -> {};\n", vnames[v], vnames[*w]).unwrap(),
+                }
             }
         }
 
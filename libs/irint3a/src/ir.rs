@@ -1,21 +1,38 @@
 // Basic rules about the IR
 //
 // Registers:
-// All registrs are 32 bits
+// Registers can hold values up to 64 bits
 // There is an infinite number of registers, identified by a unique usize
 //
 // Datatypes:
-// The IR is not typed, but basically instructions work on 2 types of data:
-// - 32 bits signed integer
+// Instructions work on 2 types of data:
+// - signed integers of 8, 16, 32 or 64 bits (IntWidth)
 // - 32 bits memory address
+// Every instruction that reads / writes integers has a width (i32 by default),
+// and all the registers it uses have this width (except the result of comparisons, always i32)
+// A register has always the same width: it's infered from the instructions, and checked by the validator
+// Values of different widths are converted with the sext / zext / trunc instructions
+//
+// Memory:
+// Memory is addressed by bytes: an i32 value at address a uses the bytes a to a + 3 (little-endian)
+// load / store read / write a value of their width
+// alloca allocates n contiguous values of its width in the frame of the function, they are freed when the function returns
+// Address arithmetic (add / sub of an integer to an address) is only valid if the result stays inside
+// the same memory area: the locals of the same frame, or the globals
 //
 // Function call / return:
-// all function arguments are 32 bits, and stored in register 0, 1, 2, etc
+// function arguments are stored in register 0, 1, 2, etc (with the width of the caller registers)
 // The notion of stack frame / calling convention is abstracted away and not present in the IR
 // There is no special register to pass arguments, or get return value: it's all chosen with call instruction
 // There is no special register to set return value: it's all chosen with ret instruction
 // All the registers remain unchanged after returning from a function call (even if callee change some registers)
 //
+// Globals:
+// A module can define global variables: an array of `size` values of a width, that lives for the whole program
+// The first values can be initialized by the module (init data), all others start at 0
+// The address of a global is obtained with the gaddr instruction,
+// and the address of the value i of a global is this address + i * <width bytes>
+//
 // Branching:
 // Jump / Br instructions can only jump to the beginning of a basic block of the same function
 //
@@ -49,6 +66,75 @@ pub struct BasicBlockId(pub usize);
 #[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, PartialOrd, Ord)]
 pub struct FunctionId(pub usize);
 
+/// Global variable unique identifier
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, PartialOrd, Ord)]
+pub struct GlobalId(pub usize);
+
+/// Width of an integer: the size of a register value, or of a memory access
+/// In the IR syntax, it's a suffix of the instruction name (add.i64), and i32 if missing
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum IntWidth {
+    I8,
+    I16,
+    I32,
+    I64,
+}
+
+impl IntWidth {
+    /// Number of bytes used in memory
+    pub fn bytes(&self) -> usize {
+        match self {
+            IntWidth::I8 => 1,
+            IntWidth::I16 => 2,
+            IntWidth::I32 => 4,
+            IntWidth::I64 => 8,
+        }
+    }
+
+    pub fn bits(&self) -> u32 {
+        self.bytes() as u32 * 8
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            IntWidth::I8 => "i8",
+            IntWidth::I16 => "i16",
+            IntWidth::I32 => "i32",
+            IntWidth::I64 => "i64",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<IntWidth> {
+        match name {
+            "i8" => Some(IntWidth::I8),
+            "i16" => Some(IntWidth::I16),
+            "i32" => Some(IntWidth::I32),
+            "i64" => Some(IntWidth::I64),
+            _ => None,
+        }
+    }
+
+    /// Keep only the lowest bits of `val`, sign-extended to 64 bits
+    pub fn sign_extend(&self, val: i64) -> i64 {
+        match self {
+            IntWidth::I8 => val as i8 as i64,
+            IntWidth::I16 => val as i16 as i64,
+            IntWidth::I32 => val as i32 as i64,
+            IntWidth::I64 => val,
+        }
+    }
+
+    /// Keep only the lowest bits of `val`, zero-extended to 64 bits
+    pub fn zero_extend(&self, val: i64) -> u64 {
+        match self {
+            IntWidth::I8 => val as u8 as u64,
+            IntWidth::I16 => val as u16 as u64,
+            IntWidth::I32 => val as u32 as u64,
+            IntWidth::I64 => val as u64,
+        }
+    }
+}
+
 /// Represent a simple IR instruction
 #[derive(Clone, Debug)]
 pub enum Ins {
@@ -57,18 +143,24 @@ pub enum Ins {
     Load(InsLoad),
     Store(InsStore),
     Alloca(InsAlloca),
-    Opbin(InsOpbin),   //add, sub, mov, div, mod
-    Cmpbin(InsCmpbin), //cmpeq, cmplt, cmpgt
+    Gaddr(InsGaddr),
+    Opbin(InsOpbin),   //add, sub, mul, div, mod, and, or, xor, shl, shr, sar
+    Cmpbin(InsCmpbin), //cmpeq, cmpne, cmplt, cmple, cmpgt, cmpge, cmpult, cmpule, cmpugt, cmpuge
+    Conv(InsConv),     //sext, zext, trunc
     Jump(InsJump),
     Br(InsBr),
+    Switch(InsSwitch),
+    Jumpt(InsJumpt),
     Call(InsCall),
+    Funaddr(InsFunaddr),
+    Callr(InsCallr),
     Ret(InsRet),
 }
 
 impl Ins {
     pub fn is_control_flow(&self) -> bool {
         match self {
-            Ins::Jump(_) | Ins::Br(_) | Ins::Ret(_) => true,
+            Ins::Jump(_) | Ins::Br(_) | Ins::Switch(_) | Ins::Jumpt(_) | Ins::Ret(_) => true,
             _ => false,
         }
     }
@@ -76,22 +168,36 @@ impl Ins {
 
 /// Instruction movi
 /// Move constant integer into a register
+/// The constant is truncated to the width of the instruction
 #[derive(Clone, Copy, Debug)]
 pub struct InsMovi {
+    width: IntWidth,
     dst: RegId,
-    const_val: i32,
+    const_val: i64,
 }
 
 impl InsMovi {
     pub fn new(dst: RegId, const_val: i32) -> Self {
-        InsMovi { dst, const_val }
+        Self::new_with_width(IntWidth::I32, dst, const_val as i64)
+    }
+
+    pub fn new_with_width(width: IntWidth, dst: RegId, const_val: i64) -> Self {
+        InsMovi {
+            width,
+            dst,
+            const_val: width.sign_extend(const_val),
+        }
+    }
+
+    pub fn width(&self) -> IntWidth {
+        self.width
     }
 
     pub fn dst(&self) -> RegId {
         self.dst
     }
 
-    pub fn const_val(&self) -> i32 {
+    pub fn const_val(&self) -> i64 {
         self.const_val
     }
 }
@@ -119,16 +225,25 @@ impl InsMovr {
 }
 
 /// Instruction load
-/// Read the 32bit address stored in src register, and load the value at that address to the register dst
+/// Read the 32bit address stored in src register, and load the value of the instruction width at that address to the register dst
 #[derive(Clone, Copy, Debug)]
 pub struct InsLoad {
+    width: IntWidth,
     dst: RegId,
     src: RegId,
 }
 
 impl InsLoad {
     pub fn new(dst: RegId, src: RegId) -> Self {
-        InsLoad { dst, src }
+        Self::new_with_width(IntWidth::I32, dst, src)
+    }
+
+    pub fn new_with_width(width: IntWidth, dst: RegId, src: RegId) -> Self {
+        InsLoad { width, dst, src }
+    }
+
+    pub fn width(&self) -> IntWidth {
+        self.width
     }
 
     pub fn dst(&self) -> RegId {
@@ -142,15 +257,25 @@ impl InsLoad {
 
 /// Instruction store
 /// Read the 32bit adress stored in dst register, and store the content of src register at that address
+/// (only the bytes of the instruction width are written)
 #[derive(Clone, Copy, Debug)]
 pub struct InsStore {
+    width: IntWidth,
     dst: RegId,
     src: RegId,
 }
 
 impl InsStore {
     pub fn new(dst: RegId, src: RegId) -> Self {
-        InsStore { dst, src }
+        Self::new_with_width(IntWidth::I32, dst, src)
+    }
+
+    pub fn new_with_width(width: IntWidth, dst: RegId, src: RegId) -> Self {
+        InsStore { width, dst, src }
+    }
+
+    pub fn width(&self) -> IntWidth {
+        self.width
     }
 
     pub fn dst(&self) -> RegId {
@@ -163,20 +288,64 @@ impl InsStore {
 }
 
 /// Instruction Alloca
-/// Allocate a variable local to the function, and write it's memory address in dst register
+/// Allocate a variable of `size` values of the instruction width local to the function,
+/// and write the memory address of its first byte in dst register
 #[derive(Clone, Copy, Debug)]
 pub struct InsAlloca {
+    width: IntWidth,
     dst: RegId,
+    size: usize,
 }
 
 impl InsAlloca {
-    pub fn new(dst: RegId) -> Self {
-        InsAlloca { dst }
+    pub fn new(dst: RegId, size: usize) -> Self {
+        Self::new_with_width(IntWidth::I32, dst, size)
+    }
+
+    pub fn new_with_width(width: IntWidth, dst: RegId, size: usize) -> Self {
+        InsAlloca { width, dst, size }
+    }
+
+    pub fn width(&self) -> IntWidth {
+        self.width
     }
 
     pub fn dst(&self) -> RegId {
         self.dst
     }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Number of allocated bytes
+    pub fn bytes(&self) -> usize {
+        self.size * self.width.bytes()
+    }
+}
+
+/// Instruction gaddr
+/// Write the memory address of a global variable in dst register
+/// The global must exist in the current Module
+/// (checked with validator module)
+#[derive(Clone, Copy, Debug)]
+pub struct InsGaddr {
+    dst: RegId,
+    global: GlobalId,
+}
+
+impl InsGaddr {
+    pub fn new(dst: RegId, global: GlobalId) -> Self {
+        InsGaddr { dst, global }
+    }
+
+    pub fn dst(&self) -> RegId {
+        self.dst
+    }
+
+    pub fn global(&self) -> GlobalId {
+        self.global
+    }
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -186,14 +355,22 @@ pub enum InsOpbinKind {
     Mul,
     Div,
     Mod,
+    And,
+    Or,
+    Xor,
+    Shl, // shift left
+    Shr, // logical shift right (fills with 0)
+    Sar, // arithmetic shift right (fills with the sign bit)
 }
 
 /// Represent multiple instructions for binary operations
-/// Read int32 values from both src registers, compute and store result in dst register
-/// regroup instructions add, sub, mul, div, mod
+/// Read integer values of the instruction width from both src registers, compute and store result in dst register
+/// regroup instructions add, sub, mul, div, mod, and, or, xor, shl, shr, sar
+/// The shift instructions only use the lowest bits of src2 (5 bits for i32, 6 for i64)
 #[derive(Clone, Copy, Debug)]
 pub struct InsOpbin {
     kind: InsOpbinKind,
+    width: IntWidth,
     dst: RegId,
     src1: RegId,
     src2: RegId,
@@ -201,8 +378,19 @@ pub struct InsOpbin {
 
 impl InsOpbin {
     pub fn new(kind: InsOpbinKind, dst: RegId, src1: RegId, src2: RegId) -> Self {
+        Self::new_with_width(IntWidth::I32, kind, dst, src1, src2)
+    }
+
+    pub fn new_with_width(
+        width: IntWidth,
+        kind: InsOpbinKind,
+        dst: RegId,
+        src1: RegId,
+        src2: RegId,
+    ) -> Self {
         InsOpbin {
             kind,
+            width,
             dst,
             src1,
             src2,
@@ -213,6 +401,10 @@ impl InsOpbin {
         self.kind
     }
 
+    pub fn width(&self) -> IntWidth {
+        self.width
+    }
+
     pub fn dst(&self) -> RegId {
         self.dst
     }
@@ -229,16 +421,25 @@ impl InsOpbin {
 #[derive(Clone, Copy, Debug)]
 pub enum InsCmpbinKind {
     Eq,
+    Ne,
     Lt,
+    Le,
     Gt,
+    Ge,
+    Ult, // unsigned comparisons: the values are read as uint32
+    Ule,
+    Ugt,
+    Uge,
 }
 
 /// Represent multiple instructions for binary comparisons
-/// Read int32 values from both src registers, compute and store result in dst register: 1 is cmp is true, 0 if false
-/// regroup instructions cmpeq, cmplt, cmpgt
+/// Read integer values of the instruction width from both src registers,
+/// compute and store result in dst register (i32): 1 is cmp is true, 0 if false
+/// regroup instructions cmpeq, cmpne, cmplt, cmple, cmpgt, cmpge, and the unsigned cmpult, cmpule, cmpugt, cmpuge
 #[derive(Clone, Copy, Debug)]
 pub struct InsCmpbin {
     kind: InsCmpbinKind,
+    width: IntWidth,
     dst: RegId,
     src1: RegId,
     src2: RegId,
@@ -246,8 +447,19 @@ pub struct InsCmpbin {
 
 impl InsCmpbin {
     pub fn new(kind: InsCmpbinKind, dst: RegId, src1: RegId, src2: RegId) -> Self {
+        Self::new_with_width(IntWidth::I32, kind, dst, src1, src2)
+    }
+
+    pub fn new_with_width(
+        width: IntWidth,
+        kind: InsCmpbinKind,
+        dst: RegId,
+        src1: RegId,
+        src2: RegId,
+    ) -> Self {
         InsCmpbin {
             kind,
+            width,
             dst,
             src1,
             src2,
@@ -258,6 +470,11 @@ impl InsCmpbin {
         self.kind
     }
 
+    /// Width of the compared values
+    pub fn width(&self) -> IntWidth {
+        self.width
+    }
+
     pub fn dst(&self) -> RegId {
         self.dst
     }
@@ -271,6 +488,59 @@ impl InsCmpbin {
     }
 }
 
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum InsConvKind {
+    Sext,  // sign extension to a bigger width
+    Zext,  // zero extension to a bigger width
+    Trunc, // keep the lowest bits, for a smaller width
+}
+
+/// Represent the conversions between integer widths
+/// Read the value of width `from` in src register, convert it, and store the value of width `to` in dst register
+/// regroup instructions sext, zext, trunc, the syntax is <ins>.<from>.<to> (eg: sext.i8.i32)
+/// sext and zext must go to a bigger width, trunc to a smaller one
+/// (checked with validator module)
+#[derive(Clone, Copy, Debug)]
+pub struct InsConv {
+    kind: InsConvKind,
+    from: IntWidth,
+    to: IntWidth,
+    dst: RegId,
+    src: RegId,
+}
+
+impl InsConv {
+    pub fn new(kind: InsConvKind, from: IntWidth, to: IntWidth, dst: RegId, src: RegId) -> Self {
+        InsConv {
+            kind,
+            from,
+            to,
+            dst,
+            src,
+        }
+    }
+
+    pub fn kind(&self) -> InsConvKind {
+        self.kind
+    }
+
+    pub fn from(&self) -> IntWidth {
+        self.from
+    }
+
+    pub fn to(&self) -> IntWidth {
+        self.to
+    }
+
+    pub fn dst(&self) -> RegId {
+        self.dst
+    }
+
+    pub fn src(&self) -> RegId {
+        self.src
+    }
+}
+
 /// Instruction jump
 /// Unconditional jump to a basic block
 /// The basic block must belong to the current function
@@ -324,6 +594,114 @@ impl InsBr {
     }
 }
 
+/// Instruction switch
+/// Multi-way jump: compare the value of the register src with every case value,
+/// jump to the basic block of the case equal to it, or to dst-default if there is none
+/// The case values are truncated to the width of the instruction, they must be unique
+/// The basic blocks must belong to the current function
+/// (checked with validator module)
+#[derive(Clone, Debug)]
+pub struct InsSwitch {
+    width: IntWidth,
+    src: RegId,
+    cases: Vec<(i64, BasicBlockId)>,
+    dst_default: BasicBlockId,
+}
+
+impl InsSwitch {
+    pub fn new(src: RegId, cases: Vec<(i64, BasicBlockId)>, dst_default: BasicBlockId) -> Self {
+        Self::new_with_width(IntWidth::I32, src, cases, dst_default)
+    }
+
+    pub fn new_with_width(
+        width: IntWidth,
+        src: RegId,
+        cases: Vec<(i64, BasicBlockId)>,
+        dst_default: BasicBlockId,
+    ) -> Self {
+        let cases = cases
+            .into_iter()
+            .map(|(val, bb)| (width.sign_extend(val), bb))
+            .collect();
+        InsSwitch {
+            width,
+            src,
+            cases,
+            dst_default,
+        }
+    }
+
+    pub fn width(&self) -> IntWidth {
+        self.width
+    }
+
+    pub fn src(&self) -> RegId {
+        self.src
+    }
+
+    pub fn cases(&self) -> &[(i64, BasicBlockId)] {
+        &self.cases
+    }
+
+    pub fn dst_default(&self) -> BasicBlockId {
+        self.dst_default
+    }
+
+    /// Basic block where the switch jumps when src has the value val
+    pub fn target(&self, val: i64) -> BasicBlockId {
+        let val = self.width.sign_extend(val);
+        self.cases
+            .iter()
+            .find(|(case_val, _)| *case_val == val)
+            .map_or(self.dst_default, |(_, bb)| *bb)
+    }
+}
+
+/// Instruction jumpt
+/// Jump table: jump to the basic block at the index given by the register src in the table
+/// The index is the unsigned value of src in the width of the instruction,
+/// it must be smaller than the size of the table (the interpreter panics otherwise)
+/// The table must not be empty, and the basic blocks must belong to the current function
+/// (checked with validator module)
+#[derive(Clone, Debug)]
+pub struct InsJumpt {
+    width: IntWidth,
+    src: RegId,
+    table: Vec<BasicBlockId>,
+}
+
+impl InsJumpt {
+    pub fn new(src: RegId, table: Vec<BasicBlockId>) -> Self {
+        Self::new_with_width(IntWidth::I32, src, table)
+    }
+
+    pub fn new_with_width(width: IntWidth, src: RegId, table: Vec<BasicBlockId>) -> Self {
+        InsJumpt { width, src, table }
+    }
+
+    pub fn width(&self) -> IntWidth {
+        self.width
+    }
+
+    pub fn src(&self) -> RegId {
+        self.src
+    }
+
+    pub fn table(&self) -> &[BasicBlockId] {
+        &self.table
+    }
+
+    /// Basic block where the jump table goes when src has the value val, None if it's outside of the table
+    pub fn target(&self, val: i64) -> Option<BasicBlockId> {
+        let idx = self.width.zero_extend(val);
+        if idx < self.table.len() as u64 {
+            Some(self.table[idx as usize])
+        } else {
+            None
+        }
+    }
+}
+
 /// Instruction call
 /// Call specified function with arguments stored in given args registers, and store return value in dst register
 /// The function id must exit in the current Module
@@ -353,6 +731,60 @@ impl InsCall {
     }
 }
 
+/// Instruction funaddr
+/// Store the address of the function fun in the dst register
+/// The address is an i32 value, that can only be used by callr (or compared / moved around)
+/// The function id must exit in the current Module
+/// (checked with validator module)
+#[derive(Clone, Copy, Debug)]
+pub struct InsFunaddr {
+    dst: RegId,
+    fun: FunctionId,
+}
+
+impl InsFunaddr {
+    pub fn new(dst: RegId, fun: FunctionId) -> Self {
+        InsFunaddr { dst, fun }
+    }
+
+    pub fn dst(&self) -> RegId {
+        self.dst
+    }
+
+    pub fn fun(&self) -> FunctionId {
+        self.fun
+    }
+}
+
+/// Instruction callr
+/// Indirect call: call the function whose address is in the register fptr,
+/// with arguments stored in given args registers, and store return value in dst register
+/// The address must have been computed by a funaddr instruction (checked at runtime)
+#[derive(Clone, Debug)]
+pub struct InsCallr {
+    dst: RegId,
+    fptr: RegId,
+    args: Vec<RegId>,
+}
+
+impl InsCallr {
+    pub fn new(dst: RegId, fptr: RegId, args: Vec<RegId>) -> Self {
+        InsCallr { dst, fptr, args }
+    }
+
+    pub fn dst(&self) -> RegId {
+        self.dst
+    }
+
+    pub fn fptr(&self) -> RegId {
+        self.fptr
+    }
+
+    pub fn args(&self) -> &Vec<RegId> {
+        &self.args
+    }
+}
+
 /// Instruction ret
 /// Stop the current function, and return the value in the register src
 #[derive(Clone, Copy, Debug)]
@@ -370,14 +802,54 @@ impl InsRet {
     }
 }
 
+/// Debug location of an instruction in the source file it was generated from
+/// Lines and columns start at 1
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DebugLoc {
+    file: String,
+    line: usize,
+    col: usize,
+}
+
+impl DebugLoc {
+    pub fn new(file: &str, line: usize, col: usize) -> Self {
+        DebugLoc {
+            file: file.to_string(),
+            line,
+            col,
+        }
+    }
+
+    pub fn file(&self) -> &str {
+        &self.file
+    }
+
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    pub fn col(&self) -> usize {
+        self.col
+    }
+}
+
+impl std::fmt::Display for DebugLoc {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.col)
+    }
+}
+
 /// A Basic block is an ordered sequence of instructions that must end with a control flow instruction.
 /// This rule is not enforced by the struct implementation, but by an extern validation module.
 /// It's possible to insert or remove instructions anywhere from the list,
 /// to change the position of one instruction, or to mutate one instruction
+/// Every instruction may have a debug location, that follows the instruction when it's moved
+#[derive(Clone)]
 pub struct BasicBlock {
     id: BasicBlockId,
     fun_id: FunctionId,
     ins_list: Vec<Ins>,
+    locs_list: Vec<Option<DebugLoc>>,
 }
 
 impl BasicBlock {
@@ -386,6 +858,7 @@ impl BasicBlock {
             id,
             fun_id,
             ins_list: vec![],
+            locs_list: vec![],
         }
     }
 
@@ -418,44 +891,91 @@ impl BasicBlock {
         self.ins_list.as_mut_slice().iter_mut()
     }
 
+    /// Returns the debug location of the instruction at the position `idx`, if it has one
+    pub fn get_ins_loc(&self, idx: usize) -> Option<&DebugLoc> {
+        self.locs_list[idx].as_ref()
+    }
+
+    /// Change the debug location of the instruction at the position `idx`
+    pub fn set_ins_loc(&mut self, idx: usize, loc: Option<DebugLoc>) {
+        self.locs_list[idx] = loc;
+    }
+
     /// Add an instruction at the end of the basic block
     pub fn push_ins(&mut self, ins: Ins) {
+        self.push_ins_with_loc(ins, None);
+    }
+
+    /// Add an instruction with a debug location at the end of the basic block
+    pub fn push_ins_with_loc(&mut self, ins: Ins, loc: Option<DebugLoc>) {
         self.ins_list.push(ins);
+        self.locs_list.push(loc);
     }
 
     /// Remove the final instruction of the basic block
     pub fn pop_ins(&mut self) {
         self.ins_list.pop().unwrap();
+        self.locs_list.pop();
     }
 
     /// Insert instruction `ins` at the position `idx`, all other instructions after will be shifted to the right
     pub fn insert_ins(&mut self, idx: usize, ins: Ins) {
         self.ins_list.insert(idx, ins);
+        self.locs_list.insert(idx, None);
     }
 
     /// Remove instruction at the position `idx`, all other instructions after will be shifted to the left
     pub fn remove_ins(&mut self, idx: usize) {
         self.ins_list.remove(idx);
+        self.locs_list.remove(idx);
     }
 
     /// Move the instruction at the position `old_idx` to the position `new_idx`, shifting instructions in between.
     pub fn move_ins(&mut self, old_idx: usize, new_idx: usize) {
         let ins = self.ins_list.remove(old_idx);
         self.ins_list.insert(new_idx, ins);
+        let loc = self.locs_list.remove(old_idx);
+        self.locs_list.insert(new_idx, loc);
+    }
+}
+
+/// Optional signature of a function: number of arguments, and if it returns a value
+/// The arguments are in the registers 0 to nb_args - 1 of the callee
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Signature {
+    nb_args: usize,
+    is_void: bool,
+}
+
+impl Signature {
+    pub fn new(nb_args: usize, is_void: bool) -> Self {
+        Signature { nb_args, is_void }
+    }
+
+    pub fn nb_args(&self) -> usize {
+        self.nb_args
+    }
+
+    /// A void function doesn't return a value: the register given to ret can be uninitialized
+    pub fn is_void(&self) -> bool {
+        self.is_void
     }
 }
 
 /// Function definition
 /// A function has no types or registers, it simply pass argument and return values through registers as it wants
+/// It may have a signature, that is checked by the validator (calls and registers used for arguments)
 /// A function can be extern: only a declaration, no code
 ///
 /// A function is an ordered sequence of basic block
 /// It's possible to insert, remove and change the order of basic blocks
 /// The basic block at position 0 is the entry point of the function
 /// The ordering after 0 doesn't change program execution, only the display of code
+#[derive(Clone)]
 pub struct Function {
     id: FunctionId,
     is_extern: bool,
+    signature: Option<Signature>,
     bbs: HashMap<BasicBlockId, BasicBlock>,
     bbs_list: Vec<BasicBlockId>,
     bb_count: usize, //counter to create unique ids
@@ -466,6 +986,7 @@ impl Function {
         Function {
             id,
             is_extern,
+            signature: None,
             bbs: HashMap::new(),
             bbs_list: vec![],
             bb_count: 0,
@@ -480,6 +1001,14 @@ impl Function {
         self.is_extern
     }
 
+    pub fn signature(&self) -> Option<Signature> {
+        self.signature
+    }
+
+    pub fn set_signature(&mut self, signature: Option<Signature>) {
+        self.signature = signature;
+    }
+
     pub fn basic_blocks_list(&self) -> &[BasicBlockId] {
         assert!(!self.is_extern);
         &self.bbs_list
@@ -546,14 +1075,51 @@ impl Function {
     }
 }
 
+/// Global variable definition
+/// `size` is the number of values of `width`
+/// `init` gives the value of the first values, the others are initialized to 0
+#[derive(Clone)]
+pub struct Global {
+    id: GlobalId,
+    width: IntWidth,
+    size: usize,
+    init: Vec<i64>,
+}
+
+impl Global {
+    pub fn id(&self) -> GlobalId {
+        self.id
+    }
+
+    pub fn width(&self) -> IntWidth {
+        self.width
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Number of bytes of the global
+    pub fn bytes(&self) -> usize {
+        self.size * self.width.bytes()
+    }
+
+    pub fn init(&self) -> &[i64] {
+        &self.init
+    }
+}
+
 /// A module represent the whole definition of an IR file
-/// It's a sequence of functions
+/// It's a sequence of global variables and functions
 /// It's possible to add or remove functions
 /// Remove a function also remove all the basic blocks
 /// You also need to use this class if you want to add/remove a basic block for a function
+#[derive(Clone)]
 pub struct Module {
     funs: Vec<Function>,
     funs_by_id: HashMap<FunctionId, usize>, //value is the index in funs vector
+    globals: Vec<Global>,
+    globals_by_id: HashMap<GlobalId, usize>, //value is the index in globals vector
 }
 
 impl Module {
@@ -561,9 +1127,50 @@ impl Module {
         Module {
             funs: vec![],
             funs_by_id: HashMap::new(),
+            globals: vec![],
+            globals_by_id: HashMap::new(),
         }
     }
 
+    pub fn globals(&self) -> &[Global] {
+        &self.globals
+    }
+
+    pub fn get_global(&self, id: GlobalId) -> Option<&Global> {
+        let idx = *self.globals_by_id.get(&id)?;
+        Some(&self.globals[idx])
+    }
+
+    /// Create a new global variable of `size` i32 values, with the first values initialized to `init`
+    pub fn create_global(&mut self, id: GlobalId, size: usize, init: Vec<i32>) {
+        let init = init.into_iter().map(|x| x as i64).collect();
+        self.create_global_with_width(id, IntWidth::I32, size, init);
+    }
+
+    /// Create a new global variable of `size` values of `width`, with the first values initialized to `init`
+    /// (init values are truncated to the width)
+    pub fn create_global_with_width(
+        &mut self,
+        id: GlobalId,
+        width: IntWidth,
+        size: usize,
+        init: Vec<i64>,
+    ) {
+        if self.globals_by_id.contains_key(&id) {
+            panic!("Module already has a global with id #{}", id.0);
+        }
+
+        let init = init.into_iter().map(|x| width.sign_extend(x)).collect();
+        let global_idx = self.globals.len();
+        self.globals.push(Global {
+            id,
+            width,
+            size,
+            init,
+        });
+        self.globals_by_id.insert(id, global_idx);
+    }
+
     pub fn funs(&self) -> &[Function] {
         &self.funs
     }
@@ -591,6 +1198,20 @@ impl Module {
         self.create_new_function(id, true);
     }
 
+    /// Add a copy of a function (usually from another module) with the id `id`
+    /// The instructions are copied as is: the ids of the functions and globals they use aren't changed
+    pub fn import_function(&mut self, fun: &Function, id: FunctionId) {
+        self.create_new_function(id, fun.is_extern);
+        let res = self.get_fun_mut(id).unwrap();
+        res.signature = fun.signature;
+        res.bbs = fun.bbs.clone();
+        res.bbs_list = fun.bbs_list.clone();
+        res.bb_count = fun.bb_count;
+        for bb in res.bbs.values_mut() {
+            bb.fun_id = id;
+        }
+    }
+
     fn create_new_function(&mut self, id: FunctionId, is_extern: bool) {
         if self.funs_by_id.get(&id).is_some() {
             panic!("Module already has a function with id #{}", id.0);
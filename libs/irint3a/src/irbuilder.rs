@@ -1,4 +1,5 @@
 use crate::ir;
+use crate::irdiagnostics::Diagnostic;
 use crate::irvalidation;
 
 /// An IRBuilder is linked to a function, and can manipulate basic blocks / instructions
@@ -8,11 +9,16 @@ use crate::irvalidation;
 pub struct IRBuilder<'a> {
     fun: &'a mut ir::Function,
     bb_id: Option<ir::BasicBlockId>,
+    debug_loc: Option<ir::DebugLoc>,
 }
 
 impl<'a> IRBuilder<'a> {
     pub fn new(fun: &'a mut ir::Function) -> Self {
-        IRBuilder { fun, bb_id: None }
+        IRBuilder {
+            fun,
+            bb_id: None,
+            debug_loc: None,
+        }
     }
 
     /// Returns the function id the IRBuilder is linked to
@@ -36,15 +42,26 @@ impl<'a> IRBuilder<'a> {
         self.bb_id = None;
     }
 
+    /// Returns the debug location given to the inserted instructions
+    pub fn get_debug_loc(&self) -> Option<&ir::DebugLoc> {
+        self.debug_loc.as_ref()
+    }
+
+    /// Change the debug location given to all the instructions inserted after this call
+    /// (None to insert instructions without location)
+    pub fn set_debug_loc(&mut self, loc: Option<ir::DebugLoc>) {
+        self.debug_loc = loc;
+    }
+
     ///Create a basic block at the end of the function
     pub fn create_basic_block(&mut self) -> ir::BasicBlockId {
         self.fun.create_basic_block()
     }
 
     /// run the validation on the current function
-    /// panic is the function is invalid
-    pub fn validate(&self) {
-        irvalidation::validate_function(self.fun, None);
+    /// Returns the errors if the function is invalid
+    pub fn validate(&self) -> Result<(), Vec<Diagnostic>> {
+        irvalidation::validate_function(self.fun, None)
     }
 
     /// Append an instruction at the insert point
@@ -54,7 +71,7 @@ impl<'a> IRBuilder<'a> {
             .bb_id
             .expect("Cannot insert instruction: no insert point set");
         let bb = self.fun.get_basic_block_mut(id);
-        bb.push_ins(ins);
+        bb.push_ins_with_loc(ins, self.debug_loc.clone());
     }
 
     pub fn ins_movi(&mut self, dst: ir::RegId, const_val: i32) {
@@ -73,8 +90,34 @@ impl<'a> IRBuilder<'a> {
         self.append_ins(ir::Ins::Store(ir::InsStore::new(dst, src)));
     }
 
-    pub fn ins_alloca(&mut self, dst: ir::RegId) {
-        self.append_ins(ir::Ins::Alloca(ir::InsAlloca::new(dst)));
+    pub fn ins_alloca(&mut self, dst: ir::RegId, size: usize) {
+        self.append_ins(ir::Ins::Alloca(ir::InsAlloca::new(dst, size)));
+    }
+
+    pub fn ins_movi_w(&mut self, width: ir::IntWidth, dst: ir::RegId, const_val: i64) {
+        self.append_ins(ir::Ins::Movi(ir::InsMovi::new_with_width(
+            width, dst, const_val,
+        )));
+    }
+
+    pub fn ins_load_w(&mut self, width: ir::IntWidth, dst: ir::RegId, src: ir::RegId) {
+        self.append_ins(ir::Ins::Load(ir::InsLoad::new_with_width(width, dst, src)));
+    }
+
+    pub fn ins_store_w(&mut self, width: ir::IntWidth, dst: ir::RegId, src: ir::RegId) {
+        self.append_ins(ir::Ins::Store(ir::InsStore::new_with_width(
+            width, dst, src,
+        )));
+    }
+
+    pub fn ins_alloca_w(&mut self, width: ir::IntWidth, dst: ir::RegId, size: usize) {
+        self.append_ins(ir::Ins::Alloca(ir::InsAlloca::new_with_width(
+            width, dst, size,
+        )));
+    }
+
+    pub fn ins_gaddr(&mut self, dst: ir::RegId, global: ir::GlobalId) {
+        self.append_ins(ir::Ins::Gaddr(ir::InsGaddr::new(dst, global)));
     }
 
     pub fn ins_add(&mut self, dst: ir::RegId, src1: ir::RegId, src2: ir::RegId) {
@@ -122,6 +165,60 @@ impl<'a> IRBuilder<'a> {
         )));
     }
 
+    pub fn ins_and(&mut self, dst: ir::RegId, src1: ir::RegId, src2: ir::RegId) {
+        self.append_ins(ir::Ins::Opbin(ir::InsOpbin::new(
+            ir::InsOpbinKind::And,
+            dst,
+            src1,
+            src2,
+        )));
+    }
+
+    pub fn ins_or(&mut self, dst: ir::RegId, src1: ir::RegId, src2: ir::RegId) {
+        self.append_ins(ir::Ins::Opbin(ir::InsOpbin::new(
+            ir::InsOpbinKind::Or,
+            dst,
+            src1,
+            src2,
+        )));
+    }
+
+    pub fn ins_xor(&mut self, dst: ir::RegId, src1: ir::RegId, src2: ir::RegId) {
+        self.append_ins(ir::Ins::Opbin(ir::InsOpbin::new(
+            ir::InsOpbinKind::Xor,
+            dst,
+            src1,
+            src2,
+        )));
+    }
+
+    pub fn ins_shl(&mut self, dst: ir::RegId, src1: ir::RegId, src2: ir::RegId) {
+        self.append_ins(ir::Ins::Opbin(ir::InsOpbin::new(
+            ir::InsOpbinKind::Shl,
+            dst,
+            src1,
+            src2,
+        )));
+    }
+
+    pub fn ins_shr(&mut self, dst: ir::RegId, src1: ir::RegId, src2: ir::RegId) {
+        self.append_ins(ir::Ins::Opbin(ir::InsOpbin::new(
+            ir::InsOpbinKind::Shr,
+            dst,
+            src1,
+            src2,
+        )));
+    }
+
+    pub fn ins_sar(&mut self, dst: ir::RegId, src1: ir::RegId, src2: ir::RegId) {
+        self.append_ins(ir::Ins::Opbin(ir::InsOpbin::new(
+            ir::InsOpbinKind::Sar,
+            dst,
+            src1,
+            src2,
+        )));
+    }
+
     pub fn ins_cmpeq(&mut self, dst: ir::RegId, src1: ir::RegId, src2: ir::RegId) {
         self.append_ins(ir::Ins::Cmpbin(ir::InsCmpbin::new(
             ir::InsCmpbinKind::Eq,
@@ -149,6 +246,108 @@ impl<'a> IRBuilder<'a> {
         )));
     }
 
+    pub fn ins_cmpne(&mut self, dst: ir::RegId, src1: ir::RegId, src2: ir::RegId) {
+        self.append_ins(ir::Ins::Cmpbin(ir::InsCmpbin::new(
+            ir::InsCmpbinKind::Ne,
+            dst,
+            src1,
+            src2,
+        )));
+    }
+
+    pub fn ins_cmple(&mut self, dst: ir::RegId, src1: ir::RegId, src2: ir::RegId) {
+        self.append_ins(ir::Ins::Cmpbin(ir::InsCmpbin::new(
+            ir::InsCmpbinKind::Le,
+            dst,
+            src1,
+            src2,
+        )));
+    }
+
+    pub fn ins_cmpge(&mut self, dst: ir::RegId, src1: ir::RegId, src2: ir::RegId) {
+        self.append_ins(ir::Ins::Cmpbin(ir::InsCmpbin::new(
+            ir::InsCmpbinKind::Ge,
+            dst,
+            src1,
+            src2,
+        )));
+    }
+
+    pub fn ins_cmpult(&mut self, dst: ir::RegId, src1: ir::RegId, src2: ir::RegId) {
+        self.append_ins(ir::Ins::Cmpbin(ir::InsCmpbin::new(
+            ir::InsCmpbinKind::Ult,
+            dst,
+            src1,
+            src2,
+        )));
+    }
+
+    pub fn ins_cmpule(&mut self, dst: ir::RegId, src1: ir::RegId, src2: ir::RegId) {
+        self.append_ins(ir::Ins::Cmpbin(ir::InsCmpbin::new(
+            ir::InsCmpbinKind::Ule,
+            dst,
+            src1,
+            src2,
+        )));
+    }
+
+    pub fn ins_cmpugt(&mut self, dst: ir::RegId, src1: ir::RegId, src2: ir::RegId) {
+        self.append_ins(ir::Ins::Cmpbin(ir::InsCmpbin::new(
+            ir::InsCmpbinKind::Ugt,
+            dst,
+            src1,
+            src2,
+        )));
+    }
+
+    pub fn ins_cmpuge(&mut self, dst: ir::RegId, src1: ir::RegId, src2: ir::RegId) {
+        self.append_ins(ir::Ins::Cmpbin(ir::InsCmpbin::new(
+            ir::InsCmpbinKind::Uge,
+            dst,
+            src1,
+            src2,
+        )));
+    }
+
+    /// Binary operation on values of any width (ins_add, ins_sub, etc are for i32)
+    pub fn ins_opbin_w(
+        &mut self,
+        width: ir::IntWidth,
+        kind: ir::InsOpbinKind,
+        dst: ir::RegId,
+        src1: ir::RegId,
+        src2: ir::RegId,
+    ) {
+        self.append_ins(ir::Ins::Opbin(ir::InsOpbin::new_with_width(
+            width, kind, dst, src1, src2,
+        )));
+    }
+
+    /// Comparison of values of any width (ins_cmpeq, ins_cmplt, etc are for i32)
+    pub fn ins_cmpbin_w(
+        &mut self,
+        width: ir::IntWidth,
+        kind: ir::InsCmpbinKind,
+        dst: ir::RegId,
+        src1: ir::RegId,
+        src2: ir::RegId,
+    ) {
+        self.append_ins(ir::Ins::Cmpbin(ir::InsCmpbin::new_with_width(
+            width, kind, dst, src1, src2,
+        )));
+    }
+
+    pub fn ins_conv(
+        &mut self,
+        kind: ir::InsConvKind,
+        from: ir::IntWidth,
+        to: ir::IntWidth,
+        dst: ir::RegId,
+        src: ir::RegId,
+    ) {
+        self.append_ins(ir::Ins::Conv(ir::InsConv::new(kind, from, to, dst, src)));
+    }
+
     pub fn ins_jump(&mut self, dst: ir::BasicBlockId) {
         self.append_ins(ir::Ins::Jump(ir::InsJump::new(dst)));
     }
@@ -162,10 +361,57 @@ impl<'a> IRBuilder<'a> {
         self.append_ins(ir::Ins::Br(ir::InsBr::new(src, dst_true, dst_false)));
     }
 
+    pub fn ins_switch(
+        &mut self,
+        src: ir::RegId,
+        cases: Vec<(i64, ir::BasicBlockId)>,
+        dst_default: ir::BasicBlockId,
+    ) {
+        self.append_ins(ir::Ins::Switch(ir::InsSwitch::new(src, cases, dst_default)));
+    }
+
+    pub fn ins_switch_w(
+        &mut self,
+        width: ir::IntWidth,
+        src: ir::RegId,
+        cases: Vec<(i64, ir::BasicBlockId)>,
+        dst_default: ir::BasicBlockId,
+    ) {
+        self.append_ins(ir::Ins::Switch(ir::InsSwitch::new_with_width(
+            width,
+            src,
+            cases,
+            dst_default,
+        )));
+    }
+
+    pub fn ins_jumpt(&mut self, src: ir::RegId, table: Vec<ir::BasicBlockId>) {
+        self.append_ins(ir::Ins::Jumpt(ir::InsJumpt::new(src, table)));
+    }
+
+    pub fn ins_jumpt_w(
+        &mut self,
+        width: ir::IntWidth,
+        src: ir::RegId,
+        table: Vec<ir::BasicBlockId>,
+    ) {
+        self.append_ins(ir::Ins::Jumpt(ir::InsJumpt::new_with_width(
+            width, src, table,
+        )));
+    }
+
     pub fn ins_call(&mut self, dst: ir::RegId, fun: ir::FunctionId, args: Vec<ir::RegId>) {
         self.append_ins(ir::Ins::Call(ir::InsCall::new(dst, fun, args)));
     }
 
+    pub fn ins_funaddr(&mut self, dst: ir::RegId, fun: ir::FunctionId) {
+        self.append_ins(ir::Ins::Funaddr(ir::InsFunaddr::new(dst, fun)));
+    }
+
+    pub fn ins_callr(&mut self, dst: ir::RegId, fptr: ir::RegId, args: Vec<ir::RegId>) {
+        self.append_ins(ir::Ins::Callr(ir::InsCallr::new(dst, fptr, args)));
+    }
+
     pub fn ins_ret(&mut self, src: ir::RegId) {
         self.append_ins(ir::Ins::Ret(ir::InsRet::new(src)));
     }
@@ -0,0 +1,135 @@
+// Diagnostics reported by the parser and the validator
+//
+// Every diagnostic has a stable code, that doesn't change with the message:
+// - P001 to P024: errors found by the parser (irparser)
+// - V001 to V019: errors and warnings found by the validator (irvalidation)
+// - L001 to L006: errors found by the linker (irlinker)
+// The position in the source file is only known for modules built by the parser
+
+use std::fmt;
+use std::io::Write;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+}
+
+/// Position in the source file, lines and columns start at 1
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SourcePos {
+    pub line: usize,
+    pub col: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: &'static str,
+    pub mess: String,
+    pub pos: Option<SourcePos>,
+    pub fun: Option<String>, // name of the function
+    pub bb: Option<String>,  // name of the basic block
+    pub ins: Option<usize>,  // index of the instruction in the basic block
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, code: &'static str, mess: String) -> Self {
+        Diagnostic {
+            severity,
+            code,
+            mess,
+            pos: None,
+            fun: None,
+            bb: None,
+            ins: None,
+        }
+    }
+
+    pub fn error(code: &'static str, mess: String) -> Self {
+        Diagnostic::new(Severity::Error, code, mess)
+    }
+
+    pub fn warning(code: &'static str, mess: String) -> Self {
+        Diagnostic::new(Severity::Warning, code, mess)
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    // Where the error is in the code: function, basic block and instruction
+    fn context(&self) -> Option<String> {
+        let fun = self.fun.as_ref()?;
+        Some(match (&self.bb, self.ins) {
+            (Some(bb), Some(ins)) => format!("{}, {}, instruction #{}", fun, bb, ins),
+            (Some(bb), None) => format!("{}, {}", fun, bb),
+            _ => fun.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(pos) = self.pos {
+            write!(f, "{}:{}: ", pos.line, pos.col)?;
+        }
+        write!(f, "{}[{}]: {}", self.severity.name(), self.code, self.mess)?;
+        if let Some(context) = self.context() {
+            write!(f, " (in {})", context)?;
+        }
+        Ok(())
+    }
+}
+
+/// Write the diagnostics of the file `path`, with the line of `source` they refer to:
+///
+///
+/// ```text
+/// error[P014]: Instruction call: unknown function fact
+///   --> prog.ir:12:3
+///    |
+/// 12 |   call %r1, fact, %r0
+///    |   ^
+///    = in main, L0
+/// ```
+pub fn write_diagnostics(os: &mut dyn Write, diags: &[Diagnostic], path: &str, source: &str) {
+    let lines: Vec<_> = source.lines().collect();
+
+    for diag in diags {
+        writeln!(os, "{}[{}]: {}", diag.severity.name(), diag.code, diag.mess).unwrap();
+
+        let pos = diag
+            .pos
+            .filter(|pos| pos.line >= 1 && pos.line <= lines.len());
+        let margin = " ".repeat(pos.map_or(0, |pos| pos.line.to_string().len()));
+        match pos {
+            Some(pos) => {
+                writeln!(os, "{}--> {}:{}:{}", margin, path, pos.line, pos.col).unwrap();
+                writeln!(os, "{} |", margin).unwrap();
+                writeln!(os, "{} | {}", pos.line, lines[pos.line - 1]).unwrap();
+                // keep the tabs of the line, so that the marker is under the right column
+                let indent: String = lines[pos.line - 1]
+                    .chars()
+                    .take(pos.col - 1)
+                    .map(|c| if c == '\t' { '\t' } else { ' ' })
+                    .collect();
+                writeln!(os, "{} | {}^", margin, indent).unwrap();
+            }
+            None => writeln!(os, "--> {}", path).unwrap(),
+        }
+        if let Some(context) = diag.context() {
+            writeln!(os, "{} = in {}", margin, context).unwrap();
+        }
+        writeln!(os).unwrap();
+    }
+}
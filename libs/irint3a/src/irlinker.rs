@@ -0,0 +1,299 @@
+// Linker: merge several modules into a single one
+//
+// Functions and globals are symbols, identified by their names in the modules (irnames):
+// - a function or a global can be defined only once (duplicate symbol)
+// - a function declaration (.declare) is resolved by name to the definition in another module,
+//   the call and funaddr instructions are changed to use the definition
+// - a declaration with a signature must have the same signature as the definition
+// - the declarations without definition stay extern functions (eg: the natives of the runtime).
+//   They keep their ids (the natives are bound by id), so all the declarations of one name must have the same id.
+//   The declarations of one id may have different names (the first one is kept), but not different signatures.
+//   If the ids of the natives are given, every declaration without definition must be one of them (unresolved symbol)
+// - functions and globals without names are local to their module
+//
+// The definitions keep their ids when possible.
+// When the id is already used by an extern function or a definition from a previous module,
+// the definition gets a new id, bigger than all the ids of all the modules.
+// The entry point is the function 0, it should be defined in the first module
+//
+// The errors are diagnostics (see irdiagnostics), with the name of the symbol in the message:
+// - L001: duplicate function definition
+// - L002: duplicate global
+// - L003: declaration with a different signature than the definition
+// - L004: unresolved function
+// - L005: extern function declared with different ids
+// - L006: extern function declared with different signatures
+// The linked module is then validated, and the diagnostics of the validator are returned unchanged
+
+use std::collections::{HashMap, HashSet};
+
+use crate::ir;
+use crate::irdiagnostics::Diagnostic;
+use crate::irnames;
+use crate::irvalidation;
+
+// New ids of the functions and globals of one module
+struct Relocations {
+    funs: HashMap<ir::FunctionId, ir::FunctionId>,
+    globals: HashMap<ir::GlobalId, ir::GlobalId>,
+}
+
+#[derive(Default)]
+pub struct Linker {
+    modules: Vec<(ir::Module, irnames::ModuleNames)>,
+    natives: Option<HashSet<ir::FunctionId>>,
+}
+
+impl Linker {
+    pub fn new() -> Self {
+        Linker {
+            modules: vec![],
+            natives: None,
+        }
+    }
+
+    /// Add a module to link, the modules are merged in the order they are added
+    pub fn add_module(&mut self, module: ir::Module, names: irnames::ModuleNames) {
+        self.modules.push((module, names));
+    }
+
+    /// Set the ids of the native functions of the runtime
+    /// All the declarations without definition must then be natives
+    /// If not set, the unresolved declarations are kept as extern functions
+    pub fn set_natives(&mut self, ids: &[ir::FunctionId]) {
+        self.natives = Some(ids.iter().copied().collect());
+    }
+
+    /// Merge all the modules
+    /// Returns all the errors found if some symbols can't be linked, or if the linked module is invalid
+    pub fn link(self) -> Result<(ir::Module, irnames::ModuleNames), Vec<Diagnostic>> {
+        let mut errs = vec![];
+
+        // 1) Find the definitions of all the functions and globals
+        let mut defs = HashMap::new();
+        let mut globals = HashSet::new();
+        for (mod_idx, (module, names)) in self.modules.iter().enumerate() {
+            for fun in module.funs().iter().filter(|fun| !fun.is_extern()) {
+                if let Some(name) = names.get_function_name(fun.id()) {
+                    if defs.insert(name, (mod_idx, fun.id())).is_some() {
+                        errs.push(link_error("L001", "Duplicate function definition", name));
+                    }
+                }
+            }
+            for global in module.globals() {
+                if let Some(name) = names.get_global_name(global.id()) {
+                    if !globals.insert(name) {
+                        errs.push(link_error("L002", "Duplicate global", name));
+                    }
+                }
+            }
+        }
+
+        // 2) Resolve the declarations, or keep them as extern functions
+        let mut externs: HashMap<ir::FunctionId, (Option<&str>, Option<ir::Signature>)> =
+            HashMap::new();
+        let mut externs_ids = HashMap::new();
+        for (module, names) in &self.modules {
+            for fun in module.funs().iter().filter(|fun| fun.is_extern()) {
+                let name = names.get_function_name(fun.id());
+                let symbol = name
+                    .map(|name| name.to_string())
+                    .unwrap_or_else(|| format!("#{}", fun.id().0));
+
+                if let Some((def_mod, def_id)) = name.and_then(|name| defs.get(name)) {
+                    let def = self.modules[*def_mod].0.get_fun(*def_id).unwrap();
+                    if let (Some(sig), Some(def_sig)) = (fun.signature(), def.signature()) {
+                        if sig != def_sig {
+                            errs.push(link_error(
+                                "L003",
+                                "Declaration with a different signature than the definition",
+                                &symbol,
+                            ));
+                        }
+                    }
+                    continue;
+                }
+
+                if let Some(natives) = &self.natives {
+                    if !natives.contains(&fun.id()) {
+                        errs.push(link_error("L004", "Unresolved function", &symbol));
+                    }
+                }
+                if let Some(name) = name {
+                    if *externs_ids.entry(name).or_insert(fun.id()) != fun.id() {
+                        errs.push(link_error(
+                            "L005",
+                            "Extern function declared with different ids",
+                            &symbol,
+                        ));
+                    }
+                }
+                match externs.get(&fun.id()) {
+                    Some((_, Some(sig)))
+                        if fun.signature().is_some() && fun.signature() != Some(*sig) =>
+                    {
+                        errs.push(link_error(
+                            "L006",
+                            "Extern function declared with different signatures",
+                            &symbol,
+                        ))
+                    }
+                    Some((_, Some(_))) => {}
+                    Some((other, None)) => {
+                        let name = other.or(name);
+                        externs.insert(fun.id(), (name, fun.signature()));
+                    }
+                    None => {
+                        externs.insert(fun.id(), (name, fun.signature()));
+                    }
+                }
+            }
+        }
+
+        if !errs.is_empty() {
+            return Err(errs);
+        }
+
+        // 3) Give ids to the definitions
+        let max_fun = self
+            .modules
+            .iter()
+            .flat_map(|(module, _)| module.funs().iter().map(|fun| fun.id().0))
+            .max()
+            .unwrap_or(0);
+        let max_global = self
+            .modules
+            .iter()
+            .flat_map(|(module, _)| module.globals().iter().map(|global| global.id().0))
+            .max()
+            .unwrap_or(0);
+        let mut next_fun = max_fun + 1;
+        let mut next_global = max_global + 1;
+        let mut used_funs: HashSet<_> = externs.keys().copied().collect();
+        let mut used_globals = HashSet::new();
+
+        let mut relocs = vec![];
+        for (module, _) in &self.modules {
+            let mut reloc = Relocations {
+                funs: HashMap::new(),
+                globals: HashMap::new(),
+            };
+            for fun in module.funs().iter().filter(|fun| !fun.is_extern()) {
+                let id = new_id(fun.id(), &mut used_funs, &mut next_fun, ir::FunctionId);
+                reloc.funs.insert(fun.id(), id);
+            }
+            for global in module.globals() {
+                let id = new_id(
+                    global.id(),
+                    &mut used_globals,
+                    &mut next_global,
+                    ir::GlobalId,
+                );
+                reloc.globals.insert(global.id(), id);
+            }
+            relocs.push(reloc);
+        }
+
+        // the declarations use the ids of the definitions, or stay extern functions
+        for mod_idx in 0..self.modules.len() {
+            let (module, names) = &self.modules[mod_idx];
+            for fun in module.funs().iter().filter(|fun| fun.is_extern()) {
+                let def = names
+                    .get_function_name(fun.id())
+                    .and_then(|name| defs.get(name));
+                let id = match def {
+                    Some((def_mod, def_id)) => relocs[*def_mod].funs[def_id],
+                    None => fun.id(),
+                };
+                relocs[mod_idx].funs.insert(fun.id(), id);
+            }
+        }
+
+        // 4) Build the linked module
+        let mut res = ir::Module::new();
+        let mut res_names = irnames::ModuleNames::new();
+
+        let mut externs: Vec<_> = externs.into_iter().collect();
+        externs.sort_by_key(|(id, _)| *id);
+        for (id, (name, signature)) in externs {
+            res.create_extern_function(id);
+            res.get_fun_mut(id).unwrap().set_signature(signature);
+            if let Some(name) = name {
+                res_names.add_function(id, name.to_string());
+            }
+        }
+
+        for ((module, names), reloc) in self.modules.iter().zip(&relocs) {
+            for global in module.globals() {
+                let id = reloc.globals[&global.id()];
+                res.create_global_with_width(
+                    id,
+                    global.width(),
+                    global.size(),
+                    global.init().to_vec(),
+                );
+                if let Some(name) = names.get_global_name(global.id()) {
+                    res_names.add_global(id, name.to_string());
+                }
+            }
+
+            for fun in module.funs().iter().filter(|fun| !fun.is_extern()) {
+                let id = reloc.funs[&fun.id()];
+                res.import_function(fun, id);
+                relocate_fun(res.get_fun_mut(id).unwrap(), reloc);
+                if let (Some(name), Some(fun_names)) = (
+                    names.get_function_name(fun.id()),
+                    names.get_function(fun.id()),
+                ) {
+                    res_names.import_function(id, name.to_string(), fun_names);
+                }
+            }
+        }
+
+        // eg: a declaration without signature called with a different number of arguments than the definition
+        irvalidation::validate_module(&res, Some(&res_names))?;
+        Ok((res, res_names))
+    }
+}
+
+fn link_error(code: &'static str, mess: &str, symbol: &str) -> Diagnostic {
+    Diagnostic::error(code, format!("{}: {}", mess, symbol))
+}
+
+// Keep the id if it's not used yet, or generate a new one
+fn new_id<T: Copy + Eq + std::hash::Hash>(
+    id: T,
+    used: &mut HashSet<T>,
+    next: &mut usize,
+    make_id: fn(usize) -> T,
+) -> T {
+    let id = if used.contains(&id) {
+        *next += 1;
+        make_id(*next - 1)
+    } else {
+        id
+    };
+    used.insert(id);
+    id
+}
+
+// Change the ids of the functions and globals used by the instructions
+fn relocate_fun(fun: &mut ir::Function, reloc: &Relocations) {
+    for bb_id in fun.basic_blocks_list().to_vec() {
+        for ins in fun.get_basic_block_mut(bb_id).iter_mut() {
+            match ins {
+                ir::Ins::Gaddr(gaddr) => {
+                    *gaddr = ir::InsGaddr::new(gaddr.dst(), reloc.globals[&gaddr.global()]);
+                }
+                ir::Ins::Call(call) => {
+                    *call =
+                        ir::InsCall::new(call.dst(), reloc.funs[&call.fun()], call.args().clone());
+                }
+                ir::Ins::Funaddr(funaddr) => {
+                    *funaddr = ir::InsFunaddr::new(funaddr.dst(), reloc.funs[&funaddr.fun()]);
+                }
+                _ => {}
+            }
+        }
+    }
+}
@@ -1,4 +1,4 @@
-//In the IR, basic blocks, functions and registers are all represented by ids with are unique integers
+//In the IR, basic blocks, functions, globals and registers are all represented by ids with are unique integers
 //This file Contains structs to give identifier names to all these ids
 
 use std::collections::HashMap;
@@ -8,6 +8,7 @@ use std::hash::Hash;
 use crate::ir;
 use crate::registers::GetRegistersUse;
 
+#[derive(Clone)]
 struct IdsMapper<T> {
     //TODO: this implem need to clone strings
     id2name: HashMap<T, String>,
@@ -54,6 +55,7 @@ impl<T: Clone + Copy + Eq + PartialEq + Hash> IdsMapper<T> {
     }
 }
 
+#[derive(Clone)]
 pub struct FunctionNames {
     id: ir::FunctionId,
     regs: IdsMapper<ir::RegId>,
@@ -138,6 +140,7 @@ impl FunctionNames {
 pub struct ModuleNames {
     funs: IdsMapper<ir::FunctionId>,
     funs_names: HashMap<ir::FunctionId, FunctionNames>,
+    globals: IdsMapper<ir::GlobalId>,
 }
 
 impl ModuleNames {
@@ -145,9 +148,22 @@ impl ModuleNames {
         ModuleNames {
             funs: IdsMapper::new(),
             funs_names: HashMap::new(),
+            globals: IdsMapper::new(),
         }
     }
 
+    pub fn add_global(&mut self, id: ir::GlobalId, name: String) {
+        self.globals.insert(id, name);
+    }
+
+    pub fn get_global_id(&self, name: &str) -> Option<ir::GlobalId> {
+        self.globals.name2id(name)
+    }
+
+    pub fn get_global_name(&self, id: ir::GlobalId) -> Option<&str> {
+        self.globals.id2name(id)
+    }
+
     pub fn add_function(&mut self, id: ir::FunctionId, name: String) {
         self.funs.insert(id, name);
         self.funs_names.insert(id, FunctionNames::new(id));
@@ -161,6 +177,14 @@ impl ModuleNames {
         self.funs.id2name(id)
     }
 
+    /// Add the name of a function, with a copy of the names of its basic blocks and registers
+    pub fn import_function(&mut self, id: ir::FunctionId, name: String, fun_names: &FunctionNames) {
+        self.funs.insert(id, name);
+        let mut fun_names = fun_names.clone();
+        fun_names.id = id;
+        self.funs_names.insert(id, fun_names);
+    }
+
     pub fn get_function(&self, id: ir::FunctionId) -> Option<&FunctionNames> {
         self.funs_names.get(&id)
     }
@@ -169,8 +193,16 @@ impl ModuleNames {
         self.funs_names.get_mut(&id)
     }
 
-    // Give generic names to all unnamed functions, basic blocks and registers
+    // Give generic names to all unnamed globals, functions, basic blocks and registers
     pub fn complete_undefined(&mut self, module: &ir::Module) {
+        // 0) Give name to all globals
+        for global in module.globals() {
+            if self.get_global_name(global.id()).is_none() {
+                let global_name = self.globals.gen_name("g");
+                self.add_global(global.id(), global_name);
+            }
+        }
+
         // 1) Find and give name to all local funstions
         let mut all_funs: Vec<_> = module.funs().iter().map(|fun| fun.id()).collect();
         all_funs.sort();
@@ -1,25 +1,40 @@
 // Module to parse an IR Input file and generate some valid code
 //
 // File Syntax:
-// A file is a sequence of function definitions and declarations:
+// A file is a sequence of global variables, function definitions and declarations:
 // We can add comments anywhere using ';' <text>
 //
+// Global variable:
+// '.global'('.'<width>)? <id@int> <name@str> <size@int> (<init@int>)*
+// size is the number of values of width (i32 if missing),
+// the optional init values (that can be negative) are the values of the first elements
+//
 // Function declaration
-// '.declare' <id@int> <name@str>
+// '.declare' <id@int> <name@str> <signature>?
 //
 // Function definition:
-// '.define' <id@int> <name@str> code
+// '.define' <id@int> <name@str> <signature>? code
+//
+// Signature (optional): <nb-args@int> ('void')?
+// The arguments are the registers %r0 to %r<nb-args - 1>, 'void' if the function doesn't return a value
+// eg: '.define 1 _fact 1', '.declare 258 _exit 1 void'
 //
 // code is a sequence of instructions
 // each instruction may have a label
 // A label means the beginning of a new basic block
 //
+// Integer widths:
+// <width> is one of i8, i16, i32, i64
+// Instructions that work on integers can have a width suffix: <ins>.<width> (eg: 'add.i8'), i32 if missing
+// It's allowed for movi, load, store, alloca, switch, jumpt, and all binary operations and comparisons
+//
 // Instructions syntax:
-// - movi: 'movi' %<dts-reg@str>, <val@int>
+// - movi: 'movi' %<dts-reg@str>, <val@int>  (val can be negative)
 // - movr: 'movr' %<dst-reg@str>, %<src-reg@str>
 // - load: 'load' %<dst-reg@str>, %<src-reg@str>
 // - store: 'store' %<dst-reg@str>, %<src-reg@str>
-// - alloca: 'alloca' %<dst-reg@str>
+// - alloca: 'alloca' %<dst-reg@str> (, <size@int>)?  (size in number of values of width, 1 if missing)
+// - gaddr: 'gaddr' %<dst-reg@str>, <global@str>
 // - add: 'add' %<dst-reg@str>, %<src1-reg@str>, %<src2-reg@str>
 // - sub: 'sub' %<dst-reg@str>, %<src1-reg@str>, %<src2-reg@str>
 // - mul: 'mul' %<dst-reg@str>, %<src1-reg@str>, %<src2-reg@str>
@@ -28,36 +43,129 @@
 // - cmpeq: 'cmpeq' %<dst-reg@str>, %<src1-reg@str>, %<src2-reg@str>
 // - cmplt: 'cmplt' %<dst-reg@str>, %<src1-reg@str>, %<src2-reg@str>
 // - cmpgt: 'cmpgt' %<dst-reg@str>, %<src1-reg@str>, %<src2-reg@str>
+// - and: 'and' %<dst-reg@str>, %<src1-reg@str>, %<src2-reg@str>
+// - or: 'or' %<dst-reg@str>, %<src1-reg@str>, %<src2-reg@str>
+// - xor: 'xor' %<dst-reg@str>, %<src1-reg@str>, %<src2-reg@str>
+// - shl: 'shl' %<dst-reg@str>, %<src1-reg@str>, %<src2-reg@str>
+// - shr: 'shr' %<dst-reg@str>, %<src1-reg@str>, %<src2-reg@str>
+// - sar: 'sar' %<dst-reg@str>, %<src1-reg@str>, %<src2-reg@str>
+// - cmpne: 'cmpne' %<dst-reg@str>, %<src1-reg@str>, %<src2-reg@str>
+// - cmple: 'cmple' %<dst-reg@str>, %<src1-reg@str>, %<src2-reg@str>
+// - cmpge: 'cmpge' %<dst-reg@str>, %<src1-reg@str>, %<src2-reg@str>
+// - cmpult: 'cmpult' %<dst-reg@str>, %<src1-reg@str>, %<src2-reg@str>
+// - cmpule: 'cmpule' %<dst-reg@str>, %<src1-reg@str>, %<src2-reg@str>
+// - cmpugt: 'cmpugt' %<dst-reg@str>, %<src1-reg@str>, %<src2-reg@str>
+// - cmpuge: 'cmpuge' %<dst-reg@str>, %<src1-reg@str>, %<src2-reg@str>
+// - sext: 'sext'.<from>.<to> %<dst-reg@str>, %<src-reg@str>
+// - zext: 'zext'.<from>.<to> %<dst-reg@str>, %<src-reg@str>
+// - trunc: 'trunc'.<from>.<to> %<dst-reg@str>, %<src-reg@str>
 // - jump: 'jump' <dst-bb@str>
 // - br: 'br' %<src-reg@str>, <dst-true-bb@str>, <dst-false-bb@str>
+// - switch: 'switch' %<src-reg@str>, <dst-default-bb@str> (, <val@int>, <dst-bb@str>)*
+// - jumpt: 'jumpt' %<src-reg@str> (, <dst-bb@str>)+
 // - call: 'call' %<dst-reg@str>, <fun@str> (, %<arg-i-reg@str>)*
+// - funaddr: 'funaddr' %<dst-reg@str>, <fun@str>
+// - callr: 'callr' %<dst-reg@str>, %<fptr-reg@str> (, %<arg-i-reg@str>)*
 // - ret: 'ret' %<src-reg@str>
-
-use std::collections::HashSet;
+//
+// Debug locations:
+// An instruction can be followed by a comment '; !loc <file@str>:<line@int>:<col@int>'
+// It gives the location in the source file the instruction was generated from
+// All other comments are ignored
+//
+// Errors:
+// The parser doesn't stop at the first error, all errors are returned as diagnostics, with their position in the file
+// The module is validated only if there is no syntax error, the validation errors also have the position of the code
+// Codes of the syntax errors:
+// - P001: invalid syntax (label without instruction, empty argument)
+// - P002: invalid global definition
+// - P003: invalid function definition
+// - P004: unknown definition
+// - P005: invalid label (a number)
+// - P006: duplicate function id
+// - P007: duplicate global id
+// - P008: duplicate basic block
+// - P009: unknown instruction
+// - P010: unknown width
+// - P011: wrong number of arguments
+// - P012: invalid argument kind (eg: a label instead of a register)
+// - P013: unknown basic block
+// - P014: unknown function
+// - P015: unknown global
+// - P016: invalid debug location
+// - P017: instruction outside of a function
+// - P018: basic block without label
+// - P019: duplicate function name
+// - P020: duplicate global name
+// - P021: width suffix on an instruction without width, or too many width suffixes
+// - P022: conversion without the 2 widths <from>.<to>
+// - P023: not enough arguments
+// - P024: invalid number of arguments of a switch (pairs of value and label)
+
+use std::collections::{HashMap, HashSet};
 
 use crate::ir;
 use crate::irbuilder::IRBuilder;
+use crate::irdiagnostics::{Diagnostic, Severity, SourcePos};
 use crate::irnames;
 use crate::irvalidation;
-use asmparser::parser::{Ins, InsArg, InsLabel, Item};
+use asmparser::parser::{Ins, InsArg, InsLabel, Item, Position};
+
+// Error of a parse function, the position and the context are added by the caller
+struct ParseError {
+    code: &'static str,
+    mess: String,
+}
+
+impl ParseError {
+    fn new(code: &'static str, mess: String) -> Self {
+        ParseError { code, mess }
+    }
+}
+
+type ParseResult<T> = Result<T, ParseError>;
 
 #[derive(Debug)]
 struct ParsedBasicBlock {
     id: String,
     code: Vec<Ins>,
+    pos: Position,
+}
+
+#[derive(Debug)]
+struct ParsedGlobal {
+    id: ir::GlobalId,
+    name: String,
+    width: ir::IntWidth,
+    size: usize,
+    init: Vec<i64>,
+    pos: Position,
 }
 
 #[derive(Debug)]
 struct ParsedFunction {
     id: ir::FunctionId,
     name: String,
+    signature: Option<ir::Signature>,
     bbs: Option<Vec<ParsedBasicBlock>>,
+    pos: Position,
+}
+
+// Positions in the file of the code of the module, to report the validation errors
+#[derive(Default)]
+struct SourcePositions {
+    globals: HashMap<ir::GlobalId, SourcePos>,
+    funs: HashMap<ir::FunctionId, SourcePos>,
+    bbs: HashMap<(ir::FunctionId, ir::BasicBlockId), SourcePos>,
+    ins: HashMap<(ir::FunctionId, ir::BasicBlockId, usize), SourcePos>,
 }
 
 pub struct Parser {
     ps: asmparser::parser::Parser,
     module: ir::Module,
     names: irnames::ModuleNames,
+    diags: Vec<Diagnostic>,
+    positions: SourcePositions,
 }
 
 impl Parser {
@@ -69,11 +177,42 @@ impl Parser {
         Parser::new(asmparser::parser::Parser::from_str(s))
     }
 
-    pub fn build(mut self) -> (ir::Module, irnames::ModuleNames) {
-        let funs = self.r_file();
-        self.add_module(&funs);
-        irvalidation::validate_module(&self.module);
-        (self.module, self.names)
+    /// Parse and validate the module
+    /// Returns all the syntax or validation errors if it fails
+    pub fn build(self) -> Result<(ir::Module, irnames::ModuleNames), Vec<Diagnostic>> {
+        let (module, names, _warns) = self.build_with_warnings()?;
+        Ok((module, names))
+    }
+
+    /// Same as `build`, and also returns the validation warnings of the module
+    #[allow(clippy::type_complexity)]
+    pub fn build_with_warnings(
+        mut self,
+    ) -> Result<(ir::Module, irnames::ModuleNames, Vec<Diagnostic>), Vec<Diagnostic>> {
+        let (globals, funs) = self.r_file();
+        self.add_globals(globals);
+        self.add_module(funs);
+
+        // the module is incomplete if there are syntax errors, it can't be validated
+        if !self.diags.is_empty() {
+            self.diags
+                .sort_by_key(|diag| diag.pos.map(|pos| (pos.line, pos.col)));
+            return Err(self.diags);
+        }
+
+        let errs = irvalidation::check_module(&self.module);
+        if !errs.is_empty() {
+            return Err(errs
+                .iter()
+                .map(|err| self.validation_diagnostic(err, Severity::Error))
+                .collect());
+        }
+
+        let warns = irvalidation::check_indirect_calls(&self.module)
+            .iter()
+            .map(|warn| self.validation_diagnostic(warn, Severity::Warning))
+            .collect();
+        Ok((self.module, self.names, warns))
     }
 
     fn new(ps: asmparser::parser::Parser) -> Self {
@@ -81,125 +220,182 @@ impl Parser {
             ps,
             module: ir::Module::new(),
             names: irnames::ModuleNames::new(),
+            diags: vec![],
+            positions: SourcePositions::default(),
         }
     }
 
-    fn r_file(&mut self) -> Vec<ParsedFunction> {
-        let mut res = vec![];
+    fn error(&mut self, code: &'static str, mess: String, pos: Position) {
+        let mut diag = Diagnostic::error(code, mess);
+        diag.pos = Some(source_pos(pos));
+        self.diags.push(diag);
+    }
 
-        loop {
-            if self.ps.peek().is_none() {
-                break;
+    // Convert a validation error, with the position of the code
+    fn validation_diagnostic(
+        &self,
+        err: &irvalidation::ValidationError,
+        severity: Severity,
+    ) -> Diagnostic {
+        use irvalidation::ValidationError;
+
+        let mut res = err.to_diagnostic(severity, Some(&self.names));
+        res.pos = match err {
+            ValidationError::Ins(err) => {
+                self.positions.ins.get(&(err.fun_id, err.bb_id, err.ins_id))
             }
+            ValidationError::BasicBlock(err) => self.positions.bbs.get(&(err.fun_id, err.bb_id)),
+            ValidationError::Function(err) => self.positions.funs.get(&err.fun_id),
+            ValidationError::Global(err) => self.positions.globals.get(&err.global_id),
+        }
+        .copied();
+        res
+    }
 
-            res.push(self.r_fun());
+    fn r_file(&mut self) -> (Vec<ParsedGlobal>, Vec<ParsedFunction>) {
+        let mut globals = vec![];
+        let mut funs = vec![];
+
+        loop {
+            let is_global = match self.ps.peek() {
+                None => break,
+                Some(Item::Def(def)) => def.line.starts_with("global"),
+                _ => false,
+            };
+            if is_global {
+                globals.extend(self.r_global());
+            } else {
+                funs.extend(self.r_fun());
+            }
         }
 
-        res
+        (globals, funs)
     }
 
-    fn r_fun(&mut self) -> ParsedFunction {
+    fn r_global(&mut self) -> Option<ParsedGlobal> {
         let def = match self.ps.next() {
             Some(Item::Def(def)) => def,
-            _ => panic!("Expected definition"),
+            _ => unreachable!(),
         };
 
-        let (is_extern, def_id) = if def.line.starts_with("declare") {
-            (true, &def.line[7..])
-        } else if def.line.starts_with("define") {
-            (false, &def.line[6..])
-        } else {
-            panic!("Invalid definition {}", def.line);
-        };
-
-        let def_id = def_id.trim();
-        let sep_pos = def_id
-            .find(' ')
-            .expect("Invalid def syntax: must be .define <fn-id> <fn-name>");
-        let def_name = &def_id[sep_pos + 1..];
-        let def_id = &def_id[0..sep_pos];
-
-        let def_id = def_id
-            .trim()
-            .parse::<usize>()
-            .expect("function id must be a number");
-        let def_id = ir::FunctionId(def_id);
-
-        if is_extern {
-            return ParsedFunction {
-                id: def_id,
-                name: def_name.to_string(),
-                bbs: None,
-            };
+        match parse_global(&def.line, def.pos) {
+            Ok(global) => Some(global),
+            Err(err) => {
+                self.error(err.code, err.mess, def.pos);
+                None
+            }
         }
+    }
 
-        let mut bbs = vec![];
-
-        loop {
-            match self.ps.peek() {
-                None | Some(Item::Def(_)) => break,
-                _ => {}
+    fn r_fun(&mut self) -> Option<ParsedFunction> {
+        let def = match self.ps.next() {
+            Some(Item::Def(def)) => def,
+            Some(Item::Ins(ins)) => {
+                let mess = format!("Instruction {} outside of a function", ins.name);
+                self.error("P017", mess, ins.pos);
+                return None;
             }
-            bbs.push(self.r_bb());
-        }
+            Some(Item::Error(err)) => {
+                self.error("P001", err.mess.to_string(), err.pos);
+                return None;
+            }
+            None => unreachable!(),
+        };
 
-        ParsedFunction {
-            id: def_id,
-            name: def_name.to_string(),
-            bbs: Some(bbs),
+        let (is_extern, def_id) = if let Some(def_id) = def.line.strip_prefix("declare") {
+            (true, def_id)
+        } else if let Some(def_id) = def.line.strip_prefix("define") {
+            (false, def_id)
+        } else {
+            self.error("P004", format!("Invalid definition .{}", def.line), def.pos);
+            // skip the code that follows
+            self.r_bbs();
+            return None;
+        };
+
+        let bbs = if is_extern { None } else { Some(self.r_bbs()) };
+        match parse_fun_def(def_id) {
+            Ok((id, name, signature)) => Some(ParsedFunction {
+                id,
+                name,
+                signature,
+                bbs,
+                pos: def.pos,
+            }),
+            Err(err) => {
+                self.error(err.code, err.mess, def.pos);
+                None
+            }
         }
     }
 
-    fn r_bb(&mut self) -> ParsedBasicBlock {
-        let mut code = vec![];
-        let mut id = None;
+    // Read all the basic blocks until the next definition
+    fn r_bbs(&mut self) -> Vec<ParsedBasicBlock> {
+        let mut bbs: Vec<ParsedBasicBlock> = vec![];
 
         loop {
-            let ins = match self.ps.peek() {
+            if let None | Some(Item::Def(_)) = self.ps.peek() {
+                break;
+            }
+            let ins = match self.ps.next() {
                 Some(Item::Ins(ins)) => ins,
-                _ => {
-                    break;
+                Some(Item::Error(err)) => {
+                    self.error("P001", err.mess.to_string(), err.pos);
+                    continue;
                 }
+                _ => unreachable!(),
             };
 
-            if code.len() == 0 {
-                match &ins.label {
-                    Some(InsLabel::IdName(label)) => {
-                        id = Some(label.clone());
-                    }
-                    Some(InsLabel::IdInt(_)) => panic!("Label cannot be a int"),
-                    None => panic!("First instruction in a basic block must have a label"),
+            // A label means the beginning of a new basic block
+            match &ins.label {
+                Some(InsLabel::IdName(label)) => bbs.push(ParsedBasicBlock {
+                    id: label.clone(),
+                    code: vec![],
+                    pos: ins.label_pos.unwrap(),
+                }),
+                Some(InsLabel::IdInt(label)) => {
+                    let mess = format!("Label cannot be a int: {}", label);
+                    self.error("P005", mess, ins.label_pos.unwrap());
+                    continue;
                 }
-            } else {
-                match ins.label {
-                    Some(InsLabel::IdName(_)) => {
-                        break;
-                    }
-                    Some(InsLabel::IdInt(_)) => panic!("Label cannot be a int"),
-                    None => {}
+                None if bbs.is_empty() => {
+                    let mess = "First instruction in a basic block must have a label".to_string();
+                    self.error("P018", mess, ins.pos);
+                    continue;
                 }
+                None => {}
             }
-
-            let ins = match self.ps.next() {
-                Some(Item::Ins(ins)) => ins,
-                _ => unreachable!(),
-            };
-            code.push(ins);
+            bbs.last_mut().unwrap().code.push(ins);
         }
 
-        if code.len() == 0 {
-            panic!("Basic block cannot be empty");
-        }
+        bbs
+    }
 
-        ParsedBasicBlock {
-            id: id.unwrap(),
-            code,
+    fn add_globals(&mut self, globals: Vec<ParsedGlobal>) {
+        for global in globals {
+            if self.module.get_global(global.id).is_some() {
+                let mess = format!("Duplicate global id {}", global.id.0);
+                self.error("P007", mess, global.pos);
+                continue;
+            }
+            if self.names.get_global_id(&global.name).is_some() {
+                let mess = format!("Duplicate global name {}", global.name);
+                self.error("P020", mess, global.pos);
+                continue;
+            }
+
+            self.positions
+                .globals
+                .insert(global.id, source_pos(global.pos));
+            self.names.add_global(global.id, global.name);
+            self.module
+                .create_global_with_width(global.id, global.width, global.size, global.init);
         }
     }
 
-    fn add_module(&mut self, funs: &Vec<ParsedFunction>) {
-        self.add_fun_defs(funs);
-        for fun in funs {
+    fn add_module(&mut self, funs: Vec<ParsedFunction>) {
+        let funs = self.add_fun_defs(funs);
+        for fun in &funs {
             self.add_fun(fun);
         }
     }
@@ -211,42 +407,84 @@ impl Parser {
         }
 
         // 2) Register All basic blocs and register names
-        self.add_bbs_defs(fun);
+        let bbs_ids = self.add_bbs_defs(fun);
         self.add_regs_defs(fun);
 
         // 3) Call FunParser to build all the code
         let fun_names = self.names.get_function(fun.id).unwrap();
-        let mut fun_ir = self.module.get_fun_mut(fun.id).unwrap();
-        let builder = IRBuilder::new(&mut fun_ir);
+        let fun_ir = self.module.get_fun_mut(fun.id).unwrap();
+        let builder = IRBuilder::new(fun_ir);
         let mut fun_ps = FunctionParser::new(builder, &self.names, fun_names);
-        fun_ps.parse(fun);
+        fun_ps.parse(fun, &bbs_ids);
+
+        self.diags.append(&mut fun_ps.diags);
+        for ((bb_id, ins_idx), pos) in fun_ps.ins_pos {
+            self.positions.ins.insert((fun.id, bb_id, ins_idx), pos);
+        }
     }
 
-    fn add_fun_defs(&mut self, funs: &Vec<ParsedFunction>) {
+    // Returns the functions that were added to the module
+    fn add_fun_defs(&mut self, funs: Vec<ParsedFunction>) -> Vec<ParsedFunction> {
+        let mut res = vec![];
         for fun in funs {
+            if self.module.get_fun(fun.id).is_some() {
+                let mess = format!("Duplicate function id {}", fun.id.0);
+                self.error("P006", mess, fun.pos);
+                continue;
+            }
+            if self.names.get_function_id(&fun.name).is_some() {
+                let mess = format!("Duplicate function name {}", fun.name);
+                self.error("P019", mess, fun.pos);
+                continue;
+            }
+
+            self.positions.funs.insert(fun.id, source_pos(fun.pos));
             self.names.add_function(fun.id, fun.name.clone());
             if fun.bbs.is_none() {
                 self.module.create_extern_function(fun.id);
             } else {
                 self.module.create_function(Some(fun.id));
             }
+            let fun_ir = self.module.get_fun_mut(fun.id).unwrap();
+            fun_ir.set_signature(fun.signature);
+            res.push(fun);
         }
+        res
     }
 
-    fn add_bbs_defs(&mut self, fun: &ParsedFunction) {
-        let fun_names = self.names.get_function_mut(fun.id).unwrap();
-        let fun_ir = self.module.get_fun_mut(fun.id).unwrap();
+    // Returns the id of every basic block, None if it's a duplicate
+    fn add_bbs_defs(&mut self, fun: &ParsedFunction) -> Vec<Option<ir::BasicBlockId>> {
+        let mut res = vec![];
 
         for bb in fun.bbs.as_ref().unwrap() {
-            let bb_name = bb.id.to_string();
-            let bb_id = fun_ir.create_basic_block();
-            fun_names.add_basic_block(bb_id, bb_name);
+            let fun_names = self.names.get_function_mut(fun.id).unwrap();
+            if fun_names.get_basic_block_id(&bb.id).is_some() {
+                self.error("P008", format!("Duplicate basic block {}", bb.id), bb.pos);
+                res.push(None);
+                continue;
+            }
+
+            let bb_id = self
+                .module
+                .get_fun_mut(fun.id)
+                .unwrap()
+                .create_basic_block();
+            fun_names.add_basic_block(bb_id, bb.id.to_string());
+            self.positions
+                .bbs
+                .insert((fun.id, bb_id), source_pos(bb.pos));
+            res.push(Some(bb_id));
         }
+        res
     }
 
     fn add_regs_defs(&mut self, fun: &ParsedFunction) {
         // 1) Make the list of all registers in the function
-        let mut all_regs: HashSet<&str> = HashSet::new();
+        // The arguments of the signature are always the first registers, even if unused
+        let args_names: Vec<_> = (0..fun.signature.map_or(0, |sig| sig.nb_args()))
+            .map(|idx| format!("r{}", idx))
+            .collect();
+        let mut all_regs: HashSet<&str> = args_names.iter().map(|x| x.as_str()).collect();
         for bb in fun.bbs.as_ref().unwrap() {
             for ins in &bb.code {
                 for arg in &ins.args {
@@ -271,6 +509,90 @@ impl Parser {
     }
 }
 
+fn source_pos(pos: Position) -> SourcePos {
+    SourcePos {
+        line: pos.line,
+        col: pos.col,
+    }
+}
+
+fn parse_global(line: &str, pos: Position) -> ParseResult<ParsedGlobal> {
+    let err = |mess: &str| ParseError::new("P002", mess.to_string());
+    let syntax_err =
+        || err("Invalid global syntax: must be .global(.<width>)? <id> <name> <size> <init>*");
+
+    let mut words = line.split_whitespace();
+    let width = match words.next().unwrap() {
+        "global" => ir::IntWidth::I32,
+        kw => kw
+            .strip_prefix("global.")
+            .and_then(ir::IntWidth::from_name)
+            .ok_or_else(|| err(&format!("Invalid global width: .{}", kw)))?,
+    };
+    let id = words
+        .next()
+        .and_then(|x| x.parse::<usize>().ok())
+        .ok_or_else(syntax_err)?;
+    let name = words.next().ok_or_else(syntax_err)?;
+    let size = words
+        .next()
+        .and_then(|x| x.parse::<usize>().ok())
+        .ok_or_else(|| err("global size must be a number"))?;
+    let init = words
+        .map(|x| {
+            x.parse::<i64>()
+                .map_err(|_| err("global init value must be a number"))
+        })
+        .collect::<ParseResult<Vec<_>>>()?;
+
+    Ok(ParsedGlobal {
+        id: ir::GlobalId(id),
+        name: name.to_string(),
+        width,
+        size,
+        init,
+        pos,
+    })
+}
+
+// Parse the end of a function definition: <id> <name> <signature>?
+fn parse_fun_def(def: &str) -> ParseResult<(ir::FunctionId, String, Option<ir::Signature>)> {
+    let err = |mess: &str| ParseError::new("P003", mess.to_string());
+    let syntax_err = || err("Invalid def syntax: must be .define <fn-id> <fn-name> <signature>?");
+
+    let mut words = def.split_whitespace();
+    let def_id = words
+        .next()
+        .ok_or_else(syntax_err)?
+        .parse::<usize>()
+        .map_err(|_| err("function id must be a number"))?;
+    let def_name = words.next().ok_or_else(syntax_err)?;
+    let signature = match words.next() {
+        None => None,
+        Some(nb_args) => {
+            let nb_args = nb_args
+                .parse::<usize>()
+                .map_err(|_| err("function number of arguments must be a number"))?;
+            let is_void = match words.next() {
+                None => false,
+                Some("void") => true,
+                Some(word) => {
+                    return Err(err(&format!(
+                        "Invalid signature: expected void, got {}",
+                        word
+                    )))
+                }
+            };
+            Some(ir::Signature::new(nb_args, is_void))
+        }
+    };
+    if let Some(word) = words.next() {
+        return Err(err(&format!("Invalid def syntax: unexpected {}", word)));
+    }
+
+    Ok((ir::FunctionId(def_id), def_name.to_string(), signature))
+}
+
 fn reg_key_to_id(s: &str) -> Option<usize> {
     match s.chars().nth(0) {
         Some(c) if c == 'r' => s[1..].parse().ok(),
@@ -292,10 +614,35 @@ fn cmp_reg_keys(a: &str, b: &str) -> std::cmp::Ordering {
     }
 }
 
+// Find the '!loc' annotation in the comments of an instruction
+fn parse_debug_loc(comments: &[String]) -> ParseResult<Option<ir::DebugLoc>> {
+    let loc = match comments
+        .iter()
+        .rev()
+        .find_map(|com| com.trim().strip_prefix("!loc "))
+    {
+        Some(loc) => loc.trim(),
+        None => return Ok(None),
+    };
+
+    let mut parts = loc.rsplitn(3, ':');
+    let col = parts.next().and_then(|col| col.parse().ok());
+    let line = parts.next().and_then(|line| line.parse().ok());
+    match (parts.next(), line, col) {
+        (Some(file), Some(line), Some(col)) => Ok(Some(ir::DebugLoc::new(file, line, col))),
+        _ => Err(ParseError::new(
+            "P016",
+            format!("Invalid debug location '{}'", loc),
+        )),
+    }
+}
+
 struct FunctionParser<'a> {
     builder: IRBuilder<'a>,
     module_names: &'a irnames::ModuleNames,
     names: &'a irnames::FunctionNames,
+    diags: Vec<Diagnostic>,
+    ins_pos: Vec<((ir::BasicBlockId, usize), SourcePos)>,
 }
 
 impl<'a> FunctionParser<'a> {
@@ -308,95 +655,231 @@ impl<'a> FunctionParser<'a> {
             builder,
             module_names,
             names,
+            diags: vec![],
+            ins_pos: vec![],
         }
     }
 
-    pub fn parse(&mut self, fun: &ParsedFunction) {
-        for bb in fun.bbs.as_ref().unwrap() {
-            self.parse_bb(bb);
+    // `bbs_ids` are the ids of the basic blocks, None for the ones that are skipped
+    pub fn parse(&mut self, fun: &ParsedFunction, bbs_ids: &[Option<ir::BasicBlockId>]) {
+        for (bb, bb_id) in fun.bbs.as_ref().unwrap().iter().zip(bbs_ids) {
+            if let Some(bb_id) = bb_id {
+                self.parse_bb(bb, *bb_id);
+            }
         }
     }
 
-    pub fn parse_bb(&mut self, bb: &ParsedBasicBlock) {
-        let bb_id = self.names.get_basic_block_id(&bb.id).unwrap();
+    pub fn parse_bb(&mut self, bb: &ParsedBasicBlock, bb_id: ir::BasicBlockId) {
         self.builder.set_insert_point(bb_id);
+        let mut ins_idx = 0;
         for ins in &bb.code {
-            self.add_ins(ins);
+            match self.add_ins(ins) {
+                Ok(()) => {
+                    self.ins_pos.push(((bb_id, ins_idx), source_pos(ins.pos)));
+                    ins_idx += 1;
+                }
+                Err(err) => {
+                    let mut diag = Diagnostic::error(err.code, err.mess);
+                    let fun_name = self.module_names.get_function_name(self.names.id());
+                    diag.pos = Some(source_pos(ins.pos));
+                    diag.fun = fun_name.map(|name| name.to_string());
+                    diag.bb = Some(bb.id.clone());
+                    self.diags.push(diag);
+                }
+            }
         }
     }
 
-    fn add_ins(&mut self, ins: &Ins) {
-        match ins.name.as_str() {
-            "movi" => self.add_ins_movi(&ins.args),
-            "movr" => self.add_ins_movr(&ins.args),
-            "load" => self.add_ins_load(&ins.args),
-            "store" => self.add_ins_store(&ins.args),
-            "alloca" => self.add_ins_alloca(&ins.args),
-            "add" => self.add_ins_add(&ins.args),
-            "sub" => self.add_ins_sub(&ins.args),
-            "mul" => self.add_ins_mul(&ins.args),
-            "div" => self.add_ins_div(&ins.args),
-            "mod" => self.add_ins_mod(&ins.args),
-            "cmpeq" => self.add_ins_cmpeq(&ins.args),
-            "cmplt" => self.add_ins_cmplt(&ins.args),
-            "cmpgt" => self.add_ins_cmpgt(&ins.args),
-            "jump" => self.add_ins_jump(&ins.args),
-            "br" => self.add_ins_br(&ins.args),
-            "call" => self.add_ins_call(&ins.args),
-            "ret" => self.add_ins_ret(&ins.args),
-            _ => panic!("Unknow instruction {}", ins.name),
+    // Add the instruction at the end of the basic block, nothing is added if it's invalid
+    fn add_ins(&mut self, ins: &Ins) -> ParseResult<()> {
+        let debug_loc = parse_debug_loc(&ins.comments)?;
+        self.builder.set_debug_loc(debug_loc);
+        // The widths are suffixes of the instruction name: <name>(.<width>)*
+        let mut parts = ins.name.split('.');
+        let name = parts.next().unwrap();
+        let widths = parts
+            .map(|w| {
+                ir::IntWidth::from_name(w).ok_or_else(|| {
+                    ParseError::new(
+                        "P010",
+                        format!("Instruction {}: invalid width {}", ins.name, w),
+                    )
+                })
+            })
+            .collect::<ParseResult<Vec<_>>>()?;
+        let args = &ins.args;
+
+        if let Some(kind) = conv_kind(name) {
+            return self.add_ins_conv(&ins.name, kind, &widths, args);
+        }
+
+        let width = match widths.as_slice() {
+            [] => ir::IntWidth::I32,
+            [width] if ins_has_width(name) => *width,
+            _ => {
+                return Err(ParseError::new(
+                    "P021",
+                    format!("Instruction {}: invalid width suffix", ins.name),
+                ))
+            }
+        };
+
+        match name {
+            "movi" => self.add_ins_movi(width, args),
+            "movr" => self.add_ins_movr(args),
+            "load" => self.add_ins_load(width, args),
+            "store" => self.add_ins_store(width, args),
+            "alloca" => self.add_ins_alloca(width, args),
+            "gaddr" => self.add_ins_gaddr(args),
+            "add" => self.add_ins_opbin(&ins.name, ir::InsOpbinKind::Add, width, args),
+            "sub" => self.add_ins_opbin(&ins.name, ir::InsOpbinKind::Sub, width, args),
+            "mul" => self.add_ins_opbin(&ins.name, ir::InsOpbinKind::Mul, width, args),
+            "div" => self.add_ins_opbin(&ins.name, ir::InsOpbinKind::Div, width, args),
+            "mod" => self.add_ins_opbin(&ins.name, ir::InsOpbinKind::Mod, width, args),
+            "and" => self.add_ins_opbin(&ins.name, ir::InsOpbinKind::And, width, args),
+            "or" => self.add_ins_opbin(&ins.name, ir::InsOpbinKind::Or, width, args),
+            "xor" => self.add_ins_opbin(&ins.name, ir::InsOpbinKind::Xor, width, args),
+            "shl" => self.add_ins_opbin(&ins.name, ir::InsOpbinKind::Shl, width, args),
+            "shr" => self.add_ins_opbin(&ins.name, ir::InsOpbinKind::Shr, width, args),
+            "sar" => self.add_ins_opbin(&ins.name, ir::InsOpbinKind::Sar, width, args),
+            "cmpeq" => self.add_ins_cmpbin(&ins.name, ir::InsCmpbinKind::Eq, width, args),
+            "cmplt" => self.add_ins_cmpbin(&ins.name, ir::InsCmpbinKind::Lt, width, args),
+            "cmpgt" => self.add_ins_cmpbin(&ins.name, ir::InsCmpbinKind::Gt, width, args),
+            "cmpne" => self.add_ins_cmpbin(&ins.name, ir::InsCmpbinKind::Ne, width, args),
+            "cmple" => self.add_ins_cmpbin(&ins.name, ir::InsCmpbinKind::Le, width, args),
+            "cmpge" => self.add_ins_cmpbin(&ins.name, ir::InsCmpbinKind::Ge, width, args),
+            "cmpult" => self.add_ins_cmpbin(&ins.name, ir::InsCmpbinKind::Ult, width, args),
+            "cmpule" => self.add_ins_cmpbin(&ins.name, ir::InsCmpbinKind::Ule, width, args),
+            "cmpugt" => self.add_ins_cmpbin(&ins.name, ir::InsCmpbinKind::Ugt, width, args),
+            "cmpuge" => self.add_ins_cmpbin(&ins.name, ir::InsCmpbinKind::Uge, width, args),
+            "jump" => self.add_ins_jump(args),
+            "br" => self.add_ins_br(args),
+            "switch" => self.add_ins_switch(width, args),
+            "jumpt" => self.add_ins_jumpt(width, args),
+            "call" => self.add_ins_call(args),
+            "funaddr" => self.add_ins_funaddr(args),
+            "callr" => self.add_ins_callr(args),
+            "ret" => self.add_ins_ret(args),
+            _ => Err(ParseError::new(
+                "P009",
+                format!("Unknown instruction {}", ins.name),
+            )),
         }
     }
 
-    fn check_args_count(&self, name: &str, args: &[InsArg], exp_len: usize) {
+    fn check_args_count(&self, name: &str, args: &[InsArg], exp_len: usize) -> ParseResult<()> {
         if args.len() != exp_len {
-            panic!(
-                "Instruction {} expected {} arguments, got {}",
-                name,
-                exp_len,
-                args.len()
-            );
+            return Err(ParseError::new(
+                "P011",
+                format!(
+                    "Instruction {} expected {} arguments, got {}",
+                    name,
+                    exp_len,
+                    args.len()
+                ),
+            ));
         }
+        Ok(())
     }
 
-    fn check_arg_const(&self, name: &str, args: &[InsArg], id: usize) -> usize {
+    fn check_args_min_count(&self, name: &str, args: &[InsArg], min_len: usize) -> ParseResult<()> {
+        if args.len() < min_len {
+            return Err(ParseError::new(
+                "P023",
+                format!(
+                    "Instruction {} expected at least {} arguments, got {}",
+                    name,
+                    min_len,
+                    args.len()
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    fn arg_kind_error(&self, name: &str, id: usize, kind: &str) -> ParseError {
+        ParseError::new(
+            "P012",
+            format!("Instruction {}: arg #{} must be {}", name, id + 1, kind),
+        )
+    }
+
+    fn check_arg_const(&self, name: &str, args: &[InsArg], id: usize) -> ParseResult<usize> {
         match &args[id] {
-            InsArg::Const(val) => *val as usize,
-            _ => panic!("Instruction {}: arg #{} must a constant", name, id + 1),
+            InsArg::Const(val) => Ok(*val as usize),
+            _ => Err(self.arg_kind_error(name, id, "a constant")),
         }
     }
 
-    fn check_arg_label(&self, name: &str, args: &[InsArg], id: usize) -> ir::BasicBlockId {
+    fn check_arg_label(
+        &self,
+        name: &str,
+        args: &[InsArg],
+        id: usize,
+    ) -> ParseResult<ir::BasicBlockId> {
         let bb = match &args[id] {
             InsArg::Name(name) => name,
-            _ => panic!("Instruction {}: arg #{} must a label", name, id + 1),
+            _ => return Err(self.arg_kind_error(name, id, "a label")),
         };
-        self.names.get_basic_block_id(&bb).unwrap()
+        self.names.get_basic_block_id(bb).ok_or_else(|| {
+            ParseError::new(
+                "P013",
+                format!("Instruction {}: unknown basic block {}", name, bb),
+            )
+        })
     }
 
-    fn check_arg_function(&self, name: &str, args: &[InsArg], id: usize) -> ir::FunctionId {
+    fn check_arg_function(
+        &self,
+        name: &str,
+        args: &[InsArg],
+        id: usize,
+    ) -> ParseResult<ir::FunctionId> {
         let fun = match &args[id] {
             InsArg::Name(name) => name,
-            _ => panic!("Instruction {}: arg #{} must a label", name, id + 1),
+            _ => return Err(self.arg_kind_error(name, id, "a function")),
         };
-        self.module_names.get_function_id(&fun).unwrap()
+        self.module_names.get_function_id(fun).ok_or_else(|| {
+            ParseError::new(
+                "P014",
+                format!("Instruction {}: unknown function {}", name, fun),
+            )
+        })
     }
 
-    fn check_arg_reg(&self, name: &str, args: &[InsArg], id: usize) -> ir::RegId {
-        let reg = match &args[id] {
-            InsArg::IdName(name) => name,
-            _ => panic!("Instruction {}: arg #{} must a register", name, id + 1),
+    fn check_arg_global(
+        &self,
+        name: &str,
+        args: &[InsArg],
+        id: usize,
+    ) -> ParseResult<ir::GlobalId> {
+        let global = match &args[id] {
+            InsArg::Name(name) => name,
+            _ => return Err(self.arg_kind_error(name, id, "a global")),
         };
-        self.names.get_register_id(&reg).unwrap()
+        self.module_names.get_global_id(global).ok_or_else(|| {
+            ParseError::new(
+                "P015",
+                format!("Instruction {}: unknown global {}", name, global),
+            )
+        })
     }
 
-    fn check_args_l(&self, name: &str, args: &[InsArg]) -> ir::BasicBlockId {
-        self.check_args_count(name, args, 1);
+    fn check_arg_reg(&self, name: &str, args: &[InsArg], id: usize) -> ParseResult<ir::RegId> {
+        match &args[id] {
+            // all registers were registered by add_regs_defs
+            InsArg::IdName(reg) => Ok(self.names.get_register_id(reg).unwrap()),
+            _ => Err(self.arg_kind_error(name, id, "a register")),
+        }
+    }
+
+    fn check_args_l(&self, name: &str, args: &[InsArg]) -> ParseResult<ir::BasicBlockId> {
+        self.check_args_count(name, args, 1)?;
         self.check_arg_label(name, args, 0)
     }
 
-    fn check_args_r(&self, name: &str, args: &[InsArg]) -> ir::RegId {
-        self.check_args_count(name, args, 1);
+    fn check_args_r(&self, name: &str, args: &[InsArg]) -> ParseResult<ir::RegId> {
+        self.check_args_count(name, args, 1)?;
         self.check_arg_reg(name, args, 0)
     }
 
@@ -404,128 +887,235 @@ impl<'a> FunctionParser<'a> {
         &self,
         name: &str,
         args: &[InsArg],
-    ) -> (ir::RegId, ir::BasicBlockId, ir::BasicBlockId) {
-        self.check_args_count(name, args, 3);
-        let r = self.check_arg_reg(name, args, 0);
-        let l1 = self.check_arg_label(name, args, 1);
-        let l2 = self.check_arg_label(name, args, 2);
-        (r, l1, l2)
+    ) -> ParseResult<(ir::RegId, ir::BasicBlockId, ir::BasicBlockId)> {
+        self.check_args_count(name, args, 3)?;
+        let r = self.check_arg_reg(name, args, 0)?;
+        let l1 = self.check_arg_label(name, args, 1)?;
+        let l2 = self.check_arg_label(name, args, 2)?;
+        Ok((r, l1, l2))
     }
 
-    fn check_args_rr(&self, name: &str, args: &[InsArg]) -> (ir::RegId, ir::RegId) {
-        self.check_args_count(name, args, 2);
-        let r1 = self.check_arg_reg(name, args, 0);
-        let r2 = self.check_arg_reg(name, args, 1);
-        (r1, r2)
+    fn check_args_rr(&self, name: &str, args: &[InsArg]) -> ParseResult<(ir::RegId, ir::RegId)> {
+        self.check_args_count(name, args, 2)?;
+        let r1 = self.check_arg_reg(name, args, 0)?;
+        let r2 = self.check_arg_reg(name, args, 1)?;
+        Ok((r1, r2))
     }
 
-    fn check_args_rrr(&self, name: &str, args: &[InsArg]) -> (ir::RegId, ir::RegId, ir::RegId) {
-        self.check_args_count(name, args, 3);
-        let r1 = self.check_arg_reg(name, args, 0);
-        let r2 = self.check_arg_reg(name, args, 1);
-        let r3 = self.check_arg_reg(name, args, 2);
-        (r1, r2, r3)
+    fn check_args_rrr(
+        &self,
+        name: &str,
+        args: &[InsArg],
+    ) -> ParseResult<(ir::RegId, ir::RegId, ir::RegId)> {
+        self.check_args_count(name, args, 3)?;
+        let r1 = self.check_arg_reg(name, args, 0)?;
+        let r2 = self.check_arg_reg(name, args, 1)?;
+        let r3 = self.check_arg_reg(name, args, 2)?;
+        Ok((r1, r2, r3))
     }
 
-    fn check_args_rc(&self, name: &str, args: &[InsArg]) -> (ir::RegId, usize) {
-        self.check_args_count(name, args, 2);
-        let r1 = self.check_arg_reg(name, args, 0);
-        let r2 = self.check_arg_const(name, args, 1);
-        (r1, r2)
+    fn check_args_rc(&self, name: &str, args: &[InsArg]) -> ParseResult<(ir::RegId, usize)> {
+        self.check_args_count(name, args, 2)?;
+        let r1 = self.check_arg_reg(name, args, 0)?;
+        let r2 = self.check_arg_const(name, args, 1)?;
+        Ok((r1, r2))
     }
 
-    fn add_ins_movi(&mut self, args: &[InsArg]) {
-        let (dst, val) = self.check_args_rc("movi", args);
-        self.builder.ins_movi(dst, val as i32);
+    fn check_arg_int(&self, name: &str, args: &[InsArg], id: usize) -> ParseResult<i64> {
+        match &args[id] {
+            InsArg::Const(val) => Ok(*val as i64),
+            InsArg::Name(val) => val
+                .parse::<i64>()
+                .map_err(|_| self.arg_kind_error(name, id, "an integer")),
+            _ => Err(self.arg_kind_error(name, id, "an integer")),
+        }
     }
 
-    fn add_ins_movr(&mut self, args: &[InsArg]) {
-        let (dst, src) = self.check_args_rr("movr", args);
+    fn add_ins_movi(&mut self, width: ir::IntWidth, args: &[InsArg]) -> ParseResult<()> {
+        self.check_args_count("movi", args, 2)?;
+        let dst = self.check_arg_reg("movi", args, 0)?;
+        let val = self.check_arg_int("movi", args, 1)?;
+        self.builder.ins_movi_w(width, dst, val);
+        Ok(())
+    }
+
+    fn add_ins_movr(&mut self, args: &[InsArg]) -> ParseResult<()> {
+        let (dst, src) = self.check_args_rr("movr", args)?;
         self.builder.ins_movr(dst, src);
+        Ok(())
     }
 
-    fn add_ins_load(&mut self, args: &[InsArg]) {
-        let (dst, src) = self.check_args_rr("load", args);
-        self.builder.ins_load(dst, src);
+    fn add_ins_load(&mut self, width: ir::IntWidth, args: &[InsArg]) -> ParseResult<()> {
+        let (dst, src) = self.check_args_rr("load", args)?;
+        self.builder.ins_load_w(width, dst, src);
+        Ok(())
     }
 
-    fn add_ins_store(&mut self, args: &[InsArg]) {
-        let (dst, src) = self.check_args_rr("store", args);
-        self.builder.ins_store(dst, src);
+    fn add_ins_store(&mut self, width: ir::IntWidth, args: &[InsArg]) -> ParseResult<()> {
+        let (dst, src) = self.check_args_rr("store", args)?;
+        self.builder.ins_store_w(width, dst, src);
+        Ok(())
     }
 
-    fn add_ins_alloca(&mut self, args: &[InsArg]) {
-        let dst = self.check_args_r("alloca", args);
-        self.builder.ins_alloca(dst);
+    fn add_ins_alloca(&mut self, width: ir::IntWidth, args: &[InsArg]) -> ParseResult<()> {
+        let (dst, size) = if args.len() == 1 {
+            (self.check_args_r("alloca", args)?, 1)
+        } else {
+            self.check_args_rc("alloca", args)?
+        };
+        self.builder.ins_alloca_w(width, dst, size);
+        Ok(())
     }
 
-    fn add_ins_add(&mut self, args: &[InsArg]) {
-        let (dst, src1, src2) = self.check_args_rrr("add", args);
-        self.builder.ins_add(dst, src1, src2);
+    fn add_ins_gaddr(&mut self, args: &[InsArg]) -> ParseResult<()> {
+        self.check_args_count("gaddr", args, 2)?;
+        let dst = self.check_arg_reg("gaddr", args, 0)?;
+        let global = self.check_arg_global("gaddr", args, 1)?;
+        self.builder.ins_gaddr(dst, global);
+        Ok(())
     }
 
-    fn add_ins_sub(&mut self, args: &[InsArg]) {
-        let (dst, src1, src2) = self.check_args_rrr("sub", args);
-        self.builder.ins_sub(dst, src1, src2);
+    fn add_ins_opbin(
+        &mut self,
+        name: &str,
+        kind: ir::InsOpbinKind,
+        width: ir::IntWidth,
+        args: &[InsArg],
+    ) -> ParseResult<()> {
+        let (dst, src1, src2) = self.check_args_rrr(name, args)?;
+        self.builder.ins_opbin_w(width, kind, dst, src1, src2);
+        Ok(())
     }
 
-    fn add_ins_mul(&mut self, args: &[InsArg]) {
-        let (dst, src1, src2) = self.check_args_rrr("mul", args);
-        self.builder.ins_mul(dst, src1, src2);
+    fn add_ins_cmpbin(
+        &mut self,
+        name: &str,
+        kind: ir::InsCmpbinKind,
+        width: ir::IntWidth,
+        args: &[InsArg],
+    ) -> ParseResult<()> {
+        let (dst, src1, src2) = self.check_args_rrr(name, args)?;
+        self.builder.ins_cmpbin_w(width, kind, dst, src1, src2);
+        Ok(())
     }
 
-    fn add_ins_div(&mut self, args: &[InsArg]) {
-        let (dst, src1, src2) = self.check_args_rrr("div", args);
-        self.builder.ins_div(dst, src1, src2);
+    fn add_ins_conv(
+        &mut self,
+        name: &str,
+        kind: ir::InsConvKind,
+        widths: &[ir::IntWidth],
+        args: &[InsArg],
+    ) -> ParseResult<()> {
+        let (from, to) = match widths {
+            [from, to] => (*from, *to),
+            _ => {
+                return Err(ParseError::new(
+                    "P022",
+                    format!("Instruction {}: expected 2 widths <from>.<to>", name),
+                ))
+            }
+        };
+        let (dst, src) = self.check_args_rr(name, args)?;
+        self.builder.ins_conv(kind, from, to, dst, src);
+        Ok(())
     }
 
-    fn add_ins_mod(&mut self, args: &[InsArg]) {
-        let (dst, src1, src2) = self.check_args_rrr("mod", args);
-        self.builder.ins_mod(dst, src1, src2);
+    fn add_ins_jump(&mut self, args: &[InsArg]) -> ParseResult<()> {
+        let dst = self.check_args_l("jump", args)?;
+        self.builder.ins_jump(dst);
+        Ok(())
     }
 
-    fn add_ins_cmpeq(&mut self, args: &[InsArg]) {
-        let (dst, src1, src2) = self.check_args_rrr("cmpeq", args);
-        self.builder.ins_cmpeq(dst, src1, src2);
+    fn add_ins_br(&mut self, args: &[InsArg]) -> ParseResult<()> {
+        let (src, dst_true, dst_false) = self.check_args_rll("br", args)?;
+        self.builder.ins_br(src, dst_true, dst_false);
+        Ok(())
     }
 
-    fn add_ins_cmplt(&mut self, args: &[InsArg]) {
-        let (dst, src1, src2) = self.check_args_rrr("cmplt", args);
-        self.builder.ins_cmplt(dst, src1, src2);
+    fn add_ins_switch(&mut self, width: ir::IntWidth, args: &[InsArg]) -> ParseResult<()> {
+        if args.len() < 2 || args.len() % 2 == 1 {
+            return Err(ParseError::new(
+                "P024",
+                format!(
+                    "switch instructions expected a register, a default label, and pairs of value and label, got {} arguments",
+                    args.len()
+                ),
+            ));
+        }
+        let src = self.check_arg_reg("switch", args, 0)?;
+        let dst_default = self.check_arg_label("switch", args, 1)?;
+        let cases = (2..args.len())
+            .step_by(2)
+            .map(|id| {
+                let val = self.check_arg_int("switch", args, id)?;
+                let dst = self.check_arg_label("switch", args, id + 1)?;
+                Ok((val, dst))
+            })
+            .collect::<ParseResult<Vec<_>>>()?;
+        self.builder.ins_switch_w(width, src, cases, dst_default);
+        Ok(())
     }
 
-    fn add_ins_cmpgt(&mut self, args: &[InsArg]) {
-        let (dst, src1, src2) = self.check_args_rrr("cmpgt", args);
-        self.builder.ins_cmpgt(dst, src1, src2);
+    fn add_ins_jumpt(&mut self, width: ir::IntWidth, args: &[InsArg]) -> ParseResult<()> {
+        self.check_args_min_count("jumpt", args, 2)?;
+        let src = self.check_arg_reg("jumpt", args, 0)?;
+        let table = (1..args.len())
+            .map(|id| self.check_arg_label("jumpt", args, id))
+            .collect::<ParseResult<Vec<_>>>()?;
+        self.builder.ins_jumpt_w(width, src, table);
+        Ok(())
     }
 
-    fn add_ins_jump(&mut self, args: &[InsArg]) {
-        let dst = self.check_args_l("jump", args);
-        self.builder.ins_jump(dst);
+    fn add_ins_call(&mut self, args: &[InsArg]) -> ParseResult<()> {
+        self.check_args_min_count("call", args, 2)?;
+        let dst = self.check_arg_reg("call", args, 0)?;
+        let fun = self.check_arg_function("call", args, 1)?;
+        let args = (2..args.len())
+            .map(|id| self.check_arg_reg("call", args, id))
+            .collect::<ParseResult<Vec<_>>>()?;
+        self.builder.ins_call(dst, fun, args);
+        Ok(())
     }
 
-    fn add_ins_br(&mut self, args: &[InsArg]) {
-        let (src, dst_true, dst_false) = self.check_args_rll("br", args);
-        self.builder.ins_br(src, dst_true, dst_false);
+    fn add_ins_funaddr(&mut self, args: &[InsArg]) -> ParseResult<()> {
+        self.check_args_count("funaddr", args, 2)?;
+        let dst = self.check_arg_reg("funaddr", args, 0)?;
+        let fun = self.check_arg_function("funaddr", args, 1)?;
+        self.builder.ins_funaddr(dst, fun);
+        Ok(())
     }
 
-    fn add_ins_call(&mut self, args: &[InsArg]) {
-        if args.len() < 2 {
-            println!(
-                "call instructions expected at least 2 arguments, got {}",
-                args.len()
-            );
-        }
-        let dst = self.check_arg_reg("call", args, 0);
-        let fun = self.check_arg_function("call", args, 1);
-        let args: Vec<_> = (2..args.len())
-            .map(|id| self.check_arg_reg("call", args, id))
-            .collect();
-        self.builder.ins_call(dst, fun, args);
+    fn add_ins_callr(&mut self, args: &[InsArg]) -> ParseResult<()> {
+        self.check_args_min_count("callr", args, 2)?;
+        let dst = self.check_arg_reg("callr", args, 0)?;
+        let fptr = self.check_arg_reg("callr", args, 1)?;
+        let args = (2..args.len())
+            .map(|id| self.check_arg_reg("callr", args, id))
+            .collect::<ParseResult<Vec<_>>>()?;
+        self.builder.ins_callr(dst, fptr, args);
+        Ok(())
     }
 
-    fn add_ins_ret(&mut self, args: &[InsArg]) {
-        let src = self.check_args_r("ret", args);
+    fn add_ins_ret(&mut self, args: &[InsArg]) -> ParseResult<()> {
+        let src = self.check_args_r("ret", args)?;
         self.builder.ins_ret(src);
+        Ok(())
+    }
+}
+
+fn conv_kind(name: &str) -> Option<ir::InsConvKind> {
+    match name {
+        "sext" => Some(ir::InsConvKind::Sext),
+        "zext" => Some(ir::InsConvKind::Zext),
+        "trunc" => Some(ir::InsConvKind::Trunc),
+        _ => None,
     }
 }
+
+// Instructions that accept a width suffix
+fn ins_has_width(name: &str) -> bool {
+    !matches!(
+        name,
+        "movr" | "gaddr" | "jump" | "br" | "call" | "funaddr" | "callr" | "ret"
+    )
+}
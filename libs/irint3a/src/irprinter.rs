@@ -26,11 +26,61 @@ impl CodePrintable for ir::Module {
     }
 }
 
+/// Give an optional annotation for every instruction of the code
+/// Used to print extra infos next to the code (eg: execution counts)
+pub trait InsAnnotator {
+    fn annotate_ins(
+        &self,
+        fun: ir::FunctionId,
+        bb: ir::BasicBlockId,
+        ins_idx: usize,
+    ) -> Option<String>;
+}
+
+/// Print the code of the module, with a column on the left for annotations
+/// The column contains the annotation for instruction lines, and '-' for all other lines
+/// Names are generated if `names` is none
+pub fn print_code_annotated(
+    module: &ir::Module,
+    writer: &mut dyn Write,
+    names: Option<&irnames::ModuleNames>,
+    annotator: &dyn InsAnnotator,
+) {
+    let mut gen_names = irnames::ModuleNames::new();
+    let names = match names {
+        Some(names) => names,
+        None => {
+            gen_names.complete_undefined(module);
+            &gen_names
+        }
+    };
+
+    let mut printer = IRPrinter::new(module, names);
+    printer.annotator = Some(annotator);
+    printer.print_mod(writer);
+}
+
+/// Print only one instruction of a function (without indentation and newline)
+/// `names` must contain all the names of the function
+pub fn print_ins(
+    module: &ir::Module,
+    writer: &mut dyn Write,
+    names: &irnames::ModuleNames,
+    fun: ir::FunctionId,
+    ins: &ir::Ins,
+) {
+    let mut printer = IRPrinter::new(module, names);
+    printer.fun = module.get_fun(fun);
+    printer.fun_names = names.get_function(fun);
+    printer.print_ins(ins, writer);
+}
+
 struct IRPrinter<'a> {
     module: &'a ir::Module,
     names: &'a irnames::ModuleNames,
     fun: Option<&'a ir::Function>,
     fun_names: Option<&'a irnames::FunctionNames>,
+    annotator: Option<&'a dyn InsAnnotator>,
 }
 
 impl<'a> IRPrinter<'a> {
@@ -40,10 +90,26 @@ impl<'a> IRPrinter<'a> {
             names,
             fun: None,
             fun_names: None,
+            annotator: None,
+        }
+    }
+
+    // Print the annotation column at the beginning of a line, if there is an annotator
+    fn print_line_prefix(&self, writer: &mut dyn Write, ann: Option<String>) {
+        if self.annotator.is_some() {
+            let ann = ann.unwrap_or_else(|| "-".to_string());
+            write!(writer, "{:>10}: ", ann).unwrap();
         }
     }
 
     fn print_mod(&mut self, writer: &mut dyn Write) {
+        for global in self.module.globals() {
+            self.print_global(global, writer);
+        }
+        if !self.module.globals().is_empty() {
+            writeln!(writer).unwrap();
+        }
+
         for fun in self.module.funs() {
             self.fun = Some(fun);
             self.fun_names = Some(self.names.get_function(fun.id()).unwrap());
@@ -52,12 +118,30 @@ impl<'a> IRPrinter<'a> {
         }
     }
 
+    fn print_global(&self, global: &ir::Global, writer: &mut dyn Write) {
+        self.print_line_prefix(writer, None);
+        write!(
+            writer,
+            ".global{} {} {} {}",
+            width_suffix(global.width()),
+            global.id().0,
+            self.names.get_global_name(global.id()).unwrap(),
+            global.size()
+        )
+        .unwrap();
+        for val in global.init() {
+            write!(writer, " {}", val).unwrap();
+        }
+        writeln!(writer).unwrap();
+    }
+
     fn print_fun(&self, writer: &mut dyn Write) {
         let fun = self.fun.unwrap();
         let fun_id = fun.id();
 
         let define_kw = if fun.is_extern() { "declare" } else { "define" };
 
+        self.print_line_prefix(writer, None);
         write!(
             writer,
             ".{} {} {} ",
@@ -66,6 +150,12 @@ impl<'a> IRPrinter<'a> {
             self.names.get_function_name(fun_id).unwrap()
         )
         .unwrap();
+        if let Some(sig) = fun.signature() {
+            write!(writer, "{} ", sig.nb_args()).unwrap();
+            if sig.is_void() {
+                write!(writer, "void ").unwrap();
+            }
+        }
 
         if !fun.is_extern() {
             write!(writer, "\n").unwrap();
@@ -80,6 +170,7 @@ impl<'a> IRPrinter<'a> {
 
         for bb_id in fun.basic_blocks_list() {
             let bb = fun.get_basic_block(*bb_id);
+            self.print_line_prefix(writer, None);
             write!(
                 writer,
                 "{}:\n",
@@ -92,9 +183,16 @@ impl<'a> IRPrinter<'a> {
     }
 
     fn print_bb(&self, bb: &ir::BasicBlock, writer: &mut dyn Write) {
-        for ins in bb.iter() {
+        for (ins_idx, ins) in bb.iter().enumerate() {
+            if let Some(annotator) = self.annotator {
+                let ann = annotator.annotate_ins(bb.fun_id(), bb.id(), ins_idx);
+                self.print_line_prefix(writer, ann);
+            }
             write!(writer, "  ").unwrap();
             self.print_ins(ins, writer);
+            if let Some(loc) = bb.get_ins_loc(ins_idx) {
+                write!(writer, " ; !loc {}", loc).unwrap();
+            }
             write!(writer, "\n").unwrap();
         }
     }
@@ -106,11 +204,17 @@ impl<'a> IRPrinter<'a> {
             ir::Ins::Load(ins) => self.print_ins_load(&ins, writer),
             ir::Ins::Store(ins) => self.print_ins_store(&ins, writer),
             ir::Ins::Alloca(ins) => self.print_ins_alloca(&ins, writer),
+            ir::Ins::Gaddr(ins) => self.print_ins_gaddr(ins, writer),
             ir::Ins::Opbin(ins) => self.print_ins_opbin(&ins, writer),
             ir::Ins::Cmpbin(ins) => self.print_ins_cmpbin(&ins, writer),
+            ir::Ins::Conv(ins) => self.print_ins_conv(ins, writer),
             ir::Ins::Jump(ins) => self.print_ins_jump(&ins, writer),
             ir::Ins::Br(ins) => self.print_ins_br(&ins, writer),
+            ir::Ins::Switch(ins) => self.print_ins_switch(ins, writer),
+            ir::Ins::Jumpt(ins) => self.print_ins_jumpt(ins, writer),
             ir::Ins::Call(ins) => self.print_ins_call(&ins, writer),
+            ir::Ins::Funaddr(ins) => self.print_ins_funaddr(ins, writer),
+            ir::Ins::Callr(ins) => self.print_ins_callr(ins, writer),
             ir::Ins::Ret(ins) => self.print_ins_ret(&ins, writer),
         }
     }
@@ -118,7 +222,14 @@ impl<'a> IRPrinter<'a> {
     fn print_ins_movi(&self, ins: &ir::InsMovi, writer: &mut dyn Write) {
         let fun_names = self.fun_names.unwrap();
         let dst = fun_names.get_register_name(ins.dst()).unwrap();
-        write!(writer, "movi %{}, {}", dst, ins.const_val()).unwrap();
+        write!(
+            writer,
+            "movi{} %{}, {}",
+            width_suffix(ins.width()),
+            dst,
+            ins.const_val()
+        )
+        .unwrap();
     }
 
     fn print_ins_movr(&self, ins: &ir::InsMovr, writer: &mut dyn Write) {
@@ -132,20 +243,44 @@ impl<'a> IRPrinter<'a> {
         let fun_names = self.fun_names.unwrap();
         let src = fun_names.get_register_name(ins.src()).unwrap();
         let dst = fun_names.get_register_name(ins.dst()).unwrap();
-        write!(writer, "load %{}, %{}", dst, src).unwrap();
+        write!(
+            writer,
+            "load{} %{}, %{}",
+            width_suffix(ins.width()),
+            dst,
+            src
+        )
+        .unwrap();
     }
 
     fn print_ins_store(&self, ins: &ir::InsStore, writer: &mut dyn Write) {
         let fun_names = self.fun_names.unwrap();
         let src = fun_names.get_register_name(ins.src()).unwrap();
         let dst = fun_names.get_register_name(ins.dst()).unwrap();
-        write!(writer, "store %{}, %{}", dst, src).unwrap();
+        write!(
+            writer,
+            "store{} %{}, %{}",
+            width_suffix(ins.width()),
+            dst,
+            src
+        )
+        .unwrap();
     }
 
     fn print_ins_alloca(&self, ins: &ir::InsAlloca, writer: &mut dyn Write) {
         let fun_names = self.fun_names.unwrap();
         let dst = fun_names.get_register_name(ins.dst()).unwrap();
-        write!(writer, "alloca %{}", dst).unwrap();
+        write!(writer, "alloca{} %{}", width_suffix(ins.width()), dst).unwrap();
+        if ins.size() != 1 {
+            write!(writer, ", {}", ins.size()).unwrap();
+        }
+    }
+
+    fn print_ins_gaddr(&self, ins: &ir::InsGaddr, writer: &mut dyn Write) {
+        let fun_names = self.fun_names.unwrap();
+        let dst = fun_names.get_register_name(ins.dst()).unwrap();
+        let global = self.names.get_global_name(ins.global()).unwrap();
+        write!(writer, "gaddr %{}, {}", dst, global).unwrap();
     }
 
     fn print_ins_opbin(&self, ins: &ir::InsOpbin, writer: &mut dyn Write) {
@@ -160,9 +295,24 @@ impl<'a> IRPrinter<'a> {
             ir::InsOpbinKind::Mul => "mul",
             ir::InsOpbinKind::Div => "div",
             ir::InsOpbinKind::Mod => "mod",
+            ir::InsOpbinKind::And => "and",
+            ir::InsOpbinKind::Or => "or",
+            ir::InsOpbinKind::Xor => "xor",
+            ir::InsOpbinKind::Shl => "shl",
+            ir::InsOpbinKind::Shr => "shr",
+            ir::InsOpbinKind::Sar => "sar",
         };
 
-        write!(writer, "{} %{}, %{}, %{}", ins_name, dst, src1, src2).unwrap();
+        write!(
+            writer,
+            "{}{} %{}, %{}, %{}",
+            ins_name,
+            width_suffix(ins.width()),
+            dst,
+            src1,
+            src2
+        )
+        .unwrap();
     }
 
     fn print_ins_cmpbin(&self, ins: &ir::InsCmpbin, writer: &mut dyn Write) {
@@ -175,9 +325,48 @@ impl<'a> IRPrinter<'a> {
             ir::InsCmpbinKind::Eq => "cmpeq",
             ir::InsCmpbinKind::Gt => "cmpgt",
             ir::InsCmpbinKind::Lt => "cmplt",
+            ir::InsCmpbinKind::Ne => "cmpne",
+            ir::InsCmpbinKind::Le => "cmple",
+            ir::InsCmpbinKind::Ge => "cmpge",
+            ir::InsCmpbinKind::Ult => "cmpult",
+            ir::InsCmpbinKind::Ule => "cmpule",
+            ir::InsCmpbinKind::Ugt => "cmpugt",
+            ir::InsCmpbinKind::Uge => "cmpuge",
+        };
+
+        write!(
+            writer,
+            "{}{} %{}, %{}, %{}",
+            ins_name,
+            width_suffix(ins.width()),
+            dst,
+            src1,
+            src2,
+        )
+        .unwrap();
+    }
+
+    fn print_ins_conv(&self, ins: &ir::InsConv, writer: &mut dyn Write) {
+        let fun_names = self.fun_names.unwrap();
+        let src = fun_names.get_register_name(ins.src()).unwrap();
+        let dst = fun_names.get_register_name(ins.dst()).unwrap();
+
+        let ins_name = match ins.kind() {
+            ir::InsConvKind::Sext => "sext",
+            ir::InsConvKind::Zext => "zext",
+            ir::InsConvKind::Trunc => "trunc",
         };
 
-        write!(writer, "{} %{}, %{}, %{}", ins_name, dst, src1, src2,).unwrap();
+        write!(
+            writer,
+            "{}.{}.{} %{}, %{}",
+            ins_name,
+            ins.from().name(),
+            ins.to().name(),
+            dst,
+            src
+        )
+        .unwrap();
     }
 
     fn print_ins_jump(&self, ins: &ir::InsJump, writer: &mut dyn Write) {
@@ -195,6 +384,36 @@ impl<'a> IRPrinter<'a> {
         write!(writer, "br %{}, {}, {}", src, dst_true_name, dst_false_name).unwrap();
     }
 
+    fn print_ins_switch(&self, ins: &ir::InsSwitch, writer: &mut dyn Write) {
+        let fun_names = self.fun_names.unwrap();
+        let src = fun_names.get_register_name(ins.src()).unwrap();
+        let dst_default = fun_names.get_basic_block_name(ins.dst_default()).unwrap();
+
+        write!(
+            writer,
+            "switch{} %{}, {}",
+            width_suffix(ins.width()),
+            src,
+            dst_default
+        )
+        .unwrap();
+        for (val, dst) in ins.cases() {
+            let dst = fun_names.get_basic_block_name(*dst).unwrap();
+            write!(writer, ", {}, {}", val, dst).unwrap();
+        }
+    }
+
+    fn print_ins_jumpt(&self, ins: &ir::InsJumpt, writer: &mut dyn Write) {
+        let fun_names = self.fun_names.unwrap();
+        let src = fun_names.get_register_name(ins.src()).unwrap();
+
+        write!(writer, "jumpt{} %{}", width_suffix(ins.width()), src).unwrap();
+        for dst in ins.table() {
+            let dst = fun_names.get_basic_block_name(*dst).unwrap();
+            write!(writer, ", {}", dst).unwrap();
+        }
+    }
+
     fn print_ins_call(&self, ins: &ir::InsCall, writer: &mut dyn Write) {
         let fun_names = self.fun_names.unwrap();
         let dst = fun_names.get_register_name(ins.dst()).unwrap();
@@ -207,9 +426,36 @@ impl<'a> IRPrinter<'a> {
         }
     }
 
+    fn print_ins_funaddr(&self, ins: &ir::InsFunaddr, writer: &mut dyn Write) {
+        let fun_names = self.fun_names.unwrap();
+        let dst = fun_names.get_register_name(ins.dst()).unwrap();
+        let fun = self.names.get_function_name(ins.fun()).unwrap();
+        write!(writer, "funaddr %{}, {}", dst, fun).unwrap();
+    }
+
+    fn print_ins_callr(&self, ins: &ir::InsCallr, writer: &mut dyn Write) {
+        let fun_names = self.fun_names.unwrap();
+        let dst = fun_names.get_register_name(ins.dst()).unwrap();
+        let fptr = fun_names.get_register_name(ins.fptr()).unwrap();
+
+        write!(writer, "callr %{}, %{}", dst, fptr).unwrap();
+        for arg in ins.args() {
+            let arg = fun_names.get_register_name(*arg).unwrap();
+            write!(writer, ", %{}", arg).unwrap();
+        }
+    }
+
     fn print_ins_ret(&self, ins: &ir::InsRet, writer: &mut dyn Write) {
         let fun_names = self.fun_names.unwrap();
         let src = fun_names.get_register_name(ins.src()).unwrap();
         write!(writer, "ret %{}", src).unwrap();
     }
 }
+
+// Suffix added to the instruction name for the width, nothing for the default i32
+fn width_suffix(width: ir::IntWidth) -> String {
+    match width {
+        ir::IntWidth::I32 => String::new(),
+        _ => format!(".{}", width.name()),
+    }
+}
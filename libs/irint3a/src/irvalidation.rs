@@ -4,67 +4,198 @@
 // Some are enforced by design and API calls, others must be checked
 //
 // Every function id must be unique [ENFORCED]
-// A function must have at least one basic block
+// Every global id must be unique [ENFORCED]
+// The init data of a global must not be bigger than its size [V001]
+// A function must have at least one basic block [V002]
 // All basic blocks of a function must be unique [ENFORCED]
-// A Basic Block must not be empty
-// The last instruction of a basic block must be a control flow instruction
-// The non-last instruction of a basick block cannot be a control flow instruction
-// Branching instructions must jump to basic blocs of the same function
-// Call instructions must reference existing functions
+// A Basic Block must not be empty [V003]
+// The last instruction of a basic block must be a control flow instruction [V004]
+// The non-last instruction of a basick block cannot be a control flow instruction [V005]
+// Branching instructions must jump to basic blocs of the same function [V006]
+// The case values of a switch must be unique [V007]
+// The table of a jumpt must not be empty [V019]
+// Call and funaddr instructions must reference existing functions [V008, V010]
+// Call instructions must have the number of arguments of the signature of the function (if any) [V009]
+// In a function with a signature, the registers read but never written must be arguments
+// (except the register returned by a void function) [V016]
+// Gaddr instructions must reference existing globals [V011]
+// Alloca instructions must allocate at least one value [V012]
+// Conversion instructions must have valid widths: sext / zext to a bigger width, trunc to a smaller one [V013]
+// Every register must always have the same width (infered from the instructions) [V014]
+// Every register must be used with the width it has [V015]
+// Funaddr instructions must reference functions with an id smaller than MAX_FUNADDR_ID [V018]
+//
+// Every rule has a stable diagnostic code (see irdiagnostics)
+//
+// Some rules only produce warnings, they don't make the validation fail
+// Indirect calls should not resolve to a function with a different number of arguments [V017]
 
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 use crate::ir;
+use crate::irdiagnostics::{Diagnostic, Severity};
+use crate::irnames;
+use crate::registers::{GetRegistersDefUse, GetRegistersWidth};
+
+/// Function addresses hold the function id on 16 bits (see the irint3a interpreter)
+pub const MAX_FUNADDR_ID: usize = 0x10000;
 
-fn validate_fun(
+// Signature of every function of the module (None if it has no signature)
+type FunSignatures = HashMap<ir::FunctionId, Option<ir::Signature>>;
+
+/// Validate if a function is rightly constructed
+/// `funs` is the optional list of all functions in the module, in order to check if a call is valid
+/// If none, calls are not checked (and neither are gaddr instructions)
+/// Returns the errors if validation failed
+pub fn validate_function(
     fun: &ir::Function,
     funs: Option<&HashSet<ir::FunctionId>>,
-    out_res: Option<&mut bool>,
-) {
-    let mut vd = FunctionValidation::new(fun, funs);
+) -> Result<(), Vec<Diagnostic>> {
+    let funs: Option<FunSignatures> = funs.map(|funs| funs.iter().map(|id| (*id, None)).collect());
+    let mut vd = FunctionValidation::new(fun, funs.as_ref(), None);
     vd.check();
-
-    let mut valid = true;
-    for err in vd.errs {
-        println!("F #{}: {:?}", fun.id().0, err);
-        valid = false;
+    if vd.errs.is_empty() {
+        Ok(())
+    } else {
+        Err(vd
+            .errs
+            .iter()
+            .map(|err| err.to_diagnostic(Severity::Error, None))
+            .collect())
     }
-    if !valid {
-        match out_res {
-            Some(out_res) => *out_res = false,
-            None => panic!("Function validation for #{} failed", fun.id().0),
-        }
+}
+
+/// Validate if a whole module is rightly constructed
+/// Returns the errors if validation failed, with the names of the functions and basic blocks from `names`
+/// The warnings are not reported, they are given by `module_warnings`
+pub fn validate_module(
+    module: &ir::Module,
+    names: Option<&irnames::ModuleNames>,
+) -> Result<(), Vec<Diagnostic>> {
+    let errs = check_module(module);
+    if errs.is_empty() {
+        Ok(())
+    } else {
+        Err(errs
+            .iter()
+            .map(|err| err.to_diagnostic(Severity::Error, names))
+            .collect())
     }
 }
 
-/// Validate if a function is rightly constructed
-/// `funs` is the optional list of all functions in the module, in order to check if a call is valid
-/// If none, calls are not checked
-/// Print errors and panics if validation failed
-pub fn validate_function(fun: &ir::Function, funs: Option<&HashSet<ir::FunctionId>>) {
-    validate_fun(fun, funs, None);
+/// Returns the warnings of the module (they don't make the validation fail)
+pub fn module_warnings(
+    module: &ir::Module,
+    names: Option<&irnames::ModuleNames>,
+) -> Vec<Diagnostic> {
+    check_indirect_calls(module)
+        .iter()
+        .map(|warn| warn.to_diagnostic(Severity::Warning, names))
+        .collect()
 }
 
-/// Validate if a whole module is rightly constructed
-/// Print errors and panics if validation failed
-pub fn validate_module(module: &ir::Module) {
-    let mut funs = HashSet::new();
+// Check all the globals and functions of the module, returns the errors
+pub(crate) fn check_module(module: &ir::Module) -> Vec<ValidationError> {
+    let mut funs = HashMap::new();
     for fun in module.funs() {
-        funs.insert(fun.id());
+        funs.insert(fun.id(), fun.signature());
+    }
+
+    let mut errs = vec![];
+    let mut globals = HashSet::new();
+    for global in module.globals() {
+        globals.insert(global.id());
+        if global.init().len() > global.size() {
+            errs.push(ValidationError::Global(GlobalError {
+                code: "V001",
+                mess: "Global init data is bigger than its size",
+                global_id: global.id(),
+            }));
+        }
     }
 
-    let mut valid = true;
     for fun in module.funs() {
-        validate_fun(fun, Some(&funs), Some(&mut valid));
+        let mut vd = FunctionValidation::new(fun, Some(&funs), Some(&globals));
+        vd.check();
+        errs.append(&mut vd.errs);
     }
+    errs
+}
 
-    if !valid {
-        panic!("Module validation failed");
+/// Look for indirect calls that may resolve to a function with the wrong arity
+/// Any function whose address is taken by a funaddr may be the target of every callr of the module
+/// The arity of a function is given by its signature. Without signature, the arity of an extern function
+/// is infered from the direct calls to it, when they all agree
+/// Returns one warning for each callr that may be wrong
+pub fn check_indirect_calls(module: &ir::Module) -> Vec<ValidationError> {
+    let mut targets = HashSet::new();
+    let mut arities: HashMap<ir::FunctionId, Option<usize>> = HashMap::new();
+    for fun in module.funs().iter().filter(|fun| !fun.is_extern()) {
+        for ins in fun_ins(fun) {
+            match ins {
+                ir::Ins::Funaddr(ins) => {
+                    targets.insert(ins.fun());
+                }
+                ir::Ins::Call(ins) => {
+                    let nb_args = ins.args().len();
+                    let arity = arities.entry(ins.fun()).or_insert(Some(nb_args));
+                    if *arity != Some(nb_args) {
+                        *arity = None;
+                    }
+                }
+                _ => {}
+            }
+        }
     }
+
+    // arities of all functions that may be called indirectly
+    let targets_arities: Vec<usize> = targets
+        .iter()
+        .filter_map(|id| module.get_fun(*id))
+        .filter_map(|fun| match fun.signature() {
+            Some(sig) => Some(sig.nb_args()),
+            None if fun.is_extern() => arities.get(&fun.id()).copied().flatten(),
+            None => None,
+        })
+        .collect();
+
+    let mut warns = vec![];
+    for fun in module.funs().iter().filter(|fun| !fun.is_extern()) {
+        for bb_id in fun.basic_blocks_list() {
+            let bb = fun.get_basic_block(*bb_id);
+            for (ins_idx, ins) in bb.iter().enumerate() {
+                let ins = match ins {
+                    ir::Ins::Callr(ins) => ins,
+                    _ => continue,
+                };
+                if targets_arities
+                    .iter()
+                    .any(|arity| *arity != ins.args().len())
+                {
+                    warns.push(ValidationError::Ins(InsError {
+                        code: "V017",
+                        mess: "Indirect call may resolve to a function with a different arity",
+                        ins_id: ins_idx,
+                        bb_id: *bb_id,
+                        fun_id: fun.id(),
+                    }));
+                }
+            }
+        }
+    }
+    warns
+}
+
+// Iterate over all instructions of a function
+fn fun_ins(fun: &ir::Function) -> impl Iterator<Item = &ir::Ins> {
+    fun.basic_blocks_list()
+        .iter()
+        .flat_map(move |bb_id| fun.get_basic_block(*bb_id).iter())
 }
 
 #[derive(Debug)]
 pub struct InsError {
+    pub code: &'static str,
     pub mess: &'static str,
     pub ins_id: usize,
     pub bb_id: ir::BasicBlockId,
@@ -73,6 +204,7 @@ pub struct InsError {
 
 #[derive(Debug)]
 pub struct BasicBlockError {
+    pub code: &'static str,
     pub mess: &'static str,
     pub bb_id: ir::BasicBlockId,
     pub fun_id: ir::FunctionId,
@@ -80,21 +212,83 @@ pub struct BasicBlockError {
 
 #[derive(Debug)]
 pub struct FunctionError {
+    pub code: &'static str,
     pub mess: &'static str,
     pub fun_id: ir::FunctionId,
 }
 
+#[derive(Debug)]
+pub struct GlobalError {
+    pub code: &'static str,
+    pub mess: &'static str,
+    pub global_id: ir::GlobalId,
+}
+
 #[derive(Debug)]
 pub enum ValidationError {
     Ins(InsError),
     BasicBlock(BasicBlockError),
     Function(FunctionError),
+    Global(GlobalError),
+}
+
+impl ValidationError {
+    /// Convert the error to a diagnostic, with the names of the function and basic block
+    /// The ids are used if there are no names
+    pub fn to_diagnostic(
+        &self,
+        severity: Severity,
+        names: Option<&irnames::ModuleNames>,
+    ) -> Diagnostic {
+        let fun_name = |fun_id: ir::FunctionId| {
+            names
+                .and_then(|names| names.get_function_name(fun_id))
+                .map(|name| name.to_string())
+                .unwrap_or_else(|| format!("#{}", fun_id.0))
+        };
+        let bb_name = |fun_id: ir::FunctionId, bb_id: ir::BasicBlockId| {
+            names
+                .and_then(|names| names.get_function(fun_id))
+                .and_then(|fun_names| fun_names.get_basic_block_name(bb_id))
+                .map(|name| name.to_string())
+                .unwrap_or_else(|| format!("#{}", bb_id.0))
+        };
+
+        match self {
+            ValidationError::Ins(err) => {
+                let mut res = Diagnostic::new(severity, err.code, err.mess.to_string());
+                res.fun = Some(fun_name(err.fun_id));
+                res.bb = Some(bb_name(err.fun_id, err.bb_id));
+                res.ins = Some(err.ins_id);
+                res
+            }
+            ValidationError::BasicBlock(err) => {
+                let mut res = Diagnostic::new(severity, err.code, err.mess.to_string());
+                res.fun = Some(fun_name(err.fun_id));
+                res.bb = Some(bb_name(err.fun_id, err.bb_id));
+                res
+            }
+            ValidationError::Function(err) => {
+                let mut res = Diagnostic::new(severity, err.code, err.mess.to_string());
+                res.fun = Some(fun_name(err.fun_id));
+                res
+            }
+            ValidationError::Global(err) => {
+                let global = names
+                    .and_then(|names| names.get_global_name(err.global_id))
+                    .map(|name| name.to_string())
+                    .unwrap_or_else(|| format!("#{}", err.global_id.0));
+                Diagnostic::new(severity, err.code, format!("{}: {}", err.mess, global))
+            }
+        }
+    }
 }
 
 struct FunctionValidation<'a> {
     fun: &'a ir::Function,
     errs: Vec<ValidationError>,
-    fun_ids: Option<&'a HashSet<ir::FunctionId>>,
+    fun_ids: Option<&'a FunSignatures>,
+    global_ids: Option<&'a HashSet<ir::GlobalId>>,
     bb_ids: HashSet<ir::BasicBlockId>,
 
     act_bb: Option<&'a ir::BasicBlock>,
@@ -102,11 +296,16 @@ struct FunctionValidation<'a> {
 }
 
 impl<'a> FunctionValidation<'a> {
-    fn new(fun: &'a ir::Function, fun_ids: Option<&'a HashSet<ir::FunctionId>>) -> Self {
+    fn new(
+        fun: &'a ir::Function,
+        fun_ids: Option<&'a FunSignatures>,
+        global_ids: Option<&'a HashSet<ir::GlobalId>>,
+    ) -> Self {
         FunctionValidation {
             fun,
             errs: vec![],
             fun_ids,
+            global_ids,
             bb_ids: HashSet::new(),
 
             act_bb: None,
@@ -123,7 +322,7 @@ impl<'a> FunctionValidation<'a> {
 
         if self.fun.basic_blocks_list().len() == 0 {
             //1)
-            self.err_fun("Function has no Basic Blocks");
+            self.err_fun("V002", "Function has no Basic Blocks");
         }
 
         for bb in self.fun.basic_blocks_list() {
@@ -136,6 +335,129 @@ impl<'a> FunctionValidation<'a> {
             self.check_bb();
         }
         self.act_bb = None;
+
+        self.check_widths();
+        self.check_args();
+    }
+
+    // Registers of the arguments, if the function has a signature
+    // 1) Every register read but never written must be an argument
+    // 2) The register returned by a void function can be uninitialized
+    fn check_args(&mut self) {
+        let sig = match self.fun.signature() {
+            Some(sig) => sig,
+            None => return,
+        };
+
+        let mut defs = HashSet::new();
+        self.for_each_ins(|_, ins| {
+            defs.extend(ins.get_register_def());
+        });
+
+        let mut uses = vec![];
+        self.for_each_ins(|vd, ins| {
+            if let ir::Ins::Ret(_) = ins {
+                if sig.is_void() {
+                    // 2)
+                    return;
+                }
+            }
+            uses.clear();
+            ins.get_registers_read(&mut uses);
+            if uses
+                .iter()
+                .any(|reg| reg.0 >= sig.nb_args() && !defs.contains(reg))
+            {
+                // 1)
+                vd.err_ins("V016", "Register never written is not an argument");
+            }
+        });
+    }
+
+    // Type inference of the registers widths
+    // 1) Every register defined by an instruction with a width, always has the same width
+    // 2) movr propagates widths between src and dst, they must be the same
+    // 3) Every register read with a width must have this width
+    // Registers whose width can't be infered (eg: function arguments) can be used with any width
+    fn check_widths(&mut self) {
+        let mut widths: HashMap<ir::RegId, ir::IntWidth> = HashMap::new();
+
+        // 1)
+        self.for_each_ins(|vd, ins| {
+            if let Some((reg, width)) = ins.get_def_width() {
+                match widths.get(&reg) {
+                    Some(reg_width) if *reg_width != width => {
+                        vd.err_ins("V014", "Register defined with different widths")
+                    }
+                    _ => {
+                        widths.insert(reg, width);
+                    }
+                }
+            }
+        });
+
+        // 2)
+        let mut changed = true;
+        while changed {
+            changed = false;
+            self.for_each_ins(|_, ins| {
+                let ins = match ins {
+                    ir::Ins::Movr(ins) => ins,
+                    _ => return,
+                };
+                match (widths.get(&ins.dst()), widths.get(&ins.src())) {
+                    (None, Some(src_width)) => {
+                        widths.insert(ins.dst(), *src_width);
+                        changed = true;
+                    }
+                    (Some(dst_width), None) => {
+                        widths.insert(ins.src(), *dst_width);
+                        changed = true;
+                    }
+                    _ => {}
+                }
+            });
+        }
+
+        // 3)
+        let mut uses = vec![];
+        self.for_each_ins(|vd, ins| {
+            if let ir::Ins::Movr(ins) = ins {
+                match (widths.get(&ins.dst()), widths.get(&ins.src())) {
+                    (Some(dst_width), Some(src_width)) if dst_width != src_width => {
+                        // 2)
+                        vd.err_ins("V014", "Register defined with different widths")
+                    }
+                    _ => {}
+                }
+            }
+
+            uses.clear();
+            ins.get_uses_width(&mut uses);
+            for (reg, width) in &uses {
+                match widths.get(reg) {
+                    Some(reg_width) if reg_width != width => {
+                        vd.err_ins("V015", "Register used with the wrong width")
+                    }
+                    _ => {}
+                }
+            }
+        });
+    }
+
+    // Call f on every instruction of the function, with act_bb and act_ins set
+    fn for_each_ins<F: FnMut(&mut Self, &'a ir::Ins)>(&mut self, mut f: F) {
+        let fun = self.fun;
+        for bb_id in fun.basic_blocks_list() {
+            let bb = fun.get_basic_block(*bb_id);
+            self.act_bb = Some(bb);
+            for (ins_idx, ins) in bb.iter().enumerate() {
+                self.act_ins = Some(ins_idx);
+                f(self, ins);
+            }
+        }
+        self.act_bb = None;
+        self.act_ins = None;
     }
 
     // 1) A Basic Block must not be empty
@@ -144,7 +466,7 @@ impl<'a> FunctionValidation<'a> {
 
         if bb.size() == 0 {
             //1)
-            self.err_bb("BasicBlock is empty");
+            self.err_bb("V003", "BasicBlock is empty");
         }
 
         for i in 0..bb.size() {
@@ -156,8 +478,13 @@ impl<'a> FunctionValidation<'a> {
 
     // 1) the last instruction must be a control flow instruction (br, jump, or ret)
     // 2) there must not be any other control flow instruction
-    // 3) branching instructions must jump to basic blocs of the same function
-    // 4) call instructions must reference existing functions
+    // 3) branching instructions must jump to basic blocs of the same function,
+    //    and the case values of a switch must be unique
+    // 4) call and funaddr instructions must reference existing functions,
+    //    with the number of arguments of the function signature
+    // 5) gaddr instructions must reference existing globals
+    // 6) alloca instructions must allocate at least one value
+    // 7) conversion instructions must have valid widths
     fn check_ins(&mut self) {
         let ins_idx = self.act_ins.unwrap();
         let bb = self.act_bb.unwrap();
@@ -166,38 +493,108 @@ impl<'a> FunctionValidation<'a> {
 
         if is_last && !ins.is_control_flow() {
             // 1)
-            return self.err_ins("Last of BasicBlock is not a control flow instruction");
+            return self.err_ins(
+                "V004",
+                "Last of BasicBlock is not a control flow instruction",
+            );
         }
 
         if !is_last && ins.is_control_flow() {
             // 2)
-            return self.err_ins("Non-last of BasicBlock is a control flow instruction");
+            return self.err_ins(
+                "V005",
+                "Non-last of BasicBlock is a control flow instruction",
+            );
         }
 
         if let ir::Ins::Jump(ins) = ins {
             if self.bb_ids.get(&ins.dst()).is_none() {
                 // 3)
-                return self.err_ins("Jump to undefined Basic Block");
+                self.err_ins("V006", "Jump to undefined Basic Block");
             }
         } else if let ir::Ins::Br(ins) = ins {
             if self.bb_ids.get(&ins.dst_true()).is_none()
                 || self.bb_ids.get(&ins.dst_false()).is_none()
             {
                 // 3)
-                return self.err_ins("Br to undefined Basic Block");
+                self.err_ins("V006", "Br to undefined Basic Block");
+            }
+        } else if let ir::Ins::Switch(ins) = ins {
+            let mut vals = HashSet::new();
+            for (val, dst) in ins.cases() {
+                if !vals.insert(*val) {
+                    // 3)
+                    return self.err_ins("V007", "Duplicate case value in switch");
+                }
+                if !self.bb_ids.contains(dst) {
+                    // 3)
+                    return self.err_ins("V006", "Switch to undefined Basic Block");
+                }
+            }
+            if !self.bb_ids.contains(&ins.dst_default()) {
+                // 3)
+                self.err_ins("V006", "Switch to undefined Basic Block");
+            }
+        } else if let ir::Ins::Jumpt(ins) = ins {
+            if ins.table().is_empty() {
+                self.err_ins("V019", "Jump table without basic blocks");
+            } else if ins.table().iter().any(|dst| !self.bb_ids.contains(dst)) {
+                // 3)
+                self.err_ins("V006", "Jump table to undefined Basic Block");
             }
         } else if let ir::Ins::Call(ins) = ins {
             if let Some(fun_ids) = self.fun_ids {
-                if fun_ids.get(&ins.fun()).is_none() {
+                match fun_ids.get(&ins.fun()) {
+                    None => {
+                        // 4)
+                        self.err_ins("V008", "Call to undefined function");
+                    }
+                    Some(Some(sig)) if sig.nb_args() != ins.args().len() => {
+                        // 4)
+                        self.err_ins("V009", "Call with the wrong number of arguments");
+                    }
+                    _ => {}
+                }
+            }
+        } else if let ir::Ins::Funaddr(ins) = ins {
+            if ins.fun().0 >= MAX_FUNADDR_ID {
+                self.err_ins("V018", "Address of a function with a too big id");
+            }
+            if let Some(fun_ids) = self.fun_ids {
+                if !fun_ids.contains_key(&ins.fun()) {
                     // 4)
-                    return self.err_ins("Call to undefined function");
+                    self.err_ins("V010", "Address of undefined function");
+                }
+            }
+        } else if let ir::Ins::Gaddr(ins) = ins {
+            if let Some(global_ids) = self.global_ids {
+                if !global_ids.contains(&ins.global()) {
+                    // 5)
+                    self.err_ins("V011", "Address of undefined global");
+                }
+            }
+        } else if let ir::Ins::Alloca(ins) = ins {
+            if ins.size() == 0 {
+                // 6)
+                self.err_ins("V012", "Alloca of 0 values");
+            }
+        } else if let ir::Ins::Conv(ins) = ins {
+            let valid = match ins.kind() {
+                ir::InsConvKind::Sext | ir::InsConvKind::Zext => {
+                    ins.from().bits() < ins.to().bits()
                 }
+                ir::InsConvKind::Trunc => ins.from().bits() > ins.to().bits(),
+            };
+            if !valid {
+                // 7)
+                self.err_ins("V013", "Invalid widths for conversion");
             }
         }
     }
 
-    fn err_ins(&mut self, mess: &'static str) {
+    fn err_ins(&mut self, code: &'static str, mess: &'static str) {
         self.errs.push(ValidationError::Ins(InsError {
+            code,
             mess,
             ins_id: self.act_ins.unwrap(),
             bb_id: self.act_bb.unwrap().id(),
@@ -205,16 +602,18 @@ impl<'a> FunctionValidation<'a> {
         }));
     }
 
-    fn err_bb(&mut self, mess: &'static str) {
+    fn err_bb(&mut self, code: &'static str, mess: &'static str) {
         self.errs.push(ValidationError::BasicBlock(BasicBlockError {
+            code,
             mess,
             bb_id: self.act_bb.unwrap().id(),
             fun_id: self.fun.id(),
         }));
     }
 
-    fn err_fun(&mut self, mess: &'static str) {
+    fn err_fun(&mut self, code: &'static str, mess: &'static str) {
         self.errs.push(ValidationError::Function(FunctionError {
+            code,
             mess,
             fun_id: self.fun.id(),
         }));
@@ -1,5 +1,7 @@
 pub mod ir;
 pub mod irbuilder;
+pub mod irdiagnostics;
+pub mod irlinker;
 pub mod irnames;
 pub mod irparser;
 pub mod irprinter;
@@ -8,6 +10,7 @@ pub mod registers;
 
 pub mod controlflow;
 pub mod digraph;
+pub mod switchlowering;
 
 #[cfg(test)]
 mod tests {
@@ -18,7 +21,7 @@ mod tests {
         use crate::irprinter::CodePrintable;
 
         let ps = irparser::Parser::from_file(path);
-        let (code, names) = ps.build();
+        let (code, names) = ps.build().unwrap();
 
         let mut code_str: Vec<u8> = vec![];
         code.print_code(&mut code_str, Some(&names));
@@ -26,7 +29,7 @@ mod tests {
         println!("CODE1: <BEG>{}<END>", code_str);
 
         let ps2 = irparser::Parser::from_str(code_str);
-        let (code, names) = ps2.build();
+        let (code, names) = ps2.build().unwrap();
 
         let mut code2_str: Vec<u8> = vec![];
         code.print_code(&mut code2_str, Some(&names));
@@ -36,6 +39,20 @@ mod tests {
         assert_eq!(code_str, code2_str);
     }
 
+    // Build the module, and returns the code and position of the errors
+    fn build_errors(ps: irparser::Parser) -> Vec<(&'static str, usize, usize)> {
+        match ps.build() {
+            Ok(_) => vec![],
+            Err(diags) => diags
+                .iter()
+                .map(|diag| {
+                    let pos = diag.pos.unwrap();
+                    (diag.code, pos.line, pos.col)
+                })
+                .collect(),
+        }
+    }
+
     #[test]
     fn lexer_printer_fn_add() {
         test_lexer_printer("./tests/fn_add.ir");
@@ -50,4 +67,719 @@ mod tests {
     fn lexer_printer_hello_42() {
         test_lexer_printer("./tests/hello_42.ir");
     }
+
+    #[test]
+    fn lexer_printer_globals() {
+        test_lexer_printer("./tests/globals.ir");
+    }
+
+    #[test]
+    fn lexer_printer_stack_array() {
+        test_lexer_printer("./tests/stack_array.ir");
+    }
+
+    #[test]
+    fn lexer_printer_bitops() {
+        test_lexer_printer("./tests/bitops.ir");
+    }
+
+    #[test]
+    fn lexer_printer_widths() {
+        test_lexer_printer("./tests/widths.ir");
+    }
+
+    #[test]
+    fn lexer_printer_funptr() {
+        test_lexer_printer("./tests/funptr.ir");
+    }
+
+    #[test]
+    fn lexer_printer_switch() {
+        test_lexer_printer("./tests/switch.ir");
+    }
+
+    #[test]
+    fn lexer_printer_debug_loc() {
+        test_lexer_printer("./tests/debug_loc.ir");
+    }
+
+    #[test]
+    fn debug_loc_data() {
+        let ps = irparser::Parser::from_file("./tests/debug_loc.ir");
+        let (mut code, _) = ps.build().unwrap();
+        let fun = code.get_fun_mut(ir::FunctionId(1)).unwrap();
+        let locs = |bb: &ir::BasicBlock| -> Vec<_> {
+            (0..bb.size())
+                .map(|idx| bb.get_ins_loc(idx).map(|loc| (loc.line(), loc.col())))
+                .collect()
+        };
+
+        let bb = fun.get_basic_block(ir::BasicBlockId(0));
+        assert_eq!(bb.get_ins_loc(4).unwrap().to_string(), "fact.le:1:32");
+        assert_eq!(
+            locs(bb),
+            vec![
+                None,
+                None,
+                Some((1, 30)),
+                Some((1, 34)),
+                Some((1, 32)),
+                Some((1, 27))
+            ]
+        );
+        // the comments after an instruction belong to it, the last !loc is used
+        let bb = fun.get_basic_block(ir::BasicBlockId(2));
+        assert_eq!(
+            locs(bb),
+            vec![
+                Some((1, 48)),
+                Some((1, 63)),
+                Some((1, 61)),
+                Some((1, 52)),
+                Some((1, 50)),
+                None,
+                None
+            ]
+        );
+
+        // locations follow the instructions
+        let bb = fun.get_basic_block_mut(ir::BasicBlockId(0));
+        bb.move_ins(3, 0);
+        bb.remove_ins(1);
+        bb.insert_ins(1, ir::Ins::Movi(ir::InsMovi::new(ir::RegId(3), 2)));
+        assert_eq!(
+            locs(bb),
+            vec![
+                Some((1, 34)),
+                None,
+                None,
+                Some((1, 30)),
+                Some((1, 32)),
+                Some((1, 27))
+            ]
+        );
+    }
+
+    #[test]
+    fn debug_loc_invalid() {
+        let ps = irparser::Parser::from_str(
+            "
+.define 0 main
+L0:
+  ret %r0 ; !loc fact.le:3
+",
+        );
+        assert_eq!(build_errors(ps), vec![("P016", 4, 3)]);
+    }
+
+    #[test]
+    fn parse_errors() {
+        // the parser reports all errors, sorted by position
+        let ps = irparser::Parser::from_str(
+            "
+.global 0 tab x
+
+.define 0 main
+L0:
+  movi %r0, 1
+  call %r1, fact, %r0
+  add %r2, %r0
+  jump Lend
+L0:
+  ret %r0
+Lend:
+  foo %r0
+  ret %r0,
+
+.declare 0 main
+",
+        );
+        let diags = ps.build().err().unwrap();
+        let errs: Vec<_> = diags
+            .iter()
+            .map(|diag| (diag.code, diag.pos.unwrap().line, diag.pos.unwrap().col))
+            .collect();
+        assert_eq!(
+            errs,
+            vec![
+                ("P002", 2, 1),
+                ("P014", 7, 3),
+                ("P011", 8, 3),
+                ("P008", 10, 1),
+                ("P009", 13, 3),
+                ("P001", 14, 3),
+                ("P006", 16, 1),
+            ]
+        );
+        assert_eq!(diags[1].mess, "Instruction call: unknown function fact");
+        assert_eq!(diags[1].fun.as_deref(), Some("main"));
+        assert_eq!(diags[1].bb.as_deref(), Some("L0"));
+    }
+
+    #[test]
+    fn parse_errors_codes() {
+        // every kind of error has its own code
+        let ps = irparser::Parser::from_str(
+            "
+  ret %r0
+.global 0 tab 1
+.global 1 tab 1
+
+.define 0 main
+  movi %r0, 1
+L0:
+  movi.i8.i8 %r0, 1
+  jump.i8 L0
+  sext.i8 %r1, %r0
+  call %r1
+  switch %r0, L0, 1
+  ret %r0
+1:
+  ret %r0
+
+.declare 1 main
+",
+        );
+        assert_eq!(
+            build_errors(ps),
+            vec![
+                ("P017", 2, 3),
+                ("P020", 4, 1),
+                ("P018", 7, 3),
+                ("P021", 9, 3),
+                ("P021", 10, 3),
+                ("P022", 11, 3),
+                ("P023", 12, 3),
+                ("P024", 13, 3),
+                ("P005", 15, 1),
+                ("P019", 18, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn diagnostics_render() {
+        let code = "
+.define 0 main
+L0:
+  call %r0, fact
+  ret %r0
+";
+        let diags = irparser::Parser::from_str(code).build().err().unwrap();
+        let mut os: Vec<u8> = vec![];
+        irdiagnostics::write_diagnostics(&mut os, &diags, "test.ir", code);
+        assert_eq!(
+            std::str::from_utf8(&os).unwrap(),
+            "error[P014]: Instruction call: unknown function fact
+ --> test.ir:4:3
+  |
+4 |   call %r0, fact
+  |   ^
+  = in main, L0
+
+"
+        );
+        assert_eq!(
+            diags[0].to_string(),
+            "4:3: error[P014]: Instruction call: unknown function fact (in main, L0)"
+        );
+    }
+
+    #[test]
+    fn validation_diagnostics() {
+        let (mut module, names) = irparser::Parser::from_file("./tests/fn_add.ir")
+            .build()
+            .unwrap();
+        let fun = module.funs()[1].id();
+        let bb = module.get_fun(fun).unwrap().basic_blocks_list()[0];
+        module.get_fun_mut(fun).unwrap().get_basic_block_mut(bb).pop_ins();
+
+        // the names of the function and basic block are used if known, else the ids
+        let diags = irvalidation::validate_module(&module, Some(&names)).err().unwrap();
+        assert_eq!(diags[0].code, "V004");
+        assert_eq!(diags[0].fun.as_deref(), names.get_function_name(fun));
+        let diags = irvalidation::validate_module(&module, None).err().unwrap();
+        assert_eq!(diags[0].fun, Some(format!("#{}", fun.0)));
+        assert_eq!(diags[0].bb, Some(format!("#{}", bb.0)));
+        assert!(diags[0].pos.is_none());
+    }
+
+    #[test]
+    fn globals_data() {
+        let ps = irparser::Parser::from_file("./tests/globals.ir");
+        let (code, names) = ps.build().unwrap();
+
+        let msg = code.get_global(ir::GlobalId(0)).unwrap();
+        assert_eq!(names.get_global_name(msg.id()), Some("msg"));
+        assert_eq!((msg.size(), msg.init()), (4, &[72, 105, 33, 0][..]));
+        assert_eq!((msg.width(), msg.bytes()), (ir::IntWidth::I8, 4));
+        let count = code.get_global(ir::GlobalId(2)).unwrap();
+        assert_eq!((count.size(), count.init()), (1, &[][..]));
+        let offsets = code.get_global(ir::GlobalId(3)).unwrap();
+        assert_eq!(offsets.init(), &[-1, -2]);
+    }
+
+    #[test]
+    fn globals_init_too_big() {
+        let ps = irparser::Parser::from_str(
+            "
+.global 0 tab 2 1 2 3
+
+.define 0 main
+L0:
+  gaddr %r0, tab
+  ret %r0
+",
+        );
+        assert_eq!(build_errors(ps), vec![("V001", 2, 1)]);
+    }
+
+    #[test]
+    fn widths_data() {
+        let ps = irparser::Parser::from_str(
+            "
+.global.i8 0 tab 3 127 128 -1
+
+.define 0 main
+L0:
+  movi.i8 %r0, 300
+  movi.i64 %r1, -1
+  alloca.i16 %r2, 3
+  ret %r0
+",
+        );
+        let (code, _names) = ps.build().unwrap();
+        let tab = code.get_global(ir::GlobalId(0)).unwrap();
+        assert_eq!(tab.init(), &[127, -128, -1]);
+
+        let fun = code.get_fun(ir::FunctionId(0)).unwrap();
+        let bb = fun.get_basic_block(fun.basic_blocks_list()[0]);
+        match (bb.get_ins(0), bb.get_ins(1), bb.get_ins(2)) {
+            (ir::Ins::Movi(i8_ins), ir::Ins::Movi(i64_ins), ir::Ins::Alloca(alloca)) => {
+                assert_eq!((i8_ins.width(), i8_ins.const_val()), (ir::IntWidth::I8, 44));
+                assert_eq!((i64_ins.width(), i64_ins.const_val()), (ir::IntWidth::I64, -1));
+                assert_eq!((alloca.size(), alloca.bytes()), (3, 6));
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn widths_invalid_use() {
+        let ps = irparser::Parser::from_str(
+            "
+.define 0 main
+L0:
+  movi.i8 %r0, 1
+  movi %r1, 2
+  movr %r2, %r0
+  add %r3, %r2, %r1
+  ret %r3
+",
+        );
+        assert_eq!(build_errors(ps), vec![("V015", 7, 3)]);
+    }
+
+    #[test]
+    fn widths_invalid_def() {
+        let ps = irparser::Parser::from_str(
+            "
+.define 0 main
+L0:
+  movi.i16 %r0, 1
+  movi %r1, 2
+  sext.i16.i32 %r0, %r1
+  ret %r0
+",
+        );
+        assert_eq!(build_errors(ps), vec![("V014", 6, 3), ("V015", 6, 3)]);
+    }
+
+    #[test]
+    fn widths_invalid_conv() {
+        let ps = irparser::Parser::from_str(
+            "
+.define 0 main
+L0:
+  movi %r0, 1
+  trunc.i32.i64 %r1, %r0
+  ret %r1
+",
+        );
+        assert_eq!(build_errors(ps), vec![("V013", 5, 3)]);
+    }
+
+    #[test]
+    fn signatures_data() {
+        let ps = irparser::Parser::from_str(
+            "
+.declare 258 exit 1 void
+
+.define 0 main
+L0:
+  movi %r0, 3
+  call %r1, add, %r0, %r0
+  call %r1, exit, %r1
+  ret %r1
+
+.define 1 add 2
+L0:
+  movi %r3, 1
+  add %r2, %r1, %r3
+  ret %r2
+",
+        );
+        let (code, names) = ps.build().unwrap();
+        let sig = |id| code.get_fun(ir::FunctionId(id)).unwrap().signature();
+        assert_eq!(sig(0), None);
+        assert_eq!(sig(1), Some(ir::Signature::new(2, false)));
+        assert_eq!(sig(258), Some(ir::Signature::new(1, true)));
+
+        // %r0 is an argument, even if it's not used
+        let add_names = names.get_function(ir::FunctionId(1)).unwrap();
+        assert_eq!(add_names.get_register_name(ir::RegId(1)), Some("r1"));
+    }
+
+    #[test]
+    fn signatures_invalid_call() {
+        let ps = irparser::Parser::from_str(
+            "
+.define 0 main
+L0:
+  movi %r0, 3
+  call %r1, fact, %r0, %r0
+  ret %r1
+
+.define 1 fact 1
+L0:
+  ret %r0
+",
+        );
+        assert_eq!(build_errors(ps), vec![("V009", 5, 3)]);
+    }
+
+    #[test]
+    fn signatures_invalid_args() {
+        let ps = irparser::Parser::from_str(
+            "
+.define 1 add 1
+L0:
+  add %r2, %r0, %r1
+  ret %r2
+",
+        );
+        assert_eq!(build_errors(ps), vec![("V016", 4, 3)]);
+    }
+
+    #[test]
+    fn switch_invalid() {
+        let ps = irparser::Parser::from_str(
+            "
+.define 0 main
+L0:
+  movi.i8 %r0, 1
+  switch.i8 %r0, L1, 1, L1, 257, L1
+L1:
+  ret %r0
+",
+        );
+        assert_eq!(build_errors(ps), vec![("V007", 5, 3)]);
+    }
+
+    #[test]
+    fn switch_lowering() {
+        use crate::irprinter::CodePrintable;
+
+        let (mut module, _names) = irparser::Parser::from_file("./tests/switch.ir").build().unwrap();
+        let count_ins = |module: &ir::Module, id| {
+            let fun = module.get_fun(ir::FunctionId(id)).unwrap();
+            let mut res = (0, 0, 0);
+            for bb_id in fun.basic_blocks_list() {
+                for ins in fun.get_basic_block(*bb_id).iter() {
+                    match ins {
+                        ir::Ins::Switch(_) => res.0 += 1,
+                        ir::Ins::Jumpt(_) => res.1 += 1,
+                        ir::Ins::Cmpbin(_) => res.2 += 1,
+                        _ => {}
+                    }
+                }
+            }
+            res
+        };
+        assert_eq!(count_ins(&module, 1), (1, 0, 0));
+
+        switchlowering::lower_switches_module(&mut module);
+        irvalidation::validate_module(&module, None).unwrap();
+        // dense: one bounds check, and one jump table
+        assert_eq!(count_ins(&module, 1), (0, 1, 1));
+        let fun = module.get_fun(ir::FunctionId(1)).unwrap();
+        let table = fun
+            .basic_blocks_list()
+            .iter()
+            .filter_map(|bb_id| match fun.get_basic_block(*bb_id).iter().last() {
+                Some(ir::Ins::Jumpt(ins)) => Some(ins.table().len()),
+                _ => None,
+            })
+            .next();
+        assert_eq!(table, Some(8));
+        // sparse: search on 5 values, 2 on the left and 3 on the right
+        assert_eq!(count_ins(&module, 2), (0, 0, 1 + 2 + 3));
+        // dense i8
+        assert_eq!(count_ins(&module, 3), (0, 1, 1));
+
+        // the jump tables can be printed and parsed again
+        let mut code_str: Vec<u8> = vec![];
+        module.print_code(&mut code_str, None);
+        let code_str = std::str::from_utf8(&code_str).unwrap();
+        assert!(code_str.contains("jumpt.i8 "));
+        let (module2, _names) = irparser::Parser::from_str(code_str).build().unwrap();
+        assert_eq!(count_ins(&module2, 1), (0, 1, 1));
+
+        // without indirect jump: search on the 7 ranges 0, 1, 2-3, 4 (default), 5, 6, 7
+        switchlowering::lower_jump_tables_module(&mut module);
+        irvalidation::validate_module(&module, None).unwrap();
+        assert_eq!(count_ins(&module, 1), (0, 0, 1 + 6));
+        assert_eq!(count_ins(&module, 2), (0, 0, 1 + 2 + 3));
+        assert_eq!(count_ins(&module, 3), (0, 0, 1 + 3));
+    }
+
+    #[test]
+    fn funaddr_invalid() {
+        let ps = irparser::Parser::from_str(
+            "
+.define 0 main
+L0:
+  movi.i8 %r0, 1
+  funaddr %r0, main
+  ret %r0
+",
+        );
+        assert_eq!(build_errors(ps), vec![("V014", 5, 3)]);
+
+        let ps = irparser::Parser::from_str(
+            "
+.define 0 main
+L0:
+  funaddr %r0, big
+  ret %r0
+
+.define 65536 big
+L0:
+  movi %r0, 0
+  ret %r0
+",
+        );
+        assert_eq!(build_errors(ps), vec![("V018", 4, 3)]);
+    }
+
+    #[test]
+    fn validate_function_errors() {
+        let mut module = ir::Module::new();
+        let id = module.create_function(None);
+        let mut builder = irbuilder::IRBuilder::new(module.get_fun_mut(id).unwrap());
+        builder.create_basic_block();
+        let diags = builder.validate().err().unwrap();
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].code, "V003");
+    }
+
+    #[test]
+    fn jumpt_invalid() {
+        let ps = irparser::Parser::from_str(
+            "
+.define 0 main
+L0:
+  movi %r0, 1
+  jumpt %r0, L1, L2
+L1:
+  ret %r0
+",
+        );
+        assert_eq!(build_errors(ps), vec![("P013", 5, 3)]);
+
+        // the parser needs at least one basic block, an empty table is only possible with the builder
+        let mut module = ir::Module::new();
+        let id = module.create_function(None);
+        let mut builder = irbuilder::IRBuilder::new(module.get_fun_mut(id).unwrap());
+        let bb = builder.create_basic_block();
+        builder.set_insert_point(bb);
+        builder.ins_movi(ir::RegId(0), 1);
+        builder.ins_jumpt(ir::RegId(0), vec![]);
+        let diags = builder.validate().err().unwrap();
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].code, "V019");
+    }
+
+    #[test]
+    fn indirect_calls_warnings() {
+        let code = "
+.declare 257 putc
+
+.define 0 main
+L0:
+  movi %r0, 65
+  call %r1, putc, %r0
+  funaddr %r2, CALLEE
+  callr %r1, %r2, %r0
+  callr %r1, %r2, %r0, %r0
+  ret %r1
+
+.define 1 id SIG
+L0:
+  ret %r0
+";
+        let code = |callee: &str, sig: &str| code.replace("CALLEE", callee).replace("SIG", sig);
+        // putc takes 1 argument, the second callr may call it with 2
+        let (module, _names) = irparser::Parser::from_str(&code("putc", "")).build().unwrap();
+        let warns = irvalidation::check_indirect_calls(&module);
+        assert_eq!(warns.len(), 1);
+        match &warns[0] {
+            irvalidation::ValidationError::Ins(err) => assert_eq!(err.ins_id, 4),
+            _ => unreachable!(),
+        }
+
+        // the arity of functions defined in the module is only known with a signature
+        let (module, _names) = irparser::Parser::from_str(&code("id", "")).build().unwrap();
+        assert_eq!(irvalidation::check_indirect_calls(&module).len(), 0);
+        let (module, _names) = irparser::Parser::from_str(&code("id", "1")).build().unwrap();
+        assert_eq!(irvalidation::check_indirect_calls(&module).len(), 1);
+    }
+
+    fn link_files(paths: &[&str], natives: Option<&[ir::FunctionId]>) -> irlinker::Linker {
+        let mut linker = irlinker::Linker::new();
+        for path in paths {
+            let (module, names) = irparser::Parser::from_file(path).build().unwrap();
+            linker.add_module(module, names);
+        }
+        if let Some(natives) = natives {
+            linker.set_natives(natives);
+        }
+        linker
+    }
+
+    #[test]
+    fn link_runtime() {
+        use crate::irprinter::CodePrintable;
+
+        let linker = link_files(&["./tests/link_main.ir", "./runtime/runtime.ir"], None);
+        let (module, names) = linker.link().unwrap();
+
+        let id = |name| names.get_function_id(name).unwrap().0;
+        assert_eq!(id("main"), 0);
+        assert_eq!(id("twice"), 1);
+        // print_int_rec collides with twice: renumbered after the biggest id (fmemfree)
+        assert_eq!(id("print_int_rec"), 269);
+        assert_eq!(id("print_int"), 2);
+        assert_eq!(id("fm_alloc"), 3);
+        assert_eq!(id("fm_free"), 4);
+        assert_eq!(names.get_function_id("exit"), Some(ir::FunctionId(258)));
+
+        // the declarations resolved by the runtime are removed
+        let externs: Vec<_> = module
+            .funs()
+            .iter()
+            .filter(|fun| fun.is_extern())
+            .map(|fun| fun.id().0)
+            .collect();
+        assert_eq!(externs, vec![257, 258, 267, 268]);
+        match module
+            .get_fun(ir::FunctionId(0))
+            .unwrap()
+            .get_basic_block(ir::BasicBlockId(0))
+            .iter()
+            .nth(1)
+        {
+            Some(ir::Ins::Call(call)) => assert_eq!(call.fun(), ir::FunctionId(2)),
+            _ => unreachable!(),
+        }
+
+        // the linked module can be printed and parsed again
+        let mut code_str: Vec<u8> = vec![];
+        module.print_code(&mut code_str, Some(&names));
+        let code_str = std::str::from_utf8(&code_str).unwrap();
+        let (module2, _names) = irparser::Parser::from_str(code_str).build().unwrap();
+        assert_eq!(module2.funs().len(), module.funs().len());
+    }
+
+    #[test]
+    fn link_errors() {
+        let natives = [257, 258, 267, 268].map(ir::FunctionId);
+        let error = |code: &'static str, mess: &str| {
+            irdiagnostics::Diagnostic::error(code, mess.to_string())
+        };
+
+        // the runtime is missing
+        let linker = link_files(&["./tests/link_main.ir"], Some(&natives));
+        assert_eq!(
+            linker.link().err().unwrap(),
+            vec![
+                error("L004", "Unresolved function: print_int"),
+                error("L004", "Unresolved function: fm_alloc"),
+                error("L004", "Unresolved function: fm_free"),
+            ]
+        );
+
+        let linker = link_files(
+            &[
+                "./tests/link_main.ir",
+                "./runtime/runtime.ir",
+                "./runtime/runtime.ir",
+            ],
+            Some(&natives),
+        );
+        let errs = linker.link().err().unwrap();
+        assert_eq!(errs.len(), 4);
+        assert_eq!(
+            errs[0],
+            error("L001", "Duplicate function definition: print_int_rec")
+        );
+
+        let mut linker = link_files(&["./runtime/runtime.ir"], Some(&natives));
+        let (module, names) = irparser::Parser::from_str(
+            "
+.declare 1 print_int 2
+.declare 2 putc 1
+
+.define 0 main
+L0:
+  movi %r0, 1
+  call %r0, print_int, %r0, %r0
+  ret %r0
+",
+        )
+        .build().unwrap();
+        linker.add_module(module, names);
+        assert_eq!(
+            linker.link().err().unwrap(),
+            vec![
+                error(
+                    "L003",
+                    "Declaration with a different signature than the definition: print_int"
+                ),
+                error("L004", "Unresolved function: putc"),
+                error("L005", "Extern function declared with different ids: putc"),
+            ]
+        );
+
+        // the diagnostics of the validation of the linked module are kept
+        let mut linker = link_files(&["./runtime/runtime.ir"], None);
+        let (module, names) = irparser::Parser::from_str(
+            "
+.declare 1 print_int
+
+.define 0 main
+L0:
+  movi %r0, 1
+  call %r0, print_int, %r0, %r0
+  ret %r0
+",
+        )
+        .build()
+        .unwrap();
+        linker.add_module(module, names);
+        let errs = linker.link().err().unwrap();
+        assert_eq!(errs.len(), 1);
+        assert_eq!(errs[0].code, "V009");
+        assert_eq!(errs[0].fun.as_deref(), Some("main"));
+    }
 }
@@ -16,11 +16,17 @@ impl GetRegistersUse for ir::Ins {
             ir::Ins::Load(ins) => ins.get_register_use(out_regs),
             ir::Ins::Store(ins) => ins.get_register_use(out_regs),
             ir::Ins::Alloca(ins) => ins.get_register_use(out_regs),
+            ir::Ins::Gaddr(ins) => ins.get_register_use(out_regs),
             ir::Ins::Opbin(ins) => ins.get_register_use(out_regs),
             ir::Ins::Cmpbin(ins) => ins.get_register_use(out_regs),
+            ir::Ins::Conv(ins) => ins.get_register_use(out_regs),
             ir::Ins::Jump(ins) => ins.get_register_use(out_regs),
             ir::Ins::Br(ins) => ins.get_register_use(out_regs),
+            ir::Ins::Switch(ins) => ins.get_register_use(out_regs),
+            ir::Ins::Jumpt(ins) => ins.get_register_use(out_regs),
             ir::Ins::Call(ins) => ins.get_register_use(out_regs),
+            ir::Ins::Funaddr(ins) => ins.get_register_use(out_regs),
+            ir::Ins::Callr(ins) => ins.get_register_use(out_regs),
             ir::Ins::Ret(ins) => ins.get_register_use(out_regs),
         }
     }
@@ -59,6 +65,12 @@ impl GetRegistersUse for ir::InsAlloca {
     }
 }
 
+impl GetRegistersUse for ir::InsGaddr {
+    fn get_register_use(&self, out_regs: &mut HashSet<ir::RegId>) {
+        out_regs.insert(self.dst());
+    }
+}
+
 impl GetRegistersUse for ir::InsOpbin {
     fn get_register_use(&self, out_regs: &mut HashSet<ir::RegId>) {
         out_regs.insert(self.src1());
@@ -75,6 +87,13 @@ impl GetRegistersUse for ir::InsCmpbin {
     }
 }
 
+impl GetRegistersUse for ir::InsConv {
+    fn get_register_use(&self, out_regs: &mut HashSet<ir::RegId>) {
+        out_regs.insert(self.src());
+        out_regs.insert(self.dst());
+    }
+}
+
 impl GetRegistersUse for ir::InsJump {
     fn get_register_use(&self, _out_regs: &mut HashSet<ir::RegId>) {}
 }
@@ -85,6 +104,18 @@ impl GetRegistersUse for ir::InsBr {
     }
 }
 
+impl GetRegistersUse for ir::InsSwitch {
+    fn get_register_use(&self, out_regs: &mut HashSet<ir::RegId>) {
+        out_regs.insert(self.src());
+    }
+}
+
+impl GetRegistersUse for ir::InsJumpt {
+    fn get_register_use(&self, out_regs: &mut HashSet<ir::RegId>) {
+        out_regs.insert(self.src());
+    }
+}
+
 impl GetRegistersUse for ir::InsCall {
     fn get_register_use(&self, out_regs: &mut HashSet<ir::RegId>) {
         for arg in self.args() {
@@ -94,8 +125,132 @@ impl GetRegistersUse for ir::InsCall {
     }
 }
 
+impl GetRegistersUse for ir::InsFunaddr {
+    fn get_register_use(&self, out_regs: &mut HashSet<ir::RegId>) {
+        out_regs.insert(self.dst());
+    }
+}
+
+impl GetRegistersUse for ir::InsCallr {
+    fn get_register_use(&self, out_regs: &mut HashSet<ir::RegId>) {
+        out_regs.insert(self.fptr());
+        for arg in self.args() {
+            out_regs.insert(*arg);
+        }
+        out_regs.insert(self.dst());
+    }
+}
+
 impl GetRegistersUse for ir::InsRet {
     fn get_register_use(&self, out_regs: &mut HashSet<ir::RegId>) {
         out_regs.insert(self.src());
     }
 }
+
+/// Know the register written by an instruction, and the registers it reads
+pub trait GetRegistersDefUse {
+    /// Register written by the instruction
+    fn get_register_def(&self) -> Option<ir::RegId>;
+
+    /// Registers read by the instruction
+    fn get_registers_read(&self, out_regs: &mut Vec<ir::RegId>);
+}
+
+impl GetRegistersDefUse for ir::Ins {
+    fn get_register_def(&self) -> Option<ir::RegId> {
+        match self {
+            ir::Ins::Movi(ins) => Some(ins.dst()),
+            ir::Ins::Movr(ins) => Some(ins.dst()),
+            ir::Ins::Load(ins) => Some(ins.dst()),
+            ir::Ins::Store(_) => None,
+            ir::Ins::Alloca(ins) => Some(ins.dst()),
+            ir::Ins::Gaddr(ins) => Some(ins.dst()),
+            ir::Ins::Opbin(ins) => Some(ins.dst()),
+            ir::Ins::Cmpbin(ins) => Some(ins.dst()),
+            ir::Ins::Conv(ins) => Some(ins.dst()),
+            ir::Ins::Jump(_) => None,
+            ir::Ins::Br(_) => None,
+            ir::Ins::Switch(_) => None,
+            ir::Ins::Jumpt(_) => None,
+            ir::Ins::Call(ins) => Some(ins.dst()),
+            ir::Ins::Funaddr(ins) => Some(ins.dst()),
+            ir::Ins::Callr(ins) => Some(ins.dst()),
+            ir::Ins::Ret(_) => None,
+        }
+    }
+
+    fn get_registers_read(&self, out_regs: &mut Vec<ir::RegId>) {
+        match self {
+            ir::Ins::Movi(_) => {}
+            ir::Ins::Movr(ins) => out_regs.push(ins.src()),
+            ir::Ins::Load(ins) => out_regs.push(ins.src()),
+            ir::Ins::Store(ins) => out_regs.extend([ins.dst(), ins.src()]),
+            ir::Ins::Alloca(_) => {}
+            ir::Ins::Gaddr(_) => {}
+            ir::Ins::Opbin(ins) => out_regs.extend([ins.src1(), ins.src2()]),
+            ir::Ins::Cmpbin(ins) => out_regs.extend([ins.src1(), ins.src2()]),
+            ir::Ins::Conv(ins) => out_regs.push(ins.src()),
+            ir::Ins::Jump(_) => {}
+            ir::Ins::Br(ins) => out_regs.push(ins.src()),
+            ir::Ins::Switch(ins) => out_regs.push(ins.src()),
+            ir::Ins::Jumpt(ins) => out_regs.push(ins.src()),
+            ir::Ins::Call(ins) => out_regs.extend(ins.args()),
+            ir::Ins::Funaddr(_) => {}
+            ir::Ins::Callr(ins) => {
+                out_regs.push(ins.fptr());
+                out_regs.extend(ins.args());
+            }
+            ir::Ins::Ret(ins) => out_regs.push(ins.src()),
+        }
+    }
+}
+
+/// Know the integer width of the registers defined and read by an instruction
+/// Addresses (including function addresses) are i32 values, and the result of a comparison is always an i32
+/// Registers without a known width (movr, br, call / callr args and result, ret) are not listed
+pub trait GetRegistersWidth {
+    /// Register written by the instruction, with its width
+    fn get_def_width(&self) -> Option<(ir::RegId, ir::IntWidth)>;
+
+    /// Registers read by the instruction, with the width they must have
+    fn get_uses_width(&self, out_uses: &mut Vec<(ir::RegId, ir::IntWidth)>);
+}
+
+impl GetRegistersWidth for ir::Ins {
+    fn get_def_width(&self) -> Option<(ir::RegId, ir::IntWidth)> {
+        match self {
+            ir::Ins::Movi(ins) => Some((ins.dst(), ins.width())),
+            ir::Ins::Load(ins) => Some((ins.dst(), ins.width())),
+            ir::Ins::Alloca(ins) => Some((ins.dst(), ir::IntWidth::I32)),
+            ir::Ins::Gaddr(ins) => Some((ins.dst(), ir::IntWidth::I32)),
+            ir::Ins::Opbin(ins) => Some((ins.dst(), ins.width())),
+            ir::Ins::Cmpbin(ins) => Some((ins.dst(), ir::IntWidth::I32)),
+            ir::Ins::Conv(ins) => Some((ins.dst(), ins.to())),
+            ir::Ins::Funaddr(ins) => Some((ins.dst(), ir::IntWidth::I32)),
+            _ => None,
+        }
+    }
+
+    fn get_uses_width(&self, out_uses: &mut Vec<(ir::RegId, ir::IntWidth)>) {
+        match self {
+            ir::Ins::Load(ins) => out_uses.push((ins.src(), ir::IntWidth::I32)),
+            ir::Ins::Store(ins) => {
+                out_uses.push((ins.dst(), ir::IntWidth::I32));
+                out_uses.push((ins.src(), ins.width()));
+            }
+            ir::Ins::Opbin(ins) => {
+                out_uses.push((ins.src1(), ins.width()));
+                out_uses.push((ins.src2(), ins.width()));
+            }
+            ir::Ins::Cmpbin(ins) => {
+                out_uses.push((ins.src1(), ins.width()));
+                out_uses.push((ins.src2(), ins.width()));
+            }
+            ir::Ins::Conv(ins) => out_uses.push((ins.src(), ins.from())),
+            ir::Ins::Switch(ins) => out_uses.push((ins.src(), ins.width())),
+            ir::Ins::Jumpt(ins) => out_uses.push((ins.src(), ins.width())),
+            ir::Ins::Callr(ins) => out_uses.push((ins.fptr(), ir::IntWidth::I32)),
+            _ => {}
+        }
+    }
+}
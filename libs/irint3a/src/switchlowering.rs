@@ -0,0 +1,268 @@
+// Lowering of switch instructions, for backends that don't support switch
+//
+// Every switch is replaced by new basic blocks added at the end of the function.
+// The new registers are numbered after the last register of the function.
+//
+// Dense switches (at least DENSE_MIN_CASES cases, spread on at most DENSE_MAX_SPREAD values per case)
+// are lowered to a jump table:
+// - the index is the value minus the smallest case value
+// - one unsigned comparison checks that the index is inside [0, spread[ (or jumps to the default block)
+// - one jumpt goes to the target of the index, the holes between the case values go to the default block
+//
+// Sparse switches are lowered to a binary search on the sorted case values,
+// with cmpeq on the last LINEAR_MAX_CASES cases of each leaf.
+//
+// Backends without indirect jump also lower the jump tables (lower_jump_tables):
+// the consecutive indexes with the same target are merged in ranges,
+// and the index is found with a binary search on the ranges (a leaf jumps directly to the target of its range).
+
+use std::collections::HashSet;
+
+use crate::ir;
+use crate::irbuilder::IRBuilder;
+use crate::registers::GetRegistersUse;
+
+const DENSE_MIN_CASES: usize = 4;
+const DENSE_MAX_SPREAD: i128 = 2;
+const LINEAR_MAX_CASES: usize = 3;
+
+/// Lower the switch instructions of all functions of a module
+pub fn lower_switches_module(module: &mut ir::Module) {
+    let ids: Vec<_> = module.funs().iter().map(|fun| fun.id()).collect();
+    for id in ids {
+        lower_switches(module.get_fun_mut(id).unwrap());
+    }
+}
+
+/// Lower the switch instructions of a function
+/// Dense switches become jumpt instructions
+pub fn lower_switches(fun: &mut ir::Function) {
+    if fun.is_extern() {
+        return;
+    }
+
+    let mut switches = vec![];
+    for bb_id in fun.basic_blocks_list() {
+        if let Some(ir::Ins::Switch(ins)) = fun.get_basic_block(*bb_id).iter().last() {
+            switches.push((*bb_id, ins.clone()));
+        }
+    }
+    let mut next_reg = next_free_reg(fun);
+
+    for (bb_id, ins) in switches {
+        fun.get_basic_block_mut(bb_id).pop_ins();
+        let mut lw = SwitchLowering {
+            builder: IRBuilder::new(fun),
+            width: ins.width(),
+            src: ins.src(),
+            dst_default: ins.dst_default(),
+            cst: ir::RegId(next_reg),
+            cond: ir::RegId(next_reg + 1),
+            idx: ir::RegId(next_reg + 2),
+        };
+        next_reg += 3;
+        lw.builder.set_insert_point(bb_id);
+        lw.lower(ins.cases());
+    }
+}
+
+/// Lower the jumpt instructions of all functions of a module
+pub fn lower_jump_tables_module(module: &mut ir::Module) {
+    let ids: Vec<_> = module.funs().iter().map(|fun| fun.id()).collect();
+    for id in ids {
+        lower_jump_tables(module.get_fun_mut(id).unwrap());
+    }
+}
+
+/// Lower the jumpt instructions of a function to comparisons and br
+/// An index outside of the table goes to the last basic block of the table
+pub fn lower_jump_tables(fun: &mut ir::Function) {
+    if fun.is_extern() {
+        return;
+    }
+
+    let mut tables = vec![];
+    for bb_id in fun.basic_blocks_list() {
+        if let Some(ir::Ins::Jumpt(ins)) = fun.get_basic_block(*bb_id).iter().last() {
+            tables.push((*bb_id, ins.clone()));
+        }
+    }
+    let mut next_reg = next_free_reg(fun);
+
+    for (bb_id, ins) in tables {
+        fun.get_basic_block_mut(bb_id).pop_ins();
+        let mut lw = JumpTableLowering {
+            builder: IRBuilder::new(fun),
+            width: ins.width(),
+            idx: ins.src(),
+            cst: ir::RegId(next_reg),
+            cond: ir::RegId(next_reg + 1),
+        };
+        next_reg += 2;
+        lw.builder.set_insert_point(bb_id);
+
+        // ranges of indexes [begin, end[ with the same target
+        let mut ranges: Vec<(usize, usize, ir::BasicBlockId)> = vec![];
+        for (idx, dst) in ins.table().iter().enumerate() {
+            match ranges.last_mut() {
+                Some(last) if last.2 == *dst => last.1 = idx + 1,
+                _ => ranges.push((idx, idx + 1, *dst)),
+            }
+        }
+        lw.search_ranges(&ranges);
+    }
+}
+
+// First register not used by the function
+fn next_free_reg(fun: &ir::Function) -> usize {
+    let mut regs = HashSet::new();
+    for bb_id in fun.basic_blocks_list() {
+        for ins in fun.get_basic_block(*bb_id).iter() {
+            ins.get_register_use(&mut regs);
+        }
+    }
+    regs.iter().map(|reg| reg.0 + 1).max().unwrap_or(0)
+}
+
+struct SwitchLowering<'a> {
+    builder: IRBuilder<'a>,
+    width: ir::IntWidth,
+    src: ir::RegId,
+    dst_default: ir::BasicBlockId,
+    cst: ir::RegId,  // constants compared with src / idx
+    cond: ir::RegId, // results of the comparisons
+    idx: ir::RegId,  // index in the jump table (dense switch)
+}
+
+impl<'a> SwitchLowering<'a> {
+    fn lower(&mut self, cases: &[(i64, ir::BasicBlockId)]) {
+        let mut cases = cases.to_vec();
+        cases.sort_by_key(|(val, _)| *val);
+
+        let (min, max) = match (cases.first(), cases.last()) {
+            (Some(first), Some(last)) => (first.0 as i128, last.0 as i128),
+            _ => return self.builder.ins_jump(self.dst_default),
+        };
+        let spread = max - min + 1;
+        if cases.len() >= DENSE_MIN_CASES && spread <= DENSE_MAX_SPREAD * cases.len() as i128 {
+            self.lower_table(&cases, spread)
+        } else {
+            self.lower_sparse(&cases)
+        }
+    }
+
+    // Bounds check, and jump table on the indexes [0, spread[
+    fn lower_table(&mut self, cases: &[(i64, ir::BasicBlockId)], spread: i128) {
+        let min = cases[0].0;
+        let mut table = vec![self.dst_default; spread as usize];
+        for (val, dst) in cases {
+            table[(*val as i128 - min as i128) as usize] = *dst;
+        }
+
+        // idx = src - min
+        self.builder.ins_movi_w(self.width, self.cst, min);
+        self.builder.ins_opbin_w(
+            self.width,
+            ir::InsOpbinKind::Sub,
+            self.idx,
+            self.src,
+            self.cst,
+        );
+
+        // the index is always inside a table covering all the values of the width
+        if spread < 1 << self.width.bits() {
+            let table_bb = self.builder.create_basic_block();
+            self.builder.ins_movi_w(self.width, self.cst, spread as i64);
+            self.builder.ins_cmpbin_w(
+                self.width,
+                ir::InsCmpbinKind::Ult,
+                self.cond,
+                self.idx,
+                self.cst,
+            );
+            self.builder.ins_br(self.cond, table_bb, self.dst_default);
+            self.builder.set_insert_point(table_bb);
+        }
+        self.builder.ins_jumpt_w(self.width, self.idx, table);
+    }
+
+    // Binary search on the sorted case values
+    fn lower_sparse(&mut self, cases: &[(i64, ir::BasicBlockId)]) {
+        if cases.len() <= LINEAR_MAX_CASES {
+            for (pos, (val, dst)) in cases.iter().enumerate() {
+                let next_bb = if pos + 1 == cases.len() {
+                    self.dst_default
+                } else {
+                    self.builder.create_basic_block()
+                };
+                self.builder.ins_movi_w(self.width, self.cst, *val);
+                self.builder.ins_cmpbin_w(
+                    self.width,
+                    ir::InsCmpbinKind::Eq,
+                    self.cond,
+                    self.src,
+                    self.cst,
+                );
+                self.builder.ins_br(self.cond, *dst, next_bb);
+                if pos + 1 < cases.len() {
+                    self.builder.set_insert_point(next_bb);
+                }
+            }
+            return;
+        }
+
+        let mid = cases.len() / 2;
+        let left_bb = self.builder.create_basic_block();
+        let right_bb = self.builder.create_basic_block();
+        self.builder.ins_movi_w(self.width, self.cst, cases[mid].0);
+        self.builder.ins_cmpbin_w(
+            self.width,
+            ir::InsCmpbinKind::Lt,
+            self.cond,
+            self.src,
+            self.cst,
+        );
+        self.builder.ins_br(self.cond, left_bb, right_bb);
+
+        self.builder.set_insert_point(left_bb);
+        self.lower_sparse(&cases[..mid]);
+        self.builder.set_insert_point(right_bb);
+        self.lower_sparse(&cases[mid..]);
+    }
+}
+
+struct JumpTableLowering<'a> {
+    builder: IRBuilder<'a>,
+    width: ir::IntWidth,
+    idx: ir::RegId,
+    cst: ir::RegId,  // constants compared with idx
+    cond: ir::RegId, // results of the comparisons
+}
+
+impl<'a> JumpTableLowering<'a> {
+    // Binary search on the ranges of indexes [begin, end[ with the same target
+    fn search_ranges(&mut self, ranges: &[(usize, usize, ir::BasicBlockId)]) {
+        if ranges.len() == 1 {
+            return self.builder.ins_jump(ranges[0].2);
+        }
+
+        let mid = ranges.len() / 2;
+        let left_bb = self.builder.create_basic_block();
+        let right_bb = self.builder.create_basic_block();
+        self.builder
+            .ins_movi_w(self.width, self.cst, ranges[mid].0 as i64);
+        self.builder.ins_cmpbin_w(
+            self.width,
+            ir::InsCmpbinKind::Ult,
+            self.cond,
+            self.idx,
+            self.cst,
+        );
+        self.builder.ins_br(self.cond, left_bb, right_bb);
+
+        self.builder.set_insert_point(left_bb);
+        self.search_ranges(&ranges[..mid]);
+        self.builder.set_insert_point(right_bb);
+        self.search_ranges(&ranges[mid..]);
+    }
+}
@@ -0,0 +1,46 @@
+use std::collections::HashMap;
+
+use crate::digraph::Digraph;
+use crate::ir;
+
+fn add_edge_once(g: &mut Digraph, v: usize, w: usize) {
+    if !g.adj(v).any(|x| *x == w) {
+        g.add_edge(v, w);
+    }
+}
+
+// Returns the index in the basic blocks list of every basic block of the function
+// The basic blocks ids are unique in the whole module, the indices are the vertices of the CFG
+pub fn basic_blocks_indices(fun: &ir::Function) -> HashMap<ir::BasicBlockRef, usize> {
+    fun.bb_list()
+        .iter()
+        .enumerate()
+        .map(|(idx, bb)| (bb.id(), idx))
+        .collect()
+}
+
+// Build a control flow graph (CFG)
+// Directed graph G=(N,E)
+// Each Node n_i in N is a basic block, identified by its index in fun.bb_list()
+// Each vertex e = (n_i, n_j) in E corresponds to a possible transfer of control
+// from block n_i to block n_j
+pub fn build_cfg(fun: &ir::Function) -> Digraph {
+    let bbs = fun.bb_list();
+    let indices = basic_blocks_indices(fun);
+    let mut g = Digraph::new(bbs.len());
+
+    for (bb_v, bb) in bbs.iter().enumerate() {
+        for ins in bb.ins_list() {
+            match ins {
+                ir::Ins::Jump(ins) => add_edge_once(&mut g, bb_v, indices[&ins.dst()]),
+                ir::Ins::Br(ins) => {
+                    add_edge_once(&mut g, bb_v, indices[&ins.dst_true()]);
+                    add_edge_once(&mut g, bb_v, indices[&ins.dst_false()]);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    g
+}
@@ -34,8 +34,8 @@ pub enum Ins {
     Const(InsConst),
     Load(InsLoad),
     Store(InsStore),
-    Opbin(InsOpbin),   //add, sub, mov, div, mod
-    Cmpbin(InsCmpbin), //cmpeq, cmplt, cmpgt
+    Opbin(InsOpbin),   //add, sub, mul, div, rem, and, or, xor, shl, shr, sar
+    Cmpbin(InsCmpbin), //cmpeq, cmpne, cmplt, cmple, cmpgt, cmpge, cmpult, cmpule, cmpugt, cmpuge
     Jump(InsJump),
     Br(InsBr),
     Call(InsCall),
@@ -54,6 +54,9 @@ impl Ins {
 /// Trait used to compute the effects of instructions on the Operands Stack
 pub trait OperandsSizeEffect {
     fn operands_size_change(&self) -> i32;
+
+    /// Number of values popped from the operands stack, before pushing the result
+    fn operands_pops(&self) -> usize;
 }
 
 impl OperandsSizeEffect for Ins {
@@ -71,6 +74,21 @@ impl OperandsSizeEffect for Ins {
             Ins::Ret(ins) => ins.operands_size_change(),
         }
     }
+
+    fn operands_pops(&self) -> usize {
+        match self {
+            Ins::Pop(ins) => ins.operands_pops(),
+            Ins::Const(ins) => ins.operands_pops(),
+            Ins::Load(ins) => ins.operands_pops(),
+            Ins::Store(ins) => ins.operands_pops(),
+            Ins::Opbin(ins) => ins.operands_pops(),
+            Ins::Cmpbin(ins) => ins.operands_pops(),
+            Ins::Jump(ins) => ins.operands_pops(),
+            Ins::Br(ins) => ins.operands_pops(),
+            Ins::Call(ins) => ins.operands_pops(),
+            Ins::Ret(ins) => ins.operands_pops(),
+        }
+    }
 }
 
 /// In the IR, Functions are identified by an unique usize (in the Module)
@@ -81,6 +99,10 @@ impl FunctionRef {
     pub fn new(id: usize) -> Self {
         FunctionRef(id)
     }
+
+    pub fn id(&self) -> usize {
+        self.0
+    }
 }
 
 impl fmt::Display for FunctionRef {
@@ -97,6 +119,10 @@ impl BasicBlockRef {
     pub fn new(id: usize) -> Self {
         BasicBlockRef(id)
     }
+
+    pub fn id(&self) -> usize {
+        self.0
+    }
 }
 
 impl fmt::Display for BasicBlockRef {
@@ -114,6 +140,10 @@ impl LocalsIndex {
     pub fn new(id: usize) -> Self {
         LocalsIndex(id)
     }
+
+    pub fn id(&self) -> usize {
+        self.0
+    }
 }
 
 impl fmt::Display for LocalsIndex {
@@ -138,6 +168,10 @@ impl OperandsSizeEffect for InsPop {
     fn operands_size_change(&self) -> i32 {
         -1
     }
+
+    fn operands_pops(&self) -> usize {
+        1
+    }
 }
 
 /// Instruction const
@@ -162,6 +196,10 @@ impl OperandsSizeEffect for InsConst {
     fn operands_size_change(&self) -> i32 {
         1
     }
+
+    fn operands_pops(&self) -> usize {
+        0
+    }
 }
 
 /// Instruction load
@@ -186,6 +224,10 @@ impl OperandsSizeEffect for InsLoad {
     fn operands_size_change(&self) -> i32 {
         1
     }
+
+    fn operands_pops(&self) -> usize {
+        0
+    }
 }
 
 /// Instruction store
@@ -210,6 +252,10 @@ impl OperandsSizeEffect for InsStore {
     fn operands_size_change(&self) -> i32 {
         -1
     }
+
+    fn operands_pops(&self) -> usize {
+        1
+    }
 }
 
 /// Represent multiple instructions for binary operations
@@ -218,7 +264,8 @@ impl OperandsSizeEffect for InsStore {
 /// It pops the right operand first, and then the left
 /// It means you must push the left operand then the right operand
 /// <ins>
-/// <ins> may be any of add, sub, mul, div, rem
+/// <ins> may be any of add, sub, mul, div, rem, and, or, xor, shl, shr, sar
+/// The shift instructions only use the 5 lowest bits of the right operand
 #[derive(Clone, Copy, Debug)]
 pub enum InsOpbin {
     Add,
@@ -226,12 +273,22 @@ pub enum InsOpbin {
     Mul,
     Div,
     Rem,
+    And,
+    Or,
+    Xor,
+    Shl, // shift left
+    Shr, // logical shift right (fills with 0)
+    Sar, // arithmetic shift right (fills with the sign bit)
 }
 
 impl OperandsSizeEffect for InsOpbin {
     fn operands_size_change(&self) -> i32 {
         -1
     }
+
+    fn operands_pops(&self) -> usize {
+        2
+    }
 }
 
 /// Represent multiple instructions for binary comparisons
@@ -241,18 +298,29 @@ impl OperandsSizeEffect for InsOpbin {
 /// It pops the right operand first, and then the left
 /// It means you must push the left operand then the right operand
 /// <ins>
-/// <ins> may be any of cmpeq, cmplt, cmpgt
+/// <ins> may be any of cmpeq, cmpne, cmplt, cmple, cmpgt, cmpge, and the unsigned cmpult, cmpule, cmpugt, cmpuge
 #[derive(Clone, Copy, Debug)]
 pub enum InsCmpbin {
     Eq,
+    Ne,
     Lt,
+    Le,
     Gt,
+    Ge,
+    Ult, // unsigned comparisons: the values are read as uint32
+    Ule,
+    Ugt,
+    Uge,
 }
 
 impl OperandsSizeEffect for InsCmpbin {
     fn operands_size_change(&self) -> i32 {
         -1
     }
+
+    fn operands_pops(&self) -> usize {
+        2
+    }
 }
 
 /// Instruction jump
@@ -277,6 +345,10 @@ impl OperandsSizeEffect for InsJump {
     fn operands_size_change(&self) -> i32 {
         0
     }
+
+    fn operands_pops(&self) -> usize {
+        0
+    }
 }
 
 /// Instruction br
@@ -310,6 +382,10 @@ impl OperandsSizeEffect for InsBr {
     fn operands_size_change(&self) -> i32 {
         -1
     }
+
+    fn operands_pops(&self) -> usize {
+        1
+    }
 }
 
 /// Instruction call
@@ -342,6 +418,10 @@ impl OperandsSizeEffect for InsCall {
     fn operands_size_change(&self) -> i32 {
         1 - (self.nb_args() as i32)
     }
+
+    fn operands_pops(&self) -> usize {
+        self.nb_args()
+    }
 }
 
 /// Instruction ret
@@ -361,6 +441,10 @@ impl OperandsSizeEffect for InsRet {
     fn operands_size_change(&self) -> i32 {
         -1
     }
+
+    fn operands_pops(&self) -> usize {
+        1
+    }
 }
 
 /// Representation of a basic block for the IR
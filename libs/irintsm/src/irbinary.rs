@@ -0,0 +1,411 @@
+// Binary encoding of an irintsm module
+//
+// All integers are encoded in LEB128 (unsigned, except const values in signed LEB128)
+//
+// Header:
+// - magic number: the 4 bytes `IRSM`
+// - version: 1 byte (VERSION)
+//
+// Function table:
+// - number of functions
+// - for every function: its id, and 1 byte for its kind (FUN_DEFINED or FUN_EXTERN)
+//
+// Code, for every defined function, in the order of the function table:
+// - number of basic blocks
+// - for every basic block: the number of instructions, then all instructions
+//
+// Every instruction is an opcode byte (see OP_*), followed by its immediates:
+// - const <val>: signed value
+// - load <index> / store <index>: index of the local
+// - jump <bb>, br <bb-true> <bb-false>: index of the basic block in the function
+// - call <fun-id> <nb-args>
+//
+// The names of functions, basic blocks and locals are not stored
+// The decoded module is validated with irvalidation
+
+use crate::ir;
+use crate::irvalidation;
+
+pub const MAGIC: &[u8; 4] = b"IRSM";
+pub const VERSION: u8 = 1;
+
+const FUN_DEFINED: u8 = 0;
+const FUN_EXTERN: u8 = 1;
+
+const OP_POP: u8 = 0x01;
+const OP_CONST: u8 = 0x02;
+const OP_LOAD: u8 = 0x03;
+const OP_STORE: u8 = 0x04;
+const OP_ADD: u8 = 0x10;
+const OP_SUB: u8 = 0x11;
+const OP_MUL: u8 = 0x12;
+const OP_DIV: u8 = 0x13;
+const OP_REM: u8 = 0x14;
+const OP_AND: u8 = 0x15;
+const OP_OR: u8 = 0x16;
+const OP_XOR: u8 = 0x17;
+const OP_SHL: u8 = 0x18;
+const OP_SHR: u8 = 0x19;
+const OP_SAR: u8 = 0x1A;
+const OP_CMPEQ: u8 = 0x20;
+const OP_CMPLT: u8 = 0x21;
+const OP_CMPGT: u8 = 0x22;
+const OP_CMPNE: u8 = 0x23;
+const OP_CMPLE: u8 = 0x24;
+const OP_CMPGE: u8 = 0x25;
+const OP_CMPULT: u8 = 0x26;
+const OP_CMPULE: u8 = 0x27;
+const OP_CMPUGT: u8 = 0x28;
+const OP_CMPUGE: u8 = 0x29;
+const OP_JUMP: u8 = 0x30;
+const OP_BR: u8 = 0x31;
+const OP_CALL: u8 = 0x32;
+const OP_RET: u8 = 0x33;
+
+/// Error when decoding a binary module
+/// offset is the position in bytes of the invalid data
+#[derive(Debug)]
+pub enum BinaryError {
+    BadMagic,
+    BadVersion(u8),
+    UnexpectedEof,
+    BadLeb128 { offset: usize },
+    BadFunctionKind { offset: usize, kind: u8 },
+    BadOpcode { offset: usize, opcode: u8 },
+    BadBasicBlock { offset: usize, index: usize },
+    TrailingBytes { offset: usize },
+    Validation(Vec<irvalidation::ValidationError>),
+}
+
+fn write_uleb128(out: &mut Vec<u8>, mut val: u64) {
+    loop {
+        let byte = (val & 0x7f) as u8;
+        val >>= 7;
+        if val == 0 {
+            out.push(byte);
+            break;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn write_sleb128(out: &mut Vec<u8>, mut val: i64) {
+    loop {
+        let byte = (val & 0x7f) as u8;
+        val >>= 7;
+        let sign_bit = byte & 0x40 != 0;
+        if (val == 0 && !sign_bit) || (val == -1 && sign_bit) {
+            out.push(byte);
+            break;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+/// Encode a module into bytes
+pub fn write_module(module: &ir::Module) -> Vec<u8> {
+    let mut out = vec![];
+    out.extend_from_slice(MAGIC);
+    out.push(VERSION);
+
+    write_uleb128(&mut out, module.fun_list().len() as u64);
+    for fun in module.fun_list() {
+        write_uleb128(&mut out, fun.id().id() as u64);
+        out.push(if fun.is_extern() {
+            FUN_EXTERN
+        } else {
+            FUN_DEFINED
+        });
+    }
+
+    for fun in module.fun_list() {
+        if !fun.is_extern() {
+            write_fun(&mut out, fun);
+        }
+    }
+
+    out
+}
+
+fn write_fun(out: &mut Vec<u8>, fun: &ir::Function) {
+    let bb_index = |bb_id: ir::BasicBlockRef| {
+        fun.bb_list()
+            .iter()
+            .position(|bb| bb.id() == bb_id)
+            .unwrap() as u64
+    };
+
+    write_uleb128(out, fun.bb_list().len() as u64);
+    for bb in fun.bb_list() {
+        write_uleb128(out, bb.ins_list().len() as u64);
+        for ins in bb.ins_list() {
+            match ins {
+                ir::Ins::Pop(_) => out.push(OP_POP),
+                ir::Ins::Const(ins) => {
+                    out.push(OP_CONST);
+                    write_sleb128(out, ins.val() as i64);
+                }
+                ir::Ins::Load(ins) => {
+                    out.push(OP_LOAD);
+                    write_uleb128(out, ins.src().id() as u64);
+                }
+                ir::Ins::Store(ins) => {
+                    out.push(OP_STORE);
+                    write_uleb128(out, ins.dst().id() as u64);
+                }
+                ir::Ins::Opbin(ins) => out.push(match ins {
+                    ir::InsOpbin::Add => OP_ADD,
+                    ir::InsOpbin::Sub => OP_SUB,
+                    ir::InsOpbin::Mul => OP_MUL,
+                    ir::InsOpbin::Div => OP_DIV,
+                    ir::InsOpbin::Rem => OP_REM,
+                    ir::InsOpbin::And => OP_AND,
+                    ir::InsOpbin::Or => OP_OR,
+                    ir::InsOpbin::Xor => OP_XOR,
+                    ir::InsOpbin::Shl => OP_SHL,
+                    ir::InsOpbin::Shr => OP_SHR,
+                    ir::InsOpbin::Sar => OP_SAR,
+                }),
+                ir::Ins::Cmpbin(ins) => out.push(match ins {
+                    ir::InsCmpbin::Eq => OP_CMPEQ,
+                    ir::InsCmpbin::Lt => OP_CMPLT,
+                    ir::InsCmpbin::Gt => OP_CMPGT,
+                    ir::InsCmpbin::Ne => OP_CMPNE,
+                    ir::InsCmpbin::Le => OP_CMPLE,
+                    ir::InsCmpbin::Ge => OP_CMPGE,
+                    ir::InsCmpbin::Ult => OP_CMPULT,
+                    ir::InsCmpbin::Ule => OP_CMPULE,
+                    ir::InsCmpbin::Ugt => OP_CMPUGT,
+                    ir::InsCmpbin::Uge => OP_CMPUGE,
+                }),
+                ir::Ins::Jump(ins) => {
+                    out.push(OP_JUMP);
+                    write_uleb128(out, bb_index(ins.dst()));
+                }
+                ir::Ins::Br(ins) => {
+                    out.push(OP_BR);
+                    write_uleb128(out, bb_index(ins.dst_true()));
+                    write_uleb128(out, bb_index(ins.dst_false()));
+                }
+                ir::Ins::Call(ins) => {
+                    out.push(OP_CALL);
+                    write_uleb128(out, ins.fun().id() as u64);
+                    write_uleb128(out, ins.nb_args() as u64);
+                }
+                ir::Ins::Ret(_) => out.push(OP_RET),
+            }
+        }
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    next_bb: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn read_u8(&mut self) -> Result<u8, BinaryError> {
+        let byte = *self.bytes.get(self.pos).ok_or(BinaryError::UnexpectedEof)?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn read_uleb128(&mut self) -> Result<u64, BinaryError> {
+        let offset = self.pos;
+        let mut res: u64 = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.read_u8()?;
+            let bits = (byte & 0x7f) as u64;
+            if shift >= 64 || (shift == 63 && bits > 1) {
+                return Err(BinaryError::BadLeb128 { offset });
+            }
+            res |= bits << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                return Ok(res);
+            }
+        }
+    }
+
+    fn read_usize(&mut self) -> Result<usize, BinaryError> {
+        let offset = self.pos;
+        let val = self.read_uleb128()?;
+        if val > usize::MAX as u64 {
+            return Err(BinaryError::BadLeb128 { offset });
+        }
+        Ok(val as usize)
+    }
+
+    fn read_sleb128_i32(&mut self) -> Result<i32, BinaryError> {
+        let offset = self.pos;
+        let mut res: i64 = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.read_u8()?;
+            if shift >= 35 {
+                return Err(BinaryError::BadLeb128 { offset });
+            }
+            res |= ((byte & 0x7f) as i64) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                if byte & 0x40 != 0 {
+                    res |= -1i64 << shift;
+                }
+                break;
+            }
+        }
+        if res < i32::MIN as i64 || res > i32::MAX as i64 {
+            return Err(BinaryError::BadLeb128 { offset });
+        }
+        Ok(res as i32)
+    }
+
+    fn read_header(&mut self) -> Result<(), BinaryError> {
+        for c in MAGIC {
+            if self.read_u8().map_err(|_| BinaryError::BadMagic)? != *c {
+                return Err(BinaryError::BadMagic);
+            }
+        }
+        let version = self.read_u8()?;
+        if version != VERSION {
+            return Err(BinaryError::BadVersion(version));
+        }
+        Ok(())
+    }
+
+    fn read_module(&mut self) -> Result<ir::Module, BinaryError> {
+        self.read_header()?;
+
+        let nb_funs = self.read_usize()?;
+        let mut table = vec![];
+        for _ in 0..nb_funs {
+            let id = ir::FunctionRef::new(self.read_usize()?);
+            let offset = self.pos;
+            let is_extern = match self.read_u8()? {
+                FUN_DEFINED => false,
+                FUN_EXTERN => true,
+                kind => return Err(BinaryError::BadFunctionKind { offset, kind }),
+            };
+            table.push((id, is_extern));
+        }
+
+        let mut funs = vec![];
+        for (id, is_extern) in table {
+            let bbs = if is_extern {
+                None
+            } else {
+                Some(self.read_fun()?)
+            };
+            funs.push(ir::Function::new(id, bbs));
+        }
+
+        if self.pos != self.bytes.len() {
+            return Err(BinaryError::TrailingBytes { offset: self.pos });
+        }
+        Ok(ir::Module::new(funs))
+    }
+
+    fn read_fun(&mut self) -> Result<Vec<ir::BasicBlock>, BinaryError> {
+        let nb_bbs = self.read_usize()?;
+        // every basic block takes at least 1 byte
+        if nb_bbs > self.bytes.len() - self.pos {
+            return Err(BinaryError::UnexpectedEof);
+        }
+        let first_bb = self.next_bb;
+        self.next_bb += nb_bbs;
+
+        let mut bbs = vec![];
+        for bb_idx in 0..nb_bbs {
+            let nb_ins = self.read_usize()?;
+            // every instruction takes at least 1 byte
+            if nb_ins > self.bytes.len() - self.pos {
+                return Err(BinaryError::UnexpectedEof);
+            }
+            let mut ins_list = vec![];
+            for _ in 0..nb_ins {
+                ins_list.push(self.read_ins(first_bb, nb_bbs)?);
+            }
+            bbs.push(ir::BasicBlock::new(
+                ir::BasicBlockRef::new(first_bb + bb_idx),
+                ins_list,
+            ));
+        }
+        Ok(bbs)
+    }
+
+    fn read_bb(
+        &mut self,
+        first_bb: usize,
+        nb_bbs: usize,
+    ) -> Result<ir::BasicBlockRef, BinaryError> {
+        let offset = self.pos;
+        let index = self.read_usize()?;
+        if index >= nb_bbs {
+            return Err(BinaryError::BadBasicBlock { offset, index });
+        }
+        Ok(ir::BasicBlockRef::new(first_bb + index))
+    }
+
+    fn read_ins(&mut self, first_bb: usize, nb_bbs: usize) -> Result<ir::Ins, BinaryError> {
+        let offset = self.pos;
+        let ins = match self.read_u8()? {
+            OP_POP => ir::Ins::Pop(ir::InsPop::new()),
+            OP_CONST => ir::Ins::Const(ir::InsConst::new(self.read_sleb128_i32()?)),
+            OP_LOAD => ir::Ins::Load(ir::InsLoad::new(ir::LocalsIndex::new(self.read_usize()?))),
+            OP_STORE => ir::Ins::Store(ir::InsStore::new(ir::LocalsIndex::new(self.read_usize()?))),
+            OP_ADD => ir::Ins::Opbin(ir::InsOpbin::Add),
+            OP_SUB => ir::Ins::Opbin(ir::InsOpbin::Sub),
+            OP_MUL => ir::Ins::Opbin(ir::InsOpbin::Mul),
+            OP_DIV => ir::Ins::Opbin(ir::InsOpbin::Div),
+            OP_REM => ir::Ins::Opbin(ir::InsOpbin::Rem),
+            OP_AND => ir::Ins::Opbin(ir::InsOpbin::And),
+            OP_OR => ir::Ins::Opbin(ir::InsOpbin::Or),
+            OP_XOR => ir::Ins::Opbin(ir::InsOpbin::Xor),
+            OP_SHL => ir::Ins::Opbin(ir::InsOpbin::Shl),
+            OP_SHR => ir::Ins::Opbin(ir::InsOpbin::Shr),
+            OP_SAR => ir::Ins::Opbin(ir::InsOpbin::Sar),
+            OP_CMPEQ => ir::Ins::Cmpbin(ir::InsCmpbin::Eq),
+            OP_CMPLT => ir::Ins::Cmpbin(ir::InsCmpbin::Lt),
+            OP_CMPGT => ir::Ins::Cmpbin(ir::InsCmpbin::Gt),
+            OP_CMPNE => ir::Ins::Cmpbin(ir::InsCmpbin::Ne),
+            OP_CMPLE => ir::Ins::Cmpbin(ir::InsCmpbin::Le),
+            OP_CMPGE => ir::Ins::Cmpbin(ir::InsCmpbin::Ge),
+            OP_CMPULT => ir::Ins::Cmpbin(ir::InsCmpbin::Ult),
+            OP_CMPULE => ir::Ins::Cmpbin(ir::InsCmpbin::Ule),
+            OP_CMPUGT => ir::Ins::Cmpbin(ir::InsCmpbin::Ugt),
+            OP_CMPUGE => ir::Ins::Cmpbin(ir::InsCmpbin::Uge),
+            OP_JUMP => ir::Ins::Jump(ir::InsJump::new(self.read_bb(first_bb, nb_bbs)?)),
+            OP_BR => {
+                let dst_true = self.read_bb(first_bb, nb_bbs)?;
+                let dst_false = self.read_bb(first_bb, nb_bbs)?;
+                ir::Ins::Br(ir::InsBr::new(dst_true, dst_false))
+            }
+            OP_CALL => {
+                let fun = ir::FunctionRef::new(self.read_usize()?);
+                let nb_args = self.read_usize()?;
+                ir::Ins::Call(ir::InsCall::new(fun, nb_args))
+            }
+            OP_RET => ir::Ins::Ret(ir::InsRet::new()),
+            opcode => return Err(BinaryError::BadOpcode { offset, opcode }),
+        };
+        Ok(ins)
+    }
+}
+
+/// Decode a module from bytes, and validate it
+pub fn read_module(bytes: &[u8]) -> Result<ir::Module, BinaryError> {
+    let mut reader = Reader {
+        bytes,
+        pos: 0,
+        next_bb: 0,
+    };
+    let module = reader.read_module()?;
+
+    let errs = irvalidation::validate_module(&module);
+    if !errs.is_empty() {
+        return Err(BinaryError::Validation(errs));
+    }
+    Ok(module)
+}
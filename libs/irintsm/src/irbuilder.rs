@@ -2,6 +2,7 @@ use std::collections::HashMap;
 
 use crate::ir;
 use crate::ir::OperandsSizeEffect;
+use crate::irnames;
 use crate::irvalidation;
 
 // basic block in construction
@@ -83,10 +84,12 @@ impl FunctionBuilder {
 /// It has an insert point, set to None by default, where new instruction are added.
 /// This insert point must be changed to the end of the basicblock where we want to insert code
 /// The created ir::Module is checked using irvalidation
+/// Functions, basic blocks and locals can optionally be named, see finish_with_names
 pub struct IRBuilder {
     funs: Vec<FunctionBuilder>,
     mapping_fun: HashMap<ir::FunctionRef, usize>,
     mapping_bb_fun: HashMap<ir::BasicBlockRef, ir::FunctionRef>,
+    names: irnames::ModuleNames,
 
     act_bb: Option<ir::BasicBlockRef>,
     operands_count: i32,
@@ -98,6 +101,7 @@ impl IRBuilder {
             funs: vec![],
             mapping_fun: HashMap::new(),
             mapping_bb_fun: HashMap::new(),
+            names: irnames::ModuleNames::new(),
 
             act_bb: None,
             operands_count: 0,
@@ -173,9 +177,39 @@ impl IRBuilder {
         bb_id
     }
 
+    /// Give a name to a function
+    pub fn set_function_name(&mut self, fun_id: ir::FunctionRef, name: String) {
+        assert!(self.mapping_fun.contains_key(&fun_id));
+        self.names.add_function(fun_id, name);
+    }
+
+    /// Give a name to a basic block, unique in its function
+    pub fn set_basic_block_name(&mut self, bb_id: ir::BasicBlockRef, name: String) {
+        let fun_id = *self.mapping_bb_fun.get(&bb_id).unwrap();
+        self.names
+            .get_function_mut(fun_id)
+            .add_basic_block(bb_id, name);
+    }
+
+    /// Give a name to a local of a function
+    pub fn set_local_name(
+        &mut self,
+        fun_id: ir::FunctionRef,
+        local: ir::LocalsIndex,
+        name: String,
+    ) {
+        assert!(self.mapping_fun.contains_key(&fun_id));
+        self.names.get_function_mut(fun_id).add_local(local, name);
+    }
+
     /// Discard the builder and create the final ir::Module
     /// Also validates the module
     pub fn finish(self) -> ir::Module {
+        self.finish_with_names().0
+    }
+
+    /// Same as finish, but also returns the names given to the functions, basic blocks and locals
+    pub fn finish_with_names(self) -> (ir::Module, irnames::ModuleNames) {
         let funs = self.funs.into_iter().map(|f| f.finish()).collect();
         let res = ir::Module::new(funs);
         let errs = irvalidation::validate_module(&res);
@@ -186,7 +220,7 @@ impl IRBuilder {
             }
             panic!("Validation failed!");
         }
-        res
+        (res, self.names)
     }
 
     /// Add an instruction at the current insert point
@@ -241,6 +275,30 @@ impl IRBuilder {
         self.add_instruction(ir::Ins::Opbin(ir::InsOpbin::Rem));
     }
 
+    pub fn ins_and(&mut self) {
+        self.add_instruction(ir::Ins::Opbin(ir::InsOpbin::And));
+    }
+
+    pub fn ins_or(&mut self) {
+        self.add_instruction(ir::Ins::Opbin(ir::InsOpbin::Or));
+    }
+
+    pub fn ins_xor(&mut self) {
+        self.add_instruction(ir::Ins::Opbin(ir::InsOpbin::Xor));
+    }
+
+    pub fn ins_shl(&mut self) {
+        self.add_instruction(ir::Ins::Opbin(ir::InsOpbin::Shl));
+    }
+
+    pub fn ins_shr(&mut self) {
+        self.add_instruction(ir::Ins::Opbin(ir::InsOpbin::Shr));
+    }
+
+    pub fn ins_sar(&mut self) {
+        self.add_instruction(ir::Ins::Opbin(ir::InsOpbin::Sar));
+    }
+
     pub fn ins_cmpeq(&mut self) {
         self.add_instruction(ir::Ins::Cmpbin(ir::InsCmpbin::Eq));
     }
@@ -253,6 +311,34 @@ impl IRBuilder {
         self.add_instruction(ir::Ins::Cmpbin(ir::InsCmpbin::Gt));
     }
 
+    pub fn ins_cmpne(&mut self) {
+        self.add_instruction(ir::Ins::Cmpbin(ir::InsCmpbin::Ne));
+    }
+
+    pub fn ins_cmple(&mut self) {
+        self.add_instruction(ir::Ins::Cmpbin(ir::InsCmpbin::Le));
+    }
+
+    pub fn ins_cmpge(&mut self) {
+        self.add_instruction(ir::Ins::Cmpbin(ir::InsCmpbin::Ge));
+    }
+
+    pub fn ins_cmpult(&mut self) {
+        self.add_instruction(ir::Ins::Cmpbin(ir::InsCmpbin::Ult));
+    }
+
+    pub fn ins_cmpule(&mut self) {
+        self.add_instruction(ir::Ins::Cmpbin(ir::InsCmpbin::Ule));
+    }
+
+    pub fn ins_cmpugt(&mut self) {
+        self.add_instruction(ir::Ins::Cmpbin(ir::InsCmpbin::Ugt));
+    }
+
+    pub fn ins_cmpuge(&mut self) {
+        self.add_instruction(ir::Ins::Cmpbin(ir::InsCmpbin::Uge));
+    }
+
     pub fn ins_jump(&mut self, dst: ir::BasicBlockRef) {
         self.add_instruction(ir::Ins::Jump(ir::InsJump::new(dst)));
     }
@@ -0,0 +1,146 @@
+// In the IR, functions, basic blocks and locals are all represented by numbers
+// This file contains structs to give optional names to all these ids
+//
+// Names are only used by the parser and the printer, the IR itself never uses them.
+// A name must be unique in its scope (functions in the module, basic blocks and locals in the function),
+// and must not be a number, or it would be parsed back as an id.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use crate::ir;
+
+struct IdsMapper<T> {
+    id2name: HashMap<T, String>,
+    name2id: HashMap<String, T>,
+}
+
+impl<T: Clone + Copy + Eq + PartialEq + Hash> IdsMapper<T> {
+    fn new() -> Self {
+        IdsMapper {
+            id2name: HashMap::new(),
+            name2id: HashMap::new(),
+        }
+    }
+
+    fn insert(&mut self, id: T, name: String) {
+        assert!(!self.id2name.contains_key(&id));
+        assert!(
+            !self.name2id.contains_key(&name),
+            "Name {} already used",
+            name
+        );
+        assert!(name.parse::<u64>().is_err(), "Name {} is a number", name);
+
+        self.id2name.insert(id, name.clone());
+        self.name2id.insert(name, id);
+    }
+
+    fn id2name(&self, id: T) -> Option<&str> {
+        self.id2name.get(&id).map(|x| x.as_str())
+    }
+
+    fn name2id(&self, name: &str) -> Option<T> {
+        self.name2id.get(name).copied()
+    }
+}
+
+/// Names of the basic blocks and locals of one function
+pub struct FunctionNames {
+    id: ir::FunctionRef,
+    bbs: IdsMapper<ir::BasicBlockRef>,
+    locals: IdsMapper<ir::LocalsIndex>,
+}
+
+impl FunctionNames {
+    pub fn new(id: ir::FunctionRef) -> Self {
+        FunctionNames {
+            id,
+            bbs: IdsMapper::new(),
+            locals: IdsMapper::new(),
+        }
+    }
+
+    pub fn id(&self) -> ir::FunctionRef {
+        self.id
+    }
+
+    pub fn add_basic_block(&mut self, id: ir::BasicBlockRef, name: String) {
+        self.bbs.insert(id, name);
+    }
+
+    pub fn get_basic_block_id(&self, name: &str) -> Option<ir::BasicBlockRef> {
+        self.bbs.name2id(name)
+    }
+
+    pub fn get_basic_block_name(&self, id: ir::BasicBlockRef) -> Option<&str> {
+        self.bbs.id2name(id)
+    }
+
+    pub fn add_local(&mut self, id: ir::LocalsIndex, name: String) {
+        self.locals.insert(id, name);
+    }
+
+    pub fn get_local_id(&self, name: &str) -> Option<ir::LocalsIndex> {
+        self.locals.name2id(name)
+    }
+
+    pub fn get_local_name(&self, id: ir::LocalsIndex) -> Option<&str> {
+        self.locals.id2name(id)
+    }
+
+    /// Returns the number of locals up to the last named one (0 if none are named)
+    pub fn named_locals_count(&self) -> usize {
+        self.locals
+            .id2name
+            .keys()
+            .map(|id| id.id() + 1)
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+/// Names of all functions of a module, and their basic blocks and locals
+pub struct ModuleNames {
+    funs: IdsMapper<ir::FunctionRef>,
+    funs_names: HashMap<ir::FunctionRef, FunctionNames>,
+}
+
+impl ModuleNames {
+    pub fn new() -> Self {
+        ModuleNames {
+            funs: IdsMapper::new(),
+            funs_names: HashMap::new(),
+        }
+    }
+
+    pub fn add_function(&mut self, id: ir::FunctionRef, name: String) {
+        self.funs.insert(id, name);
+    }
+
+    pub fn get_function_id(&self, name: &str) -> Option<ir::FunctionRef> {
+        self.funs.name2id(name)
+    }
+
+    pub fn get_function_name(&self, id: ir::FunctionRef) -> Option<&str> {
+        self.funs.id2name(id)
+    }
+
+    /// Returns the names of the basic blocks and locals of a function, if any
+    pub fn get_function(&self, id: ir::FunctionRef) -> Option<&FunctionNames> {
+        self.funs_names.get(&id)
+    }
+
+    /// Returns the names of the basic blocks and locals of a function, created if needed
+    pub fn get_function_mut(&mut self, id: ir::FunctionRef) -> &mut FunctionNames {
+        self.funs_names
+            .entry(id)
+            .or_insert_with(|| FunctionNames::new(id))
+    }
+}
+
+impl Default for ModuleNames {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -2,22 +2,35 @@ use std::collections::HashMap;
 
 use crate::ir;
 use crate::irbuilder::IRBuilder;
+use crate::irnames;
 use asmparser::parser::{Ins, InsArg, InsLabel, Item};
 
+// Functions, basic blocks and locals can be refered either by their id / index or by their name:
+// .define <fn-id> [<fn-name>]
+// .declare <fn-id> [<fn-name>]
+// .locals <name-0> <name-1> ... (optional, right after .define, names the locals from index 0)
+// <bb-id>: or <bb-name>:
+// jump %<bb-id> / %<bb-name>, call %<fn-id> / %<fn-name>, load <index> / <local-name>
+// const <val> (val can be negative)
+
 struct ParsedBasicBlock {
-    id: usize,
+    label: String, // number or name
     code: Vec<Ins>,
 }
 
 struct ParsedFunction {
     id: ir::FunctionRef,
+    name: Option<String>,
+    locals: Vec<String>,
     bbs: Option<Vec<ParsedBasicBlock>>,
 }
 
 pub struct Parser {
     ps: asmparser::parser::Parser,
     builder: IRBuilder,
-    mapping_bb: HashMap<usize, ir::BasicBlockRef>,
+    mapping_fun: HashMap<String, ir::FunctionRef>,
+    mapping_bb: HashMap<String, ir::BasicBlockRef>,
+    mapping_locals: HashMap<String, ir::LocalsIndex>,
 }
 
 impl Parser {
@@ -29,17 +42,24 @@ impl Parser {
         Parser::new(asmparser::parser::Parser::from_str(s))
     }
 
-    pub fn build(mut self) -> ir::Module {
+    pub fn build(self) -> ir::Module {
+        self.build_with_names().0
+    }
+
+    /// Same as build, but also returns the names of the functions, basic blocks and locals
+    pub fn build_with_names(mut self) -> (ir::Module, irnames::ModuleNames) {
         let funs = self.r_file();
         self.add_module(&funs);
-        self.builder.finish()
+        self.builder.finish_with_names()
     }
 
     fn new(ps: asmparser::parser::Parser) -> Self {
         Parser {
             ps,
             builder: IRBuilder::new(),
+            mapping_fun: HashMap::new(),
             mapping_bb: HashMap::new(),
+            mapping_locals: HashMap::new(),
         }
     }
 
@@ -71,19 +91,38 @@ impl Parser {
             panic!("Invalid definition {}", def.line);
         };
 
-        let def_id = def_id
-            .trim()
+        let mut def_args = def_id.split_whitespace();
+        let def_id = def_args
+            .next()
+            .expect("Invalid def syntax: must be .define <fn-id> [<fn-name>]")
             .parse::<usize>()
             .expect("function id must be a number");
         let def_id = ir::FunctionRef::new(def_id);
+        let def_name = def_args.next().map(|name| name.to_string());
+        if def_args.next().is_some() {
+            panic!("Invalid def syntax: must be .define <fn-id> [<fn-name>]");
+        }
 
         if is_extern {
             return ParsedFunction {
                 id: def_id,
+                name: def_name,
+                locals: vec![],
                 bbs: None,
             };
         }
 
+        let locals = match self.ps.peek() {
+            Some(Item::Def(def)) if def.line.starts_with("locals") => def.line[6..]
+                .split_whitespace()
+                .map(|name| name.to_string())
+                .collect(),
+            _ => vec![],
+        };
+        if !locals.is_empty() {
+            self.ps.next();
+        }
+
         let mut bbs = vec![];
 
         loop {
@@ -96,38 +135,39 @@ impl Parser {
 
         ParsedFunction {
             id: def_id,
+            name: def_name,
+            locals,
             bbs: Some(bbs),
         }
     }
 
     fn r_bb(&mut self) -> ParsedBasicBlock {
         let mut code = vec![];
-        let mut id = None;
+        let mut label = None;
 
         loop {
             let ins = match self.ps.peek() {
                 Some(Item::Ins(ins)) => ins,
+                Some(Item::Error(err)) => {
+                    panic!("{}:{}: {}", err.pos.line, err.pos.col, err.mess)
+                }
                 _ => {
                     break;
                 }
             };
 
             if code.len() == 0 {
-                match ins.label {
-                    Some(InsLabel::IdInt(label)) => {
-                        id = Some(label as usize);
+                match &ins.label {
+                    Some(InsLabel::IdInt(id)) => {
+                        label = Some(id.to_string());
                     }
-                    Some(InsLabel::IdName(_)) => panic!("Label cannot be a name"),
-                    None => panic!("First instruction in a basic block must have a label"),
-                }
-            } else {
-                match ins.label {
-                    Some(InsLabel::IdInt(_)) => {
-                        break;
+                    Some(InsLabel::IdName(name)) => {
+                        label = Some(name.clone());
                     }
-                    Some(InsLabel::IdName(_)) => panic!("Label cannot be a name"),
-                    None => {}
+                    None => panic!("First instruction in a basic block must have a label"),
                 }
+            } else if ins.label.is_some() {
+                break;
             }
 
             let ins = match self.ps.next() {
@@ -142,7 +182,7 @@ impl Parser {
         }
 
         ParsedBasicBlock {
-            id: id.unwrap(),
+            label: label.unwrap(),
             code,
         }
     }
@@ -160,6 +200,7 @@ impl Parser {
             None => return,
         };
         self.add_bbs_defs(fun);
+        self.add_locals_defs(fun);
 
         for bb in bbs {
             self.add_bb(bb);
@@ -169,6 +210,11 @@ impl Parser {
     fn add_fun_defs(&mut self, funs: &Vec<ParsedFunction>) {
         for fun in funs {
             self.builder.create_function(Some(fun.id));
+            self.mapping_fun.insert(fun.id.to_string(), fun.id);
+            if let Some(name) = &fun.name {
+                self.builder.set_function_name(fun.id, name.clone());
+                self.mapping_fun.insert(name.clone(), fun.id);
+            }
         }
     }
 
@@ -176,12 +222,26 @@ impl Parser {
         self.mapping_bb.clear();
         for bb in fun.bbs.as_ref().unwrap() {
             let bb_id = self.builder.create_basic_block(fun.id);
-            self.mapping_bb.insert(bb.id, bb_id);
+            if self.mapping_bb.insert(bb.label.clone(), bb_id).is_some() {
+                panic!("Basic block {} defined twice", bb.label);
+            }
+            if bb.label.parse::<usize>().is_err() {
+                self.builder.set_basic_block_name(bb_id, bb.label.clone());
+            }
+        }
+    }
+
+    fn add_locals_defs(&mut self, fun: &ParsedFunction) {
+        self.mapping_locals.clear();
+        for (idx, name) in fun.locals.iter().enumerate() {
+            let local = ir::LocalsIndex::new(idx);
+            self.builder.set_local_name(fun.id, local, name.clone());
+            self.mapping_locals.insert(name.clone(), local);
         }
     }
 
     fn add_bb(&mut self, bb: &ParsedBasicBlock) {
-        let bb_id = *self.mapping_bb.get(&bb.id).unwrap();
+        let bb_id = *self.mapping_bb.get(&bb.label).unwrap();
         self.builder.set_insert_point(bb_id);
         for ins in &bb.code {
             self.add_ins(ins);
@@ -202,6 +262,19 @@ impl Parser {
             "cmpeq" => self.add_ins_cmpeq(&ins.args),
             "cmplt" => self.add_ins_cmplt(&ins.args),
             "cmpgt" => self.add_ins_cmpgt(&ins.args),
+            "and" => self.add_ins_and(&ins.args),
+            "or" => self.add_ins_or(&ins.args),
+            "xor" => self.add_ins_xor(&ins.args),
+            "shl" => self.add_ins_shl(&ins.args),
+            "shr" => self.add_ins_shr(&ins.args),
+            "sar" => self.add_ins_sar(&ins.args),
+            "cmpne" => self.add_ins_cmpne(&ins.args),
+            "cmple" => self.add_ins_cmple(&ins.args),
+            "cmpge" => self.add_ins_cmpge(&ins.args),
+            "cmpult" => self.add_ins_cmpult(&ins.args),
+            "cmpule" => self.add_ins_cmpule(&ins.args),
+            "cmpugt" => self.add_ins_cmpugt(&ins.args),
+            "cmpuge" => self.add_ins_cmpuge(&ins.args),
             "jump" => self.add_ins_jump(&ins.args),
             "br" => self.add_ins_br(&ins.args),
             "call" => self.add_ins_call(&ins.args),
@@ -224,6 +297,9 @@ impl Parser {
 
         let val = match args[0] {
             InsArg::Const(val) => val as i32,
+            InsArg::Name(ref val) => val
+                .parse::<i32>()
+                .unwrap_or_else(|_| panic!("src argument must be a const")),
             _ => panic!("src argument must be a const"),
         };
 
@@ -235,12 +311,7 @@ impl Parser {
             panic!("load instruction has 1 argument");
         }
 
-        let src = match args[0] {
-            InsArg::Const(id) => id,
-            _ => panic!("src argument must be a const"),
-        };
-
-        let src = ir::LocalsIndex::new(src as usize);
+        let src = self.get_local(&args[0]);
         self.builder.ins_load(src);
     }
 
@@ -249,12 +320,7 @@ impl Parser {
             panic!("store instruction has 1 argument");
         }
 
-        let dst = match args[0] {
-            InsArg::Const(id) => id,
-            _ => panic!("dst argument must be a const"),
-        };
-
-        let dst = ir::LocalsIndex::new(dst as usize);
+        let dst = self.get_local(&args[0]);
         self.builder.ins_store(dst);
     }
 
@@ -314,16 +380,103 @@ impl Parser {
         self.builder.ins_cmpgt();
     }
 
+    fn add_ins_and(&mut self, args: &[InsArg]) {
+        if !args.is_empty() {
+            panic!("and instruction has no arguments");
+        }
+        self.builder.ins_and();
+    }
+
+    fn add_ins_or(&mut self, args: &[InsArg]) {
+        if !args.is_empty() {
+            panic!("or instruction has no arguments");
+        }
+        self.builder.ins_or();
+    }
+
+    fn add_ins_xor(&mut self, args: &[InsArg]) {
+        if !args.is_empty() {
+            panic!("xor instruction has no arguments");
+        }
+        self.builder.ins_xor();
+    }
+
+    fn add_ins_shl(&mut self, args: &[InsArg]) {
+        if !args.is_empty() {
+            panic!("shl instruction has no arguments");
+        }
+        self.builder.ins_shl();
+    }
+
+    fn add_ins_shr(&mut self, args: &[InsArg]) {
+        if !args.is_empty() {
+            panic!("shr instruction has no arguments");
+        }
+        self.builder.ins_shr();
+    }
+
+    fn add_ins_sar(&mut self, args: &[InsArg]) {
+        if !args.is_empty() {
+            panic!("sar instruction has no arguments");
+        }
+        self.builder.ins_sar();
+    }
+
+    fn add_ins_cmpne(&mut self, args: &[InsArg]) {
+        if !args.is_empty() {
+            panic!("cmpne instruction has no arguments");
+        }
+        self.builder.ins_cmpne();
+    }
+
+    fn add_ins_cmple(&mut self, args: &[InsArg]) {
+        if !args.is_empty() {
+            panic!("cmple instruction has no arguments");
+        }
+        self.builder.ins_cmple();
+    }
+
+    fn add_ins_cmpge(&mut self, args: &[InsArg]) {
+        if !args.is_empty() {
+            panic!("cmpge instruction has no arguments");
+        }
+        self.builder.ins_cmpge();
+    }
+
+    fn add_ins_cmpult(&mut self, args: &[InsArg]) {
+        if !args.is_empty() {
+            panic!("cmpult instruction has no arguments");
+        }
+        self.builder.ins_cmpult();
+    }
+
+    fn add_ins_cmpule(&mut self, args: &[InsArg]) {
+        if !args.is_empty() {
+            panic!("cmpule instruction has no arguments");
+        }
+        self.builder.ins_cmpule();
+    }
+
+    fn add_ins_cmpugt(&mut self, args: &[InsArg]) {
+        if !args.is_empty() {
+            panic!("cmpugt instruction has no arguments");
+        }
+        self.builder.ins_cmpugt();
+    }
+
+    fn add_ins_cmpuge(&mut self, args: &[InsArg]) {
+        if !args.is_empty() {
+            panic!("cmpuge instruction has no arguments");
+        }
+        self.builder.ins_cmpuge();
+    }
+
     fn add_ins_jump(&mut self, args: &[InsArg]) {
         if args.len() != 1 {
             panic!("jump instruction has 1 argument");
         }
 
-        let dst = match args[0] {
-            InsArg::IdInt(id) => id,
-            _ => panic!("dst argument must be an idint"),
-        } as usize;
-        let dst = *self.mapping_bb.get(&dst).unwrap();
+        let dst = self.get_bb(&args[0]);
 
         self.builder.ins_jump(dst);
     }
@@ -333,16 +486,8 @@ impl Parser {
             panic!("br instruction has 2 arguments");
         }
 
-        let dst_true = match args[0] {
-            InsArg::IdInt(id) => id,
-            _ => panic!("dst_true argument must be an idint"),
-        } as usize;
-        let dst_false = match args[1] {
-            InsArg::IdInt(id) => id,
-            _ => panic!("dst_false argument must be an idint"),
-        } as usize;
-        let dst_true = *self.mapping_bb.get(&dst_true).unwrap();
-        let dst_false = *self.mapping_bb.get(&dst_false).unwrap();
+        let dst_true = self.get_bb(&args[0]);
+        let dst_false = self.get_bb(&args[1]);
 
         self.builder.ins_br(dst_true, dst_false);
     }
@@ -352,15 +497,18 @@ impl Parser {
             panic!("call instruction has 2 arguments");
         }
 
-        let fun = match args[0] {
-            InsArg::IdInt(id) => id,
-            _ => panic!("fun argument must be an idint"),
+        let fun = match &args[0] {
+            InsArg::IdInt(id) => ir::FunctionRef::new(*id as usize),
+            InsArg::IdName(name) => match self.mapping_fun.get(name) {
+                Some(fun) => *fun,
+                None => panic!("Unknown function {}", name),
+            },
+            _ => panic!("fun argument must be an id"),
         };
         let nb_args = match args[1] {
             InsArg::Const(id) => id as usize,
             _ => panic!("nb_args argument must be a const"),
         };
-        let fun = ir::FunctionRef::new(fun as usize);
 
         self.builder.ins_call(fun, nb_args);
     }
@@ -372,4 +520,27 @@ impl Parser {
 
         self.builder.ins_ret();
     }
+
+    fn get_bb(&self, arg: &InsArg) -> ir::BasicBlockRef {
+        let label = match arg {
+            InsArg::IdInt(id) => id.to_string(),
+            InsArg::IdName(name) => name.clone(),
+            _ => panic!("basic block argument must be an id"),
+        };
+        match self.mapping_bb.get(&label) {
+            Some(bb) => *bb,
+            None => panic!("Unknown basic block {}", label),
+        }
+    }
+
+    fn get_local(&self, arg: &InsArg) -> ir::LocalsIndex {
+        match arg {
+            InsArg::Const(idx) => ir::LocalsIndex::new(*idx as usize),
+            InsArg::Name(name) => match self.mapping_locals.get(name) {
+                Some(local) => *local,
+                None => panic!("Unknown local {}", name),
+            },
+            _ => panic!("local argument must be an index or a name"),
+        }
+    }
 }
@@ -2,32 +2,45 @@ use std::collections::HashMap;
 use std::io::Write;
 
 use crate::ir;
+use crate::irnames;
 
 pub trait CodePrintable {
     fn print_code(&self, writer: &mut dyn Write);
+
+    /// Print the code using the names of functions, basic blocks and locals when they have one
+    fn print_code_with_names(&self, writer: &mut dyn Write, names: &irnames::ModuleNames);
 }
 
 impl CodePrintable for ir::Module {
     fn print_code(&self, writer: &mut dyn Write) {
-        let mut printer = IRPrinter::new(self);
+        let mut printer = IRPrinter::new(self, None);
+        printer.print_mod(writer);
+    }
+
+    fn print_code_with_names(&self, writer: &mut dyn Write, names: &irnames::ModuleNames) {
+        let mut printer = IRPrinter::new(self, Some(names));
         printer.print_mod(writer);
     }
 }
 
 struct IRPrinter<'a> {
     module: &'a ir::Module,
+    names: Option<&'a irnames::ModuleNames>,
     fun: Option<&'a ir::Function>,
     bb: Option<&'a ir::BasicBlock>,
-    bb_mapping: HashMap<ir::BasicBlockRef, usize>,
+    bb_mapping: HashMap<ir::BasicBlockRef, String>,
+    locals_names: Vec<String>,
 }
 
 impl<'a> IRPrinter<'a> {
-    pub fn new(module: &'a ir::Module) -> Self {
+    pub fn new(module: &'a ir::Module, names: Option<&'a irnames::ModuleNames>) -> Self {
         IRPrinter {
             module,
+            names,
             fun: None,
             bb: None,
             bb_mapping: HashMap::new(),
+            locals_names: vec![],
         }
     }
 
@@ -35,40 +48,73 @@ impl<'a> IRPrinter<'a> {
         for fun in self.module.fun_list() {
             self.fun = Some(fun);
             self.print_fun(writer);
-            write!(writer, "\n").unwrap();
+            writeln!(writer).unwrap();
         }
     }
 
     pub fn print_fun(&mut self, writer: &mut dyn Write) {
         let fun = self.fun.unwrap();
+        let fun_name = match self
+            .names
+            .and_then(|names| names.get_function_name(fun.id()))
+        {
+            Some(name) => format!(" {}", name),
+            None => String::new(),
+        };
         if fun.is_extern() {
-            write!(writer, ".declare {}\n", fun.id()).unwrap();
+            writeln!(writer, ".declare {}{}", fun.id(), fun_name).unwrap();
             return;
         }
 
-        write!(writer, ".define {}\n", fun.id()).unwrap();
+        writeln!(writer, ".define {}{}", fun.id(), fun_name).unwrap();
 
+        let fun_names = self.names.and_then(|names| names.get_function(fun.id()));
         self.bb_mapping.clear();
         for (bb_idx, bb) in fun.bb_list().iter().enumerate() {
-            self.bb_mapping.insert(bb.id(), bb_idx);
+            let label = match fun_names.and_then(|names| names.get_basic_block_name(bb.id())) {
+                Some(name) => name.to_string(),
+                None => bb_idx.to_string(),
+            };
+            self.bb_mapping.insert(bb.id(), label);
+        }
+
+        // .locals must name all locals from index 0, generate names for the missing ones
+        self.locals_names.clear();
+        if let Some(fun_names) = fun_names {
+            for idx in 0..fun_names.named_locals_count() {
+                let name = match fun_names.get_local_name(ir::LocalsIndex::new(idx)) {
+                    Some(name) => name.to_string(),
+                    None => {
+                        let mut name = format!("l{}", idx);
+                        while fun_names.get_local_id(&name).is_some() {
+                            name.push('_');
+                        }
+                        name
+                    }
+                };
+                self.locals_names.push(name);
+            }
+        }
+        if !self.locals_names.is_empty() {
+            writeln!(writer, ".locals {}", self.locals_names.join(" ")).unwrap();
         }
 
         for bb in fun.bb_list() {
             self.bb = Some(bb);
             self.print_bb(writer);
-            write!(writer, "\n").unwrap();
+            writeln!(writer).unwrap();
         }
     }
 
     pub fn print_bb(&mut self, writer: &mut dyn Write) {
         let bb = self.bb.unwrap();
-        let bb_idx = *self.bb_mapping.get(&bb.id()).unwrap();
-        write!(writer, "{}:\n", bb_idx).unwrap();
+        let label = self.bb_mapping.get(&bb.id()).unwrap();
+        writeln!(writer, "{}:", label).unwrap();
 
         for ins in bb.ins_list() {
             write!(writer, "  ").unwrap();
             self.print_ins(*ins, writer);
-            write!(writer, "\n").unwrap();
+            writeln!(writer).unwrap();
         }
     }
 
@@ -96,11 +142,11 @@ impl<'a> IRPrinter<'a> {
     }
 
     fn print_ins_load(&self, ins: ir::InsLoad, writer: &mut dyn Write) {
-        write!(writer, "load {}", ins.src()).unwrap();
+        write!(writer, "load {}", self.local_name(ins.src())).unwrap();
     }
 
     fn print_ins_store(&self, ins: ir::InsStore, writer: &mut dyn Write) {
-        write!(writer, "store {}", ins.dst()).unwrap();
+        write!(writer, "store {}", self.local_name(ins.dst())).unwrap();
     }
 
     fn print_ins_opbin(&self, ins: ir::InsOpbin, writer: &mut dyn Write) {
@@ -113,6 +159,12 @@ impl<'a> IRPrinter<'a> {
                 ir::InsOpbin::Mul => "mul",
                 ir::InsOpbin::Div => "div",
                 ir::InsOpbin::Rem => "rem",
+                ir::InsOpbin::And => "and",
+                ir::InsOpbin::Or => "or",
+                ir::InsOpbin::Xor => "xor",
+                ir::InsOpbin::Shl => "shl",
+                ir::InsOpbin::Shr => "shr",
+                ir::InsOpbin::Sar => "sar",
             }
         )
         .unwrap();
@@ -126,27 +178,47 @@ impl<'a> IRPrinter<'a> {
                 ir::InsCmpbin::Eq => "cmpeq",
                 ir::InsCmpbin::Lt => "cmplt",
                 ir::InsCmpbin::Gt => "cmpgt",
+                ir::InsCmpbin::Ne => "cmpne",
+                ir::InsCmpbin::Le => "cmple",
+                ir::InsCmpbin::Ge => "cmpge",
+                ir::InsCmpbin::Ult => "cmpult",
+                ir::InsCmpbin::Ule => "cmpule",
+                ir::InsCmpbin::Ugt => "cmpugt",
+                ir::InsCmpbin::Uge => "cmpuge",
             }
         )
         .unwrap();
     }
 
     fn print_ins_jump(&self, ins: ir::InsJump, writer: &mut dyn Write) {
-        let dst = *self.bb_mapping.get(&ins.dst()).unwrap();
+        let dst = self.bb_mapping.get(&ins.dst()).unwrap();
         write!(writer, "jump %{}", dst).unwrap();
     }
 
     fn print_ins_br(&self, ins: ir::InsBr, writer: &mut dyn Write) {
-        let dst_true = *self.bb_mapping.get(&ins.dst_true()).unwrap();
-        let dst_false = *self.bb_mapping.get(&ins.dst_false()).unwrap();
+        let dst_true = self.bb_mapping.get(&ins.dst_true()).unwrap();
+        let dst_false = self.bb_mapping.get(&ins.dst_false()).unwrap();
         write!(writer, "br %{}, %{}", dst_true, dst_false).unwrap();
     }
 
     fn print_ins_call(&self, ins: ir::InsCall, writer: &mut dyn Write) {
-        write!(writer, "call %{}, {}", ins.fun(), ins.nb_args()).unwrap();
+        match self
+            .names
+            .and_then(|names| names.get_function_name(ins.fun()))
+        {
+            Some(name) => write!(writer, "call %{}, {}", name, ins.nb_args()).unwrap(),
+            None => write!(writer, "call %{}, {}", ins.fun(), ins.nb_args()).unwrap(),
+        }
     }
 
     fn print_ins_ret(&self, _ins: ir::InsRet, writer: &mut dyn Write) {
         write!(writer, "ret").unwrap();
     }
+
+    fn local_name(&self, local: ir::LocalsIndex) -> String {
+        match self.locals_names.get(local.id()) {
+            Some(name) => name.clone(),
+            None => local.to_string(),
+        }
+    }
 }
@@ -1,6 +1,8 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 
 use crate::ir;
+use crate::ir::OperandsSizeEffect;
 
 // Check module and return all errors
 pub fn validate_module(module: &ir::Module) -> Vec<ValidationError> {
@@ -9,6 +11,107 @@ pub fn validate_module(module: &ir::Module) -> Vec<ValidationError> {
     vd.errs
 }
 
+/// Check the operands stack of all defined functions (see compute_stack_infos)
+/// This is stricter than validate_module, and not checked when building a module:
+/// the interpreter accepts extra operands when returning (eg: unused results of calls)
+/// The module must be valid (see validate_module)
+/// Returns the stack infos of every function, and all errors
+pub fn validate_module_stack(
+    module: &ir::Module,
+) -> (HashMap<ir::FunctionRef, StackInfos>, Vec<ValidationError>) {
+    let mut infos = HashMap::new();
+    let mut errs = vec![];
+    for fun in module.fun_list() {
+        if fun.is_extern() {
+            continue;
+        }
+        let (fun_infos, fun_errs) = compute_stack_infos(fun);
+        infos.insert(fun.id(), fun_infos);
+        errs.extend(fun_errs);
+    }
+    (infos, errs)
+}
+
+/// Operands stack heights of a function, computed by a dataflow analysis
+/// Unreachable basic blocks have no entry height
+#[derive(Debug)]
+pub struct StackInfos {
+    pub entry_heights: HashMap<ir::BasicBlockRef, usize>,
+    pub max_depth: usize,
+}
+
+/// Compute the operands stack height at the entry of every basic block of a function
+/// (the stack is empty at the entry of the function)
+/// Also returns the errors: height mismatch between predecessors, underflow and wrong height at ret
+/// The function must be structurally valid (see validate_module)
+pub fn compute_stack_infos(fun: &ir::Function) -> (StackInfos, Vec<ValidationError>) {
+    let mut res = StackInfos {
+        entry_heights: HashMap::new(),
+        max_depth: 0,
+    };
+    let mut errs = vec![];
+    let entry = fun.bb_list()[0].id();
+    res.entry_heights.insert(entry, 0);
+    let mut todo = vec![entry];
+
+    while let Some(bb_id) = todo.pop() {
+        let bb = fun.get_bb(bb_id);
+        let mut height = res.entry_heights[&bb_id] as i32;
+
+        for (ins_id, ins) in bb.ins_list().iter().enumerate() {
+            let mut err = |kind| {
+                errs.push(ValidationError::Stack(StackError {
+                    kind,
+                    ins_id,
+                    bb_id,
+                    fun_id: fun.id(),
+                }))
+            };
+
+            let pops = ins.operands_pops();
+            if height < pops as i32 {
+                err(StackErrorKind::Underflow {
+                    height: height as usize,
+                    pops,
+                });
+                break;
+            }
+            if let ir::Ins::Ret(_) = ins {
+                if height != 1 {
+                    err(StackErrorKind::RetOperands(height as usize));
+                }
+            }
+
+            height += ins.operands_size_change();
+            res.max_depth = std::cmp::max(res.max_depth, height as usize);
+
+            let succs = match ins {
+                ir::Ins::Jump(ins) => vec![ins.dst()],
+                ir::Ins::Br(ins) => vec![ins.dst_true(), ins.dst_false()],
+                _ => vec![],
+            };
+            for succ in succs {
+                match res.entry_heights.get(&succ) {
+                    None => {
+                        res.entry_heights.insert(succ, height as usize);
+                        todo.push(succ);
+                    }
+                    Some(succ_height) if *succ_height != height as usize => {
+                        err(StackErrorKind::HeightMismatch {
+                            dst: succ,
+                            expected: *succ_height,
+                            actual: height as usize,
+                        });
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    (res, errs)
+}
+
 #[derive(Debug)]
 pub struct InsError {
     pub mess: &'static str,
@@ -30,11 +133,83 @@ pub struct FunctionError {
     pub fun_id: ir::FunctionRef,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StackErrorKind {
+    /// The instruction pops more operands than the stack height
+    Underflow { height: usize, pops: usize },
+    /// ret must run with exactly one operand on the stack, the return value
+    RetOperands(usize),
+    /// Jump / br to a basic block with a different height than its other predecessors
+    HeightMismatch {
+        dst: ir::BasicBlockRef,
+        expected: usize,
+        actual: usize,
+    },
+}
+
+impl fmt::Display for StackErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StackErrorKind::Underflow { height, pops } => write!(
+                f,
+                "stack underflow: pops {} operands with a stack height of {}",
+                pops, height
+            ),
+            StackErrorKind::RetOperands(height) => write!(
+                f,
+                "ret with {} operands on the stack, expected only the return value",
+                height
+            ),
+            StackErrorKind::HeightMismatch {
+                dst,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "jump to basic block {} with a stack height of {}, other predecessors have {}",
+                dst, actual, expected
+            ),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct StackError {
+    pub kind: StackErrorKind,
+    pub ins_id: usize,
+    pub bb_id: ir::BasicBlockRef,
+    pub fun_id: ir::FunctionRef,
+}
+
 #[derive(Debug)]
 pub enum ValidationError {
     Ins(InsError),
     BasicBlock(BasicBlockError),
     Function(FunctionError),
+    Stack(StackError),
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValidationError::Ins(err) => write!(
+                f,
+                "Function {}, basic block {}, instruction {}: {}",
+                err.fun_id, err.bb_id, err.ins_id, err.mess
+            ),
+            ValidationError::BasicBlock(err) => write!(
+                f,
+                "Function {}, basic block {}: {}",
+                err.fun_id, err.bb_id, err.mess
+            ),
+            ValidationError::Function(err) => write!(f, "Function {}: {}", err.fun_id, err.mess),
+            ValidationError::Stack(err) => write!(
+                f,
+                "Function {}, basic block {}, instruction {}: {}",
+                err.fun_id, err.bb_id, err.ins_id, err.kind
+            ),
+        }
+    }
 }
 
 struct ModuleValidation<'a> {
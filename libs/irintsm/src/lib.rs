@@ -1,8 +1,15 @@
 pub mod ir;
+pub mod irbinary;
 pub mod irbuilder;
+pub mod irnames;
 pub mod irparser;
 pub mod irprinter;
 pub mod irvalidation;
+pub mod peephole;
+
+pub mod controlflow;
+pub mod digraph;
+pub mod liveness;
 
 #[cfg(test)]
 mod tests {
@@ -31,6 +38,28 @@ mod tests {
         assert_eq!(code_str, code2_str);
     }
 
+    fn test_lexer_printer_names(path: &str) {
+        use crate::irprinter::CodePrintable;
+
+        let ps = irparser::Parser::from_file(path);
+        let (code, names) = ps.build_with_names();
+
+        let mut code_str: Vec<u8> = vec![];
+        code.print_code_with_names(&mut code_str, &names);
+        let code_str = std::str::from_utf8(&code_str).unwrap();
+        println!("CODE1: <BEG>{}<END>", code_str);
+
+        let ps2 = irparser::Parser::from_str(code_str);
+        let (code, names) = ps2.build_with_names();
+
+        let mut code2_str: Vec<u8> = vec![];
+        code.print_code_with_names(&mut code2_str, &names);
+        let code2_str = std::str::from_utf8(&code2_str).unwrap();
+        println!("CODE2: <BEG>{}<END>", code2_str);
+
+        assert_eq!(code_str, code2_str);
+    }
+
     #[test]
     fn lexer_printer_fn_add() {
         test_lexer_printer("./tests/fn_add.ir");
@@ -45,4 +74,465 @@ mod tests {
     fn lexer_printer_hello_42() {
         test_lexer_printer("./tests/hello_42.ir");
     }
+
+    #[test]
+    fn lexer_printer_bitops() {
+        // bitops.ir has a negative constant
+        test_lexer_printer("./tests/bitops.ir");
+        let code = irparser::Parser::from_file("./tests/bitops.ir").build();
+        let fun = code.get_fun(ir::FunctionRef::new(0));
+        assert!(matches!(fun.bb_list()[0].ins_list()[0], ir::Ins::Const(ins) if ins.val() == -1));
+    }
+
+    #[test]
+    fn lexer_printer_names_fn_fact() {
+        test_lexer_printer_names("./tests/fn_fact_names.ir");
+        test_lexer_printer_names("./tests/fn_fact.ir");
+        test_lexer_printer("./tests/fn_fact_names.ir");
+    }
+
+    #[test]
+    fn names_fn_fact() {
+        use crate::irprinter::CodePrintable;
+
+        let (code, names) =
+            irparser::Parser::from_file("./tests/fn_fact_names.ir").build_with_names();
+        let fact = names.get_function_id("fact").unwrap();
+        assert_eq!(fact, ir::FunctionRef::new(1));
+        assert_eq!(
+            names.get_function_name(ir::FunctionRef::new(257)),
+            Some("putc")
+        );
+
+        let fact_names = names.get_function(fact).unwrap();
+        let fun = code.get_fun(fact);
+        assert_eq!(
+            fact_names.get_basic_block_id("loop"),
+            Some(fun.bb_list()[1].id())
+        );
+        assert_eq!(
+            fact_names.get_local_id("res"),
+            Some(ir::LocalsIndex::new(1))
+        );
+
+        // without names, everything is printed with numbers
+        let mut code_str: Vec<u8> = vec![];
+        code.print_code(&mut code_str);
+        let code_str = std::str::from_utf8(&code_str).unwrap();
+        assert!(code_str.contains("call %1, 1"));
+        assert!(code_str.contains("br %3, %2"));
+        assert!(!code_str.contains(".locals"));
+
+        let mut code_str: Vec<u8> = vec![];
+        code.print_code_with_names(&mut code_str, &names);
+        let code_str = std::str::from_utf8(&code_str).unwrap();
+        assert!(code_str.contains(".define 1 fact\n.locals n res\nentry:\n"));
+        assert!(code_str.contains("call %putc, 1"));
+        assert!(code_str.contains("br %end, %body"));
+        assert!(code_str.contains("store res"));
+    }
+
+    #[test]
+    fn names_locals_gaps() {
+        use crate::irprinter::CodePrintable;
+
+        let mut builder = irbuilder::IRBuilder::new();
+        let fun = builder.create_function(None);
+        let bb = builder.create_basic_block(fun);
+        builder.set_insert_point(bb);
+        builder.set_local_name(fun, ir::LocalsIndex::new(0), "l1".to_string());
+        builder.set_local_name(fun, ir::LocalsIndex::new(2), "x".to_string());
+        builder.ins_load(ir::LocalsIndex::new(1));
+        builder.ins_load(ir::LocalsIndex::new(3));
+        builder.ins_add();
+        builder.ins_ret();
+        let (code, names) = builder.finish_with_names();
+
+        let mut code_str: Vec<u8> = vec![];
+        code.print_code_with_names(&mut code_str, &names);
+        let code_str = std::str::from_utf8(&code_str).unwrap();
+        assert_eq!(
+            code_str,
+            ".define 0\n.locals l1 l1_ x\n0:\n  load l1_\n  load 3\n  add\n  ret\n\n\n"
+        );
+
+        let (code2, names2) = irparser::Parser::from_str(code_str).build_with_names();
+        let mut code2_str: Vec<u8> = vec![];
+        code2.print_code_with_names(&mut code2_str, &names2);
+        assert_eq!(code_str, std::str::from_utf8(&code2_str).unwrap());
+    }
+
+    // text -> binary -> text must give the same code
+    fn test_binary_roundtrip(code: &ir::Module) {
+        use crate::irprinter::CodePrintable;
+
+        let mut code_str: Vec<u8> = vec![];
+        code.print_code(&mut code_str);
+
+        let bin = irbinary::write_module(code);
+        let code2 = irbinary::read_module(&bin).unwrap();
+        let mut code2_str: Vec<u8> = vec![];
+        code2.print_code(&mut code2_str);
+
+        assert_eq!(
+            std::str::from_utf8(&code_str).unwrap(),
+            std::str::from_utf8(&code2_str).unwrap()
+        );
+        assert_eq!(irbinary::write_module(&code2), bin);
+    }
+
+    // Generate a random valid module (the operands stack is not valid)
+    fn gen_random_module(seed: u64) -> ir::Module {
+        let mut state = seed
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        let mut rand = |n: u64| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state % n
+        };
+
+        let mut builder = irbuilder::IRBuilder::new();
+        let nb_funs = 1 + rand(4) as usize;
+        let externs = [257, 258, 1 << 20];
+        for id in &externs {
+            builder.create_function(Some(ir::FunctionRef::new(*id)));
+        }
+        let funs: Vec<_> = (0..nb_funs)
+            .map(|_| builder.create_function(None))
+            .collect();
+        for fun in funs.iter().copied() {
+            let bbs: Vec<_> = (0..1 + rand(6))
+                .map(|_| builder.create_basic_block(fun))
+                .collect();
+            for bb in &bbs {
+                builder.set_insert_point(*bb);
+                for _ in 0..rand(12) {
+                    match rand(11) {
+                        0 => builder.ins_pop(),
+                        1 => builder.ins_const(match rand(4) {
+                            0 => i32::MIN,
+                            1 => i32::MAX,
+                            2 => -(rand(200) as i32),
+                            _ => rand(1 << 31) as i32,
+                        }),
+                        2 => builder.ins_load(ir::LocalsIndex::new(rand(300) as usize)),
+                        3 => builder.ins_store(ir::LocalsIndex::new(rand(300) as usize)),
+                        4 => builder.ins_add(),
+                        5 => builder.ins_rem(),
+                        6 => builder.ins_cmplt(),
+                        7 => builder.ins_sar(),
+                        8 => builder.ins_cmpuge(),
+                        9 => builder.ins_call(
+                            ir::FunctionRef::new(externs[rand(3) as usize]),
+                            rand(200) as usize,
+                        ),
+                        _ => builder.ins_call(funs[rand(nb_funs as u64) as usize], 1),
+                    }
+                }
+                match rand(3) {
+                    0 => builder.ins_ret(),
+                    1 => builder.ins_jump(bbs[rand(bbs.len() as u64) as usize]),
+                    _ => builder.ins_br(
+                        bbs[rand(bbs.len() as u64) as usize],
+                        bbs[rand(bbs.len() as u64) as usize],
+                    ),
+                }
+            }
+        }
+        builder.finish()
+    }
+
+    #[test]
+    fn binary_roundtrip_fixtures() {
+        for path in &[
+            "./tests/fn_add.ir",
+            "./tests/fn_fact.ir",
+            "./tests/fn_fact_names.ir",
+            "./tests/hello_42.ir",
+            "./tests/bitops.ir",
+        ] {
+            test_binary_roundtrip(&irparser::Parser::from_file(path).build());
+        }
+    }
+
+    #[test]
+    fn binary_roundtrip_random() {
+        for seed in 0..200 {
+            test_binary_roundtrip(&gen_random_module(seed));
+        }
+    }
+
+    #[test]
+    fn binary_errors() {
+        use irbinary::BinaryError;
+
+        let code = irparser::Parser::from_file("./tests/fn_fact.ir").build();
+        let bin = irbinary::write_module(&code);
+        assert_eq!(&bin[..5], b"IRSM\x01");
+
+        let read = |bytes: &[u8]| irbinary::read_module(bytes).err().unwrap();
+        assert!(matches!(read(b"IRS"), BinaryError::BadMagic));
+        assert!(matches!(read(b"IRSM\x07"), BinaryError::BadVersion(7)));
+        assert!(matches!(
+            read(&bin[..bin.len() - 1]),
+            BinaryError::UnexpectedEof
+        ));
+
+        let mut trailing = bin.clone();
+        trailing.push(0);
+        assert!(matches!(
+            read(&trailing),
+            BinaryError::TrailingBytes { offset } if offset == bin.len()
+        ));
+
+        // fun 0 is the first function of the table, its kind is at offset 7
+        let mut bad = bin.clone();
+        bad[7] = 2;
+        assert!(matches!(
+            read(&bad),
+            BinaryError::BadFunctionKind { offset: 7, kind: 2 }
+        ));
+
+        // fun 0 code: 1 bb, 2 instructions (const 0, ret)
+        let code_start = 5 + 1 + 2 * 2;
+        assert_eq!(&bin[code_start..code_start + 5], &[1, 2, 0x02, 0, 0x33]);
+        let mut bad = bin.clone();
+        bad[code_start + 4] = 0xff;
+        assert!(matches!(
+            read(&bad),
+            BinaryError::BadOpcode { opcode: 0xff, .. }
+        ));
+        let mut bad = bin.clone();
+        bad[code_start + 4] = 0x30; // jump to bb 0x33
+        let mut bad = bad[..code_start + 5].to_vec();
+        bad.push(7);
+        bad.extend_from_slice(&bin[code_start + 5..]);
+        assert!(matches!(
+            read(&bad),
+            BinaryError::BadBasicBlock { index: 7, .. }
+        ));
+
+        // const with more than 32 bits
+        let mut bad = bin[..code_start + 3].to_vec();
+        bad.extend_from_slice(&[0x80, 0x80, 0x80, 0x80, 0x10]);
+        bad.extend_from_slice(&bin[code_start + 4..]);
+        assert!(matches!(read(&bad), BinaryError::BadLeb128 { .. }));
+
+        // bb without control flow instruction
+        let mut bad = bin.clone();
+        bad[code_start + 1] = 1;
+        bad.remove(code_start + 4);
+        assert!(matches!(read(&bad), BinaryError::Validation(_)));
+    }
+
+    #[test]
+    fn stack_valid_fn_fact() {
+        let code = irparser::Parser::from_file("./tests/fn_fact.ir").build();
+        let (infos, errs) = irvalidation::validate_module_stack(&code);
+        assert!(errs.is_empty(), "{:?}", errs);
+
+        let fun = code.get_fun(ir::FunctionRef::new(1));
+        let infos = &infos[&fun.id()];
+        assert_eq!(infos.max_depth, 3);
+        assert_eq!(infos.entry_heights[&fun.bb_list()[3].id()], 1);
+    }
+
+    #[test]
+    fn cfg_fn_fact() {
+        let code = irparser::Parser::from_file("./tests/fn_fact.ir").build();
+        let fun = code.get_fun(ir::FunctionRef::new(1));
+        let cfg = controlflow::build_cfg(fun);
+        assert_eq!(cfg.vcount(), 4);
+        assert_eq!(cfg.ecount(), 4);
+        assert_eq!(cfg.adj(0).copied().collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(cfg.adj(1).copied().collect::<Vec<_>>(), vec![3]);
+        assert_eq!(cfg.adj(2).copied().collect::<Vec<_>>(), vec![3]);
+        assert_eq!(cfg.adj(3).count(), 0);
+    }
+
+    #[test]
+    fn liveness_dead_stores() {
+        let code = irparser::Parser::from_str(
+            "
+.define 0
+.locals n i sum tmp
+entry:
+  const 0
+  store sum
+  const 0
+  store i
+  const 5
+  store tmp
+  jump %cond
+cond:
+  load i
+  load n
+  cmplt
+  br %body, %end
+body:
+  load sum
+  load i
+  add
+  store sum
+  load i
+  const 1
+  add
+  store i
+  const 7
+  store tmp
+  jump %cond
+end:
+  load sum
+  const 1
+  store n
+  ret
+",
+        )
+        .build();
+        let fun = code.get_fun(ir::FunctionRef::new(0));
+        let bbs = fun.bb_list();
+        let local = ir::LocalsIndex::new;
+
+        let liveness = liveness::compute_liveness(fun);
+        let live_in = &liveness.live_in;
+        assert_eq!(live_in[&bbs[0].id()], [local(0)].iter().copied().collect());
+        assert_eq!(
+            live_in[&bbs[1].id()],
+            [local(0), local(1), local(2)].iter().copied().collect()
+        );
+        assert!(liveness.live_out[&bbs[3].id()].is_empty());
+
+        let dead = liveness::find_dead_stores(fun);
+        let dead: Vec<_> = dead
+            .iter()
+            .map(|st| (st.bb_id, st.ins_id, st.local))
+            .collect();
+        assert_eq!(
+            dead,
+            vec![
+                (bbs[0].id(), 5, local(3)),
+                (bbs[2].id(), 9, local(3)),
+                (bbs[3].id(), 2, local(0)),
+            ]
+        );
+        assert_eq!(liveness::find_module_dead_stores(&code).len(), 3);
+    }
+
+    #[test]
+    fn stack_errors() {
+        use irvalidation::{StackErrorKind, ValidationError};
+
+        let code = irparser::Parser::from_str(
+            "
+.define 0
+0:
+  const 1
+  br %1, %2
+1:
+  const 2
+  const 3
+  jump %3
+2:
+  const 4
+  jump %3
+3:
+  ret
+
+.define 1
+0:
+  const 1
+  add
+  ret
+
+.define 2
+0:
+  const 1
+  const 2
+  ret
+",
+        )
+        .build();
+        let (infos, errs) = irvalidation::validate_module_stack(&code);
+        let kinds: Vec<_> = errs
+            .iter()
+            .map(|err| match err {
+                ValidationError::Stack(err) => err.kind,
+                _ => unreachable!(),
+            })
+            .collect();
+
+        let fun = code.get_fun(ir::FunctionRef::new(0));
+        assert_eq!(kinds.len(), 3, "{:?}", kinds);
+        assert!(kinds.contains(&StackErrorKind::HeightMismatch {
+            dst: fun.bb_list()[3].id(),
+            expected: 1,
+            actual: 2,
+        }));
+        assert!(kinds.contains(&StackErrorKind::Underflow { height: 1, pops: 2 }));
+        assert!(kinds.contains(&StackErrorKind::RetOperands(2)));
+        assert_eq!(infos[&fun.id()].max_depth, 2);
+    }
+
+    #[test]
+    fn peephole_rules() {
+        let code = irparser::Parser::from_str(
+            "
+.declare 257
+
+.define 0
+0:
+  const 2
+  const 3
+  mul
+  const 0
+  add
+  store 0
+  load 0
+  const 7
+  pop
+  call %257, 1
+  pop
+  load 1
+  const 1
+  div
+  store 1
+  jump %1
+1:
+  load 1
+  ret
+",
+        )
+        .build();
+        let (code, stats) = peephole::optimize_module(&code);
+        assert!(irvalidation::validate_module(&code).is_empty());
+
+        let fun = code.get_fun(ir::FunctionRef::new(0));
+        assert_eq!(fun.bb_list().len(), 1);
+        let ins: Vec<_> = fun.bb_list()[0]
+            .ins_list()
+            .iter()
+            .map(|ins| format!("{:?}", ins))
+            .collect();
+        assert_eq!(
+            ins,
+            vec![
+                "Const(InsConst { val: 6 })",
+                "Call(InsCall { fun: FunctionRef(257), nb_args: 1 })",
+                "Pop(InsPop)",
+                "Load(InsLoad { src: LocalsIndex(1) })",
+                "Store(InsStore { dst: LocalsIndex(1) })",
+                "Load(InsLoad { src: LocalsIndex(1) })",
+                "Ret(InsRet)",
+            ]
+        );
+        // const 6; const 0; add is folded before the identity rule is tried
+        assert_eq!(stats.get("const_fold"), 2);
+        assert_eq!(stats.get("identity"), 1);
+        assert_eq!(stats.get("store_load"), 1);
+        assert_eq!(stats.get("push_pop"), 1);
+        assert_eq!(stats.get("jump_merge"), 1);
+        assert_eq!(stats.total(), 6);
+    }
 }
@@ -0,0 +1,126 @@
+// Liveness analysis of the locals of a function
+//
+// A local is live at some point if its value may be read by a load later, before any store to it.
+// Computed with a backward dataflow analysis on the CFG:
+// live_out(b) = U live_in(s), for all successors s of b
+// live_in(b) = uses(b) U (live_out(b) - defs(b))
+// uses(b) are the locals loaded in b before any store, and defs(b) the locals stored in b
+//
+// The arguments are the first locals: they are live at the entry of the function if they are read.
+// A store is dead when the local isn't live right after it.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::controlflow;
+use crate::ir;
+
+/// Live locals at the entry and at the exit of every basic block of a function
+#[derive(Debug)]
+pub struct LocalsLiveness {
+    pub live_in: HashMap<ir::BasicBlockRef, HashSet<ir::LocalsIndex>>,
+    pub live_out: HashMap<ir::BasicBlockRef, HashSet<ir::LocalsIndex>>,
+}
+
+/// A store instruction whose value is never loaded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeadStore {
+    pub fun_id: ir::FunctionRef,
+    pub bb_id: ir::BasicBlockRef,
+    pub ins_id: usize,
+    pub local: ir::LocalsIndex,
+}
+
+// Compute the locals used before being defined, and the locals defined, in a basic block
+fn uses_defs(bb: &ir::BasicBlock) -> (HashSet<ir::LocalsIndex>, HashSet<ir::LocalsIndex>) {
+    let mut uses = HashSet::new();
+    let mut defs = HashSet::new();
+    for ins in bb.ins_list() {
+        match ins {
+            ir::Ins::Load(ins) if !defs.contains(&ins.src()) => {
+                uses.insert(ins.src());
+            }
+            ir::Ins::Store(ins) => {
+                defs.insert(ins.dst());
+            }
+            _ => {}
+        }
+    }
+    (uses, defs)
+}
+
+/// Compute the live locals at the entry and exit of every basic block of a defined function
+pub fn compute_liveness(fun: &ir::Function) -> LocalsLiveness {
+    let bbs = fun.bb_list();
+    let cfg = controlflow::build_cfg(fun);
+    let uses_defs: Vec<_> = bbs.iter().map(uses_defs).collect();
+    let mut live_in = vec![HashSet::new(); bbs.len()];
+    let mut live_out = vec![HashSet::new(); bbs.len()];
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        // backward analysis: visiting the blocks in reverse order converges faster
+        for v in (0..bbs.len()).rev() {
+            let mut out = HashSet::new();
+            for w in cfg.adj(v) {
+                out.extend(live_in[*w].iter().copied());
+            }
+
+            let (uses, defs) = &uses_defs[v];
+            let mut new_in: HashSet<_> = out.difference(defs).copied().collect();
+            new_in.extend(uses.iter().copied());
+
+            if new_in != live_in[v] || out != live_out[v] {
+                live_in[v] = new_in;
+                live_out[v] = out;
+                changed = true;
+            }
+        }
+    }
+
+    LocalsLiveness {
+        live_in: bbs.iter().map(|bb| bb.id()).zip(live_in).collect(),
+        live_out: bbs.iter().map(|bb| bb.id()).zip(live_out).collect(),
+    }
+}
+
+/// Find all stores of a defined function whose value is never loaded
+/// They are returned in the order of the code
+pub fn find_dead_stores(fun: &ir::Function) -> Vec<DeadStore> {
+    let liveness = compute_liveness(fun);
+    let mut res = vec![];
+
+    for bb in fun.bb_list() {
+        let mut live = liveness.live_out[&bb.id()].clone();
+        let mut bb_res = vec![];
+        for (ins_id, ins) in bb.ins_list().iter().enumerate().rev() {
+            match ins {
+                ir::Ins::Load(ins) => {
+                    live.insert(ins.src());
+                }
+                ir::Ins::Store(ins) if !live.remove(&ins.dst()) => {
+                    bb_res.push(DeadStore {
+                        fun_id: fun.id(),
+                        bb_id: bb.id(),
+                        ins_id,
+                        local: ins.dst(),
+                    });
+                }
+                _ => {}
+            }
+        }
+        res.extend(bb_res.into_iter().rev());
+    }
+
+    res
+}
+
+/// Find the dead stores of all defined functions of a module
+pub fn find_module_dead_stores(module: &ir::Module) -> Vec<DeadStore> {
+    module
+        .fun_list()
+        .iter()
+        .filter(|fun| !fun.is_extern())
+        .flat_map(find_dead_stores)
+        .collect()
+}
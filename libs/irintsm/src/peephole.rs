@@ -0,0 +1,330 @@
+// Peephole optimizer: replace small patterns of instructions by shorter code
+//
+// Every rule matches a fixed number of consecutive instructions in a basic block,
+// and returns the replacement code, or None if it doesn't apply.
+// The rules are tried at every position, until none of them applies anymore.
+//
+// Every replacement must keep the same effect on the operands stack:
+// - same size change (see OperandsSizeEffect)
+// - it must not pop deeper into the stack than the matched code
+// This is checked every time a rule is applied, and panics if it's not the case.
+//
+// Rules (see RULES):
+// - store_load: `store x; load x` when it's the only load of x in the function
+// - const_fold: `const a; const b; <opbin / cmpbin>` => `const <res>`
+// - identity: `const 0; <add / sub / or / xor / shl / shr / sar>`, `const 1; <mul / div>` => nothing
+// - push_pop: `const a; pop`, `load x; pop` => nothing
+//
+// There is also one rule on the control flow graph:
+// - jump_merge: a basic block ending with `jump %b`, where b has no other predecessor,
+//   is merged with b (every basic block must end with a control flow instruction,
+//   so a jump to the next block can't just be removed)
+
+use std::collections::HashMap;
+
+use crate::ir;
+use crate::ir::OperandsSizeEffect;
+
+// Infos about the whole function, used by the rules
+// Only valid at the beginning of an iteration, but the rules never add loads,
+// so the counts can only be greater than the real ones
+struct FunctionInfos {
+    loads: HashMap<ir::LocalsIndex, usize>,
+}
+
+impl FunctionInfos {
+    fn new(bbs: &[ir::BasicBlock]) -> Self {
+        let mut loads = HashMap::new();
+        for ins in bbs.iter().flat_map(|bb| bb.ins_list().iter()) {
+            if let ir::Ins::Load(ins) = ins {
+                *loads.entry(ins.src()).or_insert(0) += 1;
+            }
+        }
+        FunctionInfos { loads }
+    }
+}
+
+struct Rule {
+    name: &'static str,
+    len: usize,
+    apply: fn(&[ir::Ins], &FunctionInfos) -> Option<Vec<ir::Ins>>,
+}
+
+const RULES: [Rule; 4] = [
+    Rule {
+        name: "store_load",
+        len: 2,
+        apply: rule_store_load,
+    },
+    Rule {
+        name: "const_fold",
+        len: 3,
+        apply: rule_const_fold,
+    },
+    Rule {
+        name: "identity",
+        len: 2,
+        apply: rule_identity,
+    },
+    Rule {
+        name: "push_pop",
+        len: 2,
+        apply: rule_push_pop,
+    },
+];
+
+const RULE_JUMP_MERGE: &str = "jump_merge";
+
+fn rule_store_load(code: &[ir::Ins], infos: &FunctionInfos) -> Option<Vec<ir::Ins>> {
+    match (code[0], code[1]) {
+        (ir::Ins::Store(st), ir::Ins::Load(ld))
+            if st.dst() == ld.src() && infos.loads[&ld.src()] == 1 =>
+        {
+            Some(vec![])
+        }
+        _ => None,
+    }
+}
+
+fn rule_const_fold(code: &[ir::Ins], _infos: &FunctionInfos) -> Option<Vec<ir::Ins>> {
+    let (a, b) = match (code[0], code[1]) {
+        (ir::Ins::Const(a), ir::Ins::Const(b)) => (a.val(), b.val()),
+        _ => return None,
+    };
+
+    // same results than the interpreter (wrapping operations)
+    // division by 0 and overflow are not folded, to keep the runtime error
+    let res = match code[2] {
+        ir::Ins::Opbin(ir::InsOpbin::Add) => a.wrapping_add(b),
+        ir::Ins::Opbin(ir::InsOpbin::Sub) => a.wrapping_sub(b),
+        ir::Ins::Opbin(ir::InsOpbin::Mul) => a.wrapping_mul(b),
+        ir::Ins::Opbin(ir::InsOpbin::Div) => a.checked_div(b)?,
+        ir::Ins::Opbin(ir::InsOpbin::Rem) => a.checked_rem(b)?,
+        ir::Ins::Opbin(ir::InsOpbin::And) => a & b,
+        ir::Ins::Opbin(ir::InsOpbin::Or) => a | b,
+        ir::Ins::Opbin(ir::InsOpbin::Xor) => a ^ b,
+        ir::Ins::Opbin(ir::InsOpbin::Shl) => a.wrapping_shl(b as u32 & 31),
+        ir::Ins::Opbin(ir::InsOpbin::Shr) => ((a as u32) >> (b & 31)) as i32,
+        ir::Ins::Opbin(ir::InsOpbin::Sar) => a.wrapping_shr(b as u32 & 31),
+        ir::Ins::Cmpbin(ir::InsCmpbin::Eq) => (a == b) as i32,
+        ir::Ins::Cmpbin(ir::InsCmpbin::Ne) => (a != b) as i32,
+        ir::Ins::Cmpbin(ir::InsCmpbin::Lt) => (a < b) as i32,
+        ir::Ins::Cmpbin(ir::InsCmpbin::Le) => (a <= b) as i32,
+        ir::Ins::Cmpbin(ir::InsCmpbin::Gt) => (a > b) as i32,
+        ir::Ins::Cmpbin(ir::InsCmpbin::Ge) => (a >= b) as i32,
+        ir::Ins::Cmpbin(ir::InsCmpbin::Ult) => ((a as u32) < (b as u32)) as i32,
+        ir::Ins::Cmpbin(ir::InsCmpbin::Ule) => ((a as u32) <= (b as u32)) as i32,
+        ir::Ins::Cmpbin(ir::InsCmpbin::Ugt) => ((a as u32) > (b as u32)) as i32,
+        ir::Ins::Cmpbin(ir::InsCmpbin::Uge) => ((a as u32) >= (b as u32)) as i32,
+        _ => return None,
+    };
+    Some(vec![ir::Ins::Const(ir::InsConst::new(res))])
+}
+
+fn rule_identity(code: &[ir::Ins], _infos: &FunctionInfos) -> Option<Vec<ir::Ins>> {
+    match (code[0], code[1]) {
+        (ir::Ins::Const(c), ir::Ins::Opbin(ir::InsOpbin::Add))
+        | (ir::Ins::Const(c), ir::Ins::Opbin(ir::InsOpbin::Sub))
+        | (ir::Ins::Const(c), ir::Ins::Opbin(ir::InsOpbin::Or))
+        | (ir::Ins::Const(c), ir::Ins::Opbin(ir::InsOpbin::Xor))
+        | (ir::Ins::Const(c), ir::Ins::Opbin(ir::InsOpbin::Shl))
+        | (ir::Ins::Const(c), ir::Ins::Opbin(ir::InsOpbin::Shr))
+        | (ir::Ins::Const(c), ir::Ins::Opbin(ir::InsOpbin::Sar))
+            if c.val() == 0 =>
+        {
+            Some(vec![])
+        }
+        (ir::Ins::Const(c), ir::Ins::Opbin(ir::InsOpbin::Mul))
+        | (ir::Ins::Const(c), ir::Ins::Opbin(ir::InsOpbin::Div))
+            if c.val() == 1 =>
+        {
+            Some(vec![])
+        }
+        _ => None,
+    }
+}
+
+fn rule_push_pop(code: &[ir::Ins], _infos: &FunctionInfos) -> Option<Vec<ir::Ins>> {
+    match (code[0], code[1]) {
+        (ir::Ins::Const(_), ir::Ins::Pop(_)) | (ir::Ins::Load(_), ir::Ins::Pop(_)) => Some(vec![]),
+        _ => None,
+    }
+}
+
+// Returns the size change of the operands stack, and the lowest height reached (relative to the start)
+fn stack_effect(code: &[ir::Ins]) -> (i32, i32) {
+    let mut height = 0;
+    let mut lowest = 0;
+    for ins in code {
+        lowest = lowest.min(height - ins.operands_pops() as i32);
+        height += ins.operands_size_change();
+    }
+    (height, lowest)
+}
+
+fn check_stack_effect(rule: &str, old: &[ir::Ins], new: &[ir::Ins]) {
+    let (old_change, old_lowest) = stack_effect(old);
+    let (new_change, new_lowest) = stack_effect(new);
+    if old_change != new_change || new_lowest < old_lowest {
+        panic!(
+            "Peephole rule {} changed the operands stack effect: {:?} => {:?}",
+            rule, old, new
+        );
+    }
+}
+
+/// Number of times each rule was applied
+#[derive(Debug, Clone)]
+pub struct PeepholeStats {
+    counts: Vec<(&'static str, usize)>,
+}
+
+impl PeepholeStats {
+    fn new() -> Self {
+        let mut counts: Vec<_> = RULES.iter().map(|rule| (rule.name, 0)).collect();
+        counts.push((RULE_JUMP_MERGE, 0));
+        PeepholeStats { counts }
+    }
+
+    fn incr(&mut self, name: &str) {
+        let entry = self.counts.iter_mut().find(|(n, _)| *n == name).unwrap();
+        entry.1 += 1;
+    }
+
+    /// Returns the name and count of every rule, in the order of the rules table
+    pub fn counts(&self) -> &[(&'static str, usize)] {
+        &self.counts
+    }
+
+    /// Returns how many times a rule was applied
+    pub fn get(&self, name: &str) -> usize {
+        self.counts
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, count)| *count)
+            .unwrap_or(0)
+    }
+
+    /// Returns the total number of rules applied
+    pub fn total(&self) -> usize {
+        self.counts.iter().map(|(_, count)| count).sum()
+    }
+}
+
+// Apply the rules once at every position of the instructions list
+// Returns true if anything changed
+fn optimize_code(code: &mut Vec<ir::Ins>, infos: &FunctionInfos, stats: &mut PeepholeStats) -> bool {
+    let mut changed = false;
+    let mut pos = 0;
+    while pos < code.len() {
+        let mut applied = false;
+        for rule in &RULES {
+            if pos + rule.len > code.len() {
+                continue;
+            }
+            let old = &code[pos..pos + rule.len];
+            if let Some(new) = (rule.apply)(old, infos) {
+                check_stack_effect(rule.name, old, &new);
+                code.splice(pos..pos + rule.len, new);
+                stats.incr(rule.name);
+                applied = true;
+                break;
+            }
+        }
+
+        if applied {
+            // the new code may match a pattern starting a little before
+            changed = true;
+            pos = pos.saturating_sub(2);
+        } else {
+            pos += 1;
+        }
+    }
+    changed
+}
+
+// Merge a basic block ending with jump with its destination, if it's the only predecessor
+// Returns true if a merge was done
+fn merge_jump(bbs: &mut Vec<ir::BasicBlock>, stats: &mut PeepholeStats) -> bool {
+    let mut preds: HashMap<ir::BasicBlockRef, usize> = HashMap::new();
+    for bb in bbs.iter() {
+        match bb.ins_list().last() {
+            Some(ir::Ins::Jump(ins)) => *preds.entry(ins.dst()).or_insert(0) += 1,
+            Some(ir::Ins::Br(ins)) => {
+                *preds.entry(ins.dst_true()).or_insert(0) += 1;
+                *preds.entry(ins.dst_false()).or_insert(0) += 1;
+            }
+            _ => {}
+        }
+    }
+
+    for src_idx in 0..bbs.len() {
+        let dst = match bbs[src_idx].ins_list().last() {
+            Some(ir::Ins::Jump(ins)) => ins.dst(),
+            _ => continue,
+        };
+        // the entry point has an implicit predecessor
+        if preds[&dst] != 1 || dst == bbs[0].id() || dst == bbs[src_idx].id() {
+            continue;
+        }
+
+        let dst_idx = bbs.iter().position(|bb| bb.id() == dst).unwrap();
+        let dst_bb = bbs.remove(dst_idx);
+        let src_idx = if dst_idx < src_idx { src_idx - 1 } else { src_idx };
+        let src_bb = &bbs[src_idx];
+
+        let mut code = src_bb.ins_list().to_vec();
+        code.pop();
+        code.extend_from_slice(dst_bb.ins_list());
+        bbs[src_idx] = ir::BasicBlock::new(src_bb.id(), code);
+        stats.incr(RULE_JUMP_MERGE);
+        return true;
+    }
+    false
+}
+
+fn optimize_function(fun: &ir::Function, stats: &mut PeepholeStats) -> ir::Function {
+    if fun.is_extern() {
+        return ir::Function::new(fun.id(), None);
+    }
+
+    let mut bbs: Vec<_> = fun
+        .bb_list()
+        .iter()
+        .map(|bb| ir::BasicBlock::new(bb.id(), bb.ins_list().to_vec()))
+        .collect();
+
+    loop {
+        let mut changed = false;
+        while merge_jump(&mut bbs, stats) {
+            changed = true;
+        }
+
+        let infos = FunctionInfos::new(&bbs);
+        for bb in &mut bbs {
+            let mut code = bb.ins_list().to_vec();
+            if optimize_code(&mut code, &infos, stats) {
+                *bb = ir::BasicBlock::new(bb.id(), code);
+                changed = true;
+            }
+        }
+
+        if !changed {
+            break;
+        }
+    }
+
+    ir::Function::new(fun.id(), Some(bbs))
+}
+
+/// Run the peephole optimizer on all functions of the module
+/// Returns the optimized module, and the number of times each rule was applied
+pub fn optimize_module(module: &ir::Module) -> (ir::Module, PeepholeStats) {
+    let mut stats = PeepholeStats::new();
+    let funs = module
+        .fun_list()
+        .iter()
+        .map(|fun| optimize_function(fun, &mut stats))
+        .collect();
+    (ir::Module::new(funs), stats)
+}
@@ -1,4 +1,5 @@
 use obuid;
+use std::collections::HashMap;
 
 #[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
 pub struct ASTUid {
@@ -17,6 +18,40 @@ impl ASTUid {
     }
 }
 
+/// Position of an AST node in the source file, lines and columns start at 1
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ASTLoc {
+    pub line: usize,
+    pub col: usize,
+}
+
+/// Source locations of the AST nodes, filled by the parser
+pub struct ASTLocs {
+    file: String,
+    locs: HashMap<ASTUid, ASTLoc>,
+}
+
+impl ASTLocs {
+    pub fn new(file: &str) -> ASTLocs {
+        ASTLocs {
+            file: file.to_string(),
+            locs: HashMap::new(),
+        }
+    }
+
+    pub fn file(&self) -> &str {
+        &self.file
+    }
+
+    pub fn add(&mut self, uid: ASTUid, loc: ASTLoc) {
+        self.locs.insert(uid, loc);
+    }
+
+    pub fn get(&self, uid: ASTUid) -> Option<ASTLoc> {
+        self.locs.get(&uid).copied()
+    }
+}
+
 pub trait AST {
     fn accept(&self, v: &mut dyn ASTVisitor);
     fn accept_children(&self, v: &mut dyn ASTVisitor);
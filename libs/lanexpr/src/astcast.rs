@@ -44,3 +44,94 @@ pub fn cast_to_expr_id<'a>(node: &'a dyn ast::ASTExpr) -> Option<&'a ast::ASTExp
     let caster = CasterToExprId::new(node);
     caster.run()
 }
+
+struct CasterToExprCall<'a> {
+    node: &'a dyn ast::ASTExpr,
+    res: Option<&'a ast::ASTExprCall>,
+}
+
+impl<'a> CasterToExprCall<'a> {
+    fn new(node: &'a dyn ast::ASTExpr) -> Self {
+        CasterToExprCall { node, res: None }
+    }
+
+    fn run(mut self) -> Option<&'a ast::ASTExprCall> {
+        self.node.accept(&mut self);
+        self.res
+    }
+}
+
+impl<'a> ast::ASTVisitor for CasterToExprCall<'a> {
+    fn visit_def_arg(&mut self, _node: &ast::ASTDefArg) {
+        unreachable!();
+    }
+    fn visit_def_fun(&mut self, _node: &ast::ASTDefFun) {
+        unreachable!();
+    }
+
+    fn visit_def_var(&mut self, _node: &ast::ASTDefVar) {
+        unreachable!();
+    }
+    fn visit_expr_block(&mut self, _node: &ast::ASTExprBlock) {}
+    fn visit_expr_call(&mut self, node: &ast::ASTExprCall) {
+        let node = unsafe { std::mem::transmute::<&ast::ASTExprCall, &'a ast::ASTExprCall>(node) };
+        self.res = Some(node);
+    }
+    fn visit_expr_const(&mut self, _node: &ast::ASTExprConst) {}
+    fn visit_expr_id(&mut self, _node: &ast::ASTExprId) {}
+    fn visit_expr_if(&mut self, _node: &ast::ASTExprIf) {}
+    fn visit_expr_let(&mut self, _node: &ast::ASTExprLet) {}
+    fn visit_expr_while(&mut self, _node: &ast::ASTExprWhile) {}
+    fn visit_type_name(&mut self, _node: &ast::ASTTypeName) {}
+}
+
+pub fn cast_to_expr_call<'a>(node: &'a dyn ast::ASTExpr) -> Option<&'a ast::ASTExprCall> {
+    let caster = CasterToExprCall::new(node);
+    caster.run()
+}
+
+struct CasterToExprBlock<'a> {
+    node: &'a dyn ast::ASTExpr,
+    res: Option<&'a ast::ASTExprBlock>,
+}
+
+impl<'a> CasterToExprBlock<'a> {
+    fn new(node: &'a dyn ast::ASTExpr) -> Self {
+        CasterToExprBlock { node, res: None }
+    }
+
+    fn run(mut self) -> Option<&'a ast::ASTExprBlock> {
+        self.node.accept(&mut self);
+        self.res
+    }
+}
+
+impl<'a> ast::ASTVisitor for CasterToExprBlock<'a> {
+    fn visit_def_arg(&mut self, _node: &ast::ASTDefArg) {
+        unreachable!();
+    }
+    fn visit_def_fun(&mut self, _node: &ast::ASTDefFun) {
+        unreachable!();
+    }
+
+    fn visit_def_var(&mut self, _node: &ast::ASTDefVar) {
+        unreachable!();
+    }
+    fn visit_expr_block(&mut self, node: &ast::ASTExprBlock) {
+        let node =
+            unsafe { std::mem::transmute::<&ast::ASTExprBlock, &'a ast::ASTExprBlock>(node) };
+        self.res = Some(node);
+    }
+    fn visit_expr_call(&mut self, _node: &ast::ASTExprCall) {}
+    fn visit_expr_const(&mut self, _node: &ast::ASTExprConst) {}
+    fn visit_expr_id(&mut self, _node: &ast::ASTExprId) {}
+    fn visit_expr_if(&mut self, _node: &ast::ASTExprIf) {}
+    fn visit_expr_let(&mut self, _node: &ast::ASTExprLet) {}
+    fn visit_expr_while(&mut self, _node: &ast::ASTExprWhile) {}
+    fn visit_type_name(&mut self, _node: &ast::ASTTypeName) {}
+}
+
+pub fn cast_to_expr_block<'a>(node: &'a dyn ast::ASTExpr) -> Option<&'a ast::ASTExprBlock> {
+    let caster = CasterToExprBlock::new(node);
+    caster.run()
+}
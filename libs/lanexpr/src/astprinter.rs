@@ -0,0 +1,284 @@
+// Pretty-printer: convert an AST back to lanexpr source code
+//
+// The printed code can be parsed again, and gives the same program.
+// Operators are printed with their symbol, and parentheses are only added when required by the precedence.
+// The added parentheses are parsed as a block with one expression, so the AST may have a few more block nodes.
+//
+// An if without else (else is an empty block) is printed without the else part.
+// The then part of an if with else is wrapped in parentheses if it's an if / let / while,
+// otherwise the else would be attached to this inner expression.
+
+use std::io::Write;
+
+use crate::ast::*;
+use crate::nativedefs;
+
+// Precedence levels, the higher binds the tighter (see the parser grammar)
+const LEVEL_EXPR: usize = 0; // if, let, while
+const LEVEL_SET: usize = 1;
+const LEVEL_EQ: usize = 2;
+const LEVEL_CMP: usize = 3;
+const LEVEL_ADD: usize = 4;
+const LEVEL_MUL: usize = 5;
+const LEVEL_UNOP: usize = 6;
+const LEVEL_ATOM: usize = 7;
+
+const INDENT: &str = "  ";
+
+// symbol and level of the binary operators
+fn binop_infos(name: &str) -> Option<(&'static str, usize)> {
+    let ops = [
+        (nativedefs::OP_SET.name(), "=", LEVEL_SET),
+        (nativedefs::OP_EQ.name(), "==", LEVEL_EQ),
+        (nativedefs::OP_LT.name(), "<", LEVEL_CMP),
+        (nativedefs::OP_GT.name(), ">", LEVEL_CMP),
+        (nativedefs::OP_ADD.name(), "+", LEVEL_ADD),
+        (nativedefs::OP_SUB.name(), "-", LEVEL_ADD),
+        (nativedefs::OP_MUL.name(), "*", LEVEL_MUL),
+        (nativedefs::OP_DIV.name(), "/", LEVEL_MUL),
+        (nativedefs::OP_MOD.name(), "%", LEVEL_MUL),
+    ];
+    ops.iter()
+        .find(|(op, _, _)| *op == name)
+        .map(|(_, sym, level)| (*sym, *level))
+}
+
+fn unop_symbol(name: &str) -> Option<&'static str> {
+    if name == nativedefs::OP_NEG.name() {
+        Some("-")
+    } else if name == nativedefs::OP_NOT.name() {
+        Some("!")
+    } else {
+        None
+    }
+}
+
+// check if an expression is an empty block
+struct EmptyBlockCheck {
+    res: bool,
+}
+
+impl ASTVisitor for EmptyBlockCheck {
+    fn visit_def_arg(&mut self, _node: &ASTDefArg) {}
+    fn visit_def_fun(&mut self, _node: &ASTDefFun) {}
+    fn visit_def_var(&mut self, _node: &ASTDefVar) {}
+    fn visit_expr_block(&mut self, node: &ASTExprBlock) {
+        self.res = node.exprs().is_empty();
+    }
+    fn visit_expr_call(&mut self, _node: &ASTExprCall) {}
+    fn visit_expr_const(&mut self, _node: &ASTExprConst) {}
+    fn visit_expr_id(&mut self, _node: &ASTExprId) {}
+    fn visit_expr_if(&mut self, _node: &ASTExprIf) {}
+    fn visit_expr_let(&mut self, _node: &ASTExprLet) {}
+    fn visit_expr_while(&mut self, _node: &ASTExprWhile) {}
+    fn visit_type_name(&mut self, _node: &ASTTypeName) {}
+}
+
+fn is_empty_block(node: &ASTExprPtr) -> bool {
+    let mut check = EmptyBlockCheck { res: false };
+    node.accept(&mut check);
+    check.res
+}
+
+pub struct ASTPrinter {
+    out: String,
+    indent: usize,
+    level: usize, // precedence level of the last printed expression
+}
+
+impl ASTPrinter {
+    pub fn new() -> Self {
+        ASTPrinter {
+            out: String::new(),
+            indent: 0,
+            level: LEVEL_ATOM,
+        }
+    }
+
+    /// Returns the source code of the program
+    pub fn print(mut self, root: &ASTExprPtr) -> String {
+        self.expr(root, LEVEL_EXPR);
+        self.out.push('\n');
+        self.out
+    }
+
+    // print the expression, with parentheses if its level is lower than min_level
+    fn expr(&mut self, node: &ASTExprPtr, min_level: usize) {
+        let beg = self.out.len();
+        node.accept(self);
+        if self.level < min_level {
+            self.out.insert(beg, '(');
+            self.out.push(')');
+        }
+        self.level = LEVEL_ATOM;
+    }
+
+    fn newline(&mut self) {
+        self.out.push('\n');
+        for _ in 0..self.indent {
+            self.out.push_str(INDENT);
+        }
+    }
+
+    fn print_binop(&mut self, node: &ASTExprCall, sym: &str, level: usize) {
+        // '=' is right-associative, all others are left-associative
+        let (left_level, right_level) = match level {
+            LEVEL_SET => (LEVEL_EQ, LEVEL_SET),
+            _ => (level, level + 1),
+        };
+        self.expr(&node.args()[0], left_level);
+        self.out.push_str(&format!(" {} ", sym));
+        self.expr(&node.args()[1], right_level);
+        self.level = level;
+    }
+}
+
+impl Default for ASTPrinter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Print the source code of the program
+pub fn print_ast(root: &ASTExprPtr, os: &mut dyn Write) {
+    let code = ASTPrinter::new().print(root);
+    os.write_all(code.as_bytes())
+        .expect("Failed to write source code");
+}
+
+/// Returns the source code of the program
+pub fn ast_to_string(root: &ASTExprPtr) -> String {
+    ASTPrinter::new().print(root)
+}
+
+impl ASTVisitor for ASTPrinter {
+    fn visit_def_arg(&mut self, node: &ASTDefArg) {
+        self.out.push_str(&format!("{}: ", node.name()));
+        node.ty().accept(self);
+    }
+
+    fn visit_def_fun(&mut self, node: &ASTDefFun) {
+        self.out.push_str(&format!("fun {}(", node.name()));
+        for (idx, arg) in node.args().iter().enumerate() {
+            if idx > 0 {
+                self.out.push_str(", ");
+            }
+            arg.accept(self);
+        }
+        self.out.push_str(") : ");
+        node.ret().accept(self);
+        self.out.push_str(" = ");
+        self.expr(node.body(), LEVEL_EXPR);
+    }
+
+    fn visit_def_var(&mut self, node: &ASTDefVar) {
+        self.out.push_str(&format!("var {} : ", node.name()));
+        match node.ty() {
+            Some(ty) => ty.accept(self),
+            None => panic!("Variable {} without a type can't be printed", node.name()),
+        }
+        self.out.push_str(" = ");
+        self.expr(node.init(), LEVEL_EXPR);
+    }
+
+    fn visit_expr_block(&mut self, node: &ASTExprBlock) {
+        match node.exprs().len() {
+            0 => self.out.push_str("()"),
+            1 => {
+                self.out.push('(');
+                self.expr(&node.exprs()[0], LEVEL_EXPR);
+                self.out.push(')');
+            }
+            len => {
+                self.out.push('(');
+                self.indent += 1;
+                for (idx, expr) in node.exprs().iter().enumerate() {
+                    self.newline();
+                    self.expr(expr, LEVEL_EXPR);
+                    if idx + 1 < len {
+                        self.out.push(';');
+                    }
+                }
+                self.indent -= 1;
+                self.newline();
+                self.out.push(')');
+            }
+        }
+        self.level = LEVEL_ATOM;
+    }
+
+    fn visit_expr_call(&mut self, node: &ASTExprCall) {
+        if let Some((sym, level)) = binop_infos(node.name()) {
+            return self.print_binop(node, sym, level);
+        }
+        if let Some(sym) = unop_symbol(node.name()) {
+            // nested unary operators are wrapped in parentheses to avoid printing '--'
+            self.out.push_str(sym);
+            self.expr(&node.args()[0], LEVEL_ATOM);
+            self.level = LEVEL_UNOP;
+            return;
+        }
+
+        self.out.push_str(&format!("{}(", node.name()));
+        for (idx, arg) in node.args().iter().enumerate() {
+            if idx > 0 {
+                self.out.push_str(", ");
+            }
+            self.expr(arg, LEVEL_EXPR);
+        }
+        self.out.push(')');
+        self.level = LEVEL_ATOM;
+    }
+
+    fn visit_expr_const(&mut self, node: &ASTExprConst) {
+        self.out.push_str(&node.val().to_string());
+        self.level = if node.val() < 0 { LEVEL_UNOP } else { LEVEL_ATOM };
+    }
+
+    fn visit_expr_id(&mut self, node: &ASTExprId) {
+        self.out.push_str(node.name());
+        self.level = LEVEL_ATOM;
+    }
+
+    fn visit_expr_if(&mut self, node: &ASTExprIf) {
+        let has_else = !is_empty_block(node.val_else());
+        self.out.push_str("if ");
+        self.expr(node.cond(), LEVEL_EXPR);
+        self.out.push_str(" then ");
+        self.expr(
+            node.val_if(),
+            if has_else { LEVEL_SET } else { LEVEL_EXPR },
+        );
+        if has_else {
+            self.out.push_str(" else ");
+            self.expr(node.val_else(), LEVEL_EXPR);
+        }
+        self.level = LEVEL_EXPR;
+    }
+
+    fn visit_expr_let(&mut self, node: &ASTExprLet) {
+        self.out.push_str("let");
+        self.indent += 1;
+        for def in node.defs() {
+            self.newline();
+            def.accept(self);
+        }
+        self.indent -= 1;
+        self.newline();
+        self.out.push_str("in ");
+        self.expr(node.val(), LEVEL_EXPR);
+        self.level = LEVEL_EXPR;
+    }
+
+    fn visit_expr_while(&mut self, node: &ASTExprWhile) {
+        self.out.push_str("while ");
+        self.expr(node.cond(), LEVEL_EXPR);
+        self.out.push_str(" do ");
+        self.expr(node.body(), LEVEL_EXPR);
+        self.level = LEVEL_EXPR;
+    }
+
+    fn visit_type_name(&mut self, node: &ASTTypeName) {
+        self.out.push_str(node.name());
+    }
+}
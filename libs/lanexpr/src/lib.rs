@@ -3,9 +3,11 @@ extern crate lazy_static;
 
 pub mod ast;
 pub mod astcast;
+pub mod astprinter;
 pub mod letype;
 pub mod nativedefs;
 pub mod parser;
+pub mod randgen;
 pub mod typecheck;
 
 pub mod bindapp;
@@ -43,10 +45,36 @@ mod tests {
         tc.check(&ast);
         let ba = tc.get_bindings();
 
-        let tr = translater::irint3a::Translater::new(&ast, &ba);
+        let tr = translater::irint3a::Translater::new_with_locations(&ast, &ba, ps.locations());
         let _code = tr.translate();
     }
 
+    // the operands stack of the irintsm code must be balanced (see validate_module_stack)
+    fn check_gen_irintsm_stack(path: &str) {
+        let mut ps = parser::Parser::new_from_file(path);
+        let ast = ps.parse();
+
+        let mut tc = typecheck::TypeCheck::new();
+        tc.check(&ast);
+        let ba = tc.get_bindings();
+
+        let code = translater::irintsmtl::Translater::new(&ast, &ba).translate();
+        let (_infos, errs) = irintsm::irvalidation::validate_module_stack(&code);
+        assert!(errs.is_empty(), "{}: {:?}", path, errs);
+    }
+
+    // print the AST, and check the printed code gives the same code when parsed and printed again
+    fn check_print(path: &str) {
+        let mut ps = parser::Parser::new_from_file(path);
+        let code = astprinter::ast_to_string(&ps.parse());
+        let mut ps = parser::Parser::new_from_str(&code);
+        let ast = ps.parse();
+        assert_eq!(astprinter::ast_to_string(&ast), code);
+
+        let mut tc = typecheck::TypeCheck::new();
+        tc.check(&ast);
+    }
+
     fn list_files(dir: &str) -> Vec<String> {
         fs::read_dir(dir)
             .unwrap()
@@ -92,4 +120,103 @@ mod tests {
             check_gen_irint3a(&f);
         }
     }
+
+    #[test]
+    fn test_gen_irintsm_stack() {
+        for dir in &["basics", "algos1", "algos2", "algos3"] {
+            for f in list_files(&format!("./tests/{}/", dir)) {
+                println!("running {}...", f);
+                check_gen_irintsm_stack(&f);
+            }
+        }
+    }
+
+    #[test]
+    fn test_printer_basics() {
+        let files = list_files("./tests/basics/");
+        for f in files {
+            println!("running {}...", f);
+            check_print(&f);
+        }
+    }
+
+    #[test]
+    fn test_randgen_type() {
+        for seed in 0..50 {
+            let code = randgen::gen_source(seed);
+            assert_eq!(code, randgen::gen_source(seed));
+
+            let mut ps = parser::Parser::new_from_str(&code);
+            let ast = ps.parse();
+            assert_eq!(astprinter::ast_to_string(&ast), code);
+            let mut tc = typecheck::TypeCheck::new();
+            tc.check(&ast);
+        }
+    }
+
+    #[test]
+    fn test_gen_negated_cmp() {
+        use irint3a::irprinter::CodePrintable as _;
+        use irintsm::irprinter::CodePrintable as _;
+
+        let code = "let
+  fun f(a: int, b: int) : int = !(a == b) + !(a < b) * 2 + !(a > b) * 4
+in
+  putc(48 + f(1, 2))";
+        let mut ps = parser::Parser::new_from_str(code);
+        let ast = ps.parse();
+        let mut tc = typecheck::TypeCheck::new();
+        tc.check(&ast);
+        let ba = tc.get_bindings();
+
+        let (code_3a, _names) = translater::irint3a::Translater::new(&ast, &ba).translate();
+        let mut out: Vec<u8> = vec![];
+        code_3a.print_code(&mut out, None);
+        let out = String::from_utf8(out).unwrap();
+        for ins in &["cmpne", "cmpge", "cmple"] {
+            assert!(out.contains(ins), "irint3a: no {} in\n{}", ins, out);
+        }
+        assert!(!out.contains("cmpeq"));
+
+        let code_sm = translater::irintsmtl::Translater::new(&ast, &ba).translate();
+        let mut out: Vec<u8> = vec![];
+        code_sm.print_code(&mut out);
+        let out = String::from_utf8(out).unwrap();
+        for ins in &["cmpne", "cmpge", "cmple"] {
+            assert!(out.contains(ins), "irintsm: no {} in\n{}", ins, out);
+        }
+        assert!(!out.contains("cmpeq"));
+    }
+
+    #[test]
+    fn test_gen_irint3a_locations() {
+        use irint3a::irprinter::CodePrintable;
+
+        let code = "let
+  fun f(a: int, b: int) : int = a / b
+in
+  putc(48 + f(7, 0))";
+        let mut ps = parser::Parser::new_from_str(code);
+        let ast = ps.parse();
+        let mut tc = typecheck::TypeCheck::new();
+        tc.check(&ast);
+        let ba = tc.get_bindings();
+
+        let tr = translater::irint3a::Translater::new_with_locations(&ast, &ba, ps.locations());
+        let (code_3a, _names) = tr.translate();
+        let mut out: Vec<u8> = vec![];
+        code_3a.print_code(&mut out, None);
+        let out = String::from_utf8(out).unwrap();
+        let ins_loc = |name: &str| {
+            out.lines()
+                .find(|l| l.trim_start().starts_with(name))
+                .and_then(|l| l.split("; !loc ").nth(1))
+                .unwrap_or_else(|| panic!("no location for {} in\n{}", name, out))
+        };
+        assert_eq!(ins_loc("div "), "<string>:2:35");
+        assert_eq!(ins_loc("load %r1"), "<string>:2:37");
+        assert_eq!(ins_loc("movi %r1, 7"), "<string>:4:15");
+        assert_eq!(ins_loc("add "), "<string>:4:11");
+        assert_eq!(ins_loc("call %r0, f1"), "<string>:4:3");
+    }
 }
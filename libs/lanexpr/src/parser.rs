@@ -2,10 +2,12 @@ use crate::ast;
 use crate::astcast;
 use crate::nativedefs;
 
+use oblexer::stream::Position;
 use oblexer::token::Token;
 
 pub struct Parser {
     ps: obparser::parser::Parser,
+    locs: ast::ASTLocs,
 }
 
 impl Parser {
@@ -24,12 +26,14 @@ impl Parser {
     pub fn new_from_file(path: &str) -> Parser {
         Parser {
             ps: obparser::parser::Parser::new_from_file(path, Parser::kws(), Parser::syms()),
+            locs: ast::ASTLocs::new(path),
         }
     }
 
     pub fn new_from_str(path: &str) -> Parser {
         Parser {
             ps: obparser::parser::Parser::new_from_str(path, Parser::kws(), Parser::syms()),
+            locs: ast::ASTLocs::new("<string>"),
         }
     }
 
@@ -37,6 +41,21 @@ impl Parser {
         self.r_file()
     }
 
+    /// Source locations of the nodes of the parsed AST
+    pub fn locations(&self) -> &ast::ASTLocs {
+        &self.locs
+    }
+
+    // Record the source location of a node
+    fn set_loc<T: ast::AST + ?Sized>(&mut self, node: Box<T>, pos: Position) -> Box<T> {
+        let loc = ast::ASTLoc {
+            line: pos.line,
+            col: pos.col,
+        };
+        self.locs.add(node.get_uid(), loc);
+        node
+    }
+
     // file: expr @eof
     fn r_file(&mut self) -> ast::ASTExprPtr {
         let res = self.r_expr();
@@ -59,6 +78,7 @@ impl Parser {
 
     // expr_if: 'if' expr 'then' expr ['else' expr]
     fn r_expr_if(&mut self) -> ast::ASTExprPtr {
+        let pos = self.ps.peek_pos();
         self.ps.eat_keyword("if");
         let cond = self.r_expr();
         self.ps.eat_keyword("then");
@@ -68,11 +88,12 @@ impl Parser {
         } else {
             ast::ASTExprBlock::new(vec![])
         };
-        ast::ASTExprIf::new(cond, if_val, else_val)
+        self.set_loc(ast::ASTExprIf::new(cond, if_val, else_val), pos)
     }
 
     // expr_let: 'let' def* 'in' expr
     fn r_expr_let(&mut self) -> ast::ASTExprPtr {
+        let pos = self.ps.peek_pos();
         self.ps.eat_keyword("let");
         let mut defs = vec![];
         while !self.ps.try_eat_keyword("in") {
@@ -80,16 +101,17 @@ impl Parser {
         }
 
         let val = self.r_expr();
-        ast::ASTExprLet::new(defs, val)
+        self.set_loc(ast::ASTExprLet::new(defs, val), pos)
     }
 
     // expr_while: 'while' expr 'do' expr
     fn r_expr_while(&mut self) -> ast::ASTExprPtr {
+        let pos = self.ps.peek_pos();
         self.ps.eat_keyword("while");
         let cond = self.r_expr();
         self.ps.eat_keyword("do");
         let body = self.r_expr();
-        ast::ASTExprWhile::new(cond, body)
+        self.set_loc(ast::ASTExprWhile::new(cond, body), pos)
     }
 
     // expr_val: expr_v5
@@ -101,19 +123,26 @@ impl Parser {
     //         | expr_v4 '=' expr_v5
     fn r_expr_v5(&mut self) -> ast::ASTExprPtr {
         let left = self.r_expr_v4();
+        let pos = self.ps.peek_pos();
         if !self.ps.try_eat_sym("=") {
             return left;
         }
         let right = self.r_expr_v5();
-        ast::ASTExprCall::new(nativedefs::OP_SET.name().to_string(), vec![left, right])
+        let res = ast::ASTExprCall::new(nativedefs::OP_SET.name().to_string(), vec![left, right]);
+        self.set_loc(res, pos)
     }
 
     // expr_v4: expr_v3 ('==' expr_v3)*
     fn r_expr_v4(&mut self) -> ast::ASTExprPtr {
         let mut res = self.r_expr_v3();
-        while self.ps.try_eat_sym("==") {
+        loop {
+            let pos = self.ps.peek_pos();
+            if !self.ps.try_eat_sym("==") {
+                break;
+            }
             let right = self.r_expr_v3();
             res = ast::ASTExprCall::new(nativedefs::OP_EQ.name().to_string(), vec![res, right]);
+            res = self.set_loc(res, pos);
         }
         res
     }
@@ -133,9 +162,11 @@ impl Parser {
                 break;
             }
             let fname = fname.unwrap();
+            let pos = self.ps.peek_pos();
             self.ps.get_token();
             let right = self.r_expr_v2();
             res = ast::ASTExprCall::new(fname.to_string(), vec![res, right]);
+            res = self.set_loc(res, pos);
         }
 
         res
@@ -156,9 +187,11 @@ impl Parser {
                 break;
             }
             let fname = fname.unwrap();
+            let pos = self.ps.peek_pos();
             self.ps.get_token();
             let right = self.r_expr_v1();
             res = ast::ASTExprCall::new(fname.to_string(), vec![res, right]);
+            res = self.set_loc(res, pos);
         }
 
         res
@@ -180,9 +213,11 @@ impl Parser {
                 break;
             }
             let fname = fname.unwrap();
+            let pos = self.ps.peek_pos();
             self.ps.get_token();
             let right = self.r_expr_vunop();
             res = ast::ASTExprCall::new(fname.to_string(), vec![res, right]);
+            res = self.set_loc(res, pos);
         }
 
         res
@@ -191,6 +226,7 @@ impl Parser {
     // expr_vunop:  expr_vprim
     //            | ('+' | '-' | '!') expr_vunop
     fn r_expr_vunop(&mut self) -> ast::ASTExprPtr {
+        let pos = self.ps.peek_pos();
         match self.ps.peek_token() {
             Token::Symbol(x) if x == "+" => {
                 self.ps.get_token();
@@ -199,18 +235,20 @@ impl Parser {
 
             Token::Symbol(x) if x == "-" => {
                 self.ps.get_token();
-                ast::ASTExprCall::new(
+                let res = ast::ASTExprCall::new(
                     nativedefs::OP_NEG.name().to_string(),
                     vec![self.r_expr_vunop()],
-                )
+                );
+                self.set_loc(res, pos)
             }
 
             Token::Symbol(x) if x == "!" => {
                 self.ps.get_token();
-                ast::ASTExprCall::new(
+                let res = ast::ASTExprCall::new(
                     nativedefs::OP_NOT.name().to_string(),
                     vec![self.r_expr_vunop()],
-                )
+                );
+                self.set_loc(res, pos)
             }
 
             _ => self.r_expr_vprim(),
@@ -220,6 +258,7 @@ impl Parser {
     // expr_vprim:  expr_vatom
     //            | expr_vprim '(' expr_list<','> ')'
     fn r_expr_vprim(&mut self) -> ast::ASTExprPtr {
+        let pos = self.ps.peek_pos();
         let mut res = self.r_expr_vatom();
         loop {
             match self.ps.peek_token() {
@@ -231,6 +270,7 @@ impl Parser {
                     let ast_id =
                         astcast::cast_to_expr_id(&*res).expect("r:expr: callee must be and id");
                     res = ast::ASTExprCall::new(ast_id.name().to_string(), args);
+                    res = self.set_loc(res, pos);
                 }
 
                 _ => break,
@@ -244,8 +284,9 @@ impl Parser {
     //            | @int
     //	          | @id
     fn r_expr_vatom(&mut self) -> ast::ASTExprPtr {
+        let pos = self.ps.peek_pos();
         let tok = self.ps.get_token();
-        match &tok {
+        let res: ast::ASTExprPtr = match &tok {
             Token::Symbol(x) if x == "(" => {
                 let res = self.r_expr_list(";");
                 self.ps.eat_sym(")");
@@ -255,7 +296,8 @@ impl Parser {
             Token::ValInt(x) => ast::ASTExprConst::new(*x as i32),
             Token::Id(x) => ast::ASTExprId::new(x.to_string()),
             _ => panic!("r:expr_vatom: Invalid token {:?}", tok),
-        }
+        };
+        self.set_loc(res, pos)
     }
 
     // expr_list<sep>:  expr (sep expr)*
@@ -297,17 +339,19 @@ impl Parser {
 
     // def_var: 'var' @id ':' type '=' expr
     fn r_def_var(&mut self) -> Box<ast::ASTDefVar> {
+        let pos = self.ps.peek_pos();
         self.ps.eat_keyword("var");
         let name = self.ps.eat_id();
         self.ps.eat_sym(":");
         let var_type = self.r_type();
         self.ps.eat_sym("=");
         let val = self.r_expr();
-        ast::ASTDefVar::new(name, Some(var_type), val)
+        self.set_loc(ast::ASTDefVar::new(name, Some(var_type), val), pos)
     }
 
     // def_fun: 'fun' @id '(' def_fun_args ')' ':' type '=' expr
     fn r_def_fun(&mut self) -> Box<ast::ASTDefFun> {
+        let pos = self.ps.peek_pos();
         self.ps.eat_keyword("fun");
         let name = self.ps.eat_id();
         self.ps.eat_sym("(");
@@ -317,7 +361,7 @@ impl Parser {
         let ret_type = self.r_type();
         self.ps.eat_sym("=");
         let body = self.r_expr();
-        ast::ASTDefFun::new(name, args, ret_type, body)
+        self.set_loc(ast::ASTDefFun::new(name, args, ret_type, body), pos)
     }
 
     // def_fun_args:  def_fun_arg ( ',' def_fun_arg )*
@@ -339,10 +383,12 @@ impl Parser {
                 _ => {}
             }
 
+            let pos = self.ps.peek_pos();
             let arg_name = self.ps.eat_id();
             self.ps.eat_sym(":");
             let arg_type = self.r_type();
-            res.push(ast::ASTDefArg::new(arg_name, arg_type));
+            let arg = ast::ASTDefArg::new(arg_name, arg_type);
+            res.push(self.set_loc(arg, pos));
             has_sep = self.ps.try_eat_sym(",");
         }
 
@@ -351,7 +397,8 @@ impl Parser {
 
     // type: @id
     fn r_type(&mut self) -> ast::ASTTypePtr {
+        let pos = self.ps.peek_pos();
         let name = self.ps.eat_id();
-        ast::ASTTypeName::new(name)
+        self.set_loc(ast::ASTTypeName::new(name), pos)
     }
 }
@@ -0,0 +1,648 @@
+// Random program generator, to test the typechecker, the translaters and the backends (similar to Csmith)
+//
+// Every generated program is well-typed, always terminates, and only depends on the seed:
+// - all variables and functions have unique names (v<N>, a<N> for arguments, i<N> for loop counters, f<N>)
+// - a function body only uses its arguments and its own local variables
+//   (nested functions can't access the variables of the enclosing function)
+// - a function can only call the functions defined before it: there is no recursion
+// - loops always have the form `let var i : int = 0 in while i < N do (...; i = i + 1)`,
+//   and the loop counters are never assigned in the loop body
+// - the number of executed instructions is bounded: every expression has an estimated cost,
+//   long loops are shortened, and costly functions aren't called
+// - divisions go through safe_div / safe_mod: no division by 0 or overflow (INT_MIN / -1)
+// - flat memory addresses go through fmem_addr, to stay in [0, FMEM_SIZE[
+//
+// The program prints values during its execution (`out`), then the values of all the main variables
+// and a checksum of the flat memory.
+// The generated AST can be converted to source code with astprinter.
+
+use crate::ast::*;
+use crate::astprinter;
+use crate::nativedefs;
+
+// Maximum nesting of expressions
+const MAX_DEPTH: usize = 6;
+// Maximum number of expressions in a block
+const MAX_BLOCK_SIZE: usize = 4;
+// Maximum number of definitions in a let
+const MAX_LET_DEFS: usize = 3;
+const MAX_FUN_ARGS: usize = 3;
+const MAX_LOOP_ITERS: u64 = 10;
+// Maximum estimated cost of a loop, the number of iterations is reduced to stay below
+const MAX_LOOP_COST: u64 = 2_000;
+// Functions with a higher estimated cost are never called
+const MAX_CALL_COST: u64 = 2_000;
+// Number of flat memory words used by the program
+const FMEM_SIZE: i32 = 64;
+const FMEM_MAX_COPY: i32 = 8;
+
+/// Pseudo-random numbers generator (xorshift64*)
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // splitmix64 step to get a good non-zero state from small seeds
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        Rng {
+            state: if z == 0 { 1 } else { z },
+        }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// Random number in [0, n[
+    pub fn below(&mut self, n: u64) -> u64 {
+        assert!(n > 0);
+        self.next_u64() % n
+    }
+
+    /// Random number in [beg, end]
+    pub fn range(&mut self, beg: u64, end: u64) -> u64 {
+        beg + self.below(end - beg + 1)
+    }
+
+    /// true with probability percent / 100
+    pub fn chance(&mut self, percent: u64) -> bool {
+        self.below(100) < percent
+    }
+}
+
+// Generated expression, with its estimated cost
+type Gen = (ASTExprPtr, u64);
+
+struct FunInfo {
+    name: String,
+    nargs: usize,
+    ret_int: bool,
+    cost: u64,
+}
+
+struct VarInfo {
+    name: String,
+    assignable: bool,
+}
+
+pub struct RandGen {
+    rng: Rng,
+    next_id: usize,
+    funs: Vec<FunInfo>, // callable functions
+    vars: Vec<VarInfo>, // variables of the current function
+}
+
+fn int_type() -> ASTTypePtr {
+    ASTTypeName::new(nativedefs::TYPE_INT.name().to_string())
+}
+
+fn void_type() -> ASTTypePtr {
+    ASTTypeName::new(nativedefs::TYPE_VOID.name().to_string())
+}
+
+fn cst(val: i32) -> ASTExprPtr {
+    ASTExprConst::new(val)
+}
+
+fn id(name: &str) -> ASTExprPtr {
+    ASTExprId::new(name.to_string())
+}
+
+fn call(name: &str, args: Vec<ASTExprPtr>) -> ASTExprPtr {
+    ASTExprCall::new(name.to_string(), args)
+}
+
+fn op(fun: &nativedefs::NativeFun, args: Vec<ASTExprPtr>) -> ASTExprPtr {
+    call(fun.name(), args)
+}
+
+fn empty_block() -> ASTExprPtr {
+    ASTExprBlock::new(vec![])
+}
+
+fn def_fun(name: &str, args: &[&str], ret_int: bool, body: ASTExprPtr) -> ASTDefPtr {
+    let args = args
+        .iter()
+        .map(|arg| ASTDefArg::new(arg.to_string(), int_type()))
+        .collect();
+    let ret = if ret_int { int_type() } else { void_type() };
+    ASTDefFun::new(name.to_string(), args, ret, body)
+}
+
+fn def_var(name: &str, init: ASTExprPtr) -> ASTDefPtr {
+    ASTDefVar::new(name.to_string(), Some(int_type()), init)
+}
+
+// let var counter : int = 0 in while counter < iters do (body; counter = counter + 1)
+fn counted_loop(counter: &str, iters: i32, mut body: Vec<ASTExprPtr>) -> ASTExprPtr {
+    body.push(op(
+        &nativedefs::OP_SET,
+        vec![
+            id(counter),
+            op(&nativedefs::OP_ADD, vec![id(counter), cst(1)]),
+        ],
+    ));
+    let cond = op(&nativedefs::OP_LT, vec![id(counter), cst(iters)]);
+    ASTExprLet::new(
+        vec![def_var(counter, cst(0))],
+        ASTExprWhile::new(cond, ASTExprBlock::new(body)),
+    )
+}
+
+// Helper functions available to all generated programs
+fn prelude_defs() -> Vec<ASTDefPtr> {
+    let eq = |a, b| op(&nativedefs::OP_EQ, vec![a, b]);
+    let neg = |a| op(&nativedefs::OP_NEG, vec![a]);
+
+    // print_int(x): print x in base 10
+    let print_rec = def_fun(
+        "print_rec",
+        &["x"],
+        false,
+        ASTExprIf::new(
+            op(&nativedefs::OP_NOT, vec![eq(id("x"), cst(0))]),
+            ASTExprBlock::new(vec![
+                call(
+                    "print_rec",
+                    vec![op(&nativedefs::OP_DIV, vec![id("x"), cst(10)])],
+                ),
+                call(
+                    nativedefs::STD_FUN_PUTC.name(),
+                    vec![op(
+                        &nativedefs::OP_ADD,
+                        vec![cst(48), op(&nativedefs::OP_MOD, vec![id("x"), cst(10)])],
+                    )],
+                ),
+            ]),
+            empty_block(),
+        ),
+    );
+    let print_int = def_fun(
+        "print_int",
+        &["x"],
+        false,
+        ASTExprLet::new(
+            vec![print_rec],
+            ASTExprIf::new(
+                op(&nativedefs::OP_LT, vec![id("x"), cst(0)]),
+                ASTExprBlock::new(vec![
+                    call(nativedefs::STD_FUN_PUTC.name(), vec![cst(45)]),
+                    call("print_rec", vec![neg(id("x"))]),
+                ]),
+                ASTExprIf::new(
+                    eq(id("x"), cst(0)),
+                    call(nativedefs::STD_FUN_PUTC.name(), vec![cst(48)]),
+                    call("print_rec", vec![id("x")]),
+                ),
+            ),
+        ),
+    );
+
+    // out(x): print x and a newline
+    let out = def_fun(
+        "out",
+        &["x"],
+        false,
+        ASTExprBlock::new(vec![
+            call("print_int", vec![id("x")]),
+            call(nativedefs::STD_FUN_PUTC.name(), vec![cst(10)]),
+        ]),
+    );
+
+    // safe_div(x, y): x / y, or x if y is 0, or -x if y is -1
+    // safe_mod(x, y): x % y, or x if y is 0, or 0 if y is -1
+    let safe_div = def_fun(
+        "safe_div",
+        &["x", "y"],
+        true,
+        ASTExprIf::new(
+            eq(id("y"), cst(0)),
+            id("x"),
+            ASTExprIf::new(
+                eq(id("y"), neg(cst(1))),
+                neg(id("x")),
+                op(&nativedefs::OP_DIV, vec![id("x"), id("y")]),
+            ),
+        ),
+    );
+    let safe_mod = def_fun(
+        "safe_mod",
+        &["x", "y"],
+        true,
+        ASTExprIf::new(
+            eq(id("y"), cst(0)),
+            id("x"),
+            ASTExprIf::new(
+                eq(id("y"), neg(cst(1))),
+                cst(0),
+                op(&nativedefs::OP_MOD, vec![id("x"), id("y")]),
+            ),
+        ),
+    );
+
+    // fmem_addr(x): address in [0, FMEM_SIZE[
+    let fmem_addr = def_fun(
+        "fmem_addr",
+        &["x"],
+        true,
+        ASTExprIf::new(
+            op(&nativedefs::OP_LT, vec![id("x"), cst(0)]),
+            op(
+                &nativedefs::OP_MOD,
+                vec![neg(id("x")), cst(FMEM_SIZE)],
+            ),
+            op(&nativedefs::OP_MOD, vec![id("x"), cst(FMEM_SIZE)]),
+        ),
+    );
+
+    vec![print_int, out, safe_div, safe_mod, fmem_addr]
+}
+
+impl RandGen {
+    pub fn new(seed: u64) -> Self {
+        RandGen {
+            rng: Rng::new(seed),
+            next_id: 0,
+            funs: vec![],
+            vars: vec![],
+        }
+    }
+
+    /// Generate a random program
+    pub fn gen_program(mut self) -> ASTExprPtr {
+        // the main function can call the prelude functions returning an int
+        for (name, nargs) in &[("safe_div", 2), ("safe_mod", 2), ("fmem_addr", 1)] {
+            self.funs.push(FunInfo {
+                name: name.to_string(),
+                nargs: *nargs,
+                ret_int: true,
+                cost: 10,
+            });
+        }
+
+        let ndefs = self.rng.range(2, 6) as usize;
+        let defs = (0..ndefs).map(|_| self.gen_def(0)).collect();
+
+        let nstmts = self.rng.range(3, 8);
+        let mut body: Vec<_> = (0..nstmts).map(|_| self.gen_void(0).0).collect();
+
+        // print the final values of the main variables, and the checksum of the flat memory
+        for var in &self.vars {
+            body.push(call("out", vec![id(&var.name)]));
+        }
+        let sum = self.new_name("v");
+        let counter = self.new_name("i");
+        body.push(ASTExprLet::new(
+            vec![def_var(&sum, cst(0))],
+            ASTExprBlock::new(vec![
+                counted_loop(
+                    &counter,
+                    FMEM_SIZE + FMEM_MAX_COPY,
+                    vec![op(
+                        &nativedefs::OP_SET,
+                        vec![
+                            id(&sum),
+                            op(
+                                &nativedefs::OP_ADD,
+                                vec![
+                                    op(&nativedefs::OP_MUL, vec![id(&sum), cst(31)]),
+                                    call(nativedefs::STD_FUN_FMEMGET.name(), vec![id(&counter)]),
+                                ],
+                            ),
+                        ],
+                    )],
+                ),
+                call("out", vec![id(&sum)]),
+            ]),
+        ));
+
+        ASTExprLet::new(
+            prelude_defs(),
+            ASTExprLet::new(defs, ASTExprBlock::new(body)),
+        )
+    }
+
+    fn new_name(&mut self, prefix: &str) -> String {
+        self.next_id += 1;
+        format!("{}{}", prefix, self.next_id)
+    }
+
+    // true if the expression at depth should be a leaf
+    fn is_leaf(&mut self, depth: usize) -> bool {
+        depth + 1 >= MAX_DEPTH || self.rng.below(MAX_DEPTH as u64) < depth as u64
+    }
+
+    // let definition: variable or function
+    fn gen_def(&mut self, depth: usize) -> ASTDefPtr {
+        if depth + 2 >= MAX_DEPTH || self.rng.chance(60) {
+            let (init, _) = self.gen_int(depth + 1);
+            let name = self.new_name("v");
+            self.vars.push(VarInfo {
+                name: name.clone(),
+                assignable: true,
+            });
+            return def_var(&name, init);
+        }
+
+        let name = self.new_name("f");
+        let ret_int = self.rng.chance(70);
+        let nargs = self.rng.below(MAX_FUN_ARGS as u64 + 1) as usize;
+        let args: Vec<_> = (0..nargs).map(|_| self.new_name("a")).collect();
+
+        // the body only sees the arguments, and the functions defined before
+        let nfuns = self.funs.len();
+        let args_vars = args
+            .iter()
+            .map(|arg| VarInfo {
+                name: arg.clone(),
+                assignable: true,
+            })
+            .collect();
+        let saved_vars = std::mem::replace(&mut self.vars, args_vars);
+        let (body, cost) = if ret_int {
+            self.gen_int(depth + 1)
+        } else {
+            self.gen_void(depth + 1)
+        };
+        self.vars = saved_vars;
+        self.funs.truncate(nfuns);
+
+        let args: Vec<_> = args.iter().map(|x| x.as_str()).collect();
+        let res = def_fun(&name, &args, ret_int, body);
+        self.funs.push(FunInfo {
+            name,
+            nargs,
+            ret_int,
+            cost,
+        });
+        res
+    }
+
+    // let defs in val, the definitions are only visible in the let
+    fn gen_let(&mut self, depth: usize, ret_int: bool) -> Gen {
+        let (nfuns, nvars) = (self.funs.len(), self.vars.len());
+        let ndefs = self.rng.range(1, MAX_LET_DEFS as u64) as usize;
+        let defs = (0..ndefs).map(|_| self.gen_def(depth + 1)).collect();
+        let (val, cost) = match ret_int {
+            true => self.gen_int(depth + 1),
+            false => self.gen_void(depth + 1),
+        };
+        self.funs.truncate(nfuns);
+        self.vars.truncate(nvars);
+        (ASTExprLet::new(defs, val), cost + ndefs as u64)
+    }
+
+    // call to a random function with the right return type (if there is one cheap enough)
+    fn gen_call(&mut self, depth: usize, ret_int: bool) -> Option<Gen> {
+        let candidates: Vec<_> = self
+            .funs
+            .iter()
+            .enumerate()
+            .filter(|(_, f)| (f.ret_int || !ret_int) && f.cost <= MAX_CALL_COST)
+            .map(|(idx, _)| idx)
+            .collect();
+        if candidates.is_empty() {
+            return None;
+        }
+
+        let fun_idx = candidates[self.rng.below(candidates.len() as u64) as usize];
+        let (name, nargs, fun_ret_int, fun_cost) = {
+            let f = &self.funs[fun_idx];
+            (f.name.clone(), f.nargs, f.ret_int, f.cost)
+        };
+        let mut cost = fun_cost + 1;
+        let args = (0..nargs)
+            .map(|_| {
+                let (arg, arg_cost) = self.gen_int(depth + 1);
+                cost += arg_cost;
+                arg
+            })
+            .collect();
+
+        // an int value can't be used as void, it's discarded in a block: (f(...); ())
+        let res = match fun_ret_int && !ret_int {
+            true => ASTExprBlock::new(vec![call(&name, args), empty_block()]),
+            false => call(&name, args),
+        };
+        Some((res, cost))
+    }
+
+    fn gen_const(&mut self) -> ASTExprPtr {
+        let val = match self.rng.below(10) {
+            0 => self.rng.next_u64() as i32 & i32::MAX,
+            1 => self.rng.below(1000) as i32,
+            _ => self.rng.below(10) as i32,
+        };
+        cst(val)
+    }
+
+    fn gen_int_leaf(&mut self) -> Gen {
+        if !self.vars.is_empty() && self.rng.chance(60) {
+            let idx = self.rng.below(self.vars.len() as u64) as usize;
+            (id(&self.vars[idx].name), 1)
+        } else {
+            (self.gen_const(), 1)
+        }
+    }
+
+    // expression of type int
+    fn gen_int(&mut self, depth: usize) -> Gen {
+        if self.is_leaf(depth) {
+            return self.gen_int_leaf();
+        }
+
+        let depth = depth + 1;
+        match self.rng.below(12) {
+            0..=3 => {
+                let ops: [&nativedefs::NativeFun; 6] = [
+                    &nativedefs::OP_ADD,
+                    &nativedefs::OP_SUB,
+                    &nativedefs::OP_MUL,
+                    &nativedefs::OP_EQ,
+                    &nativedefs::OP_LT,
+                    &nativedefs::OP_GT,
+                ];
+                let fun = ops[self.rng.below(ops.len() as u64) as usize];
+                let (left, left_cost) = self.gen_int(depth);
+                let (right, right_cost) = self.gen_int(depth);
+                (op(fun, vec![left, right]), left_cost + right_cost + 1)
+            }
+            4 => {
+                let fun: &nativedefs::NativeFun = match self.rng.chance(50) {
+                    true => &nativedefs::OP_NEG,
+                    false => &nativedefs::OP_NOT,
+                };
+                let (val, cost) = self.gen_int(depth);
+                (op(fun, vec![val]), cost + 1)
+            }
+            5 => {
+                let fun = match self.rng.chance(50) {
+                    true => "safe_div",
+                    false => "safe_mod",
+                };
+                let (left, left_cost) = self.gen_int(depth);
+                let (right, right_cost) = self.gen_int(depth);
+                (call(fun, vec![left, right]), left_cost + right_cost + 10)
+            }
+            6 => {
+                let (addr, cost) = self.gen_fmem_addr(depth);
+                (
+                    call(nativedefs::STD_FUN_FMEMGET.name(), vec![addr]),
+                    cost + 1,
+                )
+            }
+            7 => {
+                let (cond, cond_cost) = self.gen_int(depth);
+                let (val_if, if_cost) = self.gen_int(depth);
+                let (val_else, else_cost) = self.gen_int(depth);
+                (
+                    ASTExprIf::new(cond, val_if, val_else),
+                    cond_cost + std::cmp::max(if_cost, else_cost) + 1,
+                )
+            }
+            8 => self.gen_let(depth, true),
+            9 => {
+                let (stmt, stmt_cost) = self.gen_void(depth);
+                let (val, val_cost) = self.gen_int(depth);
+                (ASTExprBlock::new(vec![stmt, val]), stmt_cost + val_cost)
+            }
+            _ => match self.gen_call(depth, true) {
+                Some(res) => res,
+                None => self.gen_int_leaf(),
+            },
+        }
+    }
+
+    fn gen_fmem_addr(&mut self, depth: usize) -> Gen {
+        let (addr, cost) = self.gen_int(depth);
+        (call("fmem_addr", vec![addr]), cost + 5)
+    }
+
+    // assignment to a variable, or fmemset if none are assignable
+    fn gen_assign(&mut self, depth: usize) -> Gen {
+        let assignable: Vec<_> = self
+            .vars
+            .iter()
+            .filter(|var| var.assignable)
+            .map(|var| var.name.clone())
+            .collect();
+
+        if assignable.is_empty() {
+            let (addr, addr_cost) = self.gen_fmem_addr(depth);
+            let (val, val_cost) = self.gen_int(depth);
+            return (
+                call(nativedefs::STD_FUN_FMEMSET.name(), vec![addr, val]),
+                addr_cost + val_cost + 1,
+            );
+        }
+
+        let name = &assignable[self.rng.below(assignable.len() as u64) as usize];
+        let (val, cost) = self.gen_int(depth);
+        (op(&nativedefs::OP_SET, vec![id(name), val]), cost + 1)
+    }
+
+    // while loop with a fixed number of iterations
+    fn gen_loop(&mut self, depth: usize) -> Gen {
+        let counter = self.new_name("i");
+        self.vars.push(VarInfo {
+            name: counter.clone(),
+            assignable: false,
+        });
+        let (body, body_cost) = self.gen_void(depth);
+        self.vars.pop();
+
+        let body_cost = body_cost + 3;
+        let max_iters = std::cmp::max(1, MAX_LOOP_COST / body_cost);
+        let iters = std::cmp::min(self.rng.range(1, MAX_LOOP_ITERS), max_iters);
+        (
+            counted_loop(&counter, iters as i32, vec![body]),
+            iters * body_cost + 1,
+        )
+    }
+
+    // expression of type void
+    fn gen_void(&mut self, depth: usize) -> Gen {
+        if self.is_leaf(depth) {
+            return match self.rng.chance(20) {
+                true => {
+                    let (val, cost) = self.gen_int_leaf();
+                    (call("out", vec![val]), cost + 20)
+                }
+                false => self.gen_assign(depth),
+            };
+        }
+
+        let depth = depth + 1;
+        match self.rng.below(12) {
+            0..=2 => self.gen_assign(depth),
+            3 => {
+                let (addr, addr_cost) = self.gen_fmem_addr(depth);
+                let (val, val_cost) = self.gen_int(depth);
+                (
+                    call(nativedefs::STD_FUN_FMEMSET.name(), vec![addr, val]),
+                    addr_cost + val_cost + 1,
+                )
+            }
+            4 => {
+                let (dst, dst_cost) = self.gen_fmem_addr(depth);
+                let (src, src_cost) = self.gen_fmem_addr(depth);
+                let len = self.rng.range(1, FMEM_MAX_COPY as u64) as i32;
+                (
+                    call(nativedefs::STD_FUN_FMEMCPY.name(), vec![dst, src, cst(len)]),
+                    dst_cost + src_cost + len as u64 + 1,
+                )
+            }
+            5 => {
+                let (val, cost) = self.gen_int(depth);
+                (call("out", vec![val]), cost + 20)
+            }
+            6 => {
+                let (cond, cond_cost) = self.gen_int(depth);
+                let (val_if, if_cost) = self.gen_void(depth);
+                let (val_else, else_cost) = match self.rng.chance(50) {
+                    true => self.gen_void(depth),
+                    false => (empty_block(), 0),
+                };
+                (
+                    ASTExprIf::new(cond, val_if, val_else),
+                    cond_cost + std::cmp::max(if_cost, else_cost) + 1,
+                )
+            }
+            7 => self.gen_loop(depth),
+            8 => self.gen_let(depth, false),
+            9 => {
+                let size = self.rng.range(2, MAX_BLOCK_SIZE as u64);
+                let mut cost = 0;
+                let stmts = (0..size)
+                    .map(|_| {
+                        let (stmt, stmt_cost) = self.gen_void(depth);
+                        cost += stmt_cost;
+                        stmt
+                    })
+                    .collect();
+                (ASTExprBlock::new(stmts), cost)
+            }
+            _ => match self.gen_call(depth, false) {
+                Some(res) => res,
+                None => self.gen_assign(depth),
+            },
+        }
+    }
+}
+
+/// Generate a random program from a seed
+pub fn gen_program(seed: u64) -> ASTExprPtr {
+    RandGen::new(seed).gen_program()
+}
+
+/// Generate the source code of a random program from a seed
+pub fn gen_source(seed: u64) -> String {
+    astprinter::ast_to_string(&gen_program(seed))
+}
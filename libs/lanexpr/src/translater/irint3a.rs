@@ -12,11 +12,13 @@ use crate::bindapp::BindApp;
 use crate::bindfun::{BindFun, BindFunId};
 use crate::bindvar::BindVarId;
 use crate::nativedefs;
+use crate::translater;
 use crate::translater::defslist;
 
 pub struct Translater<'a> {
     root: &'a ast::ASTExprPtr,
     app: &'a BindApp,
+    locs: Option<&'a ast::ASTLocs>,
     module: ir::Module,
     names: irnames::ModuleNames,
     ir_funs: HashMap<BindFunId, ir::FunctionId>,
@@ -27,12 +29,24 @@ impl<'a> Translater<'a> {
         Translater {
             root,
             app,
+            locs: None,
             module: ir::Module::new(),
             names: irnames::ModuleNames::new(),
             ir_funs: HashMap::new(),
         }
     }
 
+    /// Same as new, but the instructions get the debug locations of the AST nodes they are generated from
+    pub fn new_with_locations(
+        root: &'a ast::ASTExprPtr,
+        app: &'a BindApp,
+        locs: &'a ast::ASTLocs,
+    ) -> Self {
+        let mut res = Translater::new(root, app);
+        res.locs = Some(locs);
+        res
+    }
+
     pub fn translate(mut self) -> (ir::Module, irnames::ModuleNames) {
         // 1) Add native function definitions
         self.add_native_defs();
@@ -64,7 +78,12 @@ impl<'a> Translater<'a> {
         self.gen_start_fun();
 
         // 7) Finish module
-        irvalidation::validate_module(&self.module);
+        if let Err(diags) = irvalidation::validate_module(&self.module, Some(&self.names)) {
+            for diag in diags {
+                eprintln!("{}", diag);
+            }
+            panic!("Generated invalid irint3a code");
+        }
         (self.module, self.names)
     }
 
@@ -73,7 +92,7 @@ impl<'a> Translater<'a> {
         let mut fun = self.module.get_fun_mut(fun_ir).unwrap();
 
         let builder = IRBuilder::new(&mut fun);
-        let mut tl = FunctionTranslater::new(builder, fun_bind, &self.ir_funs);
+        let mut tl = FunctionTranslater::new(builder, fun_bind, &self.ir_funs, self.locs);
         tl.run(body);
     }
 
@@ -144,6 +163,7 @@ impl ExprVal {
 struct FunctionTranslater<'a> {
     builder: IRBuilder<'a>,
     ir_funs: &'a HashMap<BindFunId, ir::FunctionId>,
+    locs: Option<&'a ast::ASTLocs>,
 
     //fiels abouts the current function being translated
     fun_bind: &'a BindFun,
@@ -159,10 +179,12 @@ impl<'a> FunctionTranslater<'a> {
         builder: IRBuilder<'a>,
         fun_bind: &'a BindFun,
         ir_funs: &'a HashMap<BindFunId, ir::FunctionId>,
+        locs: Option<&'a ast::ASTLocs>,
     ) -> Self {
         FunctionTranslater {
             builder,
             ir_funs,
+            locs,
 
             fun_bind,
             fun_vars: HashMap::new(),
@@ -198,7 +220,7 @@ impl<'a> FunctionTranslater<'a> {
         let mut args_mem = vec![];
         for var_bind in self.fun_bind.vars() {
             let reg_id = self.alloc_reg();
-            self.builder.ins_alloca(reg_id);
+            self.builder.ins_alloca(reg_id, 1);
             args_mem.push(reg_id);
             self.fun_vars.insert(var_bind.id(), reg_id);
         }
@@ -265,11 +287,27 @@ impl<'a> FunctionTranslater<'a> {
         res as usize
     }
 
+    // Give the location of the node to the instructions generated until the returned location is restored
+    fn enter_node_loc(&mut self, uid: ast::ASTUid) -> Option<ir::DebugLoc> {
+        let prev_loc = self.builder.get_debug_loc().cloned();
+        let locs = match self.locs {
+            Some(locs) => locs,
+            None => return prev_loc,
+        };
+        if let Some(loc) = locs.get(uid) {
+            let loc = ir::DebugLoc::new(locs.file(), loc.line, loc.col);
+            self.builder.set_debug_loc(Some(loc));
+        }
+        prev_loc
+    }
+
     fn tl_expr(&mut self, node: &dyn ast::ASTExpr) -> ExprVal {
         self.expr_val = None;
 
         let mut res = None;
+        let prev_loc = self.enter_node_loc(node.get_uid());
         node.accept(self);
+        self.builder.set_debug_loc(prev_loc);
         std::mem::swap(&mut res, &mut self.expr_val);
         let res = res.expect("IRint3A Translater: Internal errror: no value set by visitor");
 
@@ -303,6 +341,9 @@ impl<'a> FunctionTranslater<'a> {
             "eq" => self.builder.ins_cmpeq(reg_dst, src1, src2),
             "lt" => self.builder.ins_cmplt(reg_dst, src1, src2),
             "gt" => self.builder.ins_cmpgt(reg_dst, src1, src2),
+            "ne" => self.builder.ins_cmpne(reg_dst, src1, src2),
+            "le" => self.builder.ins_cmple(reg_dst, src1, src2),
+            "ge" => self.builder.ins_cmpge(reg_dst, src1, src2),
             _ => unreachable!(),
         }
     }
@@ -327,15 +368,9 @@ impl<'a> FunctionTranslater<'a> {
     }
 
     // translate special calls: operators
-    fn tl_call_special(
-        &mut self,
-        node: &ast::ASTExprCall,
-        reg_dst: ir::RegId,
-        args: &Vec<ir::RegId>,
-    ) {
-        let op_name = &node.name()[4..];
+    fn tl_call_special(&mut self, op_name: &str, reg_dst: ir::RegId, args: &Vec<ir::RegId>) {
         match op_name {
-            "add" | "sub" | "mul" | "div" | "mod" | "eq" | "lt" | "gt" => {
+            "add" | "sub" | "mul" | "div" | "mod" | "eq" | "lt" | "gt" | "ne" | "le" | "ge" => {
                 self.tl_call_binop(op_name, reg_dst, args)
             }
             "neg" | "not" => self.tl_call_unop(op_name, reg_dst, args),
@@ -381,7 +416,9 @@ impl<'a> ast::ASTVisitor for FunctionTranslater<'a> {
             _ => unreachable!(),
         };
 
+        let prev_loc = self.enter_node_loc(node.get_uid());
         self.builder.ins_store(var_reg, init_reg);
+        self.builder.set_debug_loc(prev_loc);
         self.free_reg(init_reg);
     }
 
@@ -407,6 +444,12 @@ impl<'a> ast::ASTVisitor for FunctionTranslater<'a> {
             return;
         }
 
+        // !(a == b), !(a < b) and !(a > b) are a single comparison
+        let (node, op_name) = match translater::negated_cmp(node) {
+            Some((cmp, op_name)) => (cmp, op_name),
+            None => (node, node.name().trim_start_matches("@op:")),
+        };
+
         // 1) Translate arguments
         let args_vals: Vec<ExprVal> = node.args().iter().map(|arg| self.tl_expr(&**arg)).collect();
         let args_regs: Vec<ir::RegId> = args_vals
@@ -421,7 +464,7 @@ impl<'a> ast::ASTVisitor for FunctionTranslater<'a> {
         let is_void = self.fun_bind.get_type_of_exp(node).unwrap().is_void();
         let dst_reg = self.alloc_reg();
         if node.name().starts_with("@op") {
-            self.tl_call_special(node, dst_reg, &args_regs);
+            self.tl_call_special(op_name, dst_reg, &args_regs);
         } else {
             self.tl_call(node, dst_reg, &args_regs);
         }
@@ -2,6 +2,7 @@ use std::collections::HashMap;
 
 use irintsm::ir;
 use irintsm::irbuilder::IRBuilder;
+use irintsm::irnames;
 
 use crate::ast;
 use crate::ast::AST;
@@ -10,6 +11,7 @@ use crate::bindapp::BindApp;
 use crate::bindfun::{BindFun, BindFunId};
 use crate::bindvar::BindVarId;
 use crate::nativedefs;
+use crate::translater;
 use crate::translater::defslist;
 
 pub struct Translater<'a> {
@@ -38,7 +40,12 @@ impl<'a> Translater<'a> {
         }
     }
 
-    pub fn translate(mut self) -> ir::Module {
+    pub fn translate(self) -> ir::Module {
+        self.translate_with_names().0
+    }
+
+    /// Same as translate, but also returns the names of the functions and locals
+    pub fn translate_with_names(mut self) -> (ir::Module, irnames::ModuleNames) {
         // 1) Add native function definitions
         self.add_native_defs();
 
@@ -47,13 +54,17 @@ impl<'a> Translater<'a> {
 
         // 3) create all IR functions for all user functions, and the user main function
         let main_fun = self.builder.create_function(None);
+        self.builder
+            .set_function_name(main_fun, "main_1".to_string());
         let main_bind = self
             .app
             .get_fun_from_native_name(nativedefs::SPE_MAIN.name());
         self.ir_funs.insert(main_bind.id(), main_fun);
-        for def in &fun_defs {
+        for (fn_idx, def) in fun_defs.iter().enumerate() {
             let bind_id = self.app.get_fun_from_ast(def.get_uid()).id();
             let fun = self.builder.create_function(None);
+            self.builder
+                .set_function_name(fun, format!("{}_{}", def.name(), fn_idx + 2));
             self.ir_funs.insert(bind_id, fun);
         }
 
@@ -73,7 +84,7 @@ impl<'a> Translater<'a> {
         //6) Generate code for the start function
         self.gen_start_fun();
 
-        self.builder.finish()
+        self.builder.finish_with_names()
     }
 
     fn tl_fun(&mut self, body: &ast::ASTExprPtr) {
@@ -96,8 +107,10 @@ impl<'a> Translater<'a> {
 
         // 2) Register locals (arguments + variables)
         for (local_idx, var_bind) in fun_bind.vars().iter().enumerate() {
-            self.act_fun_vars
-                .insert(var_bind.id(), ir::LocalsIndex::new(local_idx));
+            let local = ir::LocalsIndex::new(local_idx);
+            self.act_fun_vars.insert(var_bind.id(), local);
+            let local_name = format!("{}_{}", var_bind.name(), local_idx);
+            self.builder.set_local_name(fun, local, local_name);
         }
 
         // 3) Generate body function code and return instruction
@@ -123,11 +136,14 @@ impl<'a> Translater<'a> {
 
         // 2) Create start function
         let start_fun = self.builder.create_function(Some(ir::FunctionRef::new(0)));
+        self.builder
+            .set_function_name(start_fun, "_start".to_string());
         let start_bb = self.builder.create_basic_block(start_fun);
         self.builder.set_insert_point(start_bb);
 
-        // 3) Call user main function
+        // 3) Call user main function, and drop its result
         self.builder.ins_call(main_fun, 0);
+        self.builder.ins_pop();
 
         // 4) Call extern exit function (258) with argument 0
         self.builder.ins_const(0);
@@ -140,6 +156,7 @@ impl<'a> Translater<'a> {
     fn add_standard_fn(&mut self, name: &str, ir_addr: ir::FunctionRef) {
         let bind_id = self.app.get_fun_from_native_name(name).id();
         let fun = self.builder.create_function(Some(ir_addr));
+        self.builder.set_function_name(fun, name.to_string());
         self.ir_funs.insert(bind_id, fun);
     }
 
@@ -189,6 +206,9 @@ impl<'a> Translater<'a> {
             "eq" => self.builder.ins_cmpeq(),
             "lt" => self.builder.ins_cmplt(),
             "gt" => self.builder.ins_cmpgt(),
+            "ne" => self.builder.ins_cmpne(),
+            "le" => self.builder.ins_cmple(),
+            "ge" => self.builder.ins_cmpge(),
             "not" => {
                 self.builder.ins_const(0);
                 self.builder.ins_cmpeq();
@@ -260,6 +280,13 @@ impl<'a> ast::ASTVisitor for Translater<'a> {
             //special case for neg operator, need to insert a 0 first
             self.tl_call_neg(node);
             return;
+        } else if let Some((cmp, op_name)) = translater::negated_cmp(node) {
+            // !(a == b), !(a < b) and !(a > b) are a single comparison
+            for arg in cmp.args() {
+                self.tl_expr(&**arg);
+            }
+            self.tl_call_special(op_name);
+            return;
         }
 
         // 1) put arguments on the stack
@@ -44,6 +44,16 @@ pub fn compile_runtime_lib() -> &'static str {
 
 /// Generate LLVM IR, and use it to create a standalone binary at out_path
 pub fn compile_to_binary(root: &ast::ASTExprPtr, ba: &BindApp, out_path: &str) {
+    compile_to_binary_opt(root, ba, out_path, 0);
+}
+
+/// Same as compile_to_binary, the LLVM IR is compiled with optimization level opt_level (-O<opt_level>)
+pub fn compile_to_binary_opt(
+    root: &ast::ASTExprPtr,
+    ba: &BindApp,
+    out_path: &str,
+    opt_level: u8,
+) {
     let tmp_ir_path = format!("/tmp/tmp_cl_lanexpr_mod_{}.ll", calculate_hash(out_path));
     let tmp_o_path = format!("/tmp/tmp_cl_lanexpr_mod_{}.o", calculate_hash(out_path));
 
@@ -53,6 +63,7 @@ pub fn compile_to_binary(root: &ast::ASTExprPtr, ba: &BindApp, out_path: &str) {
         .set_input(&tmp_ir_path)
         .set_output(&tmp_o_path)
         .set_output_type(ClangOutputType::OBJECT)
+        .set_opt_level(opt_level)
         .run();
 
     let rt_o_path = compile_runtime_lib();
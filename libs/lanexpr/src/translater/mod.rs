@@ -3,3 +3,34 @@ pub mod irint3a;
 pub mod irintsmtl;
 pub mod llvmbin;
 pub mod llvmtl;
+
+use crate::ast;
+use crate::astcast;
+use crate::nativedefs;
+
+// Match `!(a == b)`, `!(a < b)` and `!(a > b)`
+// Returns the comparison node, and the name of the operator computing its negation
+pub(crate) fn negated_cmp(node: &ast::ASTExprCall) -> Option<(&ast::ASTExprCall, &'static str)> {
+    if node.name() != nativedefs::OP_NOT.name() {
+        return None;
+    }
+    // the parentheses are a block with a single expression
+    let mut arg = &*node.args()[0];
+    while let Some(block) = astcast::cast_to_expr_block(arg) {
+        if block.exprs().len() != 1 {
+            return None;
+        }
+        arg = &*block.exprs()[0];
+    }
+    let cmp = astcast::cast_to_expr_call(arg)?;
+    let op_name = if cmp.name() == nativedefs::OP_EQ.name() {
+        "ne"
+    } else if cmp.name() == nativedefs::OP_LT.name() {
+        "ge"
+    } else if cmp.name() == nativedefs::OP_GT.name() {
+        "le"
+    } else {
+        return None;
+    };
+    Some((cmp, op_name))
+}
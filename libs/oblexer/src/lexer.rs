@@ -1,4 +1,4 @@
-use crate::stream::Stream;
+use crate::stream::{Position, Stream};
 use crate::token::Token;
 use crate::trie::Trie;
 use std::collections::HashSet;
@@ -6,6 +6,7 @@ use std::collections::HashSet;
 pub struct Lexer {
     is: Stream,
     tok: Option<Token>,
+    tok_pos: Position,
     kws_set: HashSet<&'static str>,
     syms_trie: Trie,
 }
@@ -15,6 +16,7 @@ impl Lexer {
         Lexer {
             is,
             tok: None,
+            tok_pos: Position { line: 1, col: 1 },
             kws_set: kws.into_iter().collect(),
             syms_trie: Trie::from_words(syms),
         }
@@ -30,6 +32,12 @@ impl Lexer {
         res
     }
 
+    /// Returns the position of the first char of the next token
+    pub fn peek_pos(&mut self) -> Position {
+        self.get_last();
+        self.tok_pos
+    }
+
     fn get_last(&mut self) -> Token {
         match &self.tok {
             None => {
@@ -63,6 +71,7 @@ impl Lexer {
 
     fn parse_token(&mut self) -> Token {
         loop {
+            self.tok_pos = self.is.position();
             if self.is.eof() {
                 return Token::EOF;
             };
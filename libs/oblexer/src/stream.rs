@@ -75,9 +75,17 @@ impl StreamLine for StringStreamLine {
     }
 }
 
+/// Position of a character in the stream, lines and columns start at 1
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Position {
+    pub line: usize,
+    pub col: usize,
+}
+
 pub struct Stream {
     is: Box<dyn StreamLine>,
     line: Vec<char>,
+    line_num: usize,
     pos: usize,
     is_eof: bool,
 }
@@ -87,6 +95,7 @@ impl Stream {
         let mut res = Stream {
             is: Box::new(FileStreamLine::new(path)),
             line: Vec::new(),
+            line_num: 0,
             pos: 0,
             is_eof: false,
         };
@@ -98,6 +107,7 @@ impl Stream {
         let mut res = Stream {
             is: Box::new(StringStreamLine::new(data)),
             line: Vec::new(),
+            line_num: 0,
             pos: 0,
             is_eof: false,
         };
@@ -117,6 +127,14 @@ impl Stream {
         }
     }
 
+    /// Returns the position of the current char (get_char)
+    pub fn position(&self) -> Position {
+        Position {
+            line: self.line_num,
+            col: self.pos + 1,
+        }
+    }
+
     pub fn next_char(&mut self) {
         self.pos += 1;
         self.load_char();
@@ -128,6 +146,7 @@ impl Stream {
             match self.is.next_line() {
                 Some(str_line) => {
                     self.line = str_line.chars().collect();
+                    self.line_num += 1;
                     self.is_eof = false;
                 }
 
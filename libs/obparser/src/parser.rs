@@ -1,5 +1,5 @@
 use oblexer::lexer::Lexer;
-use oblexer::stream::Stream;
+use oblexer::stream::{Position, Stream};
 use oblexer::token::Token;
 
 pub struct Parser {
@@ -27,6 +27,11 @@ impl Parser {
         self.lex.get()
     }
 
+    /// Returns the position of the next token in the input
+    pub fn peek_pos(&mut self) -> Position {
+        self.lex.peek_pos()
+    }
+
     pub fn eat_eof(&mut self) {
         let tok = self.get_token();
         if !tok.is_eof() {
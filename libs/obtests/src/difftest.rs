@@ -0,0 +1,281 @@
+// Differential testing: run the same source file through many pipelines (compilers, interpreters, references)
+// and compare the output and exit code of every pipeline with the first available one (the reference).
+//
+// A pipeline is first prepared with the source file (eg: compiled), then run with some stdin data.
+// Pipelines can be unavailable on the system (eg: missing compiler), they are then skipped.
+//
+// When a pipeline differs from the reference, its stdin input is shrinked with delta debugging:
+// parts of the input are removed as long as the results still differ, to get a minimal failing input.
+
+use std::io::Write;
+use std::path::Path;
+
+use crate::utils;
+
+// Maximum number of runs to shrink an input
+const SHRINK_MAX_TRIES: usize = 500;
+
+/// Result of running a program
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExecResult {
+    pub stdout: Vec<u8>,
+    pub exit_code: i32,
+}
+
+/// One way to compile / run a source file
+pub trait Pipeline {
+    fn name(&self) -> String;
+
+    /// Prepare the pipeline for the source file (eg: compile it)
+    /// Returns false if the pipeline isn't available
+    fn prepare(&mut self, src_path: &str) -> bool;
+
+    /// Run the program with stdin data
+    fn run(&mut self, stdin: &[u8]) -> ExecResult;
+}
+
+/// Difference between the reference and another pipeline
+#[derive(Clone, Debug)]
+pub struct Mismatch {
+    pub reference: String,
+    pub pipeline: String,
+    /// Original input, before shrinking
+    pub input: Vec<u8>,
+    /// Smallest input found with different results
+    pub shrinked_input: Vec<u8>,
+    /// Results for the shrinked input
+    pub expected: ExecResult,
+    pub actual: ExecResult,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct DiffReport {
+    /// Names of the pipelines that were run (the first one is the reference)
+    pub pipelines: Vec<String>,
+    /// Names of the unavailable pipelines
+    pub skipped: Vec<String>,
+    pub mismatches: Vec<Mismatch>,
+}
+
+impl DiffReport {
+    pub fn is_ok(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+
+    pub fn print(&self, os: &mut dyn Write) {
+        writeln!(os, "Pipelines: {}", self.pipelines.join(", ")).unwrap();
+        if !self.skipped.is_empty() {
+            writeln!(os, "Skipped (unavailable): {}", self.skipped.join(", ")).unwrap();
+        }
+
+        for m in &self.mismatches {
+            writeln!(os, "Mismatch between {} and {}", m.reference, m.pipeline).unwrap();
+            writeln!(
+                os,
+                "  input ({} bytes, shrinked from {} bytes): <BEG>{}<END>",
+                m.shrinked_input.len(),
+                m.input.len(),
+                String::from_utf8_lossy(&m.shrinked_input)
+            )
+            .unwrap();
+            for (name, res) in &[(&m.reference, &m.expected), (&m.pipeline, &m.actual)] {
+                writeln!(
+                    os,
+                    "  {}: exit code {}, output: <BEG>{}<END>",
+                    name,
+                    res.exit_code,
+                    String::from_utf8_lossy(&res.stdout)
+                )
+                .unwrap();
+            }
+        }
+
+        if self.is_ok() {
+            writeln!(os, "All pipelines give the same results").unwrap();
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct DiffTest {
+    pipelines: Vec<Box<dyn Pipeline>>,
+}
+
+impl DiffTest {
+    pub fn new() -> Self {
+        DiffTest { pipelines: vec![] }
+    }
+
+    /// Add a pipeline, the first available one is the reference
+    pub fn add_pipeline(&mut self, pipeline: Box<dyn Pipeline>) {
+        self.pipelines.push(pipeline);
+    }
+
+    /// Run the source file with all pipelines, for every input
+    pub fn run(&mut self, src_path: &str, inputs: &[Vec<u8>]) -> DiffReport {
+        let mut report = DiffReport::default();
+        let mut ready = vec![];
+        for (idx, pipeline) in self.pipelines.iter_mut().enumerate() {
+            if pipeline.prepare(src_path) {
+                report.pipelines.push(pipeline.name());
+                ready.push(idx);
+            } else {
+                report.skipped.push(pipeline.name());
+            }
+        }
+        if ready.len() < 2 {
+            return report;
+        }
+
+        let ref_idx = ready[0];
+        for input in inputs {
+            let expected = self.pipelines[ref_idx].run(input);
+            for idx in &ready[1..] {
+                let actual = self.pipelines[*idx].run(input);
+                if actual != expected {
+                    report
+                        .mismatches
+                        .push(self.shrink_mismatch(ref_idx, *idx, input));
+                }
+            }
+        }
+        report
+    }
+
+    fn shrink_mismatch(&mut self, ref_idx: usize, idx: usize, input: &[u8]) -> Mismatch {
+        let shrinked_input = shrink_input(input, &mut |data| {
+            self.pipelines[ref_idx].run(data) != self.pipelines[idx].run(data)
+        });
+
+        let expected = self.pipelines[ref_idx].run(&shrinked_input);
+        let actual = self.pipelines[idx].run(&shrinked_input);
+        Mismatch {
+            reference: self.pipelines[ref_idx].name(),
+            pipeline: self.pipelines[idx].name(),
+            input: input.to_vec(),
+            shrinked_input,
+            expected,
+            actual,
+        }
+    }
+}
+
+/// Find a smaller input that is still failing (delta debugging)
+/// Removes chunks of the input, with smaller and smaller chunks, as long as it's still failing
+/// `input` must be failing
+pub fn shrink_input(input: &[u8], is_failing: &mut dyn FnMut(&[u8]) -> bool) -> Vec<u8> {
+    let mut res = input.to_vec();
+    if res.is_empty() {
+        return res;
+    }
+    if is_failing(&[]) {
+        return vec![];
+    }
+
+    let mut tries = 1;
+    let mut nchunks = 2;
+    while res.len() >= 2 && tries < SHRINK_MAX_TRIES {
+        let chunk_size = (res.len() - 1) / nchunks + 1;
+        let mut reduced = false;
+
+        for beg in (0..res.len()).step_by(chunk_size) {
+            let end = std::cmp::min(beg + chunk_size, res.len());
+            let candidate: Vec<u8> = res[..beg].iter().chain(&res[end..]).copied().collect();
+            tries += 1;
+            if is_failing(&candidate) {
+                res = candidate;
+                nchunks = std::cmp::max(nchunks - 1, 2);
+                reduced = true;
+                break;
+            }
+            if tries >= SHRINK_MAX_TRIES {
+                break;
+            }
+        }
+
+        if !reduced {
+            if nchunks >= res.len() {
+                break;
+            }
+            nchunks = std::cmp::min(nchunks * 2, res.len());
+        }
+    }
+    res
+}
+
+/// Reference pipeline: a C (compiled with gcc) or python source file next to the source file,
+/// with the same name (same as bintest)
+pub struct RefPipeline {
+    opt: bool,
+    cmd: Option<(String, Vec<String>)>,
+}
+
+impl RefPipeline {
+    /// C references are compiled with -O2 if opt is true, -O0 otherwise
+    /// Python references are only available without opt
+    pub fn new(opt: bool) -> Self {
+        RefPipeline { opt, cmd: None }
+    }
+}
+
+impl Pipeline for RefPipeline {
+    fn name(&self) -> String {
+        match self.opt {
+            true => "ref-O2".to_string(),
+            false => "ref".to_string(),
+        }
+    }
+
+    fn prepare(&mut self, src_path: &str) -> bool {
+        let c_path = Path::new(src_path).with_extension("c");
+        let py_path = Path::new(src_path).with_extension("py");
+
+        self.cmd = if c_path.is_file() {
+            let c_path = c_path.to_str().unwrap();
+            let bin_path = format!(
+                "/tmp/difftest_ref_bin_{}{}",
+                utils::calculate_hash(c_path),
+                self.name()
+            );
+            let opt = if self.opt { "-O2" } else { "-O0" };
+            let (_, status) = utils::run_cmd_status("gcc", [c_path, opt, "-o", &bin_path], &[]);
+            match status {
+                0 => Some((bin_path, vec![])),
+                _ => None,
+            }
+        } else if py_path.is_file() && !self.opt {
+            let py_path = py_path.to_str().unwrap().to_string();
+            Some(("python3".to_string(), vec![py_path]))
+        } else {
+            None
+        };
+        self.cmd.is_some()
+    }
+
+    fn run(&mut self, stdin: &[u8]) -> ExecResult {
+        let (name, args) = self.cmd.as_ref().unwrap();
+        let (stdout, exit_code) = utils::run_cmd_status(name, args, stdin);
+        ExecResult { stdout, exit_code }
+    }
+}
+
+/// Returns all inputs of a source file: the files in the input_<name> directory next to it (same as bintest),
+/// or only an empty input if there is no such directory
+pub fn list_inputs(src_path: &str) -> Vec<Vec<u8>> {
+    let path = Path::new(src_path);
+    let name = path.file_stem().unwrap().to_str().unwrap();
+    let input_dir = path.with_file_name(format!("input_{}", name));
+    if !input_dir.is_dir() {
+        return vec![vec![]];
+    }
+
+    let mut paths: Vec<_> = std::fs::read_dir(input_dir)
+        .expect("Failed to read inputs dir")
+        .map(|e| e.unwrap().path())
+        .collect();
+    paths.sort();
+    paths
+        .iter()
+        .map(|p| std::fs::read(p).expect("Failed to read input file"))
+        .collect()
+}
@@ -1,10 +1,69 @@
 pub mod bintest;
+pub mod difftest;
 pub mod utils;
 
 #[cfg(test)]
 mod tests {
+    use super::difftest::*;
+
     #[test]
     fn it_works() {
         assert_eq!(2 + 2, 4);
     }
+
+    #[test]
+    fn shrink_input_ddmin() {
+        let input = b"abcxdefghijyklm";
+        let mut runs = 0;
+        let res = shrink_input(input, &mut |data| {
+            runs += 1;
+            data.contains(&b'x') && data.contains(&b'y')
+        });
+        assert_eq!(res, b"xy");
+        assert!(runs < 100);
+    }
+
+    // Prints stdin, but the bugged version stops at the first 'z'
+    struct EchoPipeline {
+        bugged: bool,
+    }
+
+    impl Pipeline for EchoPipeline {
+        fn name(&self) -> String {
+            format!("echo{}", if self.bugged { "-bugged" } else { "" })
+        }
+
+        fn prepare(&mut self, _src_path: &str) -> bool {
+            true
+        }
+
+        fn run(&mut self, stdin: &[u8]) -> ExecResult {
+            let stdout = match self.bugged {
+                true => stdin.iter().copied().take_while(|c| *c != b'z').collect(),
+                false => stdin.to_vec(),
+            };
+            ExecResult {
+                stdout,
+                exit_code: 0,
+            }
+        }
+    }
+
+    #[test]
+    fn difftest_mismatch() {
+        let mut dt = DiffTest::new();
+        dt.add_pipeline(Box::new(EchoPipeline { bugged: false }));
+        dt.add_pipeline(Box::new(EchoPipeline { bugged: true }));
+
+        let inputs = vec![b"hello".to_vec(), b"hello zz world".to_vec()];
+        let report = dt.run("unused.le", &inputs);
+        assert_eq!(report.pipelines, vec!["echo", "echo-bugged"]);
+        assert_eq!(report.mismatches.len(), 1);
+
+        let m = &report.mismatches[0];
+        assert_eq!(m.input, b"hello zz world");
+        assert_eq!(m.shrinked_input, b"z");
+        assert_eq!(m.expected.stdout, b"z");
+        assert_eq!(m.actual.stdout, b"");
+    }
 }
@@ -40,6 +40,34 @@ where
     cmd.stdout
 }
 
+/// Run a command with stdin data, and returns its output and exit code
+/// Doesn't fail if the command fails, the exit code is -1 if the command was killed by a signal
+pub fn run_cmd_status<I, S>(name: &str, args: I, stdin_data: &[u8]) -> (Vec<u8>, i32)
+where
+    I: IntoIterator<Item = S> + std::fmt::Debug,
+    S: AsRef<std::ffi::OsStr>,
+{
+    let mut child = Command::new(name)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("Failed to run command");
+
+    // written in another thread to avoid a deadlock if the program writes a lot before reading its input
+    // the program may also exit without reading all its input
+    let mut stdin = child.stdin.take().unwrap();
+    let stdin_data = stdin_data.to_vec();
+    let writer = std::thread::spawn(move || {
+        let _ = stdin.write_all(&stdin_data);
+    });
+
+    let out = child.wait_with_output().expect("Failed to run command");
+    writer.join().unwrap();
+    (out.stdout, out.status.code().unwrap_or(-1))
+}
+
 pub fn read_file_bin(path: &str) -> Vec<u8> {
     println!("Read binary file {}", path);
     let mut f = File::open(path).expect("Failed to open file");